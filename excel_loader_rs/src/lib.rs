@@ -0,0 +1,13064 @@
+//! Loads Excel (`.xlsx`, `.xlsb`, `.xls`), OpenDocument (`.ods`) and JSON lines files into DuckDB,
+//! one table per sheet with the column types inferred from the data.
+//!
+//! The `excel_loader_rs` binary is a thin wrapper around [`run`]. Programs embedding the loader
+//! build a [`Config`] (its fields are the command line options) and call [`load_excel_into_db`];
+//! [`infer_schema`] and [`sanitize_identifier`] expose the typing and naming on their own.
+
+pub use calamine;
+pub use duckdb;
+
+use anyhow::{Context, Result};
+use calamine::{Data, Ods, Range, Reader, Sheets, Xls, Xlsb, Xlsx};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, Parser, ValueEnum};
+use duckdb::types::{TimeUnit, Value, ValueRef};
+use duckdb::Connection;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+/// Grouping/decimal conventions used to read numbers stored as text
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum NumberLocale {
+    /// 1,234.56
+    En,
+    /// 1.234,56
+    De,
+    /// 1 234,56
+    Fr,
+    /// 1'234.56
+    Ch,
+    /// Pick en or de per column, leaving ambiguous columns as text
+    Auto,
+    /// The separators `--decimal-separator` and `--thousands-separator` give
+    #[value(skip)]
+    Custom { group: char, decimal: char },
+}
+
+impl NumberLocale {
+    fn separators(&self) -> (&[char], char) {
+        match self {
+            NumberLocale::En | NumberLocale::Auto => (&[','], '.'),
+            NumberLocale::De => (&['.'], ','),
+            NumberLocale::Fr => (&[' ', '\u{a0}', '\u{202f}'], ','),
+            NumberLocale::Ch => (&['\''], '.'),
+            NumberLocale::Custom { group, decimal } => (std::slice::from_ref(group), *decimal),
+        }
+    }
+}
+
+/// A number parsed out of locale-formatted text
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LocaleNumber {
+    Int(i64),
+    /// A whole number past BIGINT's range, kept exact
+    Huge(i128),
+    Float(f64),
+}
+
+/// Currency symbols `parse_locale_number` drops from either end of a number
+const CURRENCY_SYMBOLS: [char; 5] = ['$', '€', '£', '¥', '₹'];
+
+/// Parses text such as "1.234,56" under `locale`. Grouping separators must
+/// split the integer part into groups of three, so "1.5" is not a German number.
+/// A currency symbol before or after the number is dropped ("$1,234.50", "1.234,50 €"),
+/// and a trailing `%` divides by 100 ("15%" is 0.15). Leading zeros ("007", "00123") mark codes
+/// such as ZIPs and account numbers rather than numbers, so they stay text.
+fn parse_locale_number(text: &str, locale: NumberLocale) -> Option<LocaleNumber> {
+    let (group, decimal) = locale.separators();
+    let text = text.trim();
+    let (text, percent) = match text.strip_suffix('%') {
+        Some(rest) => (rest.trim_end(), true),
+        None => (text, false),
+    };
+    fn split_sign(text: &str) -> (bool, &str) {
+        match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        }
+    }
+    // The sign may come before or after a leading symbol: -$5 and $-5
+    let (negative, body) = split_sign(text);
+    let (negative, body) = match body.strip_prefix(CURRENCY_SYMBOLS) {
+        Some(rest) if !negative => split_sign(rest.trim_start()),
+        Some(rest) => (negative, rest.trim_start()),
+        None => (negative, body.strip_suffix(CURRENCY_SYMBOLS).map_or(body, str::trim_end)),
+    };
+    let (int_part, frac_part) = match body.split_once(decimal) {
+        Some((i, f)) => (i, Some(f)),
+        None => (body, None),
+    };
+
+    if int_part.len() > 1 && int_part.starts_with('0') {
+        return None;
+    }
+    let mut digits = String::new();
+    if int_part.contains(group) {
+        for (i, part) in int_part.split(group).enumerate() {
+            let valid_len = if i == 0 { (1..=3).contains(&part.len()) } else { part.len() == 3 };
+            if !valid_len || !part.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            digits.push_str(part);
+        }
+    } else if !int_part.is_empty() && int_part.chars().all(|c| c.is_ascii_digit()) {
+        digits.push_str(int_part);
+    } else {
+        return None;
+    }
+    let sign = if negative { "-" } else { "" };
+
+    let number = match frac_part {
+        Some(f) if !f.is_empty() && f.chars().all(|c| c.is_ascii_digit()) => {
+            format!("{}{}.{}", sign, digits, f).parse().ok().map(LocaleNumber::Float)
+        }
+        Some(_) => None,
+        None => {
+            let whole = format!("{}{}", sign, digits);
+            whole.parse().map(LocaleNumber::Int).or_else(|_| whole.parse().map(LocaleNumber::Huge)).ok()
+        }
+    };
+    match number {
+        Some(LocaleNumber::Int(n)) if percent => Some(LocaleNumber::Float(n as f64 / 100.0)),
+        Some(LocaleNumber::Huge(n)) if percent => Some(LocaleNumber::Float(n as f64 / 100.0)),
+        Some(LocaleNumber::Float(v)) if percent => Some(LocaleNumber::Float(v / 100.0)),
+        other => other,
+    }
+}
+
+/// Resolves the locale used to read column `col`. `Auto` picks whichever of en/de
+/// parses every sampled string; if both do and disagree on a value, or neither
+/// does, the column is left alone.
+fn resolve_column_locale(sample: &[&[Data]], col: usize, requested: Option<NumberLocale>) -> Option<NumberLocale> {
+    let strings: Vec<&str> = sample.iter().filter_map(|row| match row.get(col) {
+        Some(Data::String(s)) => Some(s.as_str()),
+        _ => None,
+    }).collect();
+    match requested {
+        Some(NumberLocale::Auto) if !strings.is_empty() => {
+            let parsed = |locale| strings.iter().map(|s| parse_locale_number(s, locale)).collect::<Option<Vec<_>>>();
+            match (parsed(NumberLocale::En), parsed(NumberLocale::De)) {
+                (Some(en), Some(de)) if en == de => Some(NumberLocale::En),
+                (Some(_), None) => Some(NumberLocale::En),
+                (None, Some(_)) => Some(NumberLocale::De),
+                _ => None,
+            }
+        }
+        Some(NumberLocale::Auto) => None,
+        other => other,
+    }
+}
+
+/// The type of a column of whole numbers too big for BIGINT (`--bigint-overflow`)
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum BigintOverflow {
+    /// HUGEINT, which holds up to 38 digits
+    Hugeint,
+    /// VARCHAR, keeping each number's text as written
+    Text,
+}
+
+/// What `--tight-ints` does when a value outside the sampled range shows up later in the sheet
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum IntOverflow {
+    /// Size the column from every row, not only the sample
+    Widen,
+    /// Fail the sheet naming the offending value
+    Error,
+}
+
+/// Character encoding of `>>` CSV exports
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum CsvEncoding {
+    /// UTF-8 without a byte order mark (DuckDB's own output)
+    Utf8,
+    /// UTF-8 with a byte order mark, which makes Excel on Windows detect it
+    Utf8Bom,
+    /// UTF-16 little-endian with a byte order mark
+    Utf16le,
+    /// ISO-8859-1; fails if a value holds a character it can't represent
+    Latin1,
+}
+
+/// How `--query` results print to stdout (`>>` exports pick their format from the file name)
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum QueryFormat {
+    /// A bordered table, capped at `--limit` rows
+    Table,
+    /// One JSON array of objects keyed by column name
+    Json,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+/// Which files `--max-files` takes first
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum FileOrder {
+    /// Path, alphabetically
+    Name,
+    /// Last modification time, oldest first
+    Mtime,
+    /// Size, smallest first
+    Size,
+}
+
+/// File format of a `query >> file` export, picked from the file's extension, or of `--export-all`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Tsv,
+    Parquet,
+    Json,
+}
+
+impl ExportFormat {
+    fn from_path(path: &str) -> Result<Self> {
+        let ext = Path::new(path).extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+        match ext.as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "tsv" => Ok(ExportFormat::Tsv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            "json" => Ok(ExportFormat::Json),
+            _ => anyhow::bail!("Cannot export to {}: use a .csv, .tsv, .parquet or .json file name", path),
+        }
+    }
+
+    /// Options for DuckDB's `COPY ... TO`; delimited formats take `csv`'s delimiter, header and quote
+    fn copy_options(self, csv: &CsvOutput) -> String {
+        format!("({})", self.copy_option_list(csv).join(", "))
+    }
+
+    fn copy_option_list(self, csv: &CsvOutput) -> Vec<String> {
+        let literal = |c: char| format!("'{}'", c.to_string().replace('\'', "''"));
+        let delimiter = match self {
+            ExportFormat::Csv => csv.delimiter.unwrap_or(','),
+            ExportFormat::Tsv => csv.delimiter.unwrap_or('\t'),
+            ExportFormat::Parquet => return vec!["FORMAT PARQUET".to_string()],
+            ExportFormat::Json => return vec!["FORMAT JSON".to_string()],
+        };
+        let mut options = vec![format!("HEADER {}", csv.header), format!("DELIMITER {}", literal(delimiter))];
+        if let Some(quote) = csv.quote {
+            options.push(format!("QUOTE {}", literal(quote)));
+        }
+        options
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Tsv => "tsv",
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    /// Whether `--csv-crlf` and `--csv-encoding` apply
+    fn is_delimited(self) -> bool {
+        matches!(self, ExportFormat::Csv | ExportFormat::Tsv)
+    }
+}
+
+/// Compression of `query >> file` exports
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExportCompression {
+    Gzip,
+    Zstd,
+    /// Uncompressed, Parquet files included (they are snappy-compressed by default)
+    None,
+}
+
+impl ExportCompression {
+    /// The file name suffix that asks for this compression
+    fn suffix(self) -> Option<&'static str> {
+        match self {
+            ExportCompression::Gzip => Some(".gz"),
+            ExportCompression::Zstd => Some(".zst"),
+            ExportCompression::None => None,
+        }
+    }
+
+    /// DuckDB's name for it, which for Parquet is the codec inside the file
+    fn copy_name(self, format: ExportFormat) -> &'static str {
+        match self {
+            ExportCompression::Gzip => "gzip",
+            ExportCompression::Zstd => "zstd",
+            ExportCompression::None if format == ExportFormat::Parquet => "uncompressed",
+            ExportCompression::None => "none",
+        }
+    }
+}
+
+/// The format and `COPY ... TO` options of a `query >> path` export, with `--export-compression`,
+/// `--export-partition-by` and `--export-overwrite` checked against each other and the path
+/// before anything runs. A `.gz` or `.zst` after the format's extension asks for that compression;
+/// a partitioned export names a folder and takes its format from `--export-format`.
+fn export_copy_options(path: &str, csv: &CsvOutput, args: &Config) -> Result<(ExportFormat, String)> {
+    let partitioned = !args.export_partition_by.is_empty();
+    let (format, compression) = if partitioned {
+        if Path::new(path).extension().is_some() {
+            anyhow::bail!(
+                "--export-partition-by writes a folder of files, one per partition, so {} must name a folder rather than a single file; --export-format picks the files' format",
+                path
+            );
+        }
+        if !args.export_overwrite && fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_some()) {
+            anyhow::bail!("{} already holds files; pass --export-overwrite to write the partitions into it", path);
+        }
+        (args.export_format, args.export_compression)
+    } else {
+        let lower = path.to_lowercase();
+        let named = [ExportCompression::Gzip, ExportCompression::Zstd].into_iter()
+            .find(|compression| compression.suffix().is_some_and(|suffix| lower.ends_with(suffix)));
+        let format = ExportFormat::from_path(&path[..path.len() - named.and_then(ExportCompression::suffix).map_or(0, str::len)])?;
+        if named.is_some() && format == ExportFormat::Parquet {
+            anyhow::bail!("Parquet compresses inside the file, so {} should end in .parquet; pick the codec with --export-compression", path);
+        }
+        match (named, args.export_compression) {
+            (Some(named), Some(flag)) if named != flag => {
+                anyhow::bail!("{} is named as a {} file, but --export-compression asks for {}", path, named.copy_name(format), flag.copy_name(format))
+            }
+            (named, flag) => (format, flag.or(named)),
+        }
+    };
+    let compressed = compression.is_some_and(|c| c != ExportCompression::None && format != ExportFormat::Parquet);
+    if format.is_delimited() && (csv.crlf || csv.encoding != CsvEncoding::Utf8) && (compressed || partitioned) {
+        anyhow::bail!("--csv-crlf and --csv-encoding rewrite a single uncompressed file, so they can't be combined with --export-compression or --export-partition-by");
+    }
+
+    let mut options = format.copy_option_list(csv);
+    if let Some(compression) = compression {
+        options.push(format!("COMPRESSION '{}'", compression.copy_name(format)));
+    }
+    if partitioned {
+        let columns: Vec<String> = args.export_partition_by.iter().map(|column| quote_identifier(column.trim())).collect();
+        options.push(format!("PARTITION_BY ({})", columns.join(", ")));
+    }
+    if args.export_overwrite {
+        options.push("OVERWRITE_OR_IGNORE true".to_string());
+    }
+    Ok((format, format!("({})", options.join(", "))))
+}
+
+/// How CSV exports are written for their consumer: the delimiter, header and quote go to
+/// DuckDB's COPY, and the line endings and encoding are rewritten afterwards
+struct CsvOutput {
+    /// `None` keeps the format's own: a comma for .csv, a tab for .tsv
+    delimiter: Option<char>,
+    header: bool,
+    quote: Option<char>,
+    crlf: bool,
+    encoding: CsvEncoding,
+}
+
+/// Reads the single character `--csv-delimiter` and `--csv-quote` take; `\t` and `tab` spell a tab
+fn parse_csv_char(s: &str) -> Result<char, String> {
+    let c = match s {
+        "\\t" | "tab" => '\t',
+        _ => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => return Err(format!("expected a single character, got '{}'", s)),
+            }
+        }
+    };
+    if c == '\n' || c == '\r' {
+        return Err("a line break can't separate or quote values".to_string());
+    }
+    Ok(c)
+}
+
+impl CsvOutput {
+    fn from_args(args: &Config) -> Self {
+        CsvOutput {
+            delimiter: args.csv_delimiter,
+            header: !args.csv_no_header,
+            quote: args.csv_quote,
+            crlf: args.csv_crlf,
+            encoding: args.csv_encoding,
+        }
+    }
+
+    /// Rewrites a file DuckDB wrote as LF-terminated UTF-8 in place. A file that can't be
+    /// encoded is removed, so no file is left behind in the wrong encoding.
+    fn apply(&self, path: &Path) -> Result<()> {
+        if !self.crlf && self.encoding == CsvEncoding::Utf8 {
+            return Ok(());
+        }
+        let mut text = fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+        if self.crlf {
+            // Newlines inside quoted values are converted too, which is what Windows tools expect
+            text = text.replace('\n', "\r\n");
+        }
+        let bytes = match self.encoding {
+            CsvEncoding::Utf8 => text.into_bytes(),
+            CsvEncoding::Utf8Bom => [&[0xEF, 0xBB, 0xBF][..], text.as_bytes()].concat(),
+            CsvEncoding::Utf16le => [0xFEFFu16].into_iter().chain(text.encode_utf16()).flat_map(u16::to_le_bytes).collect(),
+            CsvEncoding::Latin1 => text.chars().enumerate()
+                .map(|(i, c)| u8::try_from(u32::from(c)).map_err(|_| {
+                    let line = text.chars().take(i).filter(|&c| c == '\n').count() + 1;
+                    anyhow::anyhow!("Character '{}' on line {} of {} has no Latin-1 encoding", c, line, path.display())
+                }))
+                .collect::<Result<_>>()
+                .inspect_err(|_| {
+                    let _ = fs::remove_file(path);
+                })?,
+        };
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".encoding");
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// What to do when two sheets in one run would load into the same table
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OnCollision {
+    /// Fail the sheet that got there second, naming both sources
+    Error,
+    /// Load the second sheet into `<table>_2` (or `_3`, ...) instead
+    Rename,
+    /// Let the second sheet replace the first, with a warning
+    Overwrite,
+}
+
+/// What to do with a data row holding a value its column can't take
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OnBadRow {
+    /// Leave the row out, counting it in a warning
+    Skip,
+    /// Leave the row out and write it to `--rejects-file` with the reason
+    RejectFile,
+    /// Fail the sheet, as the insert would
+    Abort,
+    /// Load the row with the cells that don't fit set to NULL, counting them in a warning
+    Null,
+}
+
+/// A row `--on-bad-row reject-file` left out of its table
+#[derive(Debug)]
+struct RejectedRow {
+    file: String,
+    sheet: String,
+    /// 1-based sheet row
+    row: usize,
+    error: String,
+    /// The row's cells as a JSON array
+    values: String,
+}
+
+/// Letter case of table and column names once they are sanitized
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum IdentifierCase {
+    Lower,
+    Upper,
+    /// Keep the case of the file, sheet and header text
+    Preserve,
+}
+
+impl IdentifierCase {
+    fn apply(self, name: String) -> String {
+        match self {
+            IdentifierCase::Lower => name.to_lowercase(),
+            IdentifierCase::Upper => name.to_uppercase(),
+            IdentifierCase::Preserve => name,
+        }
+    }
+}
+
+/// Word style of column names, applied to the sanitized header before duplicates are numbered
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum HeaderStyle {
+    /// Keep the header's own words and case: `First Name` -> `First_Name`, `firstName` stays
+    AsIs,
+    /// Lowercase words joined by underscores, splitting camelCase: `First Name`, `firstName` -> `first_name`
+    #[value(name = "snake_case")]
+    SnakeCase,
+    /// Words run together, each after the first capitalized: `First Name`, `first_name` -> `firstName`
+    #[value(name = "camelCase")]
+    CamelCase,
+}
+
+impl HeaderStyle {
+    fn apply(self, name: String) -> String {
+        if self == HeaderStyle::AsIs {
+            return name;
+        }
+        // The underscore sanitizing puts before a leading digit has to stay
+        let prefix = if name.starts_with('_') { "_" } else { "" };
+        let words = identifier_words(&name);
+        let words = words.iter().enumerate().map(|(i, word)| match self {
+            HeaderStyle::CamelCase if i > 0 => {
+                let mut chars = word.chars();
+                chars.next().map_or_else(String::new, |first| first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect())
+            }
+            _ => word.to_lowercase(),
+        });
+        let separator = if self == HeaderStyle::SnakeCase { "_" } else { "" };
+        format!("{}{}", prefix, words.collect::<Vec<_>>().join(separator))
+    }
+}
+
+/// The words of a sanitized name: split at underscores and at camelCase boundaries, where a
+/// capital follows a lowercase letter or digit, or starts a word after an acronym (`HTTPServer`)
+fn identifier_words(name: &str) -> Vec<String> {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|j| chars[j]);
+        let boundary = c.is_uppercase() && match prev {
+            Some(prev) if prev.is_lowercase() || prev.is_numeric() => true,
+            Some(prev) if prev.is_uppercase() => chars.get(i + 1).is_some_and(|next| next.is_lowercase()),
+            _ => false,
+        };
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// How `--union-by` decides which sheets share a table
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum UnionBy {
+    /// Sheets whose column names and inferred types all match
+    Schema,
+}
+
+/// What to do when a value would lose precision in its inferred column type
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OnTruncate {
+    /// Load anyway, logging the first few affected cells and a total
+    Warn,
+    /// Fail the sheet naming the first affected cell
+    Error,
+    /// Load anyway without reporting
+    Silent,
+}
+
+/// What to do with a sheet estimated to need more memory than `--max-memory`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OnMemoryLimit {
+    /// Load it anyway after logging a warning
+    Warn,
+    /// Fail the file instead of loading the sheet
+    Refuse,
+}
+
+/// What becomes of Excel error cells such as `#N/A` and `#DIV/0!` (`--error-handling`)
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ErrorHandling {
+    /// Load them as NULL
+    Null,
+    /// Load them as their text, which makes their columns VARCHAR
+    Text,
+    /// Fail the sheet naming the first one
+    Fail,
+}
+
+/// Which number comes first in slash dates such as `03/04/2024` (`--date-order`)
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum DateOrder {
+    /// Month first, as in the US: 03/04/2024 is March 4
+    Mdy,
+    /// Day first, as in most of Europe: 03/04/2024 is 3 April
+    Dmy,
+}
+
+/// What `--parse-dates` does with text it can't read in a column that is otherwise dates
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OnBadDate {
+    /// Load the cell as NULL
+    Null,
+    /// Fail the sheet naming the first such cell
+    Error,
+}
+
+/// Database the sheets are loaded into
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    Duckdb,
+    /// SQLite file; tables get INTEGER/REAL/TEXT affinities and the DuckDB-only features are unavailable
+    Sqlite,
+}
+
+/// How rows are written into DuckDB tables
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum InsertMode {
+    /// DuckDB's Appender, falling back to INSERT for a chunk it rejects
+    Appender,
+    /// Batched multi-row INSERT statements with bound parameters
+    Sql,
+}
+
+/// Which way a sheet's records run
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Orientation {
+    /// Headers in the first row, one record per row below
+    Rows,
+    /// Field names down the first column, one record per column to the right
+    Columns,
+}
+
+/// Output format of `--probe`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ProbeFormat {
+    Table,
+    Json,
+}
+
+/// Which data rows are sampled for type inference
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SampleFrom {
+    Head,
+    Tail,
+    Random,
+}
+
+/// How inference settles on a type when a column's sampled values disagree
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum TypeStrategy {
+    /// Take the type every value fits, falling back to VARCHAR
+    Widen,
+    /// Take the type most values fit, leaving the rest to `--on-bad-row`
+    Majority,
+}
+
+/// Which row of a set of duplicates `--dedup-on` keeps, in load order
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum DedupKeep {
+    First,
+    Last,
+}
+
+/// Columns that must be present in a loaded table (`--require-columns table=a,b,c`)
+#[derive(Clone, Debug)]
+pub struct RequiredColumns {
+    table: String,
+    columns: Vec<String>,
+}
+
+fn parse_required_columns(s: &str) -> Result<RequiredColumns, String> {
+    let (table, columns) = s.split_once('=').ok_or_else(|| format!("expected table=col1,col2, got '{}'", s))?;
+    let columns: Vec<String> = columns.split(',').map(str::trim).filter(|c| !c.is_empty()).map(sanitize_identifier).collect();
+    if table.trim().is_empty() || columns.is_empty() {
+        return Err(format!("expected table=col1,col2, got '{}'", s));
+    }
+    Ok(RequiredColumns { table: table.trim().to_string(), columns })
+}
+
+/// The columns `--columns` keeps from one sheet, by header name or column letter
+#[derive(Clone, Debug, PartialEq)]
+pub struct SheetColumns {
+    sheet: String,
+    columns: Vec<String>,
+}
+
+/// Parses a `--columns` such as `Sheet1:A,C,F` or `Orders:id,amount`; sheet names cannot contain a colon
+fn parse_sheet_columns(s: &str) -> Result<SheetColumns, String> {
+    let (sheet, columns) = s.split_once(':').ok_or_else(|| format!("expected Sheet:col1,col2, got '{}'", s))?;
+    let columns: Vec<String> = columns.split(',').map(|column| column.trim().to_string()).collect();
+    if sheet.trim().is_empty() || columns.iter().any(String::is_empty) {
+        return Err(format!("expected Sheet:col1,col2, got '{}'", s));
+    }
+    Ok(SheetColumns { sheet: sheet.trim().to_string(), columns })
+}
+
+/// The block of cells `--range` loads from one sheet, 0-based and inclusive like calamine's positions
+#[derive(Clone, Debug, PartialEq)]
+pub struct SheetRange {
+    sheet: String,
+    start: (u32, u32),
+    end: (u32, u32),
+}
+
+/// Parses a `--range` such as `Sheet1!B5:F200` or `'Q1 Sales'!$A$3:$H$90`
+fn parse_sheet_range(s: &str) -> Result<SheetRange, String> {
+    let (sheet, cells) = s.rsplit_once('!').ok_or_else(|| format!("expected Sheet!A1:F20, got '{}'", s))?;
+    let sheet = sheet.trim();
+    let sheet = sheet.strip_prefix('\'').and_then(|quoted| quoted.strip_suffix('\'')).map_or(sheet.to_string(), |quoted| quoted.replace("''", "'"));
+    if sheet.is_empty() {
+        return Err(format!("'{}' names no sheet before the !", s));
+    }
+    let (first, last) = cells.trim().split_once(':').ok_or_else(|| format!("expected a range like B5:F200 after the !, got '{}'", cells))?;
+    let start = cell_position(first).ok_or_else(|| format!("'{}' is not a cell like B5", first))?;
+    let end = cell_position(last).ok_or_else(|| format!("'{}' is not a cell like F200", last))?;
+    if end.0 < start.0 || end.1 < start.1 {
+        return Err(format!("{} is above or left of {}; give the top-left cell first", last, first));
+    }
+    Ok(SheetRange { sheet, start, end })
+}
+
+/// The 0-based (row, column) of an A1-style cell such as `B5` or `$B$5`, within Excel's
+/// 1048576 rows and XFD columns
+fn cell_position(cell: &str) -> Option<(u32, u32)> {
+    let cell = cell.trim();
+    let letters = cell.chars().skip_while(|&c| c == '$').take_while(char::is_ascii_alphabetic).count();
+    let (column, row) = cell.trim_start_matches('$').split_at(letters);
+    let row: u32 = row.strip_prefix('$').unwrap_or(row).parse().ok()?;
+    if letters == 0 || letters > 3 || !(1..=1_048_576).contains(&row) {
+        return None;
+    }
+    let col = column.chars().fold(0, |n, c| n * 26 + (c.to_ascii_uppercase() as u32 - 'A' as u32 + 1));
+    (col <= 16_384).then_some((row - 1, col - 1))
+}
+
+/// Collations are spliced into DDL, so only accept DuckDB collation names like `nocase` or `nocase.noaccent`
+fn parse_collation(s: &str) -> Result<String, String> {
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+        Ok(s.to_lowercase())
+    } else {
+        Err(format!("invalid collation name '{}'", s))
+    }
+}
+
+/// Schema that `--staging` loads into before swapping tables into `main`
+const STAGING_SCHEMA: &str = "_staging";
+
+/// `--db` value that loads into a database held in memory and dropped when the run ends
+const MEMORY_DB: &str = ":memory:";
+
+/// `--db` prefixes that name a MotherDuck cloud database, such as `md:sales`
+const MOTHERDUCK_PREFIXES: [&str; 2] = ["md:", "motherduck:"];
+
+/// The MotherDuck database a `--db` such as `md:sales?motherduck_token=...` names, if it names one
+fn motherduck_database(db: &str) -> Option<&str> {
+    let rest = MOTHERDUCK_PREFIXES.iter().find_map(|prefix| db.get(..prefix.len())?.eq_ignore_ascii_case(prefix).then(|| &db[prefix.len()..]))?;
+    Some(rest.split_once('?').map_or(rest, |(name, _)| name))
+}
+
+/// Whether MotherDuck will find an access token: in the `--db` string itself or in `env`, the
+/// `motherduck_token` environment variable (either case)
+fn has_motherduck_token(db: &str, env: Option<&str>) -> bool {
+    let in_db = db.split_once('?').is_some_and(|(_, options)| {
+        options.split('&').any(|option| option.split_once('=').is_some_and(|(key, value)| key.eq_ignore_ascii_case("motherduck_token") && !value.is_empty()))
+    });
+    in_db || env.is_some_and(|token| !token.is_empty())
+}
+
+/// `--db` with any MotherDuck token masked, for logs, manifests and the load log
+fn redacted_db(db: &str) -> String {
+    static TOKEN: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    let token = TOKEN.get_or_init(|| Regex::new(r"(?i)(motherduck_token=)[^&]*").unwrap());
+    token.replace_all(db, "${1}***").into_owned()
+}
+
+/// `<db>.lock`, locked for the length of a run so that overlapping runs (cron jobs that run long,
+/// say) don't race on the same database file. The OS releases the lock when the process ends,
+/// however it ends; the file stays behind, naming the last run that held it.
+struct RunLock {
+    _file: fs::File,
+}
+
+/// Locks `db`'s lock file, waiting up to `wait` for a run that holds it. Databases that are no
+/// local file (`:memory:`, MotherDuck) need no lock.
+fn acquire_run_lock(db: &str, wait: Duration, interrupted: impl Fn() -> bool) -> Result<Option<RunLock>> {
+    use std::io::Write;
+
+    if db == MEMORY_DB || motherduck_database(db).is_some() {
+        return Ok(None);
+    }
+    let path = format!("{}.lock", db);
+    let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)
+        .with_context(|| format!("Cannot open the lock file {}", path))?;
+    let deadline = Instant::now() + wait;
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(fs::TryLockError::WouldBlock) if Instant::now() < deadline && !interrupted() => {
+                if !waiting {
+                    info!("Waiting up to {:?} for the load in progress on {} (--lock-wait)", wait, db);
+                    waiting = true;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            Err(fs::TryLockError::WouldBlock) => {
+                // The holder wrote who it is; a lock file that can't be read says nothing
+                let holder = fs::read_to_string(&path).ok().filter(|text| !text.trim().is_empty())
+                    .map_or(String::new(), |text| format!(" ({})", text.trim()));
+                let advice = match wait.is_zero() {
+                    true => "wait for it with --lock-wait <seconds>, or run again once it finishes".to_string(),
+                    false => format!("gave up after waiting {:?} (--lock-wait)", wait),
+                };
+                anyhow::bail!("Another load is in progress on {}{}; {}", db, holder, advice);
+            }
+            Err(fs::TryLockError::Error(e)) => return Err(e).with_context(|| format!("Cannot lock {}", path)),
+        }
+    }
+    file.set_len(0)?;
+    write!(file, "pid {} since {}", std::process::id(), chrono::Local::now().format("%Y-%m-%d %H:%M:%S"))?;
+    Ok(Some(RunLock { _file: file }))
+}
+
+/// Opens the DuckDB database `--db` names: one held in memory, a MotherDuck cloud database
+/// (`md:name`), whose extension is installed and loaded first, or a local file. Remote files
+/// (`s3://`, `https://`) can only be attached read-only, so they are refused.
+fn open_duckdb(db: &str) -> Result<Connection> {
+    if db == MEMORY_DB {
+        return Ok(Connection::open_in_memory()?);
+    }
+    if let Some(name) = motherduck_database(db) {
+        if name.is_empty() {
+            anyhow::bail!("--db {} names no MotherDuck database; use md:<database>, such as md:my_db", redacted_db(db));
+        }
+        let env = std::env::var("motherduck_token").or_else(|_| std::env::var("MOTHERDUCK_TOKEN")).ok();
+        if !has_motherduck_token(db, env.as_deref()) {
+            anyhow::bail!("MotherDuck needs an access token: set the motherduck_token environment variable or add ?motherduck_token=<token> to --db");
+        }
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch("INSTALL motherduck; LOAD motherduck;")
+            .context("Cannot load DuckDB's motherduck extension, which md: databases need; it is downloaded on first use, so check the network connection")?;
+        conn.execute_batch(&format!("ATTACH '{}'", db.replace('\'', "''")))
+            .with_context(|| format!("Cannot attach MotherDuck database {}; check the token and the database name", name))?;
+        conn.execute_batch(&format!("USE {}", quote_identifier(name)))?;
+        info!("Loading into MotherDuck database {}", name);
+        return Ok(conn);
+    }
+    if let Some((scheme, _)) = db.split_once("://").filter(|(scheme, _)| scheme.chars().all(|c| c.is_ascii_alphanumeric())) {
+        anyhow::bail!("--db {} is a remote {} file, which DuckDB can only attach read-only; load into a local file or a MotherDuck md: database", redacted_db(db), scheme);
+    }
+    Ok(Connection::open(db)?)
+}
+
+/// Rows of the first chunk that `--explain` writes out
+const EXPLAIN_ROWS: usize = 5;
+
+/// Run history tables kept in every database; `--refresh-all` leaves them alone
+const LOAD_LOG_TABLES: [&str; 2] = ["_load_runs", "_load_run_files"];
+
+/// Where `--incremental` records each loaded file's modification time, size and tables
+const LOADED_FILES_TABLE: &str = "_loaded_files";
+
+/// Where `--incremental` records a content hash for each sheet it loaded, so a changed workbook
+/// only rebuilds the sheets whose cells changed
+const LOADED_SHEETS_TABLE: &str = "_loaded_sheets";
+
+/// Where `--store-metadata` keeps each column's original header
+const COLUMN_MAPPING_TABLE: &str = "_column_mapping";
+
+/// Where `--table-metadata` records the file, sheet and size each table was loaded from
+const TABLE_METADATA_TABLE: &str = "_table_metadata";
+
+/// Whether a table is one of the loader's own bookkeeping tables rather than loaded data
+fn is_internal_table(table: &str) -> bool {
+    LOAD_LOG_TABLES.contains(&table) || [LOADED_FILES_TABLE, LOADED_SHEETS_TABLE, COLUMN_MAPPING_TABLE, TABLE_METADATA_TABLE].contains(&table)
+}
+
+/// Sheets with more data rows than this get their own row progress bar under the file bar
+const ROW_PROGRESS_MIN_ROWS: usize = 50_000;
+
+/// Settings read from the `--config` TOML file
+#[derive(Deserialize, Default, Debug)]
+struct Settings {
+    /// `[[sheet]]` sections, tried in order; the first one matching a sheet applies
+    #[serde(default)]
+    sheet: Vec<SheetConfig>,
+    /// Top-level keys: defaults for command line options, which `Config::try_parse_with_config` applies
+    #[serde(flatten)]
+    options: toml::Table,
+    /// Whether `options` went into the command line already, so the file needn't be read again
+    #[serde(skip)]
+    options_applied: bool,
+}
+
+/// Handling for the sheets matching one `[[sheet]]` section
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SheetConfig {
+    /// Glob matched against the workbook's file name
+    #[serde(default = "any_file", deserialize_with = "deserialize_glob")]
+    file: glob::Pattern,
+    /// Sheet name, compared case-insensitively; every sheet when omitted
+    sheet: Option<String>,
+    /// Header row (0-based) counted from the first row after --skip-rows, as --header-row is;
+    /// rows above it are ignored
+    header_row: Option<u32>,
+    /// Cell text loaded as NULL
+    #[serde(default)]
+    null_tokens: Vec<String>,
+    /// Columns to keep, in this order; all columns when omitted
+    columns: Option<Vec<String>>,
+    /// Column name to DuckDB type, replacing the inferred type
+    #[serde(default)]
+    types: HashMap<String, String>,
+}
+
+/// The command line option a top-level config file key sets, named by its field (`chunk_size`)
+/// or its long flag (`chunk-size`)
+fn option_arg<'a>(command: &'a clap::Command, key: &str) -> Option<&'a clap::Arg> {
+    command.get_arguments()
+        .filter(|arg| arg.get_id() != "config" && arg.get_long().is_some())
+        .find(|arg| arg.get_id() == key.replace('-', "_").as_str() || arg.get_long() == Some(key))
+}
+
+fn any_file() -> glob::Pattern {
+    glob::Pattern::new("*").unwrap()
+}
+
+fn deserialize_glob<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<glob::Pattern, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    glob::Pattern::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+impl Settings {
+    /// Reads a config file, rejecting top-level keys that name no command line option
+    fn load(path: &Path) -> Result<Settings> {
+        let text = fs::read_to_string(path).with_context(|| format!("Cannot read config file {}", path.display()))?;
+        let settings: Settings = toml::from_str(&text).with_context(|| format!("Invalid config file {}", path.display()))?;
+        let command = Config::command();
+        if let Some(key) = settings.options.keys().find(|key| option_arg(&command, key).is_none()) {
+            anyhow::bail!("{} sets '{}', which is not an option", path.display(), key);
+        }
+        Ok(settings)
+    }
+
+    fn sheet_for(&self, file_name: &str, sheet_name: &str) -> Option<&SheetConfig> {
+        self.sheet.iter().find(|cfg| {
+            cfg.file.matches(file_name) && cfg.sheet.as_ref().is_none_or(|s| s.eq_ignore_ascii_case(sheet_name))
+        })
+    }
+}
+
+/// Column types declared in `--schema`, keyed by lowercase table name and then lowercase column name
+#[derive(Default, Debug)]
+struct SchemaOverrides(HashMap<String, HashMap<String, &'static str>>);
+
+/// One top-level `--schema` entry: `"table.column" = "TYPE"`, or a table with a type per column
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SchemaEntry {
+    Type(String),
+    Columns(HashMap<String, String>),
+}
+
+impl SchemaOverrides {
+    /// Reads a TOML file (JSON when the extension is `.json`) such as
+    /// `customers.zip = "VARCHAR"` or `{"customers": {"zip": "VARCHAR"}}`
+    fn load(path: &Path) -> Result<SchemaOverrides> {
+        let text = fs::read_to_string(path).with_context(|| format!("Cannot read schema file {}", path.display()))?;
+        let is_json = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let entries: HashMap<String, SchemaEntry> = if is_json {
+            serde_json::from_str(&text).with_context(|| format!("Invalid schema file {}", path.display()))?
+        } else {
+            toml::from_str(&text).with_context(|| format!("Invalid schema file {}", path.display()))?
+        };
+
+        let mut tables: HashMap<String, HashMap<String, &'static str>> = HashMap::new();
+        for (key, entry) in entries {
+            let columns = match entry {
+                SchemaEntry::Type(dtype) => {
+                    let (table, column) = key.split_once('.')
+                        .with_context(|| format!("Schema entry '{}' should be table.column", key))?;
+                    vec![(table.to_string(), column.to_string(), dtype)]
+                }
+                SchemaEntry::Columns(columns) => columns.into_iter().map(|(column, dtype)| (key.clone(), column, dtype)).collect(),
+            };
+            for (table, column, dtype) in columns {
+                let parsed = parse_column_type(&dtype)
+                    .with_context(|| format!("Unsupported type '{}' for {}.{} in {}; expected DECIMAL(p,s) or one of {}", dtype, table, column, path.display(), OVERRIDE_TYPES.join(", ")))?;
+                tables.entry(table.trim().to_lowercase()).or_default()
+                    .insert(sanitize_identifier(&column).to_lowercase(), parsed);
+            }
+        }
+        Ok(SchemaOverrides(tables))
+    }
+
+    fn columns_for(&self, table: &str) -> Option<&HashMap<String, &'static str>> {
+        self.0.get(&table.to_lowercase())
+    }
+}
+
+/// Column types a config file, `--schema` or `--suffix-types` may force, spelled the way the rest of the loader spells them
+const OVERRIDE_TYPES: [&str; 10] = ["BOOLEAN", "TINYINT", "SMALLINT", "INTEGER", "BIGINT", "HUGEINT", "DOUBLE", "VARCHAR", "DATE", "TIMESTAMP"];
+
+/// Resolves a user-supplied column type to its canonical spelling: one of `OVERRIDE_TYPES`
+/// or `DECIMAL(p,s)`. Each decimal spelling is interned, so it is allocated once per run
+/// however often it is parsed (every sheet of every `--watch` batch, say).
+fn parse_column_type(dtype: &str) -> Option<&'static str> {
+    static DECIMAL: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    static DECIMALS: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+    let dtype = dtype.trim();
+    if let Some(known) = OVERRIDE_TYPES.iter().copied().find(|t| t.eq_ignore_ascii_case(dtype)) {
+        return Some(known);
+    }
+    let decimal = DECIMAL.get_or_init(|| Regex::new(r"(?i)^DECIMAL\s*\(\s*(\d+)\s*,\s*(\d+)\s*\)$").unwrap());
+    let caps = decimal.captures(dtype)?;
+    let (precision, scale): (u32, u32) = (caps[1].parse().ok()?, caps[2].parse().ok()?);
+    if !(1..=38).contains(&precision) || scale > precision {
+        return None;
+    }
+    let spelling = format!("DECIMAL({},{})", precision, scale);
+    let mut decimals = DECIMALS.lock().unwrap();
+    if let Some(interned) = decimals.get(spelling.as_str()) {
+        return Some(interned);
+    }
+    let interned: &'static str = Box::leak(spelling.into_boxed_str());
+    decimals.insert(interned);
+    Some(interned)
+}
+
+/// Scale of a `DECIMAL(p,s)` type name
+fn decimal_scale(dtype: &str) -> Option<i32> {
+    dtype.strip_prefix("DECIMAL(")?.strip_suffix(')')?.split_once(',')?.1.parse().ok()
+}
+
+/// The kinds of number format `--format-map` can name instead of an exact format code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FormatClass {
+    Percent,
+    Currency,
+    Date,
+    Datetime,
+    Time,
+}
+
+/// One `--format-map` entry: columns whose cells use a matching number format load as `dtype`
+#[derive(Clone, Debug)]
+pub struct FormatRule {
+    /// A class, or `Err` holding an exact format code
+    key: Result<FormatClass, String>,
+    dtype: &'static str,
+}
+
+/// Parses `KEY=TYPE`, splitting at the last `=` since format codes may contain one (`[=1]0`)
+fn parse_format_rule(s: &str) -> Result<FormatRule, String> {
+    let (key, dtype) = s.rsplit_once('=').ok_or_else(|| format!("expected KEY=TYPE, got '{}'", s))?;
+    if key.is_empty() {
+        return Err(format!("'{}' names no format class or code before '='", s));
+    }
+    let dtype = parse_column_type(dtype).ok_or_else(|| format!("unsupported type '{}'; expected DECIMAL(p,s) or one of {}", dtype.trim(), OVERRIDE_TYPES.join(", ")))?;
+    let key = match key.trim().to_ascii_lowercase().as_str() {
+        "percent" => Ok(FormatClass::Percent),
+        "currency" => Ok(FormatClass::Currency),
+        "date" => Ok(FormatClass::Date),
+        "datetime" => Ok(FormatClass::Datetime),
+        "time" => Ok(FormatClass::Time),
+        _ => Err(key.to_string()),
+    };
+    Ok(FormatRule { key, dtype })
+}
+
+/// The `--format-map` type for cells with this format code: an exact code match first, then
+/// the first rule for the code's class
+fn mapped_format_type(rules: &[FormatRule], code: &str) -> Option<&'static str> {
+    if let Some(rule) = rules.iter().find(|rule| rule.key.as_ref().err().is_some_and(|wanted| wanted == code)) {
+        return Some(rule.dtype);
+    }
+    let class = format_class(code)?;
+    rules.iter().find(|rule| rule.key == Ok(class)).map(|rule| rule.dtype)
+}
+
+/// Classifies a number format code by what its first section shows. Quoted text, escaped
+/// characters and `[...]` tags (colours, conditions, locales) are ignored, except that a
+/// `[$€-407]` tag naming a symbol, or a currency symbol outside the tags, makes it currency; a
+/// bare locale tag such as `[$-409]` says nothing. `m` is minutes next to an `h` or `s` and
+/// months otherwise.
+fn format_class(code: &str) -> Option<FormatClass> {
+    static CURRENCY_TAG: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    let section = code.split(';').next().unwrap_or_default();
+    // `shown` keeps the literal text (quoted or escaped) a cell displays; `bare` only the codes
+    let (mut shown, mut bare) = (String::new(), String::new());
+    let (mut quoted, mut escaped, mut bracket) = (false, false, false);
+    for c in section.chars() {
+        match c {
+            _ if escaped => {
+                escaped = false;
+                shown.push(c);
+            }
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            _ if quoted => shown.push(c),
+            '[' => bracket = true,
+            ']' => bracket = false,
+            _ if bracket => {}
+            _ => {
+                shown.push(c);
+                bare.push(c.to_ascii_lowercase());
+            }
+        }
+    }
+    let tag = CURRENCY_TAG.get_or_init(|| Regex::new(r"\[\$[^\]-]+").unwrap());
+    if tag.is_match(section) || shown.contains(['$', '€', '£', '¥', '₹']) {
+        return Some(FormatClass::Currency);
+    }
+    if is_percent_format(section) {
+        return Some(FormatClass::Percent);
+    }
+    let bare = bare.replace("am/pm", "").replace("a/p", "");
+    let date = bare.contains(['y', 'd']) || (bare.contains('m') && !bare.contains(['h', 's']));
+    let time = bare.contains(['h', 's']);
+    match (date, time) {
+        (true, true) => Some(FormatClass::Datetime),
+        (true, false) => Some(FormatClass::Date),
+        (false, true) => Some(FormatClass::Time),
+        (false, false) => None,
+    }
+}
+
+/// Codes of the built-in number formats, which styles.xml refers to by id without listing
+fn builtin_format_code(id: u32) -> Option<&'static str> {
+    Some(match id {
+        0 => "General",
+        1 => "0",
+        2 => "0.00",
+        3 => "#,##0",
+        4 => "#,##0.00",
+        5 => "$#,##0_);($#,##0)",
+        6 => "$#,##0_);[Red]($#,##0)",
+        7 => "$#,##0.00_);($#,##0.00)",
+        8 => "$#,##0.00_);[Red]($#,##0.00)",
+        9 => "0%",
+        10 => "0.00%",
+        11 => "0.00E+00",
+        12 => "# ?/?",
+        13 => "# ??/??",
+        14 => "mm-dd-yy",
+        15 => "d-mmm-yy",
+        16 => "d-mmm",
+        17 => "mmm-yy",
+        18 => "h:mm AM/PM",
+        19 => "h:mm:ss AM/PM",
+        20 => "h:mm",
+        21 => "h:mm:ss",
+        22 => "m/d/yy h:mm",
+        37 => "#,##0 ;(#,##0)",
+        38 => "#,##0 ;[Red](#,##0)",
+        39 => "#,##0.00;(#,##0.00)",
+        40 => "#,##0.00;[Red](#,##0.00)",
+        44 => r#"_("$"* #,##0.00_);_("$"* \(#,##0.00\);_("$"* "-"??_);_(@_)"#,
+        45 => "mm:ss",
+        46 => "[h]:mm:ss",
+        47 => "mmss.0",
+        48 => "##0.0E+0",
+        49 => "@",
+        _ => return None,
+    })
+}
+
+/// Header-name suffixes that force a column type (`--suffix-types _dt=TIMESTAMP,_amt=DECIMAL(18,2)`)
+#[derive(Clone, Debug)]
+pub struct SuffixTypes(Vec<(String, &'static str)>);
+
+impl SuffixTypes {
+    /// Type for a column whose (sanitized) name ends in one of the suffixes; the first listed match wins
+    fn type_for(&self, column: &str) -> Option<(&str, &'static str)> {
+        let column = column.to_ascii_lowercase();
+        self.0.iter().find(|(suffix, _)| column.ends_with(suffix.as_str())).map(|(suffix, dtype)| (suffix.as_str(), *dtype))
+    }
+}
+
+/// Splits a list on commas outside parentheses, so `DECIMAL(18,2)` or `t.(a,b)` stays whole
+fn split_outside_parens(s: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                entries.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    entries.push(&s[start..]);
+    entries
+}
+
+fn parse_suffix_types(s: &str) -> Result<SuffixTypes, String> {
+    split_outside_parens(s).into_iter()
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (suffix, dtype) = entry.split_once('=').ok_or_else(|| format!("expected suffix=TYPE, got '{}'", entry.trim()))?;
+            let suffix = suffix.trim().to_ascii_lowercase();
+            if suffix.is_empty() {
+                return Err(format!("empty suffix in '{}'", entry.trim()));
+            }
+            let dtype = parse_column_type(dtype)
+                .ok_or_else(|| format!("unsupported type '{}'; expected DECIMAL(p,s) or one of {}", dtype.trim(), OVERRIDE_TYPES.join(", ")))?;
+            Ok((suffix, dtype))
+        })
+        .collect::<Result<_, _>>()
+        .map(SuffixTypes)
+}
+
+/// Text spellings that load as BOOLEAN (`--bool-strings "Yes=true,No=false"`), keyed case-insensitively
+#[derive(Clone, Debug)]
+pub struct BoolStrings(Vec<(String, bool)>);
+
+impl BoolStrings {
+    fn value_for(&self, text: &str) -> Option<bool> {
+        let text = text.trim().to_lowercase();
+        self.0.iter().find(|(spelling, _)| *spelling == text).map(|(_, value)| *value)
+    }
+}
+
+fn parse_bool_strings(s: &str) -> Result<BoolStrings, String> {
+    s.split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (text, value) = entry.split_once('=').ok_or_else(|| format!("expected text=true or text=false, got '{}'", entry.trim()))?;
+            let text = text.trim().to_lowercase();
+            if text.is_empty() {
+                return Err(format!("empty text in '{}'", entry.trim()));
+            }
+            match value.trim().to_ascii_lowercase().as_str() {
+                "true" => Ok((text, true)),
+                "false" => Ok((text, false)),
+                other => Err(format!("expected true or false for '{}', got '{}'", text, other)),
+            }
+        })
+        .collect::<Result<_, _>>()
+        .map(BoolStrings)
+}
+
+/// Turns the error cells below the header into their text (`#N/A`) for `--error-handling text`,
+/// or fails on the first one for `--error-handling fail`
+fn apply_error_handling(mut range: Range<Data>, handling: ErrorHandling) -> Result<Range<Data>> {
+    let (row0, col0) = range.start().unwrap_or_default();
+    let errors: Vec<(u32, u32, String)> = range.used_cells()
+        .filter(|(row, _, _)| *row > 0)
+        .filter_map(|(row, col, cell)| match cell {
+            Data::Error(e) => Some((row as u32, col as u32, e.to_string())),
+            _ => None,
+        })
+        .collect();
+    if let (Some((row, col, error)), ErrorHandling::Fail) = (errors.first(), handling) {
+        let header = range.get((0, *col as usize)).map(|cell| cell.to_string()).unwrap_or_default();
+        anyhow::bail!("Column '{}', row {} holds the error {} (--error-handling fail)", header, row0 + row + 1, error);
+    }
+    for (row, col, error) in errors {
+        range.set_value((row0 + row, col0 + col), Data::String(error));
+    }
+    Ok(range)
+}
+
+/// Turns `--bool-strings` text into real booleans, but only in columns whose every data cell is
+/// blank, an error, a boolean or one of the spellings, so a free-text column keeps its "Yes"
+fn apply_bool_strings(mut range: Range<Data>, strings: &BoolStrings) -> Range<Data> {
+    let (height, width) = range.get_size();
+    let (row0, col0) = range.start().unwrap_or_default();
+    let mut converted = Vec::new();
+    for col in 0..width {
+        let mut matches = Vec::new();
+        let all_boolish = (1..height).all(|row| match range.get((row, col)) {
+            Some(Data::String(text)) => match strings.value_for(text) {
+                Some(value) => {
+                    matches.push((row, value));
+                    true
+                }
+                None => false,
+            },
+            Some(Data::Empty | Data::Error(_) | Data::Bool(_)) | None => true,
+            Some(_) => false,
+        });
+        if all_boolish {
+            converted.extend(matches.into_iter().map(|(row, value)| ((row0 + row as u32, col0 + col as u32), value)));
+        }
+    }
+    for (position, value) in converted {
+        range.set_value(position, Data::Bool(value));
+    }
+    range
+}
+
+/// The date and time in text such as `2024-03-01`, `2024-03-01 08:30` or `03/01/2024 8:30:00`,
+/// reading slash dates in `order`. Date-only text comes back at midnight.
+fn parse_date_text(text: &str, order: DateOrder) -> Option<chrono::NaiveDateTime> {
+    let text = text.trim();
+    let date_format = match (text.contains('/'), order) {
+        (false, _) => "%Y-%m-%d",
+        (true, DateOrder::Mdy) => "%m/%d/%Y",
+        (true, DateOrder::Dmy) => "%d/%m/%Y",
+    };
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(text, date_format) {
+        return Some(date.and_time(chrono::NaiveTime::MIN));
+    }
+    [" %H:%M:%S%.f", "T%H:%M:%S%.f", " %H:%M", "T%H:%M"]
+        .iter()
+        .find_map(|time| chrono::NaiveDateTime::parse_from_str(text, &format!("{}{}", date_format, time)).ok())
+}
+
+/// Turns date text into ISO date cells (`--parse-dates`) in columns where at least
+/// `--date-threshold` of the non-empty data cells are dates, so they type as DATE or TIMESTAMP.
+/// Whatever else such a column holds becomes empty, or fails the sheet with `--on-bad-date error`.
+fn apply_date_strings(mut range: Range<Data>, args: &Config) -> Result<Range<Data>> {
+    let (height, width) = range.get_size();
+    let (row0, col0) = range.start().unwrap_or_default();
+    let mut converted = Vec::new();
+    for col in 0..width {
+        let (mut dates, mut filled, mut stragglers) = (Vec::new(), 0, Vec::new());
+        for row in 1..height {
+            match range.get((row, col)) {
+                Some(Data::Empty | Data::Error(_)) | None => continue,
+                Some(Data::String(text)) if text.trim().is_empty() => continue,
+                Some(Data::String(text)) => match parse_date_text(text, args.date_order) {
+                    Some(dt) => dates.push((row, dt)),
+                    None => stragglers.push(row),
+                },
+                Some(Data::DateTime(_)) => {}
+                Some(Data::DateTimeIso(v)) if parse_iso_datetime(v).is_some() => {}
+                Some(_) => stragglers.push(row),
+            }
+            filled += 1;
+        }
+        if dates.is_empty() || ((filled - stragglers.len()) as f64) < args.date_threshold * filled as f64 {
+            continue;
+        }
+        if let (Some(&row), OnBadDate::Error) = (stragglers.first(), args.on_bad_date) {
+            let header = range.get((0, col)).map(|cell| cell.to_string()).unwrap_or_default();
+            let value = range.get((row, col)).map(|cell| cell.to_string()).unwrap_or_default();
+            anyhow::bail!("Column '{}' holds dates, but row {} has '{}', which isn't one (--on-bad-date error)", header, row0 as usize + row + 1, value);
+        }
+        let position = |row: usize| (row0 + row as u32, col0 + col as u32);
+        converted.extend(stragglers.into_iter().map(|row| (position(row), Data::Empty)));
+        converted.extend(dates.into_iter().map(|(row, dt)| {
+            let iso = match dt.time() == chrono::NaiveTime::MIN {
+                true => dt.date().to_string(),
+                false => dt.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+            };
+            (position(row), Data::DateTimeIso(iso))
+        }));
+    }
+    for (position, value) in converted {
+        range.set_value(position, value);
+    }
+    Ok(range)
+}
+
+/// Reads a byte size such as `512MB`, `4GB` or `1.5G`, in binary multiples; a bare number is bytes
+fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let text = s.trim();
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let invalid = || format!("expected a size like 512MB or 4GB, got '{}'", text);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let unit = unit.trim().to_ascii_uppercase();
+    let shift = match unit.strip_suffix('B').unwrap_or(&unit).trim_end_matches('I') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(invalid()),
+    };
+    Ok((number * (1u64 << shift) as f64) as u64)
+}
+
+/// A byte count in the largest binary unit it fills, e.g. `1.5 GB`
+fn format_byte_size(bytes: u64) -> String {
+    let units = ["bytes", "KB", "MB", "GB", "TB"];
+    let exponent = (0..units.len()).rev().find(|&i| bytes >= 1u64 << (10 * i)).unwrap_or(0);
+    match exponent {
+        0 => format!("{} bytes", bytes),
+        _ => format!("{:.1} {}", bytes as f64 / (1u64 << (10 * exponent)) as f64, units[exponent]),
+    }
+}
+
+/// Reads common yes/no spellings for BOOLEAN columns
+fn parse_flag(text: &str) -> Option<bool> {
+    match text.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" | "true" | "t" | "1" => Some(true),
+        "n" | "no" | "false" | "f" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Swaps rows and columns, so a vertical key-value form reads like a sheet with a header row
+fn transpose(range: &Range<Data>) -> Range<Data> {
+    let (height, width) = range.get_size();
+    if height == 0 || width == 0 {
+        return Range::empty();
+    }
+    let mut transposed = Range::new((0, 0), (width as u32 - 1, height as u32 - 1));
+    for (row, col, cell) in range.used_cells() {
+        transposed.set_value((col as u32, row as u32), cell.clone());
+    }
+    transposed
+}
+
+/// Reads a sheet's cells the way the user asked: transposed for `--orientation columns`,
+/// cut down to start at the header row, then with any matching `[[sheet]]` section applied. With `--keep-raw` the second range
+/// holds the same rows before null tokens and projection touched them.
+fn prepare_range(range: Range<Data>, sheet_name: &str, sheet_config: Option<&SheetConfig>, args: &Config) -> Result<(Range<Data>, Option<Range<Data>>)> {
+    let range = repair_text(range, args.fix_encoding);
+    let range = match args.orientation {
+        Orientation::Rows => range,
+        Orientation::Columns => transpose(&range),
+    };
+    let range = locate_header(range, sheet_config, args);
+    let range = select_header_row(range, sheet_config.and_then(|config| config.header_row), args)?;
+    let raw = args.keep_raw.then(|| range.clone());
+    let range = if args.trim || args.normalize_whitespace {
+        clean_text(range, args.normalize_whitespace)
+    } else {
+        range
+    };
+    let range = if args.null_values.is_empty() {
+        range
+    } else {
+        blank_null_tokens(range, &args.null_values, args.trim_null_values)
+    };
+    let range = match args.error_handling {
+        ErrorHandling::Null => range,
+        handling => apply_error_handling(range, handling)?,
+    };
+    let range = match &args.bool_strings {
+        Some(strings) => apply_bool_strings(range, strings),
+        None => range,
+    };
+    let range = if args.parse_dates { apply_date_strings(range, args)? } else { range };
+    let range = match sheet_config {
+        Some(config) => apply_sheet_config(range, config)?,
+        None => range,
+    };
+    match args.columns.iter().find(|selection| selection.sheet.eq_ignore_ascii_case(sheet_name)) {
+        Some(_) if sheet_config.is_some_and(|config| config.columns.is_some()) => {
+            anyhow::bail!("--columns and a columns list in --config both select the columns of sheet {}; keep one", sheet_name)
+        }
+        Some(selection) => Ok((project_columns(range, &selection.columns, true)?, raw)),
+        None => Ok((range, raw)),
+    }
+}
+
+/// Top rows `--find-header` looks through for the header
+const FIND_HEADER_ROWS: usize = 50;
+
+/// `--find-header`: cuts a sheet down to the table in it, leaving out titles, notes and blank
+/// columns around it. The header is the first of the top rows with the most text labels over
+/// numbers, dates and other data cells, so a one-cell title or a data row loses to it even when
+/// the header has gaps. The table spans the header's columns, from its first label to its last,
+/// from the header row down. A `header_row` in the sheet's `[[sheet]]` section turns the search off.
+fn locate_header(range: Range<Data>, sheet_config: Option<&SheetConfig>, args: &Config) -> Range<Data> {
+    if !args.find_header || sheet_config.is_some_and(|config| config.header_row.is_some()) {
+        return range;
+    }
+    let (Some(start), Some(end)) = (range.start(), range.end()) else {
+        return range;
+    };
+    let blank = |cell: &Data| match cell {
+        Data::Empty => true,
+        Data::String(text) => text.trim().is_empty(),
+        _ => false,
+    };
+    // (row, first column, last column, labels less data cells) of the best row so far, relative to the range
+    let mut best: Option<(u32, u32, u32, i64)> = None;
+    for (row, cells) in range.rows().take(FIND_HEADER_ROWS).enumerate() {
+        let (Some(first), Some(last)) = (cells.iter().position(|cell| !blank(cell)), cells.iter().rposition(|cell| !blank(cell))) else {
+            continue;
+        };
+        let score: i64 = cells.iter().filter(|cell| !blank(cell)).map(|cell| if matches!(cell, Data::String(_)) { 1 } else { -1 }).sum();
+        if score > 0 && best.is_none_or(|(_, _, _, high)| score > high) {
+            best = Some((row as u32, first as u32, last as u32, score));
+        }
+    }
+    match best {
+        Some((row, first, last, _)) => range.range((start.0 + row, start.1 + first), (end.0, start.1 + last)),
+        None => range,
+    }
+}
+
+/// Drops the rows above the header: `--skip-rows` plus the 0-based header row, which a
+/// `[[sheet]]` section's `header_row` sets in place of `--header-row`
+fn select_header_row(range: Range<Data>, header_row: Option<u32>, args: &Config) -> Result<Range<Data>> {
+    let (Some(start), Some(end)) = (range.start(), range.end()) else {
+        return Ok(range);
+    };
+    if args.no_header {
+        if header_row.is_some() {
+            anyhow::bail!("A header_row in --config cannot be combined with --no-header");
+        }
+        if args.skip_rows > end.0 {
+            anyhow::bail!("--skip-rows {} skips past the last used row {}", args.skip_rows, end.0 + 1);
+        }
+        return Ok(with_generic_header(range.range((args.skip_rows.max(start.0), start.1), end)));
+    }
+    let (header, origin) = match header_row {
+        Some(row) => (row, "header_row in --config"),
+        None => (args.header_row, "--header-row"),
+    };
+    let row = match args.skip_rows + header {
+        0 => start.0,
+        row if row > end.0 => anyhow::bail!(
+            "--skip-rows {} and {} {} put the header past the last used row {}",
+            args.skip_rows, origin, header, end.0 + 1
+        ),
+        row => row,
+    };
+    let range = if row == start.0 { range } else { range.range((row, start.1), end) };
+    if args.header_rows == 1 && !args.fill_header {
+        return Ok(range);
+    }
+    if row + args.header_rows - 1 > end.0 {
+        anyhow::bail!("--header-rows {} runs past the last used row {}", args.header_rows, end.0 + 1);
+    }
+    Ok(flatten_header(range, args.header_rows, args.fill_header))
+}
+
+/// Turns the top `rows` rows of a range into one header row (`--header-rows`), joining each
+/// column's non-empty labels with `_` so a group label over its subcolumns gives `Q1_sales`.
+/// With `fill`, an empty label takes the one to its left first (`--fill-header`): merged cells
+/// keep their text only in the top-left cell, and calamine doesn't report the merges. Only the
+/// group rows are filled when there are several, since an empty bottom label usually sits under
+/// a label merged down from above.
+fn flatten_header(range: Range<Data>, rows: u32, fill: bool) -> Range<Data> {
+    let (Some(start), Some(end)) = (range.start(), range.end()) else {
+        return range;
+    };
+    let width = (end.1 - start.1 + 1) as usize;
+    let mut labels = vec![Vec::new(); width];
+    for (i, row) in range.rows().take(rows as usize).enumerate() {
+        let mut previous = String::new();
+        for (col, cell) in row.iter().enumerate() {
+            let mut label = cell.to_string().trim().to_string();
+            if fill && (rows == 1 || i + 1 < rows as usize) {
+                if label.is_empty() {
+                    label = previous.clone();
+                } else {
+                    previous = label.clone();
+                }
+            }
+            if !label.is_empty() && labels[col].last() != Some(&label) {
+                labels[col].push(label);
+            }
+        }
+    }
+    let header_row = start.0 + rows - 1;
+    let mut flattened = range.range((header_row, start.1), end);
+    for (col, parts) in labels.into_iter().enumerate() {
+        let header = if parts.is_empty() { Data::Empty } else { Data::String(parts.join("_")) };
+        flattened.set_value((header_row, start.1 + col as u32), header);
+    }
+    flattened
+}
+
+/// Empties the data cells whose text is one of `tokens` (compared after trimming when `trim`
+/// is set), so they load as NULL and don't count against type inference. The header row is kept.
+fn blank_null_tokens(mut range: Range<Data>, tokens: &[String], trim: bool) -> Range<Data> {
+    let (row0, col0) = range.start().unwrap_or_default();
+    let nulls: Vec<(u32, u32)> = range.used_cells()
+        .filter(|(row, _, _)| *row > 0)
+        .filter(|(_, _, cell)| match cell {
+            Data::String(text) => {
+                let text = if trim { text.trim() } else { text.as_str() };
+                tokens.iter().any(|token| token == text)
+            }
+            _ => false,
+        })
+        .map(|(row, col, _)| (row0 + row as u32, col0 + col as u32))
+        .collect();
+    for position in nulls {
+        range.set_value(position, Data::Empty);
+    }
+    range
+}
+
+/// Moves every row of a headerless sheet down by one, under a `column_1`, `column_2`, ... header
+/// row, so the rest of the loader can treat the first row as the header as usual
+fn with_generic_header(range: Range<Data>) -> Range<Data> {
+    let (Some(start), Some(end)) = (range.start(), range.end()) else {
+        return range;
+    };
+    let mut shifted = Range::new(start, (end.0 + 1, end.1));
+    for col in start.1..=end.1 {
+        shifted.set_value((start.0, col), Data::String(format!("column_{}", col - start.1 + 1)));
+    }
+    for (row, col, cell) in range.used_cells() {
+        shifted.set_value((start.0 + row as u32 + 1, start.1 + col as u32), cell.clone());
+    }
+    shifted
+}
+
+/// Whether a header row holds only numbers and dates, which suggests the sheet has no header
+fn looks_like_data(headers: &[Data]) -> bool {
+    let mut cells = headers.iter().filter(|cell| !matches!(cell, Data::Empty)).peekable();
+    cells.peek().is_some() && cells.all(|cell| matches!(cell, Data::Int(_) | Data::Float(_) | Data::DateTime(_)))
+}
+
+/// Whether a data row holds nothing but empty and error cells
+fn is_blank_row(row: &[Data]) -> bool {
+    row.iter().all(|cell| matches!(cell, Data::Empty | Data::Error(_)))
+}
+
+/// Strips a leading byte order mark (U+FEFF) from every text cell, header included, which some
+/// exports leave in the first cell or on every value. With `fix_encoding` (`--fix-encoding`)
+/// double-encoded text is repaired as well, see `fix_mojibake`.
+fn repair_text(mut range: Range<Data>, fix_encoding: bool) -> Range<Data> {
+    let (row0, col0) = range.start().unwrap_or_default();
+    let repaired: Vec<((u32, u32), Data)> = range.used_cells()
+        .filter_map(|(row, col, cell)| {
+            let Data::String(text) = cell else {
+                return None;
+            };
+            let stripped = text.strip_prefix('\u{feff}');
+            let fixed = fix_encoding.then(|| fix_mojibake(stripped.unwrap_or(text))).flatten();
+            let repaired = fixed.or_else(|| Some(stripped?.to_string()))?;
+            let repaired = if repaired.is_empty() { Data::Empty } else { Data::String(repaired) };
+            Some(((row0 + row as u32, col0 + col as u32), repaired))
+        })
+        .collect();
+    for (position, cell) in repaired {
+        range.set_value(position, cell);
+    }
+    range
+}
+
+/// Undoes UTF-8 text having been decoded as Windows-1252 (or Latin-1): `CafÃ©` comes back as
+/// `Café` and `donâ€™t` as `don’t`. The text is only replaced when every character maps back
+/// to a byte and the bytes are valid UTF-8, which text written in Latin-1 on purpose almost
+/// never is (a lone `é` is not).
+fn fix_mojibake(text: &str) -> Option<String> {
+    // Windows-1252's 0x80-0x9F block; the five bytes it leaves undefined decode as Latin-1 controls
+    const CP1252: [(char, u8); 27] = [
+        ('€', 0x80), ('‚', 0x82), ('ƒ', 0x83), ('„', 0x84), ('…', 0x85), ('†', 0x86), ('‡', 0x87), ('ˆ', 0x88), ('‰', 0x89),
+        ('Š', 0x8A), ('‹', 0x8B), ('Œ', 0x8C), ('Ž', 0x8E), ('‘', 0x91), ('’', 0x92), ('“', 0x93), ('”', 0x94), ('•', 0x95),
+        ('–', 0x96), ('—', 0x97), ('˜', 0x98), ('™', 0x99), ('š', 0x9A), ('›', 0x9B), ('œ', 0x9C), ('ž', 0x9E), ('Ÿ', 0x9F),
+    ];
+    if text.is_ascii() {
+        return None;
+    }
+    let bytes: Vec<u8> = text.chars()
+        .map(|c| match u8::try_from(c) {
+            Ok(byte) => Some(byte),
+            Err(_) => CP1252.iter().find(|(from, _)| *from == c).map(|(_, byte)| *byte),
+        })
+        .collect::<Option<_>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// Trims the text cells below the header, and with `collapse` squeezes inner whitespace runs
+/// to one space. `char::is_whitespace` covers tabs and non-breaking spaces (U+00A0, U+202F).
+/// Text that ends up empty becomes an empty cell, so it loads as NULL.
+fn clean_text(mut range: Range<Data>, collapse: bool) -> Range<Data> {
+    let (row0, col0) = range.start().unwrap_or_default();
+    let cleaned: Vec<((u32, u32), Data)> = range.used_cells()
+        .filter(|(row, _, _)| *row > 0)
+        .filter_map(|(row, col, cell)| {
+            let Data::String(text) = cell else {
+                return None;
+            };
+            let clean = if collapse {
+                text.split_whitespace().collect::<Vec<_>>().join(" ")
+            } else {
+                text.trim().to_string()
+            };
+            let clean = if clean.is_empty() { Data::Empty } else { Data::String(clean) };
+            (clean != *cell).then(|| ((row0 + row as u32, col0 + col as u32), clean))
+        })
+        .collect();
+    for (position, cell) in cleaned {
+        range.set_value(position, cell);
+    }
+    range
+}
+
+/// Applies a `[[sheet]]` section's null tokens and column projection to a sheet's cells
+fn apply_sheet_config(mut range: Range<Data>, config: &SheetConfig) -> Result<Range<Data>> {
+    if !config.null_tokens.is_empty() {
+        range = blank_null_tokens(range, &config.null_tokens, false);
+    }
+
+    if let Some(columns) = &config.columns {
+        if columns.is_empty() {
+            anyhow::bail!("columns must name at least one column");
+        }
+        range = project_columns(range, columns, false)?;
+    }
+
+    Ok(range)
+}
+
+/// Keeps only the given columns, in that order, matched against the header row. With `letters`,
+/// a name the header row lacks may also be a sheet column letter such as `C`
+fn project_columns(range: Range<Data>, columns: &[String], letters: bool) -> Result<Range<Data>> {
+    if range.is_empty() {
+        return Ok(range);
+    }
+    let headers: Vec<String> = range.rows().next()
+        .map(|row| unique_column_names(row, HeaderStyle::AsIs, IdentifierCase::Preserve, false, None))
+        .unwrap_or_default();
+    let (row0, col0) = range.start().unwrap_or_default();
+    let picked: Vec<usize> = columns.iter()
+        .map(|column| {
+            let wanted = sanitize_identifier(column);
+            if let Some(index) = headers.iter().position(|h| h.eq_ignore_ascii_case(&wanted)) {
+                return Ok(index);
+            }
+            match cell_position(&format!("{}1", column)).map(|(_, col)| col) {
+                Some(col) if letters && (col0..col0 + headers.len() as u32).contains(&col) => Ok((col - col0) as usize),
+                Some(_) if letters => anyhow::bail!(
+                    "Projected column '{}' is neither in the header row ({}) nor a used column of the sheet", column, headers.join(", ")
+                ),
+                _ => anyhow::bail!("Projected column '{}' is not in the header row ({})", column, headers.join(", ")),
+            }
+        })
+        .collect::<Result<_>>()?;
+    let height = range.height() as u32;
+    let mut projected = Range::new((row0, col0), (row0 + height - 1, col0 + picked.len() as u32 - 1));
+    for (row_index, row) in range.rows().enumerate() {
+        for (out_col, &src_col) in picked.iter().enumerate() {
+            if let Some(cell) = row.get(src_col) {
+                projected.set_value((row0 + row_index as u32, col0 + out_col as u32), cell.clone());
+            }
+        }
+    }
+    Ok(projected)
+}
+
+/// A constant or SQL expression column added after load (`--add-column table.column=expr`)
+#[derive(Clone, Debug)]
+pub struct AddColumn {
+    table: String,
+    column: String,
+    expr: String,
+}
+
+fn parse_add_column(s: &str) -> Result<AddColumn, String> {
+    let err = || format!("expected table.column=expression, got '{}'", s);
+    let (target, expr) = s.split_once('=').ok_or_else(err)?;
+    let (table, column) = target.trim().rsplit_once('.').ok_or_else(err)?;
+    if table.is_empty() || column.is_empty() || expr.trim().is_empty() {
+        return Err(err());
+    }
+    Ok(AddColumn { table: table.to_string(), column: sanitize_identifier(column), expr: expr.trim().to_string() })
+}
+
+/// Indexes built after load (`--index "orders.id,orders.(region,placed_at)"`): a table and
+/// the columns of each, as given
+#[derive(Clone, Debug)]
+pub struct Indexes(Vec<(String, Vec<String>)>);
+
+fn parse_indexes(s: &str) -> Result<Indexes, String> {
+    split_outside_parens(s).into_iter()
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let entry = entry.trim();
+            let err = || format!("expected table.column or table.(column,column), got '{}'", entry);
+            let (table, columns) = match entry.strip_suffix(')').and_then(|rest| rest.split_once(".(")) {
+                Some((table, columns)) => (table, columns.split(',').map(|c| c.trim().to_string()).collect::<Vec<_>>()),
+                None => entry.rsplit_once('.').map(|(table, column)| (table, vec![column.trim().to_string()])).ok_or_else(err)?,
+            };
+            if table.trim().is_empty() || columns.iter().any(String::is_empty) {
+                return Err(err());
+            }
+            Ok((table.trim().to_string(), columns))
+        })
+        .collect::<Result<_, _>>()
+        .map(Indexes)
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+// --export-format sets the file format of either kind of multi-file export
+#[command(group(clap::ArgGroup::new("export_target").args(["export_all", "export_partition_by"]).multiple(true)))]
+pub struct Config {
+    /// Path to the folder containing Excel files; `-` reads one workbook from stdin
+    #[arg(short, long, default_value = "test_data")]
+    pub path: String,
+
+    /// Name a workbook read from stdin (`--path -`) goes by; its tables start with it
+    #[arg(long, default_value = "stdin")]
+    pub stdin_name: String,
+
+    /// Contents of the workbook read from stdin
+    #[arg(skip)]
+    stdin_data: Option<Vec<u8>>,
+
+    /// Also load workbooks in subdirectories of --path; table names then start with the
+    /// subdirectory path so files of the same name in different folders don't collide
+    #[arg(long)]
+    pub recursive: bool,
+
+    /// Load only the first N of the files found under --path, after sorting them by --order;
+    /// for a quick test run over a huge folder
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_files: Option<u32>,
+
+    /// What --max-files sorts the files by before taking the first N; ties go by name
+    #[arg(long, value_enum, default_value_t = FileOrder::Name, requires = "max_files")]
+    pub order: FileOrder,
+
+    /// Path to the output DuckDB database, `:memory:` for one that goes away with the run, or
+    /// `md:<database>` for a MotherDuck cloud database
+    #[arg(short, long, default_value = "rust_speedup.duckdb")]
+    pub db: String,
+
+    /// Drop the tables this run loads (from the files and sheets found) before loading them
+    #[arg(long)]
+    pub refresh: bool,
+
+    /// Drop every table in the database except the load history before loading (only the
+    /// `--schema-name` schema's tables, with that option)
+    #[arg(long, conflicts_with = "refresh")]
+    pub refresh_all: bool,
+
+    /// Skip files whose modification time and size match the last load, recorded in
+    /// `_loaded_files`, and within a changed file the sheets whose cells hash the same as last
+    /// time, recorded in `_loaded_sheets`; with `--refresh` or `--refresh-all` every file is reloaded
+    #[arg(long, conflicts_with_all = ["single_table", "union_by", "append"])]
+    pub incremental: bool,
+
+    /// With `--incremental`, drop the tables of files that were loaded before but are gone
+    #[arg(long, requires = "incremental")]
+    pub prune: bool,
+
+    /// Sheets as `_loaded_sheets` remembers them from the last `--incremental` load, by file and sheet
+    #[arg(skip)]
+    loaded_sheets: HashMap<(String, String), SheetRecord>,
+
+    /// Stop starting new files after the first one fails (files already loading still finish)
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Set by Ctrl-C (see `stop_on_interrupt`): no new file, sheet or chunk starts once it is
+    #[arg(skip)]
+    interrupted: Arc<AtomicBool>,
+
+    /// After loading, delete rows that repeat these key columns in each loaded table, e.g. `id,month`; `*` compares whole rows
+    #[arg(long, value_delimiter = ',', num_args = 1..)]
+    pub dedup_on: Vec<String>,
+
+    /// Which of a set of duplicate rows `--dedup-on` keeps, in the order they were loaded
+    #[arg(long, value_enum, default_value_t = DedupKeep::First)]
+    pub dedup_keep: DedupKeep,
+
+    /// After loading, check every table's row count against the rows inserted into it and fail
+    /// the run on a mismatch, or on a sheet with more than `--verify-max-skipped` rows left out
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Rows per sheet `--verify` lets `--on-bad-row skip` or `reject-file` leave out
+    #[arg(long, default_value_t = 0, requires = "verify")]
+    pub verify_max_skipped: usize,
+
+    /// Exit successfully even when files fail to load; they are still listed at the end
+    #[arg(long, conflicts_with_all = ["fail_fast", "staging"])]
+    pub ignore_errors: bool,
+
+    /// Also log debug detail: each sheet's inferred column types and the DDL that runs
+    #[arg(short, long, conflicts_with = "quiet")]
+    pub verbose: bool,
+
+    /// Log errors only and hide the progress bars
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Never draw progress bars; they are already left out when stderr isn't a terminal. Log
+    /// lines still print.
+    #[arg(long)]
+    pub no_progress: bool,
+
+    /// Draw progress bars even when stderr isn't a terminal, for CI logs that render them
+    #[arg(long, conflicts_with_all = ["no_progress", "quiet"])]
+    pub force_progress: bool,
+
+    /// Query to run after loading, printing its results (repeatable; they run in order)
+    #[arg(long)]
+    pub query: Vec<String>,
+
+    /// SQL file run statement by statement after any --query: SELECTs print like --query (with
+    /// the `>>` export suffix), other statements report the rows they changed
+    #[arg(long)]
+    pub script: Option<String>,
+
+    /// After loading (and any --query), list the tables with their column and row counts, or one table's columns and types
+    #[arg(long, num_args = 0..=1, default_missing_value = "", value_name = "TABLE")]
+    pub show_schema: Option<String>,
+
+    /// After loading (and any --query), read SQL interactively until .exit or end of input
+    #[arg(long)]
+    pub repl: bool,
+
+    /// Database backend to load into
+    #[arg(long, value_enum, default_value_t = Backend::Duckdb)]
+    pub backend: Backend,
+
+    /// Give each worker thread its own DuckDB connection so files insert concurrently instead of
+    /// taking turns on one connection
+    #[arg(long, conflicts_with_all = ["single_table", "append"])]
+    pub parallel_writes: bool,
+
+    /// Load the sheets of a workbook in parallel, each through a connection of its own; sheets are
+    /// still read one at a time, a batch per thread
+    #[arg(long, conflicts_with_all = ["single_table", "append", "union_by"])]
+    pub parallel_sheets: bool,
+
+    /// Files processed at once (default: one per CPU core); 1 loads them one after another, in order
+    #[arg(long)]
+    pub threads: Option<usize>,
+
+    /// Load files in sorted path order: they still read in parallel, but each one writes, logs and
+    /// claims its table names only after every file before it has finished, so runs repeat exactly
+    #[arg(long)]
+    pub sorted: bool,
+
+    /// How DuckDB rows are written; `--append` always uses sql since sheets may cover only
+    /// some of the table's columns
+    #[arg(long, value_enum, default_value_t = InsertMode::Appender)]
+    pub insert_mode: InsertMode,
+
+    /// Rows handed to the database per insert (one Appender batch or one multi-row INSERT)
+    #[arg(long, default_value_t = 1000)]
+    pub chunk_size: usize,
+
+    /// Create each table's structure (header + inferred types) without inserting any rows
+    #[arg(long)]
+    pub schema_only: bool,
+
+    /// Only load sheets whose (original) name matches this regular expression
+    #[arg(long, value_parser = Regex::new)]
+    pub sheet_regex: Option<Regex>,
+
+    /// Only load sheets whose table name (after sanitizing, prefixes and name templates) matches this regular expression
+    #[arg(long, value_parser = Regex::new)]
+    pub table_filter: Option<Regex>,
+
+    /// Record each column's original header in `_column_mapping(table_name, column_index, original_name, sanitized_name)`
+    #[arg(long)]
+    pub store_metadata: bool,
+
+    /// Record where each table came from in `_table_metadata(table_name, source_file, sheet_name, source_rows, source_columns, file_modified_at, loaded_at)`
+    #[arg(long)]
+    pub table_metadata: bool,
+
+    /// Text that loads as NULL, e.g. `N/A,-,NULL`; matched exactly against whole cells (an empty entry matches empty text)
+    #[arg(long, value_delimiter = ',')]
+    pub null_values: Vec<String>,
+
+    /// Ignore surrounding whitespace when matching --null-values
+    #[arg(long)]
+    pub trim_null_values: bool,
+
+    /// Text that loads as BOOLEAN, e.g. `Yes=true,No=false`; matched case-insensitively, and only in columns holding nothing else
+    #[arg(long, value_parser = parse_bool_strings)]
+    pub bool_strings: Option<BoolStrings>,
+
+    /// Type text columns that are mostly dates (`2024-03-01`, `03/01/2024`, optionally with a time) as DATE or TIMESTAMP
+    #[arg(long)]
+    pub parse_dates: bool,
+
+    /// With --parse-dates, the share of a column's non-empty cells that must be dates, from 0 to 1
+    #[arg(long, default_value_t = 0.9, requires = "parse_dates")]
+    pub date_threshold: f64,
+
+    /// With --parse-dates, how to read slash dates such as 03/04/2024
+    #[arg(long, value_enum, default_value_t = DateOrder::Mdy, requires = "parse_dates")]
+    pub date_order: DateOrder,
+
+    /// Read date-times in this zone and load them as TIMESTAMPTZ: `UTC`, an offset like `+05:30`, or a name like `Europe/Berlin`
+    #[arg(long, value_parser = parse_timezone)]
+    pub timezone: Option<TimeZone>,
+
+    /// With --parse-dates, what to do with the text in a date column that isn't a date
+    #[arg(long, value_enum, default_value_t = OnBadDate::Null, requires = "parse_dates")]
+    pub on_bad_date: OnBadDate,
+
+    /// What to load for Excel error cells such as #N/A and #DIV/0!
+    #[arg(long, value_enum, default_value_t = ErrorHandling::Null)]
+    pub error_handling: ErrorHandling,
+
+    /// Trim leading and trailing whitespace (including non-breaking spaces) from text cells; cells left empty load as NULL
+    #[arg(long)]
+    pub trim: bool,
+
+    /// Collapse runs of whitespace inside text cells to one space (implies --trim)
+    #[arg(long)]
+    pub normalize_whitespace: bool,
+
+    /// Repair text that was UTF-8 read as Windows-1252 or Latin-1 on the way into the workbook, like `CafÃ©` for `Café`
+    #[arg(long)]
+    pub fix_encoding: bool,
+
+    /// Only load these sheets, e.g. `Sheet1,Data` (original names, case-insensitive)
+    #[arg(long, value_delimiter = ',')]
+    pub sheets: Vec<String>,
+
+    /// Skip these sheets, e.g. `Notes` (original names, case-insensitive)
+    #[arg(long, value_delimiter = ',')]
+    pub exclude_sheets: Vec<String>,
+
+    /// Load only this block of a sheet, e.g. `Sheet1!B5:F200`; its first row is the header.
+    /// Repeat for other sheets; sheets without one load whole
+    #[arg(long = "range", value_name = "SHEET!RANGE", value_parser = parse_sheet_range)]
+    pub ranges: Vec<SheetRange>,
+
+    /// Load only these columns of a sheet, in this order, by header name or column letter, e.g.
+    /// `Sheet1:A,C,F` or `Orders:id,amount`. Repeat for other sheets; sheets without one load whole
+    #[arg(long, value_name = "SHEET:COLUMNS", value_parser = parse_sheet_columns)]
+    pub columns: Vec<SheetColumns>,
+
+    /// Fail a table's load unless it has these columns, e.g. `orders_Sheet1=id,amount` (repeatable)
+    #[arg(long, value_parser = parse_required_columns)]
+    pub require_columns: Vec<RequiredColumns>,
+
+    /// Collation applied to every VARCHAR column at creation, e.g. NOCASE for case-insensitive text
+    #[arg(long, value_parser = parse_collation)]
+    pub text_collation: Option<String>,
+
+    /// After loading, print per column of each loaded table its value, NULL and distinct counts,
+    /// and for numeric, date and time columns the min and max; this scans every table again
+    #[arg(long)]
+    pub profile: bool,
+
+    /// After loading, print how many columns of the loaded tables got each type, and which
+    /// columns fell back to VARCHAR because their sampled values mixed types
+    #[arg(long)]
+    pub type_summary: bool,
+
+    /// Write a per-table data dictionary (columns, types, null/distinct counts, min/max, samples) as .md or .html
+    #[arg(long)]
+    pub data_dictionary: Option<String>,
+
+    /// Write a JSON summary of the run (files, sheets, tables, rows, column types, timings, errors)
+    #[arg(long)]
+    pub manifest: Option<String>,
+
+    /// When the run ends, write a one-line JSON report to stderr: status, exit code, error, totals and every file's status
+    #[arg(long)]
+    pub json_report: bool,
+
+    /// The `--manifest` summary of the load, which `--json-report` builds on
+    #[arg(skip)]
+    load_report: Option<serde_json::Value>,
+
+    /// Load number columns that use an xlsx percentage format as DOUBLE (0.15 for 15%)
+    #[arg(long)]
+    pub detect_percent: bool,
+
+    /// Type the xlsx columns whose cells share a number format, e.g. `currency=DECIMAL(18,2)`,
+    /// `date=TIMESTAMP` or `00000=VARCHAR`; the key is a class (percent, currency, date, datetime,
+    /// time) or an exact format code, which wins over a class. Repeatable.
+    #[arg(long, value_parser = parse_format_rule)]
+    pub format_map: Vec<FormatRule>,
+
+    /// Add a `_highlighted` BOOLEAN column that is true when any cell in the row has a fill colour (xlsx only)
+    #[arg(long)]
+    pub flag_highlighted: bool,
+
+    /// SQL run once every file has loaded (before --query): a script file, `@file.sql`, or inline statements
+    #[arg(long, alias = "sql-post", value_name = "SQL")]
+    pub post_sql: Option<String>,
+
+    /// SQL run right after the database opens, before any table is created: inline statements or `@file.sql`
+    #[arg(long, value_name = "SQL")]
+    pub sql_pre: Option<String>,
+
+    /// Load into a staging schema and swap the tables into `main` (or `--schema-name`) in one transaction only if every file succeeds
+    #[arg(long)]
+    pub staging: bool,
+
+    /// Create the loaded tables in this schema (created if missing) instead of `main`, keeping
+    /// several imports apart in one database; queries then look there first
+    #[arg(long, value_name = "SCHEMA", value_parser = parse_schema_name)]
+    pub schema_name: Option<String>,
+
+    /// Add a column after load, e.g. `orders_Sheet1.region='EMEA'` (constant or SQL expression, repeatable)
+    #[arg(long, value_parser = parse_add_column)]
+    pub add_column: Vec<AddColumn>,
+
+    /// Build indexes once every file has loaded, e.g. `orders_Sheet1.id,orders_Sheet1.(region,month)` (repeatable)
+    #[arg(long, value_parser = parse_indexes)]
+    pub index: Vec<Indexes>,
+
+    /// Read numbers stored as text using this locale's grouping and decimal separators
+    #[arg(long, value_enum)]
+    pub number_locale: Option<NumberLocale>,
+
+    /// Decimal separator of numbers stored as text, like `,` for 1.234,56 (the thousands separator defaults to the other of `.` and `,`)
+    #[arg(long, conflicts_with = "number_locale")]
+    pub decimal_separator: Option<char>,
+
+    /// Thousands separator of numbers stored as text, like `.` for 1.234,56
+    #[arg(long, conflicts_with = "number_locale")]
+    pub thousands_separator: Option<char>,
+
+    /// Use the smallest integer type (TINYINT..BIGINT) that fits each integer column's sampled range
+    #[arg(long)]
+    pub tight_ints: bool,
+
+    /// Skip type inference and load every column as VARCHAR holding each cell's text
+    #[arg(long, conflicts_with_all = ["schema", "suffix_types", "tight_ints", "detect_percent", "number_locale", "decimal_separator", "thousands_separator", "bool_strings", "parse_dates", "timezone", "money_columns", "format_map"])]
+    pub all_text: bool,
+
+    /// With --tight-ints, how to handle later values outside the sampled range
+    #[arg(long, value_enum, default_value_t = IntOverflow::Widen)]
+    pub int_overflow: IntOverflow,
+
+    /// Type for columns of whole numbers past BIGINT's range, such as 20-digit account numbers in text
+    #[arg(long, value_enum, default_value_t = BigintOverflow::Text)]
+    pub bigint_overflow: BigintOverflow,
+
+    /// Extra attempts at opening a workbook that fails with an I/O error, as flaky network shares do
+    #[arg(long, default_value_t = 3)]
+    pub retries: usize,
+
+    /// Seconds to wait for another load into the same --db file to finish before giving up;
+    /// by default a run that finds one in progress stops at once
+    #[arg(long, default_value_t = 0)]
+    pub lock_wait: u64,
+
+    /// Memory-map workbooks instead of reading them through a buffered file reader
+    #[arg(long)]
+    pub mmap: bool,
+
+    /// Open password of workbooks saved encrypted (.xlsx, .xlsm and .xlsb); workbooks without
+    /// one load as usual
+    #[arg(long, conflicts_with = "password_env")]
+    pub password: Option<String>,
+
+    /// Environment variable holding the --password, which keeps it off the command line
+    #[arg(long, value_name = "VAR")]
+    pub password_env: Option<String>,
+
+    /// Workbooks decrypted with --password, by file, until the file is loaded
+    #[arg(skip)]
+    decrypted_packages: Mutex<HashMap<PathBuf, Arc<[u8]>>>,
+
+    /// Memory one sheet may take once read, e.g. `512MB` or `4GB`; bigger sheets get a warning, or fail with --on-memory-limit refuse
+    #[arg(long, value_parser = parse_byte_size)]
+    pub max_memory: Option<u64>,
+
+    /// What to do with a sheet estimated to need more than --max-memory
+    #[arg(long, value_enum, default_value_t = OnMemoryLimit::Warn, requires = "max_memory")]
+    pub on_memory_limit: OnMemoryLimit,
+
+    /// Convert each sheet to `DIR/<table>.parquet` through an in-memory database instead of loading a database file
+    #[arg(long, value_name = "DIR", conflicts_with_all = [
+        "backend", "single_table", "union_by", "append", "staging", "schema_name", "incremental", "control_files", "refresh", "refresh_all",
+        "store_metadata", "table_metadata", "verify", "dedup_on", "add_column", "index", "post_sql", "sql_dump", "ddl_out", "data_dictionary", "profile", "query", "script", "show_schema", "repl", "export_all",
+    ])]
+    pub output_parquet: Option<String>,
+
+    /// After loading, copy every table to `DIR/<table>.<ext>` in --export-format, reporting each
+    /// file's rows, size and time; the loader's own `_`-prefixed bookkeeping tables are left out
+    #[arg(long, value_name = "DIR")]
+    pub export_all: Option<String>,
+
+    /// File format of --export-all, and of the files of an --export-partition-by folder
+    #[arg(long, value_enum, default_value_t = ExportFormat::Csv, requires = "export_target")]
+    pub export_format: ExportFormat,
+
+    /// Tables --export-all copies at once, each over its own connection
+    #[arg(long, default_value_t = 1, requires = "export_all", value_parser = clap::value_parser!(u32).range(1..))]
+    pub export_jobs: u32,
+
+    /// Let --export-all and --sql-dump also write the run history, incremental and metadata tables
+    #[arg(long)]
+    pub export_internal: bool,
+
+    /// Write CREATE TABLE + INSERT statements reproducing every loaded table to this .sql file
+    #[arg(long)]
+    pub sql_dump: Option<String>,
+
+    /// The VARCHAR columns this run created with --text-collation, as (table, column), lowercased,
+    /// for --sql-dump: DuckDB's catalog doesn't report a column's collation
+    #[arg(skip)]
+    collated_columns: Mutex<HashSet<(String, String)>>,
+
+    /// Write the CREATE SCHEMA, CREATE TABLE and CREATE INDEX statements the run generates to this
+    /// .sql file, tables sorted by name; with --dry-run no rows are loaded
+    #[arg(long, value_name = "FILE")]
+    pub ddl_out: Option<String>,
+
+    /// The CREATE TABLE statement each `--ddl-out` table was created with, by table
+    #[arg(skip)]
+    ddl: Mutex<Vec<(String, String)>>,
+
+    /// Append to existing tables instead of replacing them, creating any that don't exist yet
+    #[arg(long, conflicts_with_all = ["refresh", "refresh_all", "staging", "control_files"])]
+    pub append: bool,
+
+    /// Check loaded row counts against a `.ctl` sidecar next to each workbook and fail files that don't match
+    #[arg(long)]
+    pub control_files: bool,
+
+    /// Union every sheet of every file into this one table, with _source_file and _source_sheet columns
+    #[arg(long)]
+    pub single_table: Option<String>,
+
+    /// Add a VARCHAR column of this name holding each row's source file name
+    #[arg(long, conflicts_with = "single_table")]
+    pub source_column: Option<String>,
+
+    /// Add a VARCHAR column of this name holding each row's source sheet name
+    #[arg(long, conflicts_with = "single_table")]
+    pub source_sheet_column: Option<String>,
+
+    /// Add a TIMESTAMP column of this name holding the time the run started, the same in every row of every table
+    #[arg(long)]
+    pub load_timestamp_column: Option<String>,
+
+    /// Add an INTEGER column of this name holding each row's 1-based row number in its sheet, as Excel shows it
+    #[arg(long)]
+    pub row_number_column: Option<String>,
+
+    /// The time `--load-timestamp-column` stamps rows with: when the run (or `--watch` batch) started
+    #[arg(skip)]
+    load_timestamp: Mutex<Option<chrono::NaiveDateTime>>,
+
+    /// Table name pattern with `{file}`, `{sheet}` and `{index}` (1-based sheet position), e.g. `raw_{file}_{sheet}`
+    #[arg(long, value_parser = parse_name_template)]
+    pub name_template: Option<String>,
+
+    /// Prefix joined to every table name with an underscore, e.g. `raw`
+    #[arg(long)]
+    pub tables_prefix: Option<String>,
+
+    /// Word style of column names: as-is, snake_case (`First Name`, `firstName` -> `first_name`)
+    /// or camelCase; applied before duplicate headers are numbered
+    #[arg(long, visible_alias = "normalize-headers", value_enum, default_value_t = HeaderStyle::AsIs)]
+    pub header_style: HeaderStyle,
+
+    /// Letter case of table and column names; with lower or upper, headers differing only in case
+    /// (`Total`, `total`) get numbered like any other duplicate
+    #[arg(long, value_enum, default_value_t = IdentifierCase::Preserve)]
+    pub identifier_case: IdentifierCase,
+
+    /// Keep table and column names to at most this many characters, for databases and tools with a
+    /// limit (63 for Postgres); longer names are cut and end in `_` plus a hash of the whole name
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(16..))]
+    pub max_identifier_length: Option<u32>,
+
+    /// Keep table and column names to ASCII: accented letters lose their accents (`Größe` -> `Grosse`) and other characters become underscores
+    #[arg(long)]
+    pub ascii_only: bool,
+
+    /// Name the table of a workbook that has only one sheet after the file alone
+    #[arg(long)]
+    pub single_sheet_file_name: bool,
+
+    /// Merge sheets into one table per set of matching columns, with _source_file and _source_sheet columns
+    #[arg(long, value_enum, conflicts_with_all = ["single_table", "append", "parallel_writes", "control_files", "load_random"])]
+    pub union_by: Option<UnionBy>,
+
+    /// Name of each `--union-by` table, with `{file}` and `{sheet}` taken from its first sheet
+    #[arg(long, requires = "union_by", value_parser = parse_union_name)]
+    pub union_name: Option<String>,
+
+    /// Table each `--union-by` sheet merges into, worked out before loading
+    #[arg(skip)]
+    union_tables: HashMap<(PathBuf, String), String>,
+
+    /// With --single-table, widen columns whose types differ between sheets to VARCHAR instead of skipping the sheet
+    #[arg(long)]
+    pub single_table_widen: bool,
+
+    /// What to do with a row holding a value its column can't take, like text in a DOUBLE column
+    #[arg(long, value_enum, default_value_t = OnBadRow::Abort)]
+    pub on_bad_row: OnBadRow,
+
+    /// CSV file that `--on-bad-row reject-file` writes the rejected rows to
+    #[arg(long, default_value = "rejects.csv")]
+    pub rejects_file: String,
+
+    /// Rows rejected so far in this run, written out once loading is done
+    #[arg(skip)]
+    rejected_rows: Mutex<Vec<RejectedRow>>,
+
+    /// Fail a file when one of its sheets can't be read, instead of skipping the sheet and reporting it
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Sheets skipped so far in this run because they couldn't be read: file, sheet and error
+    #[arg(skip)]
+    skipped_sheets: Mutex<Vec<(String, String, String)>>,
+
+    /// What to do when DuckDB would round or truncate a value into its column type
+    #[arg(long, value_enum, default_value_t = OnTruncate::Warn)]
+    pub on_truncate: OnTruncate,
+
+    /// Report each file's sheets, dimensions and inferred schema without opening the database
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "table")]
+    pub probe: Option<ProbeFormat>,
+
+    /// Print the load plan (files, sheets, target tables, row counts, schemas) and exit without
+    /// touching the database
+    #[arg(long, conflicts_with = "probe")]
+    pub dry_run: bool,
+
+    /// Compare the tables a load would produce, their columns and row counts, with the ones
+    /// already in --db, print what would change and exit without touching the database
+    #[arg(long, conflicts_with_all = ["probe", "dry_run", "count_only", "benchmark", "output_parquet"])]
+    pub compare: bool,
+
+    /// Count each sheet's data rows (after the header and skip options), print them with a total and exit without opening the database
+    #[arg(long, conflicts_with_all = ["probe", "dry_run", "benchmark"])]
+    pub count_only: bool,
+
+    /// Load the files once per write strategy (Appender or SQL inserts, shared connection or
+    /// parallel writes), each into a fresh in-memory database, print the timings and exit
+    #[arg(long, conflicts_with_all = ["probe", "dry_run", "single_table", "union_by", "append", "incremental", "parallel_writes", "insert_mode"])]
+    pub benchmark: bool,
+
+    /// After loading, keep running and append each new or changed file in the folder once it stops changing
+    #[arg(long, conflicts_with_all = ["probe", "dry_run", "count_only", "benchmark", "repl", "staging", "output_parquet", "control_files"])]
+    pub watch: bool,
+
+    /// Seconds between --watch scans; a file loads once it looks the same on two scans in a row
+    #[arg(long, default_value_t = 2, requires = "watch", value_parser = clap::value_parser!(u64).range(1..))]
+    pub watch_interval: u64,
+
+    /// Loads per strategy with --benchmark; the fastest one is reported
+    #[arg(long, default_value_t = 1, requires = "benchmark")]
+    pub benchmark_runs: usize,
+
+    /// Print each sheet's CREATE TABLE and the INSERT for its first chunk of rows; with
+    /// `--dry-run` the statements are only printed, and nothing is loaded
+    #[arg(long)]
+    pub explain: bool,
+
+    /// Stop the whole run once this many rows have been inserted across all files and sheets
+    #[arg(long)]
+    pub max_total_rows: Option<usize>,
+
+    /// Rows inserted so far in this run, shared by every file for `--max-total-rows`
+    #[arg(skip)]
+    rows_loaded: AtomicUsize,
+
+    /// What to do when sheets from different files (or one file) map to the same table name;
+    /// `--single-table` and `--append` share tables on purpose and are never checked
+    #[arg(long, value_enum, default_value_t = OnCollision::Error)]
+    pub on_collision: OnCollision,
+
+    /// Tables written so far in this run (lowercased, as DuckDB compares them) and the sheet
+    /// each came from, shared by every file for `--on-collision`
+    #[arg(skip)]
+    tables_claimed: Mutex<HashMap<String, String>>,
+
+    /// Whose turn it is to write under `--sorted`
+    #[arg(skip)]
+    write_turns: WriteTurns,
+
+    /// Print, for every column, which type was inferred and why
+    #[arg(long)]
+    pub explain_types: bool,
+
+    /// Force column types by header suffix, e.g. `_dt=TIMESTAMP,_amt=DECIMAL(18,2),_flg=BOOLEAN`
+    #[arg(long, value_parser = parse_suffix_types)]
+    pub suffix_types: Option<SuffixTypes>,
+
+    /// Load these columns as VARCHAR whatever they hold, e.g. `zip,account_id` (sanitized names, case-insensitive)
+    #[arg(long, value_delimiter = ',')]
+    pub text_columns: Vec<String>,
+
+    /// Load these columns as exact DECIMAL(18,s) money values, e.g. `amount,price` (sanitized names,
+    /// case-insensitive), where s is --money-scale; values are rounded to it, halves away from zero
+    #[arg(long, value_delimiter = ',')]
+    pub money_columns: Vec<String>,
+
+    /// Decimal places --money-columns keep
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(0..=18), requires = "money_columns")]
+    pub money_scale: u8,
+
+    /// Create an empty table for a sheet with no cells at all, from the columns its `[[sheet]]`
+    /// section or `--schema` declares; sheets with only a header row already load as empty tables
+    #[arg(long)]
+    pub include_empty_sheets: bool,
+
+    /// Skip sheets with fewer than this many data rows, such as legend or notes tabs
+    #[arg(long)]
+    pub min_rows: Option<usize>,
+
+    /// Leave out data rows whose every cell is empty or an error, such as blank separator rows
+    #[arg(long)]
+    pub skip_blank_rows: bool,
+
+    /// Load a sheet of stacked tables, separated by blank rows or repeated header rows, as one
+    /// table per block (`{sheet}_1`, `{sheet}_2`, ...), each with its own header and types.
+    /// Blocks are split from the raw sheet, so each one's header is its first row
+    #[arg(long, conflicts_with_all = ["find_header", "skip_rows", "header_row"])]
+    pub split_on_blank: bool,
+
+    /// Add a `_raw` JSON column holding each row's original cell values, before any type coercion
+    #[arg(long)]
+    pub keep_raw: bool,
+
+    /// Read sheets as rows (headers on top) or columns (field names down the left, one record per column)
+    #[arg(long, value_enum, default_value_t = Orientation::Rows)]
+    pub orientation: Orientation,
+
+    /// Find the table in each sheet: the header is the first of the top 50 rows with the most text
+    /// labels over number, date and other data cells, and titles, notes and blank columns around
+    /// the table are left out
+    #[arg(long, conflicts_with_all = ["skip_rows", "header_row", "no_header"])]
+    pub find_header: bool,
+
+    /// Rows to skip at the top of each sheet, e.g. title banners above the header
+    #[arg(long, default_value_t = 0)]
+    pub skip_rows: u32,
+
+    /// Header row (0-based) counted from the first row after --skip-rows; rows above it are
+    /// neither loaded nor used for type inference. A `header_row` in --config takes precedence
+    #[arg(long, default_value_t = 0)]
+    pub header_row: u32,
+
+    /// Sheets have no header row: every row is data and columns are named column_1, column_2, ...
+    #[arg(long, conflicts_with = "header_row")]
+    pub no_header: bool,
+
+    /// Rows that make up the header, starting at the header row; each column's labels are joined
+    /// with `_`, so a group row over a subcolumn row gives names like `Q1_sales`
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), conflicts_with = "no_header")]
+    pub header_rows: u32,
+
+    /// Fill empty header cells from the nearest label to their left, as merged header cells
+    /// leave every cell but the first empty. With `--header-rows`, only the group rows are filled
+    #[arg(long, conflicts_with = "no_header")]
+    pub fill_header: bool,
+
+    /// TOML file whose top-level keys set defaults for any other option (`db = "sales.duckdb"`), and
+    /// whose `[[sheet]]` sections override header row, null tokens, projection and column types per sheet
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Parsed contents of `--config`
+    #[arg(skip)]
+    settings: Settings,
+
+    /// TOML or JSON file mapping `table.column` to a column type, overriding inference for those columns
+    #[arg(long)]
+    pub schema: Option<PathBuf>,
+
+    /// Parsed contents of `--schema`
+    #[arg(skip)]
+    schema_overrides: SchemaOverrides,
+
+    /// Maximum number of `--query` result rows printed to the terminal
+    #[arg(long, default_value_t = 100)]
+    pub limit: usize,
+
+    /// How `--query` results print to stdout; json and csv print every row, ignoring `--limit`
+    #[arg(long, value_enum, default_value_t = QueryFormat::Table)]
+    pub format: QueryFormat,
+
+    /// Number of data rows scanned per sheet for type inference
+    #[arg(long, default_value_t = 100)]
+    pub infer_rows: usize,
+
+    /// Removed: it was read both as --infer-rows and as --load-rows, so it is refused rather than guessed
+    #[arg(long, hide = true, value_name = "N")]
+    pub sample_rows: Option<usize>,
+
+    /// Removed alongside --sample-rows; --load-random replaces it
+    #[arg(long, hide = true)]
+    pub sample_random: bool,
+
+    /// Load only the first N data rows of each sheet, for quick prototyping against big workbooks
+    #[arg(long, value_name = "N", conflicts_with = "control_files")]
+    pub load_rows: Option<usize>,
+
+    /// With --load-rows, load N rows picked at random (kept in sheet order) instead of the first N
+    #[arg(long, requires = "load_rows", conflicts_with_all = ["keep_raw", "flag_highlighted", "row_number_column"])]
+    pub load_random: bool,
+
+    /// Where the inference sample is taken from
+    #[arg(long, value_enum, default_value_t = SampleFrom::Head)]
+    pub sample_from: SampleFrom,
+
+    /// How to type a column whose sampled values disagree: the widest type they all fit, or the most common one
+    #[arg(long, value_enum, default_value_t = TypeStrategy::Widen)]
+    pub type_strategy: TypeStrategy,
+
+    /// Export `>>` results in partitions of N rows, one numbered file each, so a failed export can resume
+    #[arg(long)]
+    pub export_partition_rows: Option<usize>,
+
+    /// ORDER BY expression used to make partitioned exports deterministic (required with --export-partition-rows)
+    #[arg(long)]
+    pub export_order_by: Option<String>,
+
+    /// Compress `>>` exports: gzip or zstd for CSV, TSV and JSON files, or the codec inside Parquet
+    /// files; `none` turns Parquet's default snappy off
+    #[arg(long, value_enum, conflicts_with = "export_partition_rows")]
+    pub export_compression: Option<ExportCompression>,
+
+    /// Write `>>` exports as a folder of Hive-style partitions, one subfolder per value of these
+    /// columns (`region=EU/`), each file in --export-format; the target names a folder
+    #[arg(long, value_delimiter = ',', conflicts_with = "export_partition_rows")]
+    pub export_partition_by: Vec<String>,
+
+    /// Let --export-partition-by write into a folder that already holds files, replacing those
+    /// of the same name
+    #[arg(long, requires = "export_partition_by")]
+    pub export_overwrite: bool,
+
+    /// Character between values in `>>` CSV exports, e.g. `;` (default: a comma, or a tab for .tsv)
+    #[arg(long, value_parser = parse_csv_char)]
+    pub csv_delimiter: Option<char>,
+
+    /// Leave the header row out of `>>` CSV exports
+    #[arg(long)]
+    pub csv_no_header: bool,
+
+    /// Character `>>` CSV exports put around values that need quoting (default: `"`)
+    #[arg(long, value_parser = parse_csv_char)]
+    pub csv_quote: Option<char>,
+
+    /// Write `>>` CSV exports with CRLF line endings
+    #[arg(long)]
+    pub csv_crlf: bool,
+
+    /// Character encoding of `>>` CSV exports
+    #[arg(long, value_enum, default_value_t = CsvEncoding::Utf8)]
+    pub csv_encoding: CsvEncoding,
+
+    /// Attempts per export partition before giving up
+    #[arg(long, default_value_t = 3)]
+    pub export_retries: usize,
+}
+
+/// Column names for a header row: sanitized, `column_N` for empty headers (N is the
+/// 1-based position), and `_2`, `_3`, ... appended to names already taken, compared
+/// case-insensitively like DuckDB does. `case` applies before duplicates are numbered.
+fn unique_column_names(headers: &[Data], style: HeaderStyle, case: IdentifierCase, ascii_only: bool, max_length: Option<u32>) -> Vec<String> {
+    let mut taken = HashSet::new();
+    headers.iter().enumerate()
+        .map(|(i, cell)| {
+            let base = case.apply(match style.apply(name_identifier(&cell.to_string(), ascii_only)) {
+                name if name.is_empty() => format!("column_{}", i + 1),
+                name => name,
+            });
+            let mut name = case.apply(fit_identifier(base.clone(), max_length));
+            let mut n = 1;
+            while !taken.insert(name.to_lowercase()) {
+                n += 1;
+                name = case.apply(fit_identifier(format!("{}_{}", base, n), max_length));
+            }
+            name
+        })
+        .collect()
+}
+
+/// Cuts a name longer than `--max-identifier-length` characters down to fit, ending it in `_`
+/// and 8 hex digits hashed from the whole name, so names sharing a long prefix stay apart
+fn fit_identifier(name: String, max_length: Option<u32>) -> String {
+    match max_length {
+        Some(max) if name.chars().count() > max as usize => {
+            let hash = name.bytes().fold(0x811c_9dc5_u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193));
+            let kept: String = name.chars().take(max as usize - 9).collect();
+            format!("{}_{:08x}", kept.trim_end_matches('_'), hash)
+        }
+        _ => name,
+    }
+}
+
+/// A sanitized table name in its final form: fitted to `--max-identifier-length`, then in `--identifier-case`
+fn table_identifier(name: String, args: &Config) -> String {
+    args.identifier_case.apply(fit_identifier(name, args.max_identifier_length))
+}
+
+/// A header or file name as a table or column name: runs of anything but letters and digits
+/// become one underscore, none are left at the ends, and a leading digit gets one in front.
+/// A name without letters or digits (`###`) comes back empty, for the caller to replace.
+pub fn sanitize_identifier(name: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            sanitized.push(c);
+        } else {
+            sanitized.push('_');
+        }
+    }
+    // Remove duplicate underscores
+    let mut result = String::new();
+    let mut last_char_was_underscore = false;
+    for c in sanitized.chars() {
+        if c == '_' {
+            if !last_char_was_underscore {
+                result.push(c);
+                last_char_was_underscore = true;
+            }
+        } else {
+            result.push(c);
+            last_char_was_underscore = false;
+        }
+    }
+    // Leading digits are common in sheet and file names ("2024") but cannot start an identifier
+    match result.trim_matches('_') {
+        name if name.starts_with(|c: char| c.is_ascii_digit()) => format!("_{}", name),
+        name => name.to_string(),
+    }
+}
+
+/// Latin letters with accents and other marks, and the plain letters `--ascii-only` spells them with
+const ASCII_FOLDS: [(&str, &str); 52] = [
+    ("ÀÁÂÃÄÅĀĂĄ", "A"), ("àáâãäåāăą", "a"), ("ÇĆĈĊČ", "C"), ("çćĉċč", "c"), ("ĎĐÐ", "D"), ("ďđð", "d"),
+    ("ÈÉÊËĒĔĖĘĚ", "E"), ("èéêëēĕėęě", "e"), ("ĜĞĠĢ", "G"), ("ĝğġģ", "g"), ("ĤĦ", "H"), ("ĥħ", "h"),
+    ("ÌÍÎÏĨĪĬĮİ", "I"), ("ìíîïĩīĭįı", "i"), ("Ĵ", "J"), ("ĵ", "j"), ("Ķ", "K"), ("ķ", "k"),
+    ("ĹĻĽĿŁ", "L"), ("ĺļľŀł", "l"), ("ÑŃŅŇ", "N"), ("ñńņň", "n"), ("ÒÓÔÕÖØŌŎŐ", "O"), ("òóôõöøōŏő", "o"),
+    ("ŔŖŘ", "R"), ("ŕŗř", "r"), ("ŚŜŞŠ", "S"), ("śŝşš", "s"), ("ŢŤŦ", "T"), ("ţťŧ", "t"),
+    ("ÙÚÛÜŨŪŬŮŰŲ", "U"), ("ùúûüũūŭůűų", "u"), ("Ŵ", "W"), ("ŵ", "w"), ("ÝŶŸ", "Y"), ("ýŷÿ", "y"),
+    ("ŹŻŽ", "Z"), ("źżž", "z"), ("Æ", "AE"), ("æ", "ae"), ("Œ", "OE"), ("œ", "oe"), ("ß", "ss"), ("ẞ", "SS"),
+    ("Þ", "TH"), ("þ", "th"), ("Ĳ", "IJ"), ("ĳ", "ij"), ("Ŋ", "NG"), ("ŋ", "ng"), ("ſ", "s"), ("ŉ", "n"),
+];
+
+/// Spells a name in ASCII: accented Latin letters lose their marks (`é` -> `e`, `ß` -> `ss`) and
+/// every other non-ASCII character becomes an underscore
+fn ascii_fold(name: &str) -> String {
+    let mut folded = String::with_capacity(name.len());
+    for c in name.chars() {
+        match ASCII_FOLDS.iter().find(|(from, _)| from.contains(c)) {
+            _ if c.is_ascii() => folded.push(c),
+            Some((_, to)) => folded.push_str(to),
+            None => folded.push('_'),
+        }
+    }
+    folded
+}
+
+/// A sheet, file or header name as an identifier, in ASCII with `--ascii-only`; empty when the
+/// name has no letters or digits, so callers pick a fallback
+fn name_identifier(name: &str, ascii_only: bool) -> String {
+    match ascii_only {
+        true => sanitize_identifier(&ascii_fold(name)),
+        false => sanitize_identifier(name),
+    }
+}
+
+/// Double-quotes an identifier for SQL, so reserved words like `Order` still work as names
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Quotes a table name that may carry the staging schema (`staging.t`)
+fn quote_table(table: &str) -> String {
+    match table.split_once('.') {
+        Some((schema, name)) => format!("{}.{}", quote_identifier(schema), quote_identifier(name)),
+        None => quote_identifier(table),
+    }
+}
+
+/// The part of a file's table names before the sheet: its name, led by the folder it sits in
+/// below `root` (the `--path` directory or glob root); `file` when nothing of either is usable
+fn table_prefix(file_path: &Path, root: &Path, args: &Config) -> String {
+    let filename = file_path.file_stem().unwrap_or_default().to_string_lossy();
+    let subdir = file_path.parent()
+        .and_then(|dir| dir.strip_prefix(root).ok())
+        .filter(|dir| !dir.as_os_str().is_empty());
+    let prefix = match subdir {
+        Some(dir) => name_identifier(&format!("{}_{}", dir.display(), filename), args.ascii_only),
+        None => name_identifier(&filename, args.ascii_only),
+    };
+    if prefix.is_empty() { "file".to_string() } else { prefix }
+}
+
+/// A sheet name as the sheet part of a table name; `sheet_<n>` (1-based position) when nothing of it is usable
+fn sheet_identifier(sheet_name: &str, all_sheets: &[String], args: &Config) -> String {
+    match name_identifier(sheet_name, args.ascii_only) {
+        name if name.is_empty() => {
+            let index = all_sheets.iter().position(|name| name == sheet_name).map_or(1, |i| i + 1);
+            format!("sheet_{}", index)
+        }
+        name => name,
+    }
+}
+
+/// A sheet's own table name, and the table its rows actually go to (`--single-table`,
+/// or the staging schema with `--staging`). `all_sheets` is every sheet in the workbook,
+/// which `{index}` and `--single-sheet-file-name` go by.
+fn sheet_tables(filename_prefix: &str, sheet_name: &str, all_sheets: &[String], args: &Config) -> (String, String) {
+    let drop_sheet = args.single_sheet_file_name && all_sheets.len() == 1;
+    let table_name = match &args.name_template {
+        None if !drop_sheet => format!("{}_{}", filename_prefix, sheet_identifier(sheet_name, all_sheets, args)),
+        template => {
+            let index = all_sheets.iter().position(|name| name == sheet_name).map_or(0, |i| i + 1);
+            let sheet = if drop_sheet { String::new() } else { sheet_identifier(sheet_name, all_sheets, args) };
+            let name = template.as_deref().unwrap_or(DEFAULT_NAME_TEMPLATE)
+                .replace("{file}", filename_prefix)
+                .replace("{sheet}", &sheet)
+                .replace("{index}", &index.to_string());
+            // Dropping the sheet can leave nothing but separators behind
+            match sanitize_identifier(&name) {
+                name if name.is_empty() => filename_prefix.to_string(),
+                name => name,
+            }
+        }
+    };
+    let table_name = table_identifier(match &args.tables_prefix {
+        Some(prefix) => format!("{}_{}", sanitize_identifier(prefix), table_name.trim_start_matches('_')),
+        None => table_name,
+    }, args);
+    let unqualified_target = match &args.single_table {
+        Some(name) => table_identifier(sanitize_identifier(name), args),
+        None => table_name.clone(),
+    };
+    (table_name, qualify_table(&unqualified_target, args))
+}
+
+/// Where a loaded table is written: into the staging schema with `--staging`, else into
+/// `--schema-name`, or left unqualified for `main`
+fn qualify_table(table: &str, args: &Config) -> String {
+    match (args.staging, &args.schema_name) {
+        (true, _) => format!("{}.{}", STAGING_SCHEMA, table),
+        (false, Some(schema)) => format!("{}.{}", schema, table),
+        (false, None) => table.to_string(),
+    }
+}
+
+/// A loaded table's name once the run is over, when staged tables have been swapped into
+/// `schema_name` (or `main`)
+fn final_table(table: &str, schema_name: Option<&str>) -> String {
+    let name = unqualified_table(table);
+    match schema_name {
+        Some(schema) => format!("{}.{}", schema, name),
+        None => name.to_string(),
+    }
+}
+
+/// A table name without the schema in front of it
+fn unqualified_table(table: &str) -> &str {
+    table.split_once('.').map_or(table, |(_, name)| name)
+}
+
+/// Accepts a `--schema-name` that is usable as an identifier as given
+fn parse_schema_name(s: &str) -> Result<String, String> {
+    match sanitize_identifier(s) {
+        name if name.is_empty() => Err("use letters, digits and underscores, e.g. raw".to_string()),
+        name if name != s => Err(format!("use letters, digits and underscores, e.g. {}", name)),
+        name => Ok(name),
+    }
+}
+
+/// Table names without `--name-template`
+const DEFAULT_NAME_TEMPLATE: &str = "{file}_{sheet}";
+
+/// Accepts a `--name-template` that uses only the `{file}`, `{sheet}` and `{index}` placeholders
+fn parse_name_template(s: &str) -> Result<String, String> {
+    check_placeholders(s, &["{file}", "{sheet}", "{index}"])
+}
+
+/// `--union-name` without a template
+const DEFAULT_UNION_NAME: &str = "{sheet}";
+
+/// Accepts a `--union-name` that uses only the `{file}` and `{sheet}` placeholders
+fn parse_union_name(s: &str) -> Result<String, String> {
+    check_placeholders(s, &["{file}", "{sheet}"])
+}
+
+/// Checks that a name template uses at least one placeholder, and only the `allowed` ones
+fn check_placeholders(s: &str, allowed: &[&str]) -> Result<String, String> {
+    let placeholder = Regex::new(r"\{[^}]*\}").unwrap();
+    let listed = match allowed {
+        [rest @ .., last] => format!("{} or {}", rest.join(", "), last),
+        [] => String::new(),
+    };
+    if let Some(unknown) = placeholder.find_iter(s).map(|m| m.as_str()).find(|p| !allowed.contains(p)) {
+        return Err(format!("unknown placeholder {}; use {}", unknown, listed));
+    }
+    if !placeholder.is_match(s) {
+        return Err(format!("the template needs at least one of {}", listed));
+    }
+    Ok(s.to_string())
+}
+
+/// The `--union-by` table a sheet merges into, if it shares its columns with another sheet,
+/// placed in the staging schema or `--schema-name` like every other target
+fn union_target(file_path: &Path, sheet_name: &str, args: &Config) -> Option<String> {
+    let table = args.union_tables.get(&(file_path.to_path_buf(), sheet_name.to_string()))?;
+    Some(qualify_table(table, args))
+}
+
+/// Sheets with the same columns, found by `plan_union_tables`
+struct UnionGroup {
+    /// Lowercased column names with their types
+    columns: Vec<(String, String)>,
+    table: String,
+    /// (file, sheet) pairs in file order
+    members: Vec<(PathBuf, String)>,
+}
+
+/// Groups every sheet by its column names and inferred types for `--union-by schema`. Each set of
+/// two or more sheets gets one table, named by `--union-name` after the first sheet in file order;
+/// a sheet with columns of its own keeps its usual table.
+fn plan_union_tables(files: &[PathBuf], root: &Path, args: &Config) -> Result<HashMap<(PathBuf, String), String>> {
+    let report = probe_report(files, root, args)?;
+    let mut groups: Vec<UnionGroup> = Vec::new();
+    // A JSON lines file always gets a table of its own
+    for (file_path, file) in files.iter().zip(&report).filter(|(file_path, _)| !is_json_lines(file_path)) {
+        let prefix = table_prefix(file_path, root, args);
+        let sheet_names: Vec<String> = file["sheets"].as_array().into_iter().flatten()
+            .filter_map(|sheet| sheet["sheet"].as_str().map(String::from))
+            .collect();
+        for sheet in file["sheets"].as_array().into_iter().flatten().filter(|sheet| sheet["table"].is_string()) {
+            let sheet_name = sheet["sheet"].as_str().unwrap_or_default();
+            let columns: Vec<(String, String)> = sheet["schema"].as_array().into_iter().flatten()
+                .map(|column| (column["name"].as_str().unwrap_or_default().to_lowercase(), column["type"].as_str().unwrap_or_default().to_string()))
+                .collect();
+            let member = (file_path.clone(), sheet_name.to_string());
+            match groups.iter_mut().find(|group| group.columns == columns) {
+                Some(group) => group.members.push(member),
+                None => {
+                    let name = args.union_name.as_deref().unwrap_or(DEFAULT_UNION_NAME)
+                        .replace("{file}", &prefix)
+                        .replace("{sheet}", &sheet_identifier(sheet_name, &sheet_names, args));
+                    let name = match (&args.tables_prefix, sanitize_identifier(&name)) {
+                        (Some(tables_prefix), name) => format!("{}_{}", sanitize_identifier(tables_prefix), name.trim_start_matches('_')),
+                        (None, name) if name.is_empty() => prefix.clone(),
+                        (None, name) => name,
+                    };
+                    let name = table_identifier(name, args);
+                    groups.push(UnionGroup { columns, table: name, members: vec![member] });
+                }
+            }
+        }
+    }
+
+    let mut tables = HashMap::new();
+    for group in &groups {
+        if group.members.len() == 1 {
+            // Sheets that would share a name but not their columns are likely a surprise
+            if groups.iter().filter(|other| other.table.eq_ignore_ascii_case(&group.table)).count() > 1 {
+                let (file, sheet) = &group.members[0];
+                warn!("{} sheet {} has different columns from the other {} sheets, so it gets a table of its own", file.display(), sheet, group.table);
+            }
+            continue;
+        }
+        let table = claim_table(args, &group.table, &format!("{} sheets with the same columns", group.members.len()))?;
+        info!("{} sheets share their columns and load into {}", group.members.len(), table);
+        for member in &group.members {
+            tables.insert(member.clone(), table.clone());
+        }
+    }
+    Ok(tables)
+}
+
+fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// The directory a `--path` glob is rooted at: its components up to the first wildcard.
+fn glob_root(path: &Path) -> PathBuf {
+    path.components().take_while(|part| !is_glob(Path::new(part.as_os_str()))).collect()
+}
+
+/// Newline-delimited JSON extracts (`.jsonl`, `.ndjson`), which DuckDB reads itself
+fn is_json_lines(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ["jsonl", "ndjson"].contains(&ext.to_string_lossy().to_lowercase().as_str()))
+}
+
+/// Files to load from `path`: the file itself, the files a glob matches, or the
+/// `.xlsx`/`.xlsb`/`.xls`/`.ods` workbooks and `.jsonl`/`.ndjson` files in the directory (and below it
+/// with `recursive`). Excel's `~$` lock files and empty files left behind by interrupted copies are skipped.
+fn collect_excel_files(path: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    let loadable = |path: &Path, len: u64| {
+        let is_excel = path.extension().is_some_and(|ext| ["xlsx", "xlsb", "xls", "ods"].contains(&ext.to_string_lossy().to_lowercase().as_str()));
+        let is_lock_file = path.file_stem().is_some_and(|stem| stem.to_string_lossy().starts_with("~$"));
+        (is_excel || is_json_lines(path)) && !is_lock_file && len > 0
+    };
+
+    let mut files = Vec::new();
+    if is_glob(path) {
+        let pattern = path.to_string_lossy();
+        // Hidden entries only match a pattern that spells out the dot
+        let options = glob::MatchOptions { require_literal_leading_dot: true, ..Default::default() };
+        for entry in glob::glob_with(&pattern, options).with_context(|| format!("Invalid glob pattern {}", pattern))? {
+            match entry {
+                Ok(file) if file.is_file() => {
+                    if loadable(&file, file.metadata()?.len()) {
+                        files.push(file);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Skipping {}: {}", e.path().display(), e),
+            }
+        }
+        // `**` follows symlinked folders, so a loop reaches the same file again and again;
+        // keep only the shortest path to each one
+        files.sort_by_key(|file| file.components().count());
+        let mut seen = HashSet::new();
+        files.retain(|file| seen.insert(file.canonicalize().unwrap_or_else(|_| file.clone())));
+        files.sort();
+        if files.is_empty() {
+            anyhow::bail!("No files matched {}", pattern);
+        }
+    } else if path.is_dir() && recursive {
+        // Following links lets walkdir spot symlink loops, which come back as errors to skip
+        let walker = walkdir::WalkDir::new(path).follow_links(true).into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with(['.', '~']));
+        for entry in walker {
+            match entry {
+                Ok(entry) if entry.file_type().is_file() => {
+                    if loadable(entry.path(), entry.metadata()?.len()) {
+                        files.push(entry.into_path());
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Skipping {}: {}", e.path().unwrap_or(path).display(), e),
+            }
+        }
+    } else if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if loadable(&entry.path(), entry.metadata()?.len()) {
+                files.push(entry.path());
+            }
+        }
+    } else if path.is_file() {
+        files.push(path.to_path_buf());
+    }
+    Ok(files)
+}
+
+/// The first `max` files in `order`, for `--max-files`; files that sort the same go by name,
+/// so the same folder always gives the same selection
+fn first_files(files: Vec<PathBuf>, order: FileOrder, max: usize) -> Result<Vec<PathBuf>> {
+    let mut keyed = files.into_iter()
+        .map(|file| {
+            let key = match order {
+                FileOrder::Name => 0,
+                FileOrder::Mtime => file_stamp(&file)?.modified,
+                FileOrder::Size => file_stamp(&file)?.size,
+            };
+            Ok((key, file))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    keyed.sort();
+    Ok(keyed.into_iter().take(max).map(|(_, file)| file).collect())
+}
+
+impl Default for Config {
+    /// The options of a bare `excel_loader_rs` command line
+    fn default() -> Self {
+        Config::parse_from(["excel_loader_rs"])
+    }
+}
+
+impl Config {
+    /// Parses a command line as `try_parse_from` does, taking each option it leaves out from a
+    /// top-level key of the `--config` file, such as `db = "sales.duckdb"` or `sheets = ["Data"]`.
+    /// An option given on the command line wins over the file, and so does one that conflicts with
+    /// a file option, which is then left out; the file wins over the built-in default.
+    pub fn try_parse_with_config<I, T>(args: I) -> Result<Config, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let command = Config::command();
+        let matches = command.clone().try_get_matches_from(&args)?;
+        let (Some(path), Some((program, given))) = (matches.get_one::<PathBuf>("config"), args.split_first()) else {
+            return Config::try_parse_from(args);
+        };
+        let invalid = |message: String| Config::command().error(clap::error::ErrorKind::InvalidValue, message);
+        let mut settings = Settings::load(path).map_err(|e| invalid(format!("{:#}", e)))?;
+        let on_command_line = |arg: &clap::Arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine);
+        // Clap records a conflict on one side only, so both sides are checked
+        let overruled = |arg: &clap::Arg| {
+            command.get_arg_conflicts_with(arg).into_iter().any(on_command_line)
+                || command.get_arguments().filter(|other| on_command_line(other)).any(|other| command.get_arg_conflicts_with(other).iter().any(|c| c.get_id() == arg.get_id()))
+        };
+        // Options from the file go in front of the command line, which clap then validates as one
+        let mut merged = vec![program.clone()];
+        for (key, value) in &settings.options {
+            // Settings::load has checked that every key names an option
+            let Some(arg) = option_arg(&command, key) else {
+                continue;
+            };
+            let long = arg.get_long().unwrap_or_default();
+            if on_command_line(arg) || overruled(arg) {
+                continue;
+            }
+            let values = match value {
+                toml::Value::Array(items) => items.iter().collect(),
+                value => vec![value],
+            };
+            for value in values {
+                match value {
+                    toml::Value::Boolean(set) if !arg.get_action().takes_values() => {
+                        if *set {
+                            merged.push(format!("--{}", long).into());
+                        }
+                    }
+                    toml::Value::String(text) => merged.push(format!("--{}={}", long, text).into()),
+                    toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => merged.push(format!("--{}={}", long, value).into()),
+                    _ => return Err(invalid(format!("{} sets '{}' to a table or date; use a string, number, boolean or list", path.display(), key))),
+                }
+            }
+        }
+        merged.extend(given.iter().cloned());
+        let mut config = Config::try_parse_from(merged)?;
+        settings.options_applied = true;
+        config.settings = settings;
+        Ok(config)
+    }
+
+    /// The flag that stops this run early once set, for `stop_on_interrupt` or a library
+    /// caller that wants to cancel a load from another thread
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupted)
+    }
+
+    fn interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    /// Reads the files `config` and `schema` point at. `run` does this itself; library callers
+    /// that set either field call it before loading. Command line options in the config file
+    /// only take effect through `try_parse_with_config`, so a file that sets any is an error for
+    /// a `Config` parsed otherwise.
+    pub fn read_option_files(&mut self) -> Result<()> {
+        match &self.config {
+            Some(_) if self.settings.options_applied => {}
+            Some(path) => {
+                let settings = Settings::load(path)?;
+                if let Some(key) = settings.options.keys().next() {
+                    anyhow::bail!("{} sets '{}', but its options are only applied by Config::try_parse_with_config", path.display(), key);
+                }
+                self.settings = settings;
+            }
+            None => {}
+        }
+        if let Some(path) = &self.schema {
+            self.schema_overrides = SchemaOverrides::load(path)?;
+        }
+        Ok(())
+    }
+
+    /// The open password for encrypted workbooks, from --password or the variable --password-env names
+    fn workbook_password(&self) -> Result<Option<String>> {
+        match &self.password_env {
+            Some(var) => std::env::var(var).map(Some).map_err(|_| anyhow::anyhow!("--password-env {}: the environment variable is not set", var)),
+            None => Ok(self.password.clone()),
+        }
+    }
+
+    /// Rejects the values clap lets through but a load can't use
+    fn validate(&self) -> Result<()> {
+        self.workbook_password()?;
+        if self.chunk_size == 0 {
+            anyhow::bail!("--chunk-size must be greater than 0");
+        }
+        if self.threads == Some(0) {
+            anyhow::bail!("--threads must be at least 1");
+        }
+        if self.benchmark_runs == 0 {
+            anyhow::bail!("--benchmark-runs must be at least 1");
+        }
+        if self.export_internal && self.export_all.is_none() && self.sql_dump.is_none() {
+            anyhow::bail!("--export-internal needs --export-all or --sql-dump");
+        }
+        if let Some(n) = self.sample_rows {
+            anyhow::bail!("--sample-rows has been removed; use --load-rows {} to load only {} rows, or --infer-rows {} to infer types from them", n, n, n);
+        }
+        if self.sample_random {
+            anyhow::bail!("--sample-random has been removed; use --load-rows N --load-random");
+        }
+        if !(0.0..=1.0).contains(&self.date_threshold) {
+            anyhow::bail!("--date-threshold must be between 0 and 1, got {}", self.date_threshold);
+        }
+        if let Some(c) = self.csv_delimiter.filter(|&c| Some(c) == self.csv_quote.or(Some('"'))) {
+            anyhow::bail!("--csv-delimiter and --csv-quote are both '{}'; values couldn't be told apart", c);
+        }
+        if self.split_on_blank && self.orientation == Orientation::Columns {
+            anyhow::bail!("--split-on-blank splits rows, so it can't be combined with --orientation columns");
+        }
+        if self.split_on_blank && self.settings.sheet.iter().any(|section| section.header_row.is_some()) {
+            anyhow::bail!("--split-on-blank takes each block's first row as its header; remove header_row from the [[sheet]] sections of --config");
+        }
+        if let Some(NumberLocale::Custom { group, decimal }) = self.text_number_locale() {
+            if group == decimal {
+                anyhow::bail!("--decimal-separator and --thousands-separator are both '{}'", decimal);
+            }
+            if let Some(c) = [group, decimal].into_iter().find(|c| c.is_ascii_digit() || "+-%".contains(*c)) {
+                anyhow::bail!("'{}' can't separate the digits of a number", c);
+            }
+        }
+        Ok(())
+    }
+
+    /// The `--load-timestamp-column` value, fixed the first time it's asked for unless the run set it
+    fn load_timestamp(&self) -> Value {
+        let started_at = *self.load_timestamp.lock().unwrap().get_or_insert_with(|| chrono::Local::now().naive_local());
+        // To the millisecond, as _load_runs records it, so rows join to their run
+        let started_at = chrono::SubsecRound::trunc_subsecs(started_at, 3);
+        Value::Timestamp(TimeUnit::Microsecond, started_at.and_utc().timestamp_micros())
+    }
+
+    /// The locale numbers stored as text are read with: `--number-locale`, or the separators
+    /// `--decimal-separator` and `--thousands-separator` give
+    fn text_number_locale(&self) -> Option<NumberLocale> {
+        match (self.decimal_separator, self.thousands_separator) {
+            (None, None) => self.number_locale,
+            (decimal, group) => Some(NumberLocale::Custom {
+                group: group.unwrap_or(if decimal == Some(',') { '.' } else { ',' }),
+                decimal: decimal.unwrap_or(if group == Some('.') { ',' } else { '.' }),
+            }),
+        }
+    }
+}
+
+/// What `load_excel_into_db` loaded
+#[derive(Debug)]
+pub struct LoadStats {
+    pub files: usize,
+    pub tables: usize,
+    pub rows: usize,
+    /// Files that failed, with the error that stopped each one
+    pub failures: Vec<(String, String)>,
+    /// Sheets that couldn't be read and were skipped: the file, the sheet and the error
+    pub skipped_sheets: Vec<(String, String, String)>,
+    pub elapsed: Duration,
+}
+
+/// Loads `files` into the database `conn` is connected to, as the command line loads them:
+/// one table per sheet, named and typed by `config`. Table names are relative to `config.path`,
+/// as with `--recursive`. A file that fails is reported in the stats, not as an error.
+/// The steps around a run (`--staging`, `--refresh`, `--union-by`, `--incremental`, the load
+/// history, exports and queries) are left to `run`.
+pub fn load_excel_into_db(files: &[PathBuf], config: &Config, conn: &Connection) -> Result<LoadStats> {
+    config.validate()?;
+    let start = Instant::now();
+    let mode = if config.append { InsertMode::Sql } else { config.insert_mode };
+    let db: Box<dyn Db> = Box::new(DuckBackend(conn.try_clone()?, mode));
+    let conn_mutex = Arc::new(Mutex::new(db));
+    let progress = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+    let outcomes = load_files(files, Path::new(&config.path), &conn_mutex, mode, &progress, config)?;
+    Ok(LoadStats {
+        files: outcomes.len(),
+        tables: outcomes.iter().map(|outcome| outcome.tables).sum(),
+        rows: outcomes.iter().map(|outcome| outcome.rows).sum(),
+        failures: outcomes.iter().filter_map(|outcome| Some((outcome.file.clone(), outcome.error.clone()?))).collect(),
+        skipped_sheets: std::mem::take(&mut *config.skipped_sheets.lock().unwrap()),
+        elapsed: start.elapsed(),
+    })
+}
+
+/// The column names and types a sheet would load with: `range` holds the header row and the
+/// data below it, as calamine reads a worksheet. `table` is the name `--schema` declarations
+/// are looked up by.
+pub fn infer_schema(range: Range<Data>, table: &str, config: &Config) -> Result<Vec<(String, String)>> {
+    let (range, _) = prepare_range(range, "", None, config)?;
+    let mut rows = range.rows();
+    let Some(headers) = rows.next() else {
+        return Ok(Vec::new());
+    };
+    let (rows_data, _) = sheet_data_rows(rows, config);
+    let schema = infer_sheet_schema(headers, &rows_data, table, None, &ColumnFormats::default(), config)?;
+    Ok(schema.names.into_iter().zip(schema.types.into_iter().map(String::from)).collect())
+}
+
+/// Exit code of a run in which some files loaded and others failed
+pub const EXIT_PARTIAL_FAILURE: u8 = 2;
+/// Exit code of a run that failed: no file loaded, or a step such as `--query` failed
+pub const EXIT_FAILURE: u8 = 3;
+/// Exit code of a run whose arguments (or config files) were invalid
+pub const EXIT_BAD_ARGS: u8 = 4;
+/// Exit code of a run stopped by Ctrl-C, as shells report a process SIGINT ended
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+/// An error in the arguments, as opposed to one that came up while loading
+#[derive(Debug)]
+struct BadArgs(String);
+
+impl std::fmt::Display for BadArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BadArgs {}
+
+fn bad_args(error: anyhow::Error) -> anyhow::Error {
+    anyhow::Error::new(BadArgs(format!("{:#}", error)))
+}
+
+/// Files that failed to load, ending a run that got through every step
+#[derive(Debug)]
+struct LoadFailure {
+    failed: usize,
+    /// Files that did load; under `--fail-fast` the rest may not have been tried
+    loaded: usize,
+    total: usize,
+}
+
+impl std::fmt::Display for LoadFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} of {} file(s) failed to load", self.failed, self.total)
+    }
+}
+
+impl std::error::Error for LoadFailure {}
+
+/// A run that Ctrl-C stopped; the tables that finished loading are kept
+#[derive(Debug)]
+struct Interrupted {
+    loaded: usize,
+    total: usize,
+}
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Interrupted after {} of {} file(s) loaded; the sheet in progress was rolled back", self.loaded, self.total)
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+static INTERRUPT_FLAG: std::sync::OnceLock<Arc<AtomicBool>> = std::sync::OnceLock::new();
+
+/// Makes the first Ctrl-C (or SIGTERM) set `flag` instead of killing the process, so the run
+/// stops starting work, rolls back the sheet in progress and closes the database cleanly. A
+/// second one ends the process at once. Does nothing on platforms without Unix signals.
+pub fn stop_on_interrupt(flag: Arc<AtomicBool>) {
+    if INTERRUPT_FLAG.set(flag).is_err() {
+        return;
+    }
+    #[cfg(unix)]
+    {
+        extern "C" fn on_interrupt(_: libc::c_int) {
+            if let Some(flag) = INTERRUPT_FLAG.get() {
+                flag.store(true, Ordering::SeqCst);
+            }
+            let message = b"\nInterrupted: finishing up; press Ctrl-C again to stop at once\n";
+            // Only async-signal-safe calls in here: no allocation, locks or logging
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+                libc::signal(libc::SIGTERM, libc::SIG_DFL);
+                libc::write(libc::STDERR_FILENO, message.as_ptr().cast(), message.len());
+            }
+        }
+        let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        unsafe {
+            libc::signal(libc::SIGINT, handler);
+            libc::signal(libc::SIGTERM, handler);
+        }
+    }
+}
+
+/// The exit code for an error `run` returned: `EXIT_BAD_ARGS`, `EXIT_INTERRUPTED`,
+/// `EXIT_PARTIAL_FAILURE` when only some files failed, or `EXIT_FAILURE`
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    if error.is::<BadArgs>() {
+        return EXIT_BAD_ARGS;
+    }
+    if error.is::<Interrupted>() {
+        return EXIT_INTERRUPTED;
+    }
+    match error.downcast_ref::<LoadFailure>() {
+        Some(failure) if failure.loaded > 0 => EXIT_PARTIAL_FAILURE,
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// Runs the command line: loads the files and then the steps after the load, writing the
+/// `--json-report` once it's done, whether it succeeded or not
+pub fn run(mut args: Config) -> Result<()> {
+    let start = Instant::now();
+    let result = run_steps(&mut args);
+    if args.json_report {
+        eprintln!("{}", run_report(&result, start.elapsed(), &args));
+    }
+    result
+}
+
+/// The `--json-report` object: the `--manifest` summary, when files were loaded, plus how the
+/// run ended and each file's status
+fn run_report(result: &Result<()>, elapsed: Duration, args: &Config) -> serde_json::Value {
+    let code = result.as_ref().err().map_or(0, exit_code);
+    let status = match code {
+        0 => "ok",
+        EXIT_PARTIAL_FAILURE => "partial",
+        EXIT_BAD_ARGS => "bad_args",
+        EXIT_INTERRUPTED => "interrupted",
+        _ => "failed",
+    };
+    let mut report = args.load_report.clone().unwrap_or_else(|| serde_json::json!({}));
+    if let Some(files) = report.get_mut("files").and_then(serde_json::Value::as_array_mut) {
+        for file in files {
+            file["status"] = serde_json::json!(if file["error"].is_null() { "ok" } else { "failed" });
+        }
+    }
+    report["status"] = serde_json::json!(status);
+    report["exit_code"] = serde_json::json!(code);
+    report["error"] = serde_json::json!(result.as_ref().err().map(|e| format!("{:#}", e)));
+    report["elapsed_ms"] = serde_json::json!(elapsed.as_millis() as u64);
+    report
+}
+
+fn run_steps(args: &mut Config) -> Result<()> {
+    args.read_option_files().map_err(bad_args)?;
+    args.validate().map_err(bad_args)?;
+    if args.backend != Backend::Duckdb {
+        let duckdb_only = [
+            ("--staging", args.staging),
+            ("--schema-name", args.schema_name.is_some()),
+            ("--verify", args.verify),
+            ("--single-table", args.single_table.is_some()),
+            ("--union-by", args.union_by.is_some()),
+            ("--append", args.append),
+            ("--add-column", !args.add_column.is_empty()),
+            ("--index", !args.index.is_empty()),
+            ("--sql-dump", args.sql_dump.is_some()),
+            ("--ddl-out", args.ddl_out.is_some()),
+            ("--data-dictionary", args.data_dictionary.is_some()),
+            ("--export-all", args.export_all.is_some()),
+            ("--profile", args.profile),
+            ("--query", !args.query.is_empty()),
+            ("--script", args.script.is_some()),
+            ("--show-schema", args.show_schema.is_some()),
+            ("--repl", args.repl),
+            ("--parallel-writes", args.parallel_writes),
+            ("--incremental", args.incremental),
+            ("--benchmark", args.benchmark),
+            ("--timezone", args.timezone.is_some()),
+            ("--dedup-on", !args.dedup_on.is_empty()),
+            ("--watch", args.watch),
+            ("--compare", args.compare),
+        ];
+        let used: Vec<&str> = duckdb_only.iter().filter(|(_, set)| *set).map(|(flag, _)| *flag).collect();
+        if !used.is_empty() {
+            return Err(bad_args(anyhow::anyhow!("Not supported with --backend sqlite: {}", used.join(", "))));
+        }
+    }
+    let start_total = Instant::now();
+    let started_at = chrono::Local::now().naive_local();
+    // Rows are stamped with the time _load_runs records for the run
+    *args.load_timestamp.lock().unwrap() = Some(started_at);
+
+    // A workbook piped in goes by --stdin-name plus the extension its bytes call for, so the
+    // rest of the run can treat it like a file without a folder
+    let (files, root) = if args.path == "-" {
+        let from_disk = [
+            ("--recursive", args.recursive),
+            ("--max-files", args.max_files.is_some()),
+            ("--mmap", args.mmap),
+            ("--flag-highlighted", args.flag_highlighted),
+            ("--detect-percent", args.detect_percent),
+            ("--format-map", !args.format_map.is_empty()),
+            ("--control-files", args.control_files),
+            ("--incremental", args.incremental),
+            ("--watch", args.watch),
+        ];
+        let used: Vec<&str> = from_disk.iter().filter(|(_, set)| *set).map(|(flag, _)| *flag).collect();
+        if !used.is_empty() {
+            return Err(bad_args(anyhow::anyhow!("Not supported when reading from stdin: {}", used.join(", "))));
+        }
+        let mut data = Vec::new();
+        std::io::stdin().read_to_end(&mut data).context("Cannot read the workbook from stdin")?;
+        let file = PathBuf::from(format!("{}.{}", args.stdin_name, workbook_extension(&data)?));
+        args.stdin_data = Some(data);
+        (vec![file], PathBuf::new())
+    } else {
+        let mut files = collect_excel_files(Path::new(&args.path), args.recursive)?;
+        if let Some(max) = args.max_files {
+            let found = files.len();
+            files = first_files(files, args.order, max as usize)?;
+            if files.len() < found {
+                info!("Loading the first {} of {} file(s) by {:?} (--max-files)", files.len(), found, args.order);
+            }
+        }
+        // Tables from a glob such as `reports/**/sales.xlsx` are prefixed with their folder below
+        // `reports`, the same way --recursive names them
+        let root = if is_glob(Path::new(&args.path)) { glob_root(Path::new(&args.path)) } else { PathBuf::from(&args.path) };
+        (files, root)
+    };
+
+    if args.count_only {
+        return count_files(&files, args);
+    }
+
+    // Sheets are grouped by their columns up front, which reads every workbook an extra time
+    if args.union_by.is_some() {
+        args.union_tables = plan_union_tables(&files, &root, args)?;
+    }
+
+    if let Some(format) = args.probe {
+        return probe_files(&files, &root, format, args);
+    }
+    if args.benchmark {
+        return benchmark_files(&files, args, &mut std::io::stdout().lock());
+    }
+    if args.dry_run {
+        if args.explain || args.ddl_out.is_some() {
+            // The statements are all --ddl-out needs, so without --explain no rows go in
+            let schema_only = args.schema_only;
+            args.schema_only |= !args.explain;
+            let conn = explain_files(&files, &root, args)?;
+            args.schema_only = schema_only;
+            if let Some(path) = &args.ddl_out {
+                let indexes = create_indexes(&conn, &args.index)?;
+                write_ddl(path, &indexes, args)?;
+            }
+        }
+        return probe_files(&files, &root, ProbeFormat::Table, args);
+    }
+    if args.compare {
+        if args.db == MEMORY_DB {
+            return Err(bad_args(anyhow::anyhow!("--compare compares with the tables already in --db, and a :memory: database has none")));
+        }
+        // The would-be tables are created empty in memory, with every column the loader adds
+        let schema_only = args.schema_only;
+        args.schema_only = true;
+        let planned = explain_files(&files, &root, args);
+        args.schema_only = schema_only;
+        return compare_files(&files, &root, &planned?, args);
+    }
+
+    info!("Found {} Excel files to process.", files.len());
+
+    // Initialize DuckDB connection
+    // Note: DuckDB handles concurrency well, but for bulk loading, 
+    // it's often best to have one connection per thread or share one if using appender.
+    // However, DuckDB's single-writer model means we should probably process files in parallel 
+    // but write sequentially OR use separate connections if DuckDB supports it (it does for WAL).
+    // For this POC, let's try parallel reading and sequential writing to be safe and fair comparison to Python v2.
+    
+    // Actually, we can do better: Read in parallel, collect data, then write. 
+    // But for 1M rows, holding in memory is fine.
+    
+    let progress = MultiProgress::with_draw_target(progress_target(args));
+
+    // We will use a mutex to protect the DB connection for sequential writing
+    // This mimics the Python optimization we did (parallel read, sequential write)
+    if let Some(dir) = &args.output_parquet {
+        fs::create_dir_all(dir).with_context(|| format!("Cannot create the --output-parquet folder {}", dir))?;
+    }
+    // Held until the run ends, so an overlapping run can't load into the same database alongside this one
+    let _lock = match args.output_parquet {
+        Some(_) => None,
+        None => acquire_run_lock(&args.db, Duration::from_secs(args.lock_wait), || args.interrupted())?,
+    };
+    let mode = if args.append { InsertMode::Sql } else { args.insert_mode };
+    let db: Box<dyn Db> = match args.backend {
+        Backend::Duckdb if args.output_parquet.is_some() => Box::new(DuckBackend(Connection::open_in_memory()?, mode)),
+        Backend::Duckdb => Box::new(DuckBackend(open_duckdb(&args.db)?, mode)),
+        Backend::Sqlite if args.db == MEMORY_DB => Box::new(SqliteBackend(rusqlite::Connection::open_in_memory()?)),
+        Backend::Sqlite if motherduck_database(&args.db).is_some() => anyhow::bail!("MotherDuck databases are DuckDB ones; --db {} needs --backend duckdb", redacted_db(&args.db)),
+        Backend::Sqlite => Box::new(SqliteBackend(rusqlite::Connection::open(&args.db)?)),
+    };
+    if let Some(sql) = &args.sql_pre {
+        run_sql_hook(db.as_ref(), sql, "--sql-pre").context("--sql-pre failed; nothing was loaded")?;
+    }
+
+    if args.db == MEMORY_DB && args.incremental {
+        warn!("--incremental has no earlier load to compare with in a {} database; every file loads", MEMORY_DB);
+    }
+    if let Some(conn) = db.duckdb().filter(|_| args.staging) {
+        let sql = format!("DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0};", STAGING_SCHEMA);
+        debug!("{}", sql);
+        conn.execute_batch(&sql)?;
+    }
+    if let (Some(conn), Some(schema)) = (db.duckdb(), &args.schema_name) {
+        conn.execute_batch(&format!("CREATE SCHEMA IF NOT EXISTS {}", quote_identifier(schema)))?;
+    }
+
+    // Refresh if requested (with --staging the old tables are dropped during the swap instead)
+    if args.refresh_all && !args.staging {
+        info!("Clearing existing tables...");
+        match (db.duckdb(), &args.schema_name) {
+            // Only this import's schema is cleared; main and other schemas keep their tables
+            (Some(conn), Some(schema)) => conn.execute_batch(&format!("DROP SCHEMA {0} CASCADE; CREATE SCHEMA {0};", quote_identifier(schema)))?,
+            _ => db.refresh()?,
+        }
+        info!("Cleared tables.");
+    } else if args.refresh && !args.staging {
+        let planned = planned_tables(&files, &root, args);
+        for table in &planned {
+            db.drop_table(table)?;
+        }
+        info!("Dropped the {} table(s) this run loads", planned.len());
+    }
+
+    // Sheets are appended to the single table as they load, so start it over on every run
+    if let Some(name) = args.single_table.as_ref().filter(|_| !args.append) {
+        db.drop_table(&qualify_table(&sanitize_identifier(name), args))?;
+    }
+    // Likewise the --union-by tables, which their sheets are appended to one by one
+    let union_tables: HashSet<&String> = args.union_tables.values().collect();
+    for table in union_tables {
+        db.drop_table(&qualify_table(table, args))?;
+    }
+
+    // Files that look the same as when they last loaded keep their tables; --refresh has just
+    // dropped those tables, so it loads everything (and --refresh-all dropped the record too)
+    let all_files = files;
+    let mut previous = Vec::new();
+    let mut stamps: HashMap<PathBuf, FileStamp> = HashMap::new();
+    let files: Vec<PathBuf> = match db.duckdb().filter(|_| args.incremental) {
+        Some(conn) => {
+            if !args.refresh_all {
+                previous = loaded_files(conn)?;
+            }
+            if !args.refresh && !args.refresh_all {
+                args.loaded_sheets = loaded_sheets(conn)?;
+            }
+            let mut changed = Vec::new();
+            for file_path in &all_files {
+                let stamp = file_stamp(file_path)?;
+                let file = file_path.display().to_string();
+                if args.refresh || !previous.iter().any(|loaded| loaded.file == file && loaded.stamp == stamp) {
+                    changed.push(file_path.clone());
+                }
+                stamps.insert(file_path.clone(), stamp);
+            }
+            if changed.len() < all_files.len() {
+                info!("Skipping {} unchanged file(s) (--incremental)", all_files.len() - changed.len());
+            }
+            changed
+        }
+        None => all_files.clone(),
+    };
+
+    // --watch goes on from the files as they were when this load started
+    let watched: HashMap<PathBuf, FileStamp> = match args.watch {
+        true => all_files.iter().filter_map(|file| Some((file.clone(), file_stamp(file).ok()?))).collect(),
+        false => HashMap::new(),
+    };
+    let conn_mutex = Arc::new(Mutex::new(db));
+    let outcomes = load_files(&files, &root, &conn_mutex, mode, &progress, args)?;
+    if let Some(budget) = args.max_total_rows.filter(|_| budget_spent(args)) {
+        info!("Stopped at the --max-total-rows budget of {} rows", budget);
+    }
+
+    for sheet in outcomes.iter().flat_map(|outcome| &outcome.sheets) {
+        debug!("{}: {} row(s)", sheet.table, sheet.rows);
+    }
+    info!("{}", load_summary(&outcomes, start_total.elapsed()));
+    if args.type_summary {
+        println!("{}", type_summary(&outcomes));
+    }
+    let failed = report_failures(&outcomes, files.len(), args.fail_fast);
+    for (file, sheet, error) in args.skipped_sheets.lock().unwrap().iter() {
+        eprintln!("Sheet {} of file {} was skipped due to a parse error: {}", sheet, file, error);
+    }
+    if args.manifest.is_some() || args.json_report {
+        let manifest = manifest_json(started_at, &outcomes, start_total.elapsed(), args);
+        if let Some(path) = &args.manifest {
+            write_manifest(path, &manifest)?;
+            info!("Wrote manifest to {}", path);
+        }
+        args.load_report = Some(manifest);
+    }
+    if args.on_bad_row == OnBadRow::RejectFile {
+        // Workers finish files in any order; each file's rows are already in sheet order
+        let mut rejected = std::mem::take(&mut *args.rejected_rows.lock().unwrap());
+        rejected.sort_by(|a, b| a.file.cmp(&b.file));
+        write_rejects(&args.rejects_file, &rejected)?;
+        info!("Wrote {} rejected row(s) to {}", rejected.len(), args.rejects_file);
+    }
+    // Files that failed still count against the run once everything else has been done
+    let finish = || failure_result(failed, outcomes.len() - failed, files.len(), args.ignore_errors);
+
+    let db = conn_mutex.lock().unwrap();
+    // The rest works on DuckDB only; main rejected the flags that need it for other backends
+    // Tables that loaded are kept when --post-sql fails; the run fails once the other steps are done
+    let post_sql = |db: &dyn Db| match &args.post_sql {
+        Some(sql) => run_sql_hook(db, sql, "--post-sql").map_err(|e| {
+            error!("--post-sql failed; the loaded tables are kept: {:#}", e);
+            e.context("--post-sql failed")
+        }),
+        None => Ok(()),
+    };
+    // Ctrl-C skips the steps after the load; what loaded is recorded as usual
+    let interrupted = || {
+        let loaded = outcomes.iter().filter(|outcome| outcome.error.is_none()).count();
+        Err(anyhow::Error::new(Interrupted { loaded, total: files.len() }))
+    };
+    let Some(conn) = db.duckdb() else {
+        if args.interrupted() {
+            return interrupted();
+        }
+        let post = post_sql(db.as_ref());
+        return finish().and(post);
+    };
+    // Every table has gone out as Parquet, and the in-memory database goes with the run
+    if args.output_parquet.is_some() {
+        return if args.interrupted() { interrupted() } else { finish() };
+    }
+
+    let run_id = write_load_log(conn, started_at, &args.path, &outcomes, start_total.elapsed())?;
+    info!("Recorded run {} in _load_runs", run_id);
+
+    if args.interrupted() {
+        if args.staging {
+            conn.execute_batch(&format!("DROP SCHEMA IF EXISTS {} CASCADE", STAGING_SCHEMA))?;
+            info!("Discarded the staged tables; main is untouched");
+        }
+        if args.incremental {
+            record_loaded_files(conn, &previous, &outcomes, &stamps, &all_files, false, args.schema_name.as_deref())?;
+        }
+        return interrupted();
+    }
+
+    if args.staging {
+        if outcomes.iter().any(|outcome| outcome.error.is_some()) {
+            conn.execute_batch(&format!("DROP SCHEMA IF EXISTS {} CASCADE", STAGING_SCHEMA))?;
+            anyhow::bail!("At least one file failed to load; staged tables were discarded and main is untouched");
+        }
+        let schema = args.schema_name.as_deref().unwrap_or("main");
+        let swapped = swap_in_staging(conn, args.refresh_all, schema)?;
+        info!("Swapped {} staged table(s) into {}", swapped, schema);
+    }
+
+    if args.incremental {
+        record_loaded_files(conn, &previous, &outcomes, &stamps, &all_files, args.prune, args.schema_name.as_deref())?;
+    }
+
+    // Before --dedup-on takes rows out on purpose
+    if args.verify {
+        let problems = verify_load(conn, &outcomes, args)?;
+        if !problems.is_empty() {
+            for problem in &problems {
+                error!("{}", problem);
+            }
+            anyhow::bail!("--verify found {} row count problem(s)", problems.len());
+        }
+        info!("Verified the row counts of every loaded sheet and table");
+    }
+
+    // From here on, names without a schema find the loaded tables first
+    if let Some(schema) = &args.schema_name {
+        conn.execute_batch(&format!("SET search_path = '{},main'", schema))?;
+    }
+
+    if !args.dedup_on.is_empty() {
+        let mut seen = HashSet::new();
+        for sheet in outcomes.iter().flat_map(|outcome| &outcome.sheets) {
+            // Staged tables have been swapped out of the staging schema by now
+            let table = final_table(&sheet.table, args.schema_name.as_deref());
+            if !seen.insert(table.to_lowercase()) {
+                continue;
+            }
+            if let Some(removed) = dedup_table(conn, &table, &args.dedup_on, args.dedup_keep)? {
+                info!("Removed {} duplicate row(s) from {} (--dedup-on)", removed, table);
+            }
+        }
+    }
+
+    if !args.add_column.is_empty() {
+        for add in &args.add_column {
+            add_column(conn, add)?;
+        }
+    }
+
+    let indexes = create_indexes(conn, &args.index)?;
+    if let Some(path) = &args.ddl_out {
+        write_ddl(path, &indexes, args)?;
+    }
+
+    let post = post_sql(db.as_ref());
+
+    if let Some(path) = &args.sql_dump {
+        let tables = write_sql_dump(conn, path, args)?;
+        info!("Wrote {} table(s) to {}", tables, path);
+    }
+
+    if let Some(path) = &args.data_dictionary {
+        write_data_dictionary(conn, path)?;
+        info!("Wrote data dictionary to {}", path);
+    }
+
+    if let Some(dir) = &args.export_all {
+        let started = Instant::now();
+        let exports = export_all_tables(conn, dir, args)?;
+        for export in &exports {
+            info!("Exported {} to {}: {} row(s), {} in {:.2?}", export.table, export.path.display(), export.rows, format_byte_size(export.bytes), export.elapsed);
+        }
+        info!("Exported {} table(s), {} in all, to {} in {:.2?}", exports.len(), format_byte_size(exports.iter().map(|e| e.bytes).sum()), dir, started.elapsed());
+    }
+
+    if args.profile {
+        let mut seen = HashSet::new();
+        for sheet in outcomes.iter().flat_map(|outcome| &outcome.sheets) {
+            let table = final_table(&sheet.table, args.schema_name.as_deref());
+            if seen.insert(table.to_lowercase()) {
+                print_profile(conn, &table, &mut std::io::stdout().lock())?;
+            }
+        }
+    }
+
+    for query_str in &args.query {
+        run_query(conn, query_str, args)?;
+    }
+
+    if let Some(path) = &args.script {
+        run_query_script(conn, path, args)?;
+    }
+
+    if let Some(table) = &args.show_schema {
+        show_schema(conn, Some(table.as_str()).filter(|t| !t.is_empty()), &mut std::io::stdout().lock())?;
+    }
+
+    if args.repl {
+        run_repl(conn, args)?;
+    }
+
+    if args.watch {
+        finish().and(post)?;
+        let conn = conn.try_clone()?;
+        drop(db);
+        args.append = true;
+        return watch_files(conn, &root, watched, &progress, args);
+    }
+
+    finish().and(post)
+}
+
+/// Loads files that show up in (or change under) `args.path` after the first load, until the
+/// process is stopped (`--watch`). The folder is scanned every `--watch-interval` seconds and a
+/// file loads once two scans in a row find the same size and modification time, so copies still
+/// being written are left alone. `args` has `--append` set, so the rows join existing tables.
+fn watch_files(conn: Connection, root: &Path, mut seen: HashMap<PathBuf, FileStamp>, progress: &MultiProgress, args: &Config) -> Result<()> {
+    let db: Box<dyn Db> = Box::new(DuckBackend(conn.try_clone()?, InsertMode::Sql));
+    let conn_mutex = Arc::new(Mutex::new(db));
+    let mut pending = HashMap::new();
+    info!("Watching {} for new or changed files, every {}s; stop with Ctrl-C", args.path, args.watch_interval);
+    loop {
+        let wake = Instant::now() + Duration::from_secs(args.watch_interval);
+        while Instant::now() < wake && !args.interrupted() {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        if args.interrupted() {
+            info!("Stopped watching {}", args.path);
+            return Ok(());
+        }
+        // A glob that matches nothing yet is not an error here
+        let files = match collect_excel_files(Path::new(&args.path), args.recursive) {
+            Ok(files) => files,
+            Err(e) => {
+                debug!("{:#}", e);
+                Vec::new()
+            }
+        };
+        let stamped: Vec<(PathBuf, FileStamp)> = files.into_iter().filter_map(|file| Some((file.clone(), file_stamp(&file).ok()?))).collect();
+        let ready = settled_files(&stamped, &mut seen, &mut pending);
+        if ready.is_empty() {
+            continue;
+        }
+
+        let started_at = chrono::Local::now().naive_local();
+        *args.load_timestamp.lock().unwrap() = Some(started_at);
+        let start = Instant::now();
+        let outcomes = load_files(&ready, root, &conn_mutex, InsertMode::Sql, progress, args)?;
+        for outcome in &outcomes {
+            match &outcome.error {
+                Some(error) => error!("Failed to load {}: {}", outcome.file, error),
+                None => info!("Loaded {}: {} row(s) into {} table(s)", outcome.file, outcome.rows, outcome.tables),
+            }
+        }
+        write_load_log(&conn, started_at, &args.path, &outcomes, start.elapsed())?;
+        if args.incremental {
+            let stamps: HashMap<PathBuf, FileStamp> = ready.iter().filter_map(|file| Some((file.clone(), *seen.get(file)?))).collect();
+            record_loaded_files(&conn, &loaded_files(&conn)?, &outcomes, &stamps, &ready, false, args.schema_name.as_deref())?;
+        }
+    }
+}
+
+/// Of the files one `--watch` scan found, those to load now: new or changed since `seen`, and
+/// looking the same as on the scan before (`pending`). They count as seen from here on, and
+/// files still changing wait in `pending` for the next scan.
+fn settled_files(stamped: &[(PathBuf, FileStamp)], seen: &mut HashMap<PathBuf, FileStamp>, pending: &mut HashMap<PathBuf, FileStamp>) -> Vec<PathBuf> {
+    let mut ready = Vec::new();
+    for (file, stamp) in stamped {
+        if seen.get(file) == Some(stamp) {
+            pending.remove(file);
+            continue;
+        }
+        if pending.insert(file.clone(), *stamp) == Some(*stamp) {
+            pending.remove(file);
+            seen.insert(file.clone(), *stamp);
+            ready.push(file.clone());
+        }
+    }
+    // Files deleted before they settled are forgotten
+    pending.retain(|file, _| stamped.iter().any(|(present, _)| present == file));
+    ready
+}
+
+/// Loads `files` once per write strategy (`--benchmark`), `--benchmark-runs` times each, into a
+/// fresh in-memory database every time, and prints the fastest run of each strategy. Everything
+/// else about the load (threads, chunk size, type options) comes from `args`.
+fn benchmark_files(files: &[PathBuf], args: &mut Config, out: &mut impl std::io::Write) -> Result<()> {
+    let strategies = [
+        (InsertMode::Appender, false),
+        (InsertMode::Appender, true),
+        (InsertMode::Sql, false),
+        (InsertMode::Sql, true),
+    ];
+    let mut table = comfy_table::Table::new();
+    table.load_preset(comfy_table::presets::UTF8_FULL_CONDENSED).set_header(["Inserts", "Writes", "Tables", "Rows", "Seconds", "Rows/s"]);
+    for (mode, parallel) in strategies {
+        let inserts = match mode { InsertMode::Appender => "Appender", InsertMode::Sql => "SQL" };
+        let writes = if parallel { "parallel" } else { "shared connection" };
+        args.insert_mode = mode;
+        args.parallel_writes = parallel;
+        let mut fastest: Option<LoadStats> = None;
+        for run in 1..=args.benchmark_runs {
+            // Each load claims table names and row budget afresh
+            args.tables_claimed.lock().unwrap().clear();
+            args.rows_loaded.store(0, Ordering::Relaxed);
+            args.rejected_rows.lock().unwrap().clear();
+            args.skipped_sheets.lock().unwrap().clear();
+            let conn = Connection::open_in_memory()?;
+            let stats = load_excel_into_db(files, args, &conn)?;
+            if let Some((file, error)) = stats.failures.first() {
+                anyhow::bail!("{} file(s) failed during the benchmark; the first, {}: {}", stats.failures.len(), file, error);
+            }
+            info!("{} inserts, {}: run {} took {:.2?}", inserts, writes, run, stats.elapsed);
+            if fastest.as_ref().is_none_or(|best| stats.elapsed < best.elapsed) {
+                fastest = Some(stats);
+            }
+        }
+        let stats = fastest.expect("--benchmark-runs is at least 1");
+        let seconds = stats.elapsed.as_secs_f64();
+        table.add_row([
+            inserts.to_string(),
+            writes.to_string(),
+            stats.tables.to_string(),
+            stats.rows.to_string(),
+            format!("{:.2}", seconds),
+            format!("{:.0}", stats.rows as f64 / seconds.max(f64::EPSILON)),
+        ]);
+    }
+    writeln!(out, "{}", table)?;
+    Ok(())
+}
+
+/// The data rows of each selected sheet in a workbook, as a load would see them after the header
+/// and skip options (and `--skip-blank-rows`)
+fn count_sheet_rows(file_path: &Path, args: &Config) -> Result<Vec<(String, usize)>> {
+    if is_json_lines(file_path) {
+        let rows = check_json_lines(&fs::read_to_string(file_path).context("Cannot open file")?)?;
+        return Ok(vec![(json_lines_sheet(file_path), rows)]);
+    }
+    let mut workbook = with_retries(file_path, args.retries, || open_spreadsheet(file_path, args)).context("Cannot open file")?;
+    let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    let mut counts = Vec::new();
+    for sheet_name in select_sheets(&workbook.sheet_names(), file_path, args) {
+        let range = match workbook.worksheet_range(&sheet_name) {
+            Ok(range) => restrict_to_range(range, &sheet_name, args),
+            Err(e) if !args.strict => {
+                warn!("Skipping {}: cannot read it: {}", sheet_name, e);
+                continue;
+            }
+            Err(e) => return Err(e).with_context(|| format!("Cannot read sheet {} (--strict)", sheet_name)),
+        };
+        let (range, _) = prepare_range(range, &sheet_name, args.settings.sheet_for(&file_name, &sheet_name), args)
+            .with_context(|| format!("Reading sheet {}", sheet_name))?;
+        let data_rows = range.rows().skip(1);
+        let rows = if args.skip_blank_rows { data_rows.filter(|row| !is_blank_row(row)).count() } else { data_rows.count() };
+        counts.push((sheet_name, rows));
+    }
+    Ok(counts)
+}
+
+/// `--count-only`: prints every sheet's data rows and the total. Nothing is inferred and no
+/// database is opened.
+fn count_files(files: &[PathBuf], args: &Config) -> Result<()> {
+    let mut table = comfy_table::Table::new();
+    table.load_preset(comfy_table::presets::UTF8_FULL_CONDENSED).set_header(["File", "Sheet", "Rows"]);
+    let (mut total, mut failed) = (0, 0);
+    for file_path in files {
+        match count_sheet_rows(file_path, args) {
+            Ok(counts) => {
+                for (sheet, rows) in counts {
+                    total += rows;
+                    table.add_row([file_path.display().to_string(), sheet, rows.to_string()]);
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                error!("Error counting {}: {:#}", file_path.display(), e);
+            }
+        }
+    }
+    table.add_row(["Total".to_string(), String::new(), total.to_string()]);
+    println!("{}", table);
+    match failed {
+        0 => Ok(()),
+        n => Err(anyhow::anyhow!("{} of {} file(s) could not be counted", n, files.len())),
+    }
+}
+
+/// How much of the overall progress bar a file is worth: its size in bytes (the buffered input
+/// for stdin), and at least 1 so empty or unreadable sizes still move the bar
+fn input_size(file_path: &Path, args: &Config) -> u64 {
+    let size = match &args.stdin_data {
+        Some(data) => data.len() as u64,
+        None => fs::metadata(file_path).map_or(0, |meta| meta.len()),
+    };
+    size.max(1)
+}
+
+/// Where the progress bars draw: nowhere with `--quiet` or `--no-progress`, or when stderr isn't a
+/// terminal (redirected to a file, or a CI log), where their control characters garble the output;
+/// `--force-progress` draws them anyway
+fn progress_target(args: &Config) -> indicatif::ProgressDrawTarget {
+    if args.quiet || args.no_progress {
+        indicatif::ProgressDrawTarget::hidden()
+    } else if args.force_progress {
+        indicatif::ProgressDrawTarget::term_like(Box::new(console::Term::buffered_stderr()))
+    } else if std::io::IsTerminal::is_terminal(&std::io::stderr()) {
+        indicatif::ProgressDrawTarget::stderr()
+    } else {
+        indicatif::ProgressDrawTarget::hidden()
+    }
+}
+
+/// Loads every file, several at once on the `--threads` pool, and reports how each one went.
+/// A file that fails is logged and recorded in its outcome; the others carry on unless `--fail-fast`.
+fn load_files(files: &[PathBuf], root: &Path, conn_mutex: &Arc<Mutex<Box<dyn Db>>>, mode: InsertMode, progress: &MultiProgress, args: &Config) -> Result<Vec<FileOutcome>> {
+    // The bar counts bytes rather than files, so one huge workbook among many small ones
+    // doesn't leave the ETA claiming the run is nearly done
+    let sizes: Vec<u64> = files.iter().map(|file_path| input_size(file_path, args)).collect();
+    let pb = progress.add(ProgressBar::new(sizes.iter().sum()));
+    pb.set_style(ProgressStyle::default_bar()
+        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {prefix} files, {bytes}/{total_bytes} ({eta}) {msg}")
+        .unwrap()
+        .progress_chars("#>-"));
+    let files_done = AtomicUsize::new(0);
+    pb.set_prefix(format!("0/{}", files.len()));
+
+    let any_failed = AtomicBool::new(false);
+    let outcomes = Mutex::new(Vec::new());
+
+    // With --parallel-writes every rayon job gets a connection of its own to the same database.
+    // Sheets still load one transaction each; they never share a table, so the transactions
+    // don't conflict. A connection that can't be opened falls back to the shared one.
+    let worker_db = || -> Option<Arc<Mutex<Box<dyn Db>>>> {
+        let db = conn_mutex.lock().unwrap();
+        let conn = db.duckdb().filter(|_| args.parallel_writes)?.try_clone().ok()?;
+        Some(Arc::new(Mutex::new(Box::new(DuckBackend(conn, mode)))))
+    };
+
+    let load_file = |worker: &mut Option<Arc<Mutex<Box<dyn Db>>>>, file_path: &PathBuf, size: u64| {
+        if (args.fail_fast && any_failed.load(Ordering::Relaxed)) || args.interrupted() {
+            args.write_turns.finish(file_path);
+            return;
+        }
+        let conn_mutex = worker.as_ref().unwrap_or(conn_mutex);
+        let filename = file_path.file_stem().unwrap().to_string_lossy();
+        let sanitized_filename = table_prefix(file_path, root, args);
+        
+        let start_read = Instant::now();
+        
+        // Read Excel file
+        let mut outcome = FileOutcome { file: file_path.display().to_string(), tables: 0, rows: 0, duration: Duration::ZERO, error: None, sheets: Vec::new() };
+        let result = process_excel_file(file_path, &sanitized_filename, conn_mutex, progress, args);
+        // A file that failed before writing anything still reports in its turn
+        args.write_turns.wait(file_path);
+        match result {
+            Ok(loaded) => {
+                let duration = start_read.elapsed();
+                let message = format!("Processed {} ({} sheets) in {:.2?}", filename, loaded.len(), duration);
+                // Without a bar to show it in, the message goes to the log
+                if pb.is_hidden() {
+                    info!("{}", message);
+                } else {
+                    pb.set_message(message);
+                }
+                outcome.tables = loaded.len();
+                outcome.rows = loaded.iter().map(|sheet| sheet.rows).sum();
+                outcome.sheets = loaded;
+            },
+            Err(e) => {
+                any_failed.store(true, Ordering::Relaxed);
+                pb.suspend(|| error!("Error processing {}: {:#}", filename, e));
+                outcome.error = Some(format!("{:#}", e));
+            }
+        }
+        outcome.duration = start_read.elapsed();
+        outcomes.lock().unwrap().push(outcome);
+        pb.set_prefix(format!("{}/{}", files_done.fetch_add(1, Ordering::Relaxed) + 1, files.len()));
+        pb.inc(size);
+        args.write_turns.finish(file_path);
+    };
+
+    // A pool of zero threads is rayon's default of one per core
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(args.threads.unwrap_or(0)).build()?;
+    if args.sorted {
+        let mut order: Vec<usize> = (0..files.len()).collect();
+        order.sort_by(|&a, &b| files[a].cmp(&files[b]));
+        args.write_turns.start(order.iter().map(|&i| files[i].clone()));
+        // Files are taken in order from a shared counter by threads of their own: a file waiting
+        // for its turn on a pool thread could hold up the --parallel-sheets jobs of the file before it
+        let next = AtomicUsize::new(0);
+        let workers = pool.current_num_threads().min(files.len());
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    let mut worker = worker_db();
+                    while let Some(&i) = order.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        pool.install(|| load_file(&mut worker, &files[i], sizes[i]));
+                    }
+                });
+            }
+        });
+    } else {
+        pool.install(|| files.par_iter().zip(&sizes).for_each_init(worker_db, |worker, (file_path, &size)| load_file(worker, file_path, size)));
+    }
+
+    pb.finish_with_message("Done!");
+    Ok(outcomes.into_inner().unwrap())
+}
+
+/// Splits a trailing `>> target` export suffix off a query. Only a `>>` followed by a
+/// single path-like word at the very end counts, so the bit-shift operator (`a >> 2`),
+/// JSON's `->>` and `>>` inside a string literal stay part of the SQL
+fn split_export_target(query_str: &str) -> (&str, Option<&str>) {
+    static EXPORT: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    let export = EXPORT.get_or_init(|| Regex::new(r"(?s)^(.*\S)\s*>>\s*([^\s'\x22;]+)\s*;?\s*$").unwrap());
+    if let Some(caps) = export.captures(query_str) {
+        let (query, target) = (caps.get(1).unwrap().as_str(), caps.get(2).unwrap().as_str());
+        let pathlike = target.contains(['.', '/', '\\']) && target.parse::<f64>().is_err();
+        if !query.ends_with('-') && pathlike {
+            return (query.trim(), Some(target));
+        }
+    }
+    (query_str, None)
+}
+
+/// Runs one `--query` (or REPL line): `query >> file.csv` exports the results, anything
+/// else prints them as a table
+fn run_query(conn: &Connection, query_str: &str, args: &Config) -> Result<()> {
+    let (query, output_file) = split_export_target(query_str);
+
+    let csv_output = CsvOutput::from_args(args);
+    if let (Some(path), Some(rows_per_part)) = (output_file, args.export_partition_rows) {
+        let order_by = args.export_order_by.as_deref()
+            .context("--export-partition-rows requires --export-order-by so partitions are stable across runs")?;
+        let written = export_partitioned(conn, query, path, rows_per_part, order_by, args.export_retries, &csv_output)?;
+        info!("Saved query results to {} partition file(s) next to {}", written, path);
+    } else if let Some(path) = output_file {
+        // Use DuckDB's COPY command for fast export
+        let (format, options) = export_copy_options(path, &csv_output, args)?;
+        let copy_sql = format!("COPY ({}) TO '{}' {}", query, path, options);
+        debug!("{}", copy_sql);
+        let exported = conn.execute(&copy_sql, []).map_err(anyhow::Error::from)
+            .and_then(|_| if format.is_delimited() { csv_output.apply(Path::new(path)) } else { Ok(()) });
+        match exported {
+            Ok(_) => info!("Saved query results to {}", path),
+            Err(e) => error!("Error exporting to {}: {}", path, e),
+        }
+    } else {
+        info!("Executing query: {}", query);
+        match args.format {
+            QueryFormat::Table => print_query(conn, query, args.limit)?,
+            format => {
+                let mut stdout = std::io::stdout().lock();
+                let count = write_query(conn, query, format, &mut stdout)?;
+                info!("Query returned {} rows.", count);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// One table `--export-all` wrote out
+struct TableExport {
+    table: String,
+    path: PathBuf,
+    rows: usize,
+    bytes: u64,
+    elapsed: Duration,
+}
+
+/// Copies every table of the current schema to `dir/<table>.<ext>` (`--export-all`), leaving out
+/// the loader's bookkeeping tables unless `--export-internal`. `--export-jobs` tables are copied
+/// at once, each job over a connection of its own; the exports come back in table-name order.
+fn export_all_tables(conn: &Connection, dir: &str, args: &Config) -> Result<Vec<TableExport>> {
+    fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir))?;
+    let tables: Vec<String> = conn
+        .prepare("SELECT table_name FROM information_schema.tables WHERE table_schema = current_schema() AND table_type = 'BASE TABLE' ORDER BY table_name")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?
+        .into_iter()
+        .filter(|table| args.export_internal || !is_internal_table(table))
+        .collect();
+    let format = args.export_format;
+    let csv_output = CsvOutput::from_args(args);
+    let export = |conn: &Connection, table: &str| -> Result<TableExport> {
+        let started = Instant::now();
+        let path = Path::new(dir).join(format!("{}.{}", table, format.extension()));
+        let copy_sql = format!(
+            "COPY {} TO '{}' {}",
+            quote_identifier(table), path.to_string_lossy().replace('\'', "''"), format.copy_options(&csv_output)
+        );
+        debug!("{}", copy_sql);
+        let rows = conn.execute(&copy_sql, []).with_context(|| format!("Cannot export {} to {}", table, path.display()))?;
+        if format.is_delimited() {
+            csv_output.apply(&path)?;
+        }
+        let bytes = fs::metadata(&path)?.len();
+        Ok(TableExport { table: table.to_string(), path, rows, bytes, elapsed: started.elapsed() })
+    };
+
+    let connections = (0..(args.export_jobs as usize).min(tables.len())).map(|_| conn.try_clone()).collect::<Result<Vec<_>, _>>()?;
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(tables.len()));
+    std::thread::scope(|scope| {
+        for conn in connections {
+            let (tables, next, results, export) = (&tables, &next, &results, &export);
+            scope.spawn(move || {
+                while let Some(table) = tables.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = export(&conn, table);
+                    results.lock().unwrap().push((table, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(table, _)| *table);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Writes every row of a query's results as JSON or CSV (`--format`), returning the row count
+fn write_query(conn: &Connection, query: &str, format: QueryFormat, out: &mut impl std::io::Write) -> Result<usize> {
+    let (query, decimal_text) = renderable_query(conn, query);
+    let mut stmt = conn.prepare(&query)?;
+    let mut rows = stmt.query([])?;
+    let names = rows.as_ref().map(|stmt| stmt.column_names()).unwrap_or_default();
+    let csv_field = |text: &str| if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    };
+
+    let mut count = 0;
+    match format {
+        QueryFormat::Json => write!(out, "[")?,
+        _ => writeln!(out, "{}", names.iter().map(|name| csv_field(name)).collect::<Vec<_>>().join(","))?,
+    }
+    while let Some(row) = rows.next()? {
+        let cells = (0..names.len()).map(|i| row.get_ref(i)).collect::<Result<Vec<_>, _>>()?;
+        match format {
+            QueryFormat::Json => {
+                // Built by hand so the keys keep the query's column order
+                let fields: Vec<String> = names.iter().zip(cells).enumerate()
+                    .map(|(i, (name, value))| {
+                        let value = match value {
+                            ValueRef::Text(text) if decimal_text.get(i) == Some(&true) => decimal_json(&String::from_utf8_lossy(text)),
+                            value => json_value(value),
+                        };
+                        format!("{}: {}", serde_json::Value::from(name.as_str()), value)
+                    })
+                    .collect();
+                write!(out, "{}\n  {{{}}}", if count == 0 { "" } else { "," }, fields.join(", "))?;
+            }
+            _ => {
+                let fields: Vec<String> = cells.into_iter()
+                    .map(|value| if value == ValueRef::Null { String::new() } else { csv_field(&format_value(value)) })
+                    .collect();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+        }
+        count += 1;
+    }
+    if format == QueryFormat::Json {
+        writeln!(out, "{}]", if count == 0 { "" } else { "\n" })?;
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+/// A result cell as JSON for `--format json`: numbers and booleans unquoted, NULL as null,
+/// and everything else as the text the table view shows
+fn json_value(value: ValueRef) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Boolean(v) => v.into(),
+        ValueRef::TinyInt(v) => v.into(),
+        ValueRef::SmallInt(v) => v.into(),
+        ValueRef::Int(v) => v.into(),
+        ValueRef::BigInt(v) => v.into(),
+        ValueRef::UTinyInt(v) => v.into(),
+        ValueRef::USmallInt(v) => v.into(),
+        ValueRef::UInt(v) => v.into(),
+        ValueRef::UBigInt(v) => v.into(),
+        // NaN and infinity have no JSON form and come out as null
+        ValueRef::Float(v) => v.into(),
+        ValueRef::Double(v) => v.into(),
+        ValueRef::Decimal(v) => decimal_json(&v.to_string()),
+        // HUGEINT can be wider than a JSON parser's integers, so it stays exact as text
+        other => format_value(other).into(),
+    }
+}
+
+/// Whether the duckdb crate can hand a column of this type over as a `ValueRef`; DECIMALs with
+/// a scale and more than 28 digits overflow its decimal type, so they count as unsupported
+fn renders_natively(column_type: &str) -> bool {
+    const NATIVE: [&str; 22] = [
+        "BOOLEAN", "TINYINT", "SMALLINT", "INTEGER", "BIGINT", "HUGEINT", "UTINYINT", "USMALLINT", "UINTEGER",
+        "UBIGINT", "FLOAT", "DOUBLE", "VARCHAR", "BLOB", "UUID", "DATE", "TIME", "TIMESTAMP", "TIMESTAMP_S",
+        "TIMESTAMP_MS", "TIMESTAMP_NS", "TIMESTAMP WITH TIME ZONE",
+    ];
+    if let Some(spec) = column_type.strip_prefix("DECIMAL(").and_then(|spec| spec.strip_suffix(')')) {
+        let mut parts = spec.split(',').map(|part| part.trim().parse::<u32>().unwrap_or(0));
+        let (precision, scale) = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+        return precision <= 28 || scale == 0;
+    }
+    NATIVE.contains(&column_type)
+}
+
+/// The query results are read from: columns of a type the duckdb crate can't hand over
+/// (lists, structs, maps, intervals, enums, wide DECIMALs) come back cast to the text DuckDB
+/// writes for them rather than panicking mid-row. Also says, per column, which ones are
+/// DECIMALs read as text, so `--format json` can still write them as numbers. Statements
+/// DuckDB can't describe, like DDL, run as they are.
+fn renderable_query(conn: &Connection, query: &str) -> (String, Vec<bool>) {
+    let query = query.trim().trim_end_matches(';').trim_end();
+    let columns: Vec<(String, String)> = match conn.prepare(&format!("DESCRIBE {}", query))
+        .and_then(|mut stmt| stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect())
+    {
+        Ok(columns) => columns,
+        Err(_) => return (query.to_string(), Vec::new()),
+    };
+    let decimal_text: Vec<bool> = columns.iter()
+        .map(|(_, column_type)| column_type.starts_with("DECIMAL(") && !renders_natively(column_type))
+        .collect();
+    if columns.iter().all(|(_, column_type)| renders_natively(column_type)) {
+        return (query.to_string(), decimal_text);
+    }
+    // Columns are renamed by position first, so duplicate or odd names can't clash
+    let select: Vec<String> = columns.iter().enumerate()
+        .map(|(i, (name, column_type))| {
+            let alias = quote_identifier(name);
+            if renders_natively(column_type) { format!("c{} AS {}", i, alias) } else { format!("CAST(c{} AS VARCHAR) AS {}", i, alias) }
+        })
+        .collect();
+    let positions: Vec<String> = (0..columns.len()).map(|i| format!("c{}", i)).collect();
+    (format!("SELECT {} FROM ({}\n) AS q({})", select.join(", "), query, positions.join(", ")), decimal_text)
+}
+
+/// A DECIMAL as a JSON number when a double holds it exactly (15 significant digits or fewer),
+/// and as its exact text otherwise, so a large SUM doesn't pick up float error
+fn decimal_json(text: &str) -> serde_json::Value {
+    let digits = text.trim_start_matches('-').trim_start_matches(['0', '.']).chars().filter(char::is_ascii_digit).count();
+    match text.parse::<f64>() {
+        Ok(v) if digits <= 15 => v.into(),
+        _ => text.into(),
+    }
+}
+
+/// Prints a query's results to stdout as a table, up to `limit` rows
+fn print_query(conn: &Connection, query: &str, limit: usize) -> Result<()> {
+    write_table(conn, query, limit, &mut std::io::stdout().lock())
+}
+
+/// Writes a query's results as a table, up to `limit` rows, followed by the row count
+fn write_table(conn: &Connection, query: &str, limit: usize, out: &mut impl std::io::Write) -> Result<()> {
+    let (query, _) = renderable_query(conn, query);
+    let mut stmt = conn.prepare(&query)?;
+    let mut rows = stmt.query([])?;
+    let names = rows.as_ref().map(|stmt| stmt.column_names()).unwrap_or_default();
+
+    let mut table = comfy_table::Table::new();
+    table.load_preset(comfy_table::presets::UTF8_FULL_CONDENSED).set_header(&names);
+    let mut count = 0;
+    while let Some(row) = rows.next()? {
+        if count < limit {
+            let cells = (0..names.len()).map(|i| row.get_ref(i).map(format_value)).collect::<Result<Vec<_>, _>>()?;
+            table.add_row(cells);
+        }
+        count += 1;
+    }
+    writeln!(out, "{}", table)?;
+    if count > limit {
+        writeln!(out, "Showing {} of {} rows (raise --limit to see more).", limit, count)?;
+    } else {
+        writeln!(out, "Query returned {} rows.", count)?;
+    }
+    Ok(())
+}
+
+/// Writes what the database holds (`--show-schema`): every table with its column and row counts,
+/// or, given a table (`name` or `schema.name`, case-insensitive), its columns and types
+fn show_schema(conn: &Connection, table: Option<&str>, out: &mut impl std::io::Write) -> Result<()> {
+    let literal = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    let tables: Vec<(String, String)> = conn
+        .prepare("SELECT table_schema, table_name FROM information_schema.tables WHERE table_type = 'BASE TABLE' ORDER BY table_schema, table_name")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    let Some(table) = table else {
+        if tables.is_empty() {
+            writeln!(out, "The database holds no tables.")?;
+            return Ok(());
+        }
+        let counts: Vec<String> = tables.iter()
+            .map(|(schema, name)| format!(
+                "SELECT {0} AS table_schema, {1} AS table_name, \
+                 (SELECT count(*) FROM information_schema.columns WHERE table_schema = {0} AND table_name = {1}) AS columns, \
+                 (SELECT count(*) FROM {2}.{3}) AS rows",
+                literal(schema), literal(name), quote_identifier(schema), quote_identifier(name)
+            ))
+            .collect();
+        return write_table(conn, &format!("{} ORDER BY table_schema, table_name", counts.join(" UNION ALL ")), usize::MAX, out);
+    };
+    // A bare name is looked up in the current schema (main, or --schema-name) before any other
+    let current: String = conn.query_row("SELECT current_schema()", [], |row| row.get(0))?;
+    let (schema, name) = tables.iter()
+        .filter(|(schema, name)| format!("{}.{}", schema, name).eq_ignore_ascii_case(table) || name.eq_ignore_ascii_case(table))
+        .min_by_key(|(schema, _)| *schema != current)
+        .with_context(|| format!("No table named {}; --show-schema without a name lists the tables", table))?;
+    write_table(
+        conn,
+        &format!(
+            "SELECT column_name, data_type, is_nullable FROM information_schema.columns WHERE table_schema = {} AND table_name = {} ORDER BY ordinal_position",
+            literal(schema), literal(name)
+        ),
+        usize::MAX,
+        out,
+    )
+}
+
+/// Reads SQL from the terminal until `.exit` or end of input. Lines run like `--query`, so
+/// `>> file.csv` exports; `.tables` and `.schema [table]` describe the database. A failing
+/// line prints its error and the prompt comes back.
+fn run_repl(conn: &Connection, args: &Config) -> Result<()> {
+    let mut editor = rustyline::DefaultEditor::new()?;
+    println!("Enter SQL, .tables, .schema [table] or .exit");
+    loop {
+        let line = match editor.readline("sql> ") {
+            Ok(line) => line,
+            Err(rustyline::error::ReadlineError::Interrupted) => continue,
+            Err(rustyline::error::ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let line = line.trim().trim_end_matches(';').trim();
+        if line.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line);
+
+        let result = match line.split_once(char::is_whitespace).unwrap_or((line, "")) {
+            (".exit" | ".quit", _) => break,
+            (".tables", _) => print_query(
+                conn,
+                "SELECT table_schema, table_name FROM information_schema.tables ORDER BY table_schema, table_name",
+                usize::MAX,
+            ),
+            (".schema", table) => {
+                let filter = match table.trim() {
+                    "" => String::new(),
+                    table => format!("WHERE table_name ILIKE '{}'", table.replace('\'', "''")),
+                };
+                print_query(
+                    conn,
+                    &format!("SELECT table_name, column_name, data_type FROM information_schema.columns {} ORDER BY table_name, ordinal_position", filter),
+                    usize::MAX,
+                )
+            }
+            (meta, _) if meta.starts_with('.') => Err(anyhow::anyhow!("Unknown command {}; try .tables, .schema [table] or .exit", meta)),
+            _ => run_query(conn, line, args),
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {:#}", e);
+        }
+    }
+    Ok(())
+}
+
+/// One line totalling a run: files, tables, rows and rows per second over the wall-clock time
+fn load_summary(outcomes: &[FileOutcome], elapsed: Duration) -> String {
+    let loaded = outcomes.iter().filter(|outcome| outcome.error.is_none()).count();
+    let tables: usize = outcomes.iter().map(|outcome| outcome.tables).sum();
+    let rows: usize = outcomes.iter().map(|outcome| outcome.rows).sum();
+    let secs = elapsed.as_secs_f64();
+    let throughput = if secs > 0.0 { format!("{:.0} rows/s", rows as f64 / secs) } else { "n/a rows/s".to_string() };
+    format!("Loaded {} row(s) into {} table(s) from {} of {} file(s) in {:.2?} ({})", rows, tables, loaded, outcomes.len(), elapsed, throughput)
+}
+
+/// `--type-summary`: how many loaded columns got each type, most common first, then the columns
+/// that fell back to VARCHAR because their sampled values mixed types
+fn type_summary(outcomes: &[FileOutcome]) -> String {
+    let sheets: Vec<&LoadedSheet> = outcomes.iter().flat_map(|outcome| &outcome.sheets).collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for (_, dtype) in sheets.iter().flat_map(|sheet| &sheet.columns) {
+        *counts.entry(dtype.as_str()).or_default() += 1;
+    }
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let mut rendered = comfy_table::Table::new();
+    rendered.load_preset(comfy_table::presets::UTF8_FULL_CONDENSED).set_header(["type", "columns"]);
+    for (dtype, n) in &counts {
+        rendered.add_row([dtype.to_string(), n.to_string()]);
+    }
+    let mixed: Vec<String> = sheets.iter()
+        .flat_map(|sheet| sheet.mixed_columns.iter().map(move |column| format!("{}.{}", sheet.table, column)))
+        .collect();
+    let mut summary = format!("Column types across {} table(s):\n{}\nFell back to VARCHAR due to mixed types: {}", sheets.len(), rendered, mixed.len());
+    if !mixed.is_empty() {
+        summary.push_str(&format!(" ({})", mixed.join(", ")));
+    }
+    summary
+}
+
+/// Prints a table of the files that failed and why, so errors that flashed by in the progress
+/// bar aren't lost. Returns how many failed.
+fn report_failures(outcomes: &[FileOutcome], total: usize, fail_fast: bool) -> usize {
+    let failures: Vec<(&str, &str)> = outcomes.iter()
+        .filter_map(|outcome| Some((outcome.file.as_str(), outcome.error.as_deref()?)))
+        .collect();
+    if failures.is_empty() {
+        return 0;
+    }
+    let mut table = comfy_table::Table::new();
+    table.load_preset(comfy_table::presets::UTF8_FULL_CONDENSED).set_header(["File", "Error"]);
+    for (file, error) in &failures {
+        table.add_row([file, error]);
+    }
+    eprintln!("{} of {} file(s) failed:\n{}", failures.len(), total, table);
+    if fail_fast && outcomes.len() < total {
+        eprintln!("Stopped after the first failure (--fail-fast); {} file(s) were not attempted", total - outcomes.len());
+    }
+    failures.len()
+}
+
+/// How a run with `failed` of `total` files failing (and `loaded` loading) ends: with an error,
+/// so the exit code is non-zero, unless `--ignore-errors` lets it pass
+fn failure_result(failed: usize, loaded: usize, total: usize, ignore_errors: bool) -> Result<()> {
+    match failed {
+        0 => Ok(()),
+        n if ignore_errors => {
+            warn!("{} of {} file(s) failed to load; exiting successfully (--ignore-errors)", n, total);
+            Ok(())
+        }
+        failed => Err(LoadFailure { failed, loaded, total }.into()),
+    }
+}
+
+/// Picks the rows used for type inference. Random samples keep sheet order so
+/// the sample reads the same way the data does.
+fn sample_rows<'a>(rows: &[&'a [Data]], n: usize, from: SampleFrom) -> Vec<&'a [Data]> {
+    if rows.len() <= n {
+        return rows.to_vec();
+    }
+    match from {
+        SampleFrom::Head => rows[..n].to_vec(),
+        SampleFrom::Tail => rows[rows.len() - n..].to_vec(),
+        SampleFrom::Random => {
+            let mut picked = rand::seq::index::sample(&mut rand::thread_rng(), rows.len(), n).into_vec();
+            picked.sort_unstable();
+            picked.into_iter().map(|i| rows[i]).collect()
+        }
+    }
+}
+
+/// The data rows of a sheet that get loaded: all of them, or the `--load-rows` sample. The first
+/// N rows are taken without walking the rest of the sheet. A `--load-random` sample also comes
+/// with each row's index among the sheet's data rows, so messages can name its sheet row.
+fn sheet_data_rows<'a>(rows: impl Iterator<Item = &'a [Data]>, args: &Config) -> (Vec<&'a [Data]>, Option<Vec<usize>>) {
+    match args.load_rows {
+        Some(n) if args.load_random => {
+            let rows: Vec<&[Data]> = rows.collect();
+            if rows.len() <= n {
+                return (rows, None);
+            }
+            let mut picked = rand::seq::index::sample(&mut rand::thread_rng(), rows.len(), n).into_vec();
+            picked.sort_unstable();
+            (picked.iter().map(|&i| rows[i]).collect(), Some(picked))
+        }
+        Some(n) => (rows.take(n).collect(), None),
+        None => (rows.collect(), None),
+    }
+}
+
+/// Infers the DuckDB type of column `col` from the sampled rows, picking the
+/// widest type that fits every non-empty value. Empty and error cells are ignored.
+/// With a number locale, text that parses as a number counts as numeric.
+fn infer_column_type(sample: &[&[Data]], col: usize, locale: Option<NumberLocale>) -> &'static str {
+    let mut inferred: Option<&'static str> = None;
+    for row in sample {
+        let Some(cell_type) = cell_type(row.get(col), locale) else { continue };
+        inferred = Some(match (inferred, cell_type) {
+            (None, t) => t,
+            (Some(a), b) if a == b => a,
+            (Some("BIGINT"), "DOUBLE") | (Some("DOUBLE"), "BIGINT") => "DOUBLE",
+            // A double can't hold them exactly, so huge whole numbers only widen with other whole ones
+            (Some("BIGINT"), "HUGEINT") | (Some("HUGEINT"), "BIGINT") => "HUGEINT",
+            (Some("DATE"), "TIMESTAMP") | (Some("TIMESTAMP"), "DATE") => "TIMESTAMP",
+            _ => return "VARCHAR",
+        });
+    }
+    inferred.unwrap_or("VARCHAR")
+}
+
+/// The type one cell on its own would give its column; None for blanks and errors
+fn cell_type(cell: Option<&Data>, locale: Option<NumberLocale>) -> Option<&'static str> {
+    Some(match cell {
+        Some(Data::String(text)) => match locale.and_then(|l| parse_locale_number(text, l)) {
+            Some(LocaleNumber::Int(_)) => "BIGINT",
+            Some(LocaleNumber::Huge(_)) => "HUGEINT",
+            Some(LocaleNumber::Float(_)) => "DOUBLE",
+            None => "VARCHAR",
+        },
+        Some(Data::Int(_)) => "BIGINT",
+        Some(Data::Float(_)) => "DOUBLE",
+        Some(Data::Bool(_)) => "BOOLEAN",
+        // Date-only cells are whole serials; any time of day makes the column a TIMESTAMP
+        Some(Data::DateTime(v)) if v.as_f64().fract() == 0.0 => "DATE",
+        Some(Data::DateTime(_)) => "TIMESTAMP",
+        // ODS stores dates as ISO text; durations stay text since INTERVAL results can't be read back
+        Some(Data::DateTimeIso(v)) => match parse_iso_datetime(v) {
+            Some(dt) if dt.time() == chrono::NaiveTime::MIN => "DATE",
+            Some(_) => "TIMESTAMP",
+            None => "VARCHAR",
+        },
+        Some(Data::DurationIso(_)) => "VARCHAR",
+        Some(Data::Empty) | Some(Data::Error(_)) | None => return None,
+    })
+}
+
+/// Whether a column inferred as VARCHAR only because its sampled values don't agree on a type,
+/// rather than for holding nothing but text
+fn falls_back_to_text(sample: &[&[Data]], col: usize, locale: Option<NumberLocale>) -> bool {
+    infer_column_type(sample, col, locale) == "VARCHAR"
+        && sample.iter().any(|row| cell_type(row.get(col), locale).is_some_and(|t| t != "VARCHAR"))
+}
+
+/// Picks the type most sampled values of a column share (`--type-strategy majority`). Values are
+/// counted by kind, so whole numbers side with fractions and dates with timestamps, and the
+/// winning kind takes its widest type. A tie, or a column of mostly text, infers as usual.
+fn majority_column_type(sample: &[&[Data]], col: usize, locale: Option<NumberLocale>) -> (&'static str, usize, usize) {
+    let mut counts: HashMap<&'static str, usize> = HashMap::new();
+    for row in sample {
+        let cell = match row.get(col) {
+            Some(Data::Empty) | Some(Data::Error(_)) | None => continue,
+            Some(cell) => cell,
+        };
+        let cell_type = infer_column_type(&[std::slice::from_ref(cell)], 0, locale);
+        *counts.entry(cell_type).or_default() += 1;
+    }
+    let count = |t: &str| counts.get(t).copied().unwrap_or(0);
+    let total: usize = counts.values().sum();
+    let kinds = [
+        (
+            if count("DOUBLE") > 0 { "DOUBLE" } else if count("HUGEINT") > 0 { "HUGEINT" } else { "BIGINT" },
+            count("BIGINT") + count("HUGEINT") + count("DOUBLE"),
+        ),
+        (if count("TIMESTAMP") > 0 { "TIMESTAMP" } else { "DATE" }, count("DATE") + count("TIMESTAMP")),
+        ("BOOLEAN", count("BOOLEAN")),
+        ("VARCHAR", count("VARCHAR")),
+    ];
+    let best = kinds.iter().map(|(_, n)| *n).max().unwrap_or(0);
+    match kinds.iter().filter(|(_, n)| *n == best).collect::<Vec<_>>()[..] {
+        [(dtype, n)] if *dtype != "VARCHAR" => (dtype, *n, total),
+        _ => (infer_column_type(sample, col, locale), total, total),
+    }
+}
+
+/// Explains in words why `infer_column_type` picks its type for one column (`--explain-types`)
+fn explain_column_type(sample: &[&[Data]], col: usize, locale: Option<NumberLocale>) -> String {
+    let chosen = infer_column_type(sample, col, locale);
+    let (mut whole, mut fractional, mut text, mut booleans, mut dates, mut datetimes, mut empty) = (0, 0, 0, 0, 0, 0, 0);
+    let mut seen: Option<&'static str> = None;
+    let mut conflict = None;
+    for (row_index, row) in sample.iter().enumerate() {
+        let cell_type = match row.get(col) {
+            Some(Data::String(value)) => match locale.and_then(|l| parse_locale_number(value, l)) {
+                Some(LocaleNumber::Int(_)) => { whole += 1; "BIGINT" }
+                Some(LocaleNumber::Huge(_)) => { whole += 1; "HUGEINT" }
+                Some(LocaleNumber::Float(_)) => { fractional += 1; "DOUBLE" }
+                None => { text += 1; "VARCHAR" }
+            },
+            Some(Data::Int(_)) => { whole += 1; "BIGINT" }
+            Some(Data::Float(v)) if v.fract() == 0.0 => { whole += 1; "DOUBLE" }
+            Some(Data::Float(_)) => { fractional += 1; "DOUBLE" }
+            Some(Data::Bool(_)) => { booleans += 1; "BOOLEAN" }
+            Some(Data::DateTime(v)) if v.as_f64().fract() == 0.0 => { dates += 1; "DATE" }
+            Some(Data::DateTime(_)) => { datetimes += 1; "TIMESTAMP" }
+            Some(Data::DateTimeIso(v)) => match parse_iso_datetime(v) {
+                Some(dt) if dt.time() == chrono::NaiveTime::MIN => { dates += 1; "DATE" }
+                Some(_) => { datetimes += 1; "TIMESTAMP" }
+                None => { text += 1; "VARCHAR" }
+            },
+            Some(Data::Empty) | Some(Data::Error(_)) | None => { empty += 1; continue; }
+            Some(_) => { text += 1; "VARCHAR" }
+        };
+        match seen {
+            None => seen = Some(cell_type),
+            Some(previous) if previous != cell_type && conflict.is_none() => {
+                let numeric = |t| t == "BIGINT" || t == "HUGEINT" || t == "DOUBLE";
+                let temporal = |t| t == "DATE" || t == "TIMESTAMP";
+                let widens = (numeric(previous) && numeric(cell_type)) || (temporal(previous) && temporal(cell_type));
+                if !widens {
+                    conflict = Some((previous, cell_type, row_index + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let counts: Vec<String> = [
+        (whole, "whole number"), (fractional, "fractional value"), (text, "text value"),
+        (booleans, "boolean"), (dates, "date"), (datetimes, "date-time"), (empty, "empty cell"),
+    ]
+        .iter()
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, what)| format!("{} {}{}", n, what, if *n == 1 { "" } else { "s" }))
+        .collect();
+    let mut reason = format!("{}: sampled {} rows", chosen, sample.len());
+    if !counts.is_empty() {
+        reason.push_str(&format!(" ({})", counts.join(", ")));
+    }
+    if let Some((a, b, row)) = conflict {
+        reason.push_str(&format!(", conflict between {} and {} at sampled row {}", a, b, row));
+    } else if chosen == "VARCHAR" && text == 0 {
+        reason.push_str(", no typed values to go on");
+    }
+    if let Some(locale) = locale {
+        reason.push_str(&format!(", text numbers read as {:?}", locale));
+    }
+    reason
+}
+
+/// Integer value of a cell in an integer-typed column, if it has one
+fn cell_integer(cell: Option<&Data>, locale: Option<NumberLocale>) -> Option<i64> {
+    match cell {
+        Some(Data::Int(v)) => Some(*v),
+        // `as` would saturate a whole float past i64 into i64::MAX
+        Some(Data::Float(v)) if v.fract() == 0.0 && (i64::MIN as f64..i64::MAX as f64).contains(v) => Some(*v as i64),
+        Some(Data::String(text)) => match locale.and_then(|l| parse_locale_number(text, l)) {
+            Some(LocaleNumber::Int(v)) => Some(v),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Describes how a cell would lose precision when stored in a column of `dtype`, if it would
+fn truncation(cell: &Data, locale: Option<NumberLocale>, dtype: &str) -> Option<String> {
+    let value = match cell {
+        Data::Int(v) => LocaleNumber::Int(*v),
+        Data::Float(v) => LocaleNumber::Float(*v),
+        Data::String(text) => locale.and_then(|l| parse_locale_number(text, l))?,
+        _ => return None,
+    };
+    match (value, dtype) {
+        (LocaleNumber::Float(v), "TINYINT" | "SMALLINT" | "INTEGER" | "BIGINT") if v.fract() != 0.0 => {
+            Some(format!("{} would be rounded to a whole {}", v, dtype))
+        }
+        (LocaleNumber::Int(v), "DOUBLE") if v.unsigned_abs() > 1 << f64::MANTISSA_DIGITS => {
+            Some(format!("{} would be stored as {}", v, v as f64))
+        }
+        (LocaleNumber::Huge(v), "DOUBLE") => Some(format!("{} would be stored as {}", v, v as f64)),
+        (LocaleNumber::Float(v), _) => {
+            let scale = decimal_scale(dtype)?;
+            let factor = 10f64.powi(scale);
+            let rounded = (v * factor).round() / factor;
+            (rounded != v).then(|| format!("{} would be rounded to {} decimal place(s) in {}", v, scale, dtype))
+        }
+        _ => None,
+    }
+}
+
+/// A double as exact decimal text with `scale` places, rounded half away from zero. It starts
+/// from the shortest text that reads back as the double, the way a spreadsheet shows it, so
+/// 1.005 rounds to 1.01 and 0.1 + 0.2 gives 0.30 rather than carrying float error along.
+fn decimal_text(v: f64, scale: i32) -> String {
+    let scale = scale.max(0) as usize;
+    // Display never uses an exponent, however large or small the value
+    let text = v.abs().to_string();
+    let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+    let mut digits: Vec<u8> = format!("{}{:0<scale$}", whole, &fraction[..fraction.len().min(scale)]).into_bytes();
+    if fraction.as_bytes().get(scale).is_some_and(|digit| *digit >= b'5') {
+        // Rounding up carries through any trailing nines
+        let carried = digits.iter().rposition(|digit| *digit != b'9');
+        for digit in &mut digits[carried.map_or(0, |i| i + 1)..] {
+            *digit = b'0';
+        }
+        match carried {
+            Some(i) => digits[i] += 1,
+            None => digits.insert(0, b'1'),
+        }
+    }
+    let sign = if v < 0.0 && digits.iter().any(|digit| *digit != b'0') { "-" } else { "" };
+    let (whole, fraction) = digits.split_at(digits.len() - scale);
+    let (whole, fraction) = (String::from_utf8_lossy(whole), String::from_utf8_lossy(fraction));
+    if scale == 0 { format!("{}{}", sign, whole) } else { format!("{}{}.{}", sign, whole, fraction) }
+}
+
+/// The value bound for one cell going into a column of `dtype`
+fn cell_value(cell: &Data, dtype: &str, locale: Option<NumberLocale>) -> Value {
+    match cell {
+        // NaN and infinities have no place in a spreadsheet column, so they load as NULL
+        Data::Float(v) if !v.is_finite() => Value::Null,
+        Data::Float(v) if dtype == "BOOLEAN" => Value::Boolean(*v != 0.0),
+        Data::String(v) if dtype == "BOOLEAN" && parse_flag(v).is_some() => Value::Boolean(parse_flag(v).unwrap_or_default()),
+        // Numbers in a text column read as they do in the sheet: 1001, not 1001.0
+        Data::Int(_) | Data::Float(_) if dtype == "VARCHAR" => cell_text(cell).map_or(Value::Null, Value::Text),
+        Data::Int(v) => Value::BigInt(*v),
+        // Bound as a double, 1.005 would land on the binary value below it and round down
+        Data::Float(v) if dtype.starts_with("DECIMAL(") => Value::Text(decimal_text(*v, decimal_scale(dtype).unwrap_or(0))),
+        Data::Float(v) => Value::Double(*v),
+        Data::String(v) => match locale.and_then(|l| parse_locale_number(v, l)) {
+            Some(LocaleNumber::Int(n)) if dtype != "VARCHAR" => Value::BigInt(n),
+            Some(LocaleNumber::Huge(n)) if dtype != "VARCHAR" => Value::HugeInt(n),
+            Some(LocaleNumber::Float(n)) if dtype.starts_with("DECIMAL(") => Value::Text(decimal_text(n, decimal_scale(dtype).unwrap_or(0))),
+            Some(LocaleNumber::Float(n)) if dtype != "VARCHAR" => Value::Double(n),
+            _ => Value::Text(v.clone()),
+        },
+        Data::Bool(v) => Value::Boolean(*v),
+        // DuckDB can't bind dates directly, so DATE columns get ISO text that INSERT casts
+        Data::DateTime(v) => match v.as_datetime() {
+            Some(dt) if dtype == "DATE" => Value::Text(dt.date().to_string()),
+            Some(dt) => Value::Timestamp(TimeUnit::Microsecond, dt.and_utc().timestamp_micros()),
+            None => Value::Null,
+        },
+        Data::DateTimeIso(v) => match parse_iso_datetime(v) {
+            Some(dt) if dtype == "DATE" => Value::Text(dt.date().to_string()),
+            Some(dt) if dtype == "TIMESTAMP" => Value::Timestamp(TimeUnit::Microsecond, dt.and_utc().timestamp_micros()),
+            _ => Value::Text(v.clone()),
+        },
+        Data::DurationIso(v) => Value::Text(iso_duration_interval(v).unwrap_or_else(|| v.clone())),
+        Data::Error(_) | Data::Empty => Value::Null,
+    }
+}
+
+/// The date and time of an ISO 8601 date (`2024-03-01`) or date-time (`2024-03-01T08:30:00`),
+/// the forms ODS files store date cells in
+fn parse_iso_datetime(text: &str) -> Option<chrono::NaiveDateTime> {
+    let text = text.trim();
+    chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f").ok()
+        .or_else(|| chrono::NaiveDate::parse_from_str(text, "%Y-%m-%d").ok().map(|date| date.and_time(chrono::NaiveTime::MIN)))
+}
+
+/// The zone `--timezone` reads naive Excel date-times in: UTC, a fixed offset such as `+05:30`,
+/// or a named zone such as `Europe/Berlin` from the system tz database (`/usr/share/zoneinfo`,
+/// or `$TZDIR`)
+#[derive(Clone, Debug)]
+pub struct TimeZone {
+    name: String,
+    /// UTC instants (seconds) at which the offset changes, each with the offset from then on
+    transitions: Vec<(i64, i32)>,
+    /// Offset (seconds east of UTC) before the first transition
+    initial: i32,
+    /// Offsets after the last transition, from the tz file's POSIX rule
+    rule: Option<PosixRule>,
+}
+
+/// A POSIX TZ rule such as `CET-1CEST,M3.5.0,M10.5.0/3`: standard time, with daylight saving
+/// time between two yearly `Mm.w.d/time` dates
+#[derive(Clone, Debug, PartialEq)]
+struct PosixRule {
+    std: i32,
+    dst: Option<(i32, RuleDate, RuleDate)>,
+}
+
+/// The `d`th weekday (0 is Sunday) of week `w` (5 is the last) of month `m`, at `time` seconds
+/// past local midnight
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct RuleDate {
+    month: u32,
+    week: u32,
+    weekday: u32,
+    time: i64,
+}
+
+impl RuleDate {
+    /// The UTC instant this date falls on in `year`, in local time at `offset`
+    fn instant(&self, year: i32, offset: i32) -> Option<i64> {
+        use chrono::Datelike;
+        let first = chrono::NaiveDate::from_ymd_opt(year, self.month, 1)?;
+        let mut day = 1 + (self.weekday + 7 - first.weekday().num_days_from_sunday()) % 7 + (self.week - 1) * 7;
+        while chrono::NaiveDate::from_ymd_opt(year, self.month, day).is_none() {
+            day -= 7;
+        }
+        let midnight = chrono::NaiveDate::from_ymd_opt(year, self.month, day)?.and_time(chrono::NaiveTime::MIN);
+        Some(midnight.and_utc().timestamp() + self.time - offset as i64)
+    }
+}
+
+impl PosixRule {
+    fn offset_at(&self, utc: i64) -> i32 {
+        let Some((dst, start, end)) = self.dst else { return self.std };
+        let year = chrono::DateTime::from_timestamp(utc + self.std as i64, 0).map_or(1970, |dt| chrono::Datelike::year(&dt));
+        let (Some(start), Some(end)) = (start.instant(year, self.std), end.instant(year, dst)) else { return self.std };
+        // Southern-hemisphere rules start daylight saving time late in the year and end it early
+        let in_dst = if start < end { (start..end).contains(&utc) } else { !(end..start).contains(&utc) };
+        if in_dst { dst } else { self.std }
+    }
+}
+
+impl TimeZone {
+    fn fixed(name: &str, offset: i32) -> Self {
+        TimeZone { name: name.to_string(), transitions: Vec::new(), initial: offset, rule: None }
+    }
+
+    /// The offset in force at a UTC instant
+    fn offset_at(&self, utc: i64) -> i32 {
+        let passed = self.transitions.partition_point(|&(at, _)| at <= utc);
+        match &self.rule {
+            Some(rule) if passed == self.transitions.len() => rule.offset_at(utc),
+            _ if passed == 0 => self.initial,
+            _ => self.transitions[passed - 1].1,
+        }
+    }
+
+    /// The UTC instant (in microseconds) of a local time, given as microseconds since the epoch
+    /// as if it were UTC. A time that occurs twice when the clocks go back is taken at its earlier
+    /// instant, and a time skipped when they go forward moves forward by the gap (02:30 becomes 03:30).
+    fn to_utc_micros(&self, local: i64) -> i64 {
+        let seconds = local.div_euclid(1_000_000);
+        let (before, after) = (self.offset_at(seconds - 86_400), self.offset_at(seconds + 86_400));
+        let fits = |offset: i32| self.offset_at(seconds - offset as i64) == offset;
+        let offset = match (fits(before), fits(after)) {
+            (true, true) => before.max(after),
+            (true, false) => before,
+            (false, true) => after,
+            (false, false) => before,
+        };
+        local - offset as i64 * 1_000_000
+    }
+}
+
+/// Reads `--timezone`: `UTC`, an offset like `+05:30`, `-0800` or `UTC+2`, or a tz database name
+fn parse_timezone(s: &str) -> Result<TimeZone, String> {
+    let text = s.trim();
+    if ["utc", "z", "gmt"].contains(&text.to_ascii_lowercase().as_str()) {
+        return Ok(TimeZone::fixed("UTC", 0));
+    }
+    let offset_text = ["UTC", "GMT"].iter().find_map(|p| text.strip_prefix(p)).unwrap_or(text);
+    if offset_text.starts_with(['+', '-']) {
+        let (sign, digits) = offset_text.split_at(1);
+        let digits = digits.replace(':', "");
+        let (hours, minutes) = match digits.len() {
+            1 | 2 => (digits.as_str(), "0"),
+            4 => digits.split_at(2),
+            _ => return Err(format!("expected an offset like +05:30, got '{}'", text)),
+        };
+        let (Ok(hours), Ok(minutes)) = (hours.parse::<i32>(), minutes.parse::<i32>()) else {
+            return Err(format!("expected an offset like +05:30, got '{}'", text));
+        };
+        if hours > 14 || minutes > 59 {
+            return Err(format!("offset '{}' is out of range", text));
+        }
+        let offset = (hours * 3600 + minutes * 60) * if sign == "-" { -1 } else { 1 };
+        return Ok(TimeZone::fixed(text, offset));
+    }
+    if text.is_empty() || text.starts_with('/') || text.split('/').any(|part| part == ".." || part.is_empty()) {
+        return Err(format!("'{}' is not a time zone name like Europe/Berlin", text));
+    }
+    let dir = std::env::var("TZDIR").unwrap_or_else(|_| "/usr/share/zoneinfo".to_string());
+    let path = Path::new(&dir).join(text);
+    let data = fs::read(&path).map_err(|e| format!("unknown time zone '{}' ({}: {})", text, path.display(), e))?;
+    parse_tzif(text, &data).ok_or_else(|| format!("{} is not a valid tz database file", path.display()))
+}
+
+/// Reads a compiled tz database file (RFC 8536 TZif), using the 64-bit data of version 2 and later
+fn parse_tzif(name: &str, data: &[u8]) -> Option<TimeZone> {
+    let header = |at: usize| -> Option<[usize; 6]> {
+        let bytes = data.get(at..at + 44).filter(|h| h.starts_with(b"TZif"))?;
+        let count = |i: usize| u32::from_be_bytes([bytes[20 + 4 * i], bytes[21 + 4 * i], bytes[22 + 4 * i], bytes[23 + 4 * i]]) as usize;
+        // isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt
+        Some([count(0), count(1), count(2), count(3), count(4), count(5)])
+    };
+    let block_len = |[isut, isstd, leap, time, types, chars]: [usize; 6], time_size: usize| {
+        time * (time_size + 1) + types * 6 + chars + leap * (time_size + 4) + isstd + isut
+    };
+    let counts = header(0)?;
+    let (start, counts, time_size) = match data[4] {
+        0 => (44, counts, 4),
+        _ => {
+            let second = 44 + block_len(counts, 4);
+            (second + 44, header(second)?, 8)
+        }
+    };
+    let [_, _, _, time_count, type_count, _] = counts;
+    let times = start;
+    let indices = times + time_count * time_size;
+    let types = indices + time_count;
+    let offset_of = |index: usize| -> Option<i32> {
+        let at = types + index * 6;
+        data.get(at..at + 4).filter(|_| index < type_count).map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let mut transitions = Vec::with_capacity(time_count);
+    for i in 0..time_count {
+        let raw = data.get(times + i * time_size..times + (i + 1) * time_size)?;
+        let at = match time_size {
+            4 => i32::from_be_bytes(raw.try_into().ok()?) as i64,
+            _ => i64::from_be_bytes(raw.try_into().ok()?),
+        };
+        transitions.push((at, offset_of(*data.get(indices + i)? as usize)?));
+    }
+    let rule = match time_size {
+        8 => std::str::from_utf8(data.get(start + block_len(counts, 8)..)?).ok()
+            .and_then(|footer| footer.trim_matches('\n').lines().next())
+            .and_then(parse_posix_rule),
+        _ => None,
+    };
+    Some(TimeZone { name: name.to_string(), transitions, initial: offset_of(0)?, rule })
+}
+
+/// Reads a POSIX TZ rule of the form tz files end with. Rules whose dates aren't `Mm.w.d` are
+/// not read, leaving the file's last transition in force.
+fn parse_posix_rule(text: &str) -> Option<PosixRule> {
+    /// Skips a zone abbreviation: letters, or anything in angle brackets (`<+0330>`)
+    fn skip_name(text: &str) -> Option<&str> {
+        match text.strip_prefix('<') {
+            Some(rest) => rest.split_once('>').map(|(_, rest)| rest),
+            None => {
+                let end = text.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(text.len());
+                (end >= 3).then(|| &text[end..])
+            }
+        }
+    }
+    /// Reads `[+-]hh[:mm[:ss]]` as seconds, returning the rest of the text
+    fn take_time(text: &str) -> Option<(i64, &str)> {
+        let end = text.find(|c: char| !(c.is_ascii_digit() || matches!(c, ':' | '+' | '-'))).unwrap_or(text.len());
+        let (time, rest) = text.split_at(end);
+        let (sign, time) = match time.strip_prefix('-') {
+            Some(time) => (-1, time),
+            None => (1, time.trim_start_matches('+')),
+        };
+        let mut seconds = 0;
+        for (i, part) in time.split(':').enumerate() {
+            seconds += part.parse::<i64>().ok()? * [3600, 60, 1].get(i)?;
+        }
+        Some((sign * seconds, rest))
+    }
+    fn take_date(text: &str) -> Option<(RuleDate, &str)> {
+        let text = text.strip_prefix(",M")?;
+        let end = text.find([',', '/']).unwrap_or(text.len());
+        let mut parts = text[..end].split('.').map(|part| part.parse::<u32>().ok());
+        let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+        if !(1..=12).contains(&month) || !(1..=5).contains(&week) || weekday > 6 {
+            return None;
+        }
+        let (time, rest) = match text[end..].strip_prefix('/') {
+            Some(rest) => take_time(rest)?,
+            None => (7200, &text[end..]),
+        };
+        Some((RuleDate { month, week, weekday, time }, rest))
+    }
+
+    // POSIX offsets are hours west of UTC, the opposite of the offsets used here
+    let (std, rest) = take_time(skip_name(text)?)?;
+    let std = -std as i32;
+    if rest.is_empty() {
+        return Some(PosixRule { std, dst: None });
+    }
+    let rest = skip_name(rest)?;
+    let (dst, rest) = match rest.starts_with(',') {
+        true => (std + 3600, rest),
+        false => take_time(rest).map(|(offset, rest)| (-offset as i32, rest))?,
+    };
+    let (starts, rest) = take_date(rest)?;
+    let (ends, _) = take_date(rest)?;
+    Some(PosixRule { std, dst: Some((dst, starts, ends)) })
+}
+
+/// The value for a cell going into a TIMESTAMPTZ column: its date and time read in `zone`
+fn zoned_cell_value(cell: &Data, zone: &TimeZone) -> Value {
+    match cell_value(cell, "TIMESTAMP", None) {
+        Value::Timestamp(unit, local) => Value::Timestamp(unit, zone.to_utc_micros(local)),
+        value => value,
+    }
+}
+
+/// An ISO 8601 duration (`PT1H30M`, `P1DT2H`, `-PT45S`) as interval text DuckDB casts with
+/// `::INTERVAL` (`1 hours 30 minutes`, `1 days 2 hours`). Durations load as this text rather than as
+/// INTERVAL columns because the duckdb crate can't read INTERVAL values back.
+fn iso_duration_interval(text: &str) -> Option<String> {
+    let text = text.trim();
+    let (sign, rest) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text),
+    };
+    let rest = rest.strip_prefix('P')?;
+    let (date, time) = match rest.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (rest, None),
+    };
+    let (mut years, mut months, mut days, mut micros) = (0i64, 0i64, 0i64, 0i64);
+    let mut components = 0;
+    let mut number = String::new();
+    for c in date.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'Y' | 'M' | 'W' | 'D' => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                components += 1;
+                match c {
+                    'Y' => years += n,
+                    'M' => months += n,
+                    'W' => days += 7 * n,
+                    _ => days += n,
+                }
+            }
+            _ => return None,
+        }
+    }
+    if let Some(time) = time {
+        let date_components = components;
+        for c in time.chars() {
+            match c {
+                '0'..='9' | '.' => number.push(c),
+                ',' => number.push('.'),
+                'H' | 'M' | 'S' if number.is_empty() => return None,
+                'H' | 'M' | 'S' => {
+                    let n: f64 = number.parse().ok()?;
+                    number.clear();
+                    components += 1;
+                    let unit = match c {
+                        'H' => 3_600_000_000.0,
+                        'M' => 60_000_000.0,
+                        _ => 1_000_000.0,
+                    };
+                    micros += (n * unit).round() as i64;
+                }
+                _ => return None,
+            }
+        }
+        if components == date_components {
+            return None;
+        }
+    }
+    if components == 0 || !number.is_empty() {
+        return None;
+    }
+
+    // DuckDB rejects fractional units and clock times of 24 hours or more, so every part is a whole unit
+    let seconds = micros / 1_000_000;
+    let parts: Vec<String> = [
+        (years, "years"), (months, "months"), (days, "days"), (seconds / 3600, "hours"),
+        (seconds / 60 % 60, "minutes"), (seconds % 60, "seconds"), (micros % 1_000_000, "microseconds"),
+    ]
+        .iter()
+        .filter(|(n, _)| *n != 0)
+        .map(|(n, unit)| format!("{}{} {}", sign, n, unit))
+        .collect();
+    match parts.is_empty() {
+        true => Some("0 seconds".to_string()),
+        false => Some(parts.join(" ")),
+    }
+}
+
+/// A cell as `--all-text` stores it: numbers written the shortest way that reads back the
+/// same (`1`, not `1.0`), dates as ISO dates or timestamps, and empty and error cells as NULL
+fn cell_text(cell: &Data) -> Option<String> {
+    match cell {
+        Data::Empty | Data::Error(_) => None,
+        Data::Float(v) if !v.is_finite() => None,
+        Data::DateTime(v) => v.as_datetime().map(timestamp_text),
+        Data::DateTimeIso(v) => Some(parse_iso_datetime(v).map_or_else(|| v.clone(), timestamp_text)),
+        Data::DurationIso(v) => Some(iso_duration_interval(v).unwrap_or_else(|| v.clone())),
+        Data::String(v) => Some(v.clone()),
+        Data::Int(v) => Some(v.to_string()),
+        Data::Float(v) => Some(v.to_string()),
+        Data::Bool(v) => Some(v.to_string()),
+    }
+}
+
+/// A date-time as ISO text, leaving off the time of day at midnight
+fn timestamp_text(dt: chrono::NaiveDateTime) -> String {
+    match dt.time() == chrono::NaiveTime::MIN {
+        true => dt.date().to_string(),
+        false => dt.format("%Y-%m-%d %H:%M:%S%.f").to_string(),
+    }
+}
+
+/// Microseconds since the epoch for a DuckDB time value in `unit`
+fn to_micros(unit: TimeUnit, value: i64) -> i64 {
+    match unit {
+        TimeUnit::Second => value * 1_000_000,
+        TimeUnit::Millisecond => value * 1_000,
+        TimeUnit::Microsecond => value,
+        TimeUnit::Nanosecond => value / 1_000,
+    }
+}
+
+/// How many bytes of a BLOB the query output shows before cutting it short
+const BLOB_PREVIEW_BYTES: usize = 16;
+
+/// Renders one result cell for the `--query` table
+fn format_value(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Boolean(v) => v.to_string(),
+        ValueRef::TinyInt(v) => v.to_string(),
+        ValueRef::SmallInt(v) => v.to_string(),
+        ValueRef::Int(v) => v.to_string(),
+        ValueRef::BigInt(v) => v.to_string(),
+        ValueRef::HugeInt(v) => v.to_string(),
+        ValueRef::UTinyInt(v) => v.to_string(),
+        ValueRef::USmallInt(v) => v.to_string(),
+        ValueRef::UInt(v) => v.to_string(),
+        ValueRef::UBigInt(v) => v.to_string(),
+        ValueRef::Float(v) => v.to_string(),
+        ValueRef::Double(v) => v.to_string(),
+        ValueRef::Decimal(v) => v.to_string(),
+        ValueRef::Text(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+        // Hex in DuckDB's own \xAA form, cut short so one large blob doesn't swamp the table
+        ValueRef::Blob(bytes) => {
+            let hex: String = bytes.iter().take(BLOB_PREVIEW_BYTES).map(|b| format!("\\x{:02X}", b)).collect();
+            if bytes.len() > BLOB_PREVIEW_BYTES { format!("{}... ({} bytes)", hex, bytes.len()) } else { hex }
+        }
+        ValueRef::Timestamp(unit, v) => {
+            let micros = to_micros(unit, v);
+            chrono::DateTime::<chrono::Utc>::from_timestamp(micros.div_euclid(1_000_000), (micros.rem_euclid(1_000_000) * 1_000) as u32)
+                .map_or_else(|| v.to_string(), |dt| dt.naive_utc().to_string())
+        }
+        ValueRef::Date32(days) => chrono::NaiveDate::from_ymd_opt(1970, 1, 1)
+            .and_then(|epoch| epoch.checked_add_signed(chrono::Duration::days(days as i64)))
+            .map_or_else(|| days.to_string(), |date| date.to_string()),
+        ValueRef::Time64(unit, v) => {
+            let micros = to_micros(unit, v);
+            chrono::NaiveTime::from_num_seconds_from_midnight_opt(micros.div_euclid(1_000_000) as u32, (micros.rem_euclid(1_000_000) * 1_000) as u32)
+                .map_or_else(|| v.to_string(), |time| time.to_string())
+        }
+    }
+}
+
+/// A row's cells as a JSON array for `--keep-raw`, as close to what calamine read as JSON allows
+fn raw_row_json(row: &[Data]) -> String {
+    let values: Vec<serde_json::Value> = row.iter()
+        .map(|cell| match cell {
+            Data::Int(v) => serde_json::json!(v),
+            Data::Float(v) => serde_json::json!(v),
+            Data::String(v) | Data::DateTimeIso(v) | Data::DurationIso(v) => serde_json::json!(v),
+            Data::Bool(v) => serde_json::json!(v),
+            // Dates keep the serial number Excel stored, not the converted timestamp
+            Data::DateTime(v) => serde_json::json!(v.as_f64()),
+            Data::Error(e) => serde_json::json!(e.to_string()),
+            Data::Empty => serde_json::Value::Null,
+        })
+        .collect();
+    serde_json::Value::Array(values).to_string()
+}
+
+/// Values [`check_truncation`] warns about one by one before it only counts them
+const TRUNCATIONS_SHOWN: usize = 10;
+
+/// Applies `--on-truncate` to data rows before they are loaded, adding the values that lose
+/// precision to `found`; [`warn_truncated`] reports the rest once the sheet's rows are checked
+fn check_truncation(rows_data: &[&[Data]], data_index: &dyn Fn(usize) -> usize, schema: &SheetSchema, table_name: &str, policy: OnTruncate, found: &mut usize) -> Result<()> {
+    if policy == OnTruncate::Silent {
+        return Ok(());
+    }
+    for (row_index, row) in rows_data.iter().enumerate() {
+        for (i, cell) in row.iter().enumerate().take(schema.types.len()) {
+            let Some(loss) = truncation(cell, schema.locales[i], schema.types[i]) else {
+                continue;
+            };
+            if policy == OnTruncate::Error {
+                anyhow::bail!(
+                    "Value in {}.{} (data row {}) would lose precision: {}; use --on-truncate warn to load anyway",
+                    table_name, schema.names[i], data_index(row_index) + 1, loss
+                );
+            }
+            *found += 1;
+            if *found <= TRUNCATIONS_SHOWN {
+                warn!("{}.{} (data row {}): {}", table_name, schema.names[i], data_index(row_index) + 1, loss);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Counts the values that lose precision past the ones [`check_truncation`] showed
+fn warn_truncated(found: usize, table_name: &str) {
+    if found > TRUNCATIONS_SHOWN {
+        warn!("{} value(s) in {} lose precision ({} not shown)", found, table_name, found - TRUNCATIONS_SHOWN);
+    }
+}
+
+/// Fails on the first cell that can't be loaded into the type its column was declared with,
+/// naming the sheet row, so a bad value in a `--schema` or config column is easy to find.
+/// Inferred columns fit their cells by construction and aren't checked.
+fn check_declared_types(rows_data: &[&[Data]], data_index: &dyn Fn(usize) -> usize, schema: &SheetSchema, first_data_row: u32, sheet_label: &str) -> Result<()> {
+    for (row_index, row) in rows_data.iter().enumerate() {
+        for (i, cell) in row.iter().enumerate().take(schema.types.len()) {
+            if schema.declared[i] && !cell_fits(cell, schema.types[i], schema.locales[i]) {
+                anyhow::bail!(
+                    "{}, row {}: value '{}' in column {} cannot be loaded as the declared {}",
+                    sheet_label, first_data_row as usize + data_index(row_index) + 1, cell, schema.names[i], schema.types[i]
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Why a data row can't be inserted, naming its first cell that doesn't fit the column type
+fn misfit_cell(row: &[Data], names: &[String], types: &[&str], locales: &[Option<NumberLocale>]) -> Option<String> {
+    let (i, cell) = row.iter().enumerate().take(types.len()).find(|(i, cell)| !cell_fits(cell, types[*i], locales[*i]))?;
+    Some(format!("value '{}' in column {} cannot be loaded as {}", cell, names[i], types[i]))
+}
+
+/// A chunk of rows the database refused although every cell passed [`misfit_cell`], which
+/// `load_sheet` answers by loading the sheet again with that chunk checked row by row
+struct RefusedChunk {
+    chunk: usize,
+    error: anyhow::Error,
+}
+
+impl std::fmt::Display for RefusedChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl std::fmt::Debug for RefusedChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "chunk {} refused: {:?}", self.chunk, self.error)
+    }
+}
+
+impl std::error::Error for RefusedChunk {}
+
+/// Scratch copy of a sheet's table that [`insertable_rows`] tries rows against
+const ROW_CHECK_TABLE: &str = "_row_check";
+
+/// Splits a chunk's values into the rows `probe` takes and the ones it refuses, each with its
+/// index in the chunk and the database's error. `probe` is a second connection holding a
+/// temporary [`ROW_CHECK_TABLE`], so a refused row fails there and not the sheet's transaction.
+fn insertable_rows(probe: &dyn Db, columns: &[(String, &str)], values: Vec<Value>) -> (Vec<Value>, Vec<(usize, String)>) {
+    if probe.insert_chunk(ROW_CHECK_TABLE, columns, values.clone()).is_ok() {
+        return (values, Vec::new());
+    }
+    let mut kept = Vec::with_capacity(values.len());
+    let mut refused = Vec::new();
+    for (i, row) in values.chunks(columns.len()).enumerate() {
+        match probe.insert_chunk(ROW_CHECK_TABLE, columns, row.to_vec()) {
+            Ok(()) => kept.extend_from_slice(row),
+            Err(e) => refused.push((i, e.to_string())),
+        }
+    }
+    (kept, refused)
+}
+
+/// Writes `--on-bad-row reject-file` rows to a CSV file: where each came from, why it was
+/// left out, and its cells as a JSON array
+fn write_rejects(path: &str, rejected: &[RejectedRow]) -> Result<()> {
+    let field = |text: &str| match text.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    };
+    let mut csv = String::from("file,sheet,row,error,values\n");
+    for reject in rejected {
+        let fields = [field(&reject.file), field(&reject.sheet), reject.row.to_string(), field(&reject.error), field(&reject.values)];
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    fs::write(path, csv).with_context(|| format!("Cannot write rejects file {}", path))
+}
+
+/// Whether a cell converts to `dtype` on insert. Number text counts when it parses plainly or
+/// under the column's locale; integer and DECIMAL types also need the value to be in range.
+fn cell_fits(cell: &Data, dtype: &str, locale: Option<NumberLocale>) -> bool {
+    let number = match cell {
+        Data::Empty | Data::Error(_) => return true,
+        _ if dtype == "VARCHAR" => return true,
+        Data::Float(v) if !v.is_finite() => return true,
+        Data::Bool(_) => return !matches!(dtype, "DATE" | "TIMESTAMP" | "TIMESTAMPTZ"),
+        Data::DateTime(_) => return matches!(dtype, "DATE" | "TIMESTAMP" | "TIMESTAMPTZ"),
+        Data::DateTimeIso(v) => return matches!(dtype, "DATE" | "TIMESTAMP" | "TIMESTAMPTZ") && parse_iso_datetime(v).is_some(),
+        Data::DurationIso(_) => return false,
+        Data::String(v) if dtype == "BOOLEAN" => return parse_flag(v).is_some(),
+        Data::String(v) if matches!(dtype, "DATE" | "TIMESTAMP" | "TIMESTAMPTZ") => {
+            let v = v.trim();
+            return chrono::NaiveDate::parse_from_str(v, "%Y-%m-%d").is_ok()
+                || ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"].iter().any(|f| chrono::NaiveDateTime::parse_from_str(v, f).is_ok());
+        }
+        Data::Int(v) => *v as f64,
+        Data::Float(v) => *v,
+        Data::String(v) => match locale.and_then(|l| parse_locale_number(v, l)) {
+            Some(LocaleNumber::Int(n)) => n as f64,
+            Some(LocaleNumber::Huge(n)) => n as f64,
+            Some(LocaleNumber::Float(n)) => n,
+            None => match v.trim().parse::<f64>() {
+                Ok(n) if n.is_finite() => n,
+                _ => return false,
+            },
+        },
+    };
+    let int_range = match dtype {
+        "TINYINT" => i8::MIN as f64..=i8::MAX as f64,
+        "SMALLINT" => i16::MIN as f64..=i16::MAX as f64,
+        "INTEGER" => i32::MIN as f64..=i32::MAX as f64,
+        "BIGINT" => i64::MIN as f64..=i64::MAX as f64,
+        "BOOLEAN" => return true,
+        "DATE" | "TIMESTAMP" | "TIMESTAMPTZ" => return false,
+        _ => match dtype.strip_prefix("DECIMAL(").and_then(|t| t.split_once(',')) {
+            Some((precision, _)) => {
+                let (precision, scale) = (precision.parse::<i32>().unwrap_or(38), decimal_scale(dtype).unwrap_or(0));
+                return number.abs() < 10f64.powi(precision - scale);
+            }
+            None => return true,
+        },
+    };
+    int_range.contains(&number.round())
+}
+
+/// The smallest DuckDB integer type holding every value in `min..=max`
+fn tightest_int_type(min: i64, max: i64) -> &'static str {
+    if min >= i8::MIN as i64 && max <= i8::MAX as i64 {
+        "TINYINT"
+    } else if min >= i16::MIN as i64 && max <= i16::MAX as i64 {
+        "SMALLINT"
+    } else if min >= i32::MIN as i64 && max <= i32::MAX as i64 {
+        "INTEGER"
+    } else {
+        "BIGINT"
+    }
+}
+
+/// `--verify`: compares each loaded table's `count(*)` with the rows this run inserted into it, and
+/// each sheet's inserted rows with the rows it should have loaded, allowing `--verify-max-skipped`
+/// rows set aside per sheet. Tables that `--append` added to are only checked sheet by sheet, as
+/// they held rows before the run. Returns one line per problem.
+fn verify_load(conn: &Connection, outcomes: &[FileOutcome], args: &Config) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+    // Per table, in load order: the rows each sheet inserted
+    let mut tables: Vec<(String, Vec<usize>)> = Vec::new();
+    for outcome in outcomes {
+        for sheet in &outcome.sheets {
+            let missing = sheet.expected_rows.saturating_sub(sheet.rows);
+            if missing > args.verify_max_skipped {
+                problems.push(format!(
+                    "{} sheet {}: {} of {} row(s) did not load (--verify-max-skipped {})",
+                    outcome.file, sheet.sheet, missing, sheet.expected_rows, args.verify_max_skipped
+                ));
+            }
+            let table = final_table(&sheet.table, args.schema_name.as_deref());
+            match tables.iter_mut().find(|(name, _)| name.eq_ignore_ascii_case(&table)) {
+                Some((_, rows)) => rows.push(sheet.rows),
+                None => tables.push((table, vec![sheet.rows])),
+            }
+        }
+    }
+    if args.append {
+        return Ok(problems);
+    }
+    for (table, rows) in tables {
+        let merged = args.single_table.is_some() || args.union_tables.values().any(|union| union.eq_ignore_ascii_case(unqualified_table(&table)));
+        let count: usize = conn.query_row(&format!("SELECT count(*) FROM {}", quote_table(&table)), [], |row| row.get(0))?;
+        // A table that --on-collision overwrite replaced holds the rows of whichever sheet loaded last
+        let matches = if merged { count == rows.iter().sum::<usize>() } else { rows.contains(&count) };
+        if !matches {
+            let inserted = rows.iter().map(usize::to_string).collect::<Vec<_>>().join(" + ");
+            problems.push(format!("Table {} holds {} row(s), but {} were inserted", table, count, inserted));
+        }
+    }
+    Ok(problems)
+}
+
+/// `--dedup-on`: deletes the rows of `table` that repeat an earlier (or, keeping the last, a later)
+/// row's `keys`, in load order, and returns how many went. `*` keys on every column but the
+/// `_source_file` and `_source_sheet` columns of merged tables, so overlapping files dedup too.
+/// A table without all the key columns is left alone, with a warning.
+fn dedup_table(conn: &Connection, table: &str, keys: &[String], keep: DedupKeep) -> Result<Option<usize>> {
+    let (schema, name) = table.split_once('.').unwrap_or(("main", table));
+    let mut stmt = conn.prepare("SELECT column_name FROM information_schema.columns WHERE table_schema = ? AND table_name = ? ORDER BY ordinal_position")?;
+    let columns: Vec<String> = stmt.query_map([schema, name], |row| row.get(0))?.collect::<Result<_, _>>()?;
+    let key_columns: Vec<&String> = if keys.iter().any(|key| key.trim() == "*") {
+        columns.iter().filter(|column| !matches!(column.as_str(), "_source_file" | "_source_sheet")).collect()
+    } else {
+        let mut found = Vec::new();
+        for key in keys {
+            let Some(column) = columns.iter().find(|column| column.eq_ignore_ascii_case(key.trim())) else {
+                warn!("Not deduplicating {}: it has no column {}", table, key.trim());
+                return Ok(None);
+            };
+            found.push(column);
+        }
+        found
+    };
+    let partition: Vec<String> = key_columns.iter().map(|column| quote_identifier(column)).collect();
+    let order = match keep {
+        DedupKeep::First => "rowid",
+        DedupKeep::Last => "rowid DESC",
+    };
+    let sql = format!(
+        "DELETE FROM {0} WHERE rowid IN (SELECT rowid FROM {0} QUALIFY row_number() OVER (PARTITION BY {1} ORDER BY {2}) > 1)",
+        quote_table(table), partition.join(", "), order
+    );
+    debug!("{}", sql);
+    Ok(Some(conn.execute(&sql, [])?))
+}
+
+/// Creates the `--single-table` target from the first sheet that reaches it, or checks a later
+/// sheet's columns against it. Returns false when the sheet cannot be merged and must be skipped.
+fn prepare_single_table(conn: &Connection, table: &str, columns: &[(String, &str)], column_ddl: &[String], widen: bool, sheet_label: &str) -> Result<bool> {
+    let (schema, name) = table.split_once('.').unwrap_or(("main", table));
+    let mut stmt = conn.prepare(
+        "SELECT column_name, data_type FROM information_schema.columns WHERE table_schema = ? AND table_name = ? ORDER BY ordinal_position",
+    )?;
+    let existing: Vec<(String, String)> = stmt.query_map([schema, name], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    if existing.is_empty() {
+        let sql = create_table_sql(table, column_ddl, false);
+        debug!("{}", sql);
+        conn.execute(&sql, [])?;
+        return Ok(true);
+    }
+
+    let same_names = existing.len() == columns.len()
+        && existing.iter().zip(columns).all(|((a, _), (b, _))| a.eq_ignore_ascii_case(b));
+    if !same_names {
+        let names: Vec<&str> = columns.iter().map(|(n, _)| n.as_str()).filter(|n| !n.starts_with("_source_")).collect();
+        warn!("Skipping {}: columns ({}) do not match {}", sheet_label, names.join(", "), table);
+        return Ok(false);
+    }
+
+    let mismatched: Vec<&str> = existing.iter().zip(columns)
+        .filter(|((_, existing_type), (_, dtype))| !existing_type.eq_ignore_ascii_case(dtype))
+        .map(|((name, _), _)| name.as_str())
+        .collect();
+    if mismatched.is_empty() {
+        return Ok(true);
+    }
+    if !widen {
+        warn!(
+            "Skipping {}: column type(s) differ from {} for {} (use --single-table-widen to widen them to VARCHAR)",
+            sheet_label, table, mismatched.join(", ")
+        );
+        return Ok(false);
+    }
+    for column in mismatched {
+        let sql = format!("ALTER TABLE {} ALTER COLUMN {} TYPE VARCHAR", quote_table(table), quote_identifier(column));
+        debug!("{}", sql);
+        conn.execute(&sql, [])?;
+    }
+    Ok(true)
+}
+
+/// Whether values of type `incoming` can be appended to a column of type `existing` without loss
+fn appendable(existing: &str, incoming: &str) -> bool {
+    const INTS: [&str; 5] = ["TINYINT", "SMALLINT", "INTEGER", "BIGINT", "HUGEINT"];
+    let int_rank = |t: &str| INTS.iter().position(|i| i.eq_ignore_ascii_case(t));
+    existing.eq_ignore_ascii_case(incoming)
+        || existing.eq_ignore_ascii_case("VARCHAR")
+        || match (int_rank(incoming), int_rank(existing)) {
+            (Some(a), Some(b)) => a <= b,
+            (Some(_), None) => existing.eq_ignore_ascii_case("DOUBLE"),
+            _ => false,
+        }
+}
+
+/// Creates an `--append` target from the first sheet that reaches it. Later sheets add any new
+/// columns; columns they lack are left NULL, and a column whose type can't take the sheet's values fails it.
+fn prepare_append_table(conn: &Connection, table: &str, columns: &[(String, &str)], column_ddl: &[String], sheet_label: &str) -> Result<()> {
+    let (schema, name) = table.split_once('.').unwrap_or(("main", table));
+    let mut stmt = conn.prepare(
+        "SELECT column_name, data_type FROM information_schema.columns WHERE table_schema = ? AND table_name = ?",
+    )?;
+    let existing: Vec<(String, String)> = stmt.query_map([schema, name], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    if existing.is_empty() {
+        let sql = create_table_sql(table, column_ddl, false);
+        debug!("{}", sql);
+        conn.execute(&sql, [])?;
+        return Ok(());
+    }
+
+    for ((column, dtype), ddl) in columns.iter().zip(column_ddl) {
+        match existing.iter().find(|(name, _)| name.eq_ignore_ascii_case(column)) {
+            Some((_, existing_type)) if !appendable(existing_type, dtype) => anyhow::bail!(
+                "Cannot append {} to {}: column {} is {} in the table but {} in the sheet",
+                sheet_label, table, column, existing_type, dtype
+            ),
+            Some(_) => {}
+            None => {
+                let sql = format!("ALTER TABLE {} ADD COLUMN {}", quote_table(table), ddl);
+                debug!("{}", sql);
+                conn.execute(&sql, [])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `--sorted`: hands the database to one file at a time, in sorted order. A file reads in
+/// parallel with the others until it has something to write or log, then waits for every
+/// file before it to finish.
+#[derive(Default, Debug)]
+struct WriteTurns {
+    /// Each file's place in the order, and the place whose turn it is
+    state: Mutex<(HashMap<PathBuf, usize>, usize)>,
+    turn_over: Condvar,
+}
+
+impl WriteTurns {
+    fn start(&self, files: impl IntoIterator<Item = PathBuf>) {
+        *self.state.lock().unwrap() = (files.into_iter().enumerate().map(|(i, file)| (file, i)).collect(), 0);
+    }
+
+    /// Blocks until every file before `file` has finished. Files outside the order (no
+    /// `--sorted`, `--explain`, the library) go straight through; waiting again is free.
+    fn wait(&self, file: &Path) {
+        let mut state = self.state.lock().unwrap();
+        let Some(&place) = state.0.get(file) else { return };
+        while state.1 < place {
+            state = self.turn_over.wait(state).unwrap();
+        }
+    }
+
+    /// Passes the turn on once `file` has finished, waiting for its turn first if need be
+    fn finish(&self, file: &Path) {
+        self.wait(file);
+        let mut state = self.state.lock().unwrap();
+        if let Some(&place) = state.0.get(file) {
+            state.1 = place + 1;
+            self.turn_over.notify_all();
+        }
+    }
+}
+
+/// How one file fared in this run, as recorded in `_load_run_files`
+struct FileOutcome {
+    file: String,
+    tables: usize,
+    rows: usize,
+    duration: Duration,
+    error: Option<String>,
+    sheets: Vec<LoadedSheet>,
+}
+
+/// Appends this run to `_load_runs` and its files to `_load_run_files`, creating them on first use,
+/// so the database carries its own load history. Returns the new run id.
+fn write_load_log(conn: &Connection, started_at: chrono::NaiveDateTime, path: &str, outcomes: &[FileOutcome], elapsed: Duration) -> Result<i64> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS _load_runs (
+            run_id BIGINT, started_at TIMESTAMP, finished_at TIMESTAMP, path VARCHAR,
+            files BIGINT, failed_files BIGINT, tables BIGINT, rows BIGINT, duration_ms BIGINT
+        );
+        CREATE TABLE IF NOT EXISTS _load_run_files (
+            run_id BIGINT, file VARCHAR, tables BIGINT, rows BIGINT, duration_ms BIGINT, error VARCHAR
+        );",
+    )?;
+    let run_id: i64 = conn.query_row("SELECT COALESCE(MAX(run_id), 0) + 1 FROM _load_runs", [], |row| row.get(0))?;
+    let timestamp = |t: chrono::NaiveDateTime| t.format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+
+    conn.execute(
+        "INSERT INTO _load_runs VALUES (?, CAST(? AS TIMESTAMP), CAST(? AS TIMESTAMP), ?, ?, ?, ?, ?, ?)",
+        duckdb::params![
+            run_id,
+            timestamp(started_at),
+            timestamp(chrono::Local::now().naive_local()),
+            path,
+            outcomes.len() as i64,
+            outcomes.iter().filter(|o| o.error.is_some()).count() as i64,
+            outcomes.iter().map(|o| o.tables).sum::<usize>() as i64,
+            outcomes.iter().map(|o| o.rows).sum::<usize>() as i64,
+            elapsed.as_millis() as i64,
+        ],
+    )?;
+    let mut insert = conn.prepare("INSERT INTO _load_run_files VALUES (?, ?, ?, ?, ?, ?)")?;
+    for outcome in outcomes {
+        insert.execute(duckdb::params![
+            run_id,
+            outcome.file,
+            outcome.tables as i64,
+            outcome.rows as i64,
+            outcome.duration.as_millis() as i64,
+            outcome.error,
+        ])?;
+    }
+    Ok(run_id)
+}
+
+/// A file's modification time (microseconds since the epoch) and size, which `--incremental`
+/// compares to tell whether it changed since the last load
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FileStamp {
+    modified: i64,
+    size: i64,
+}
+
+fn file_stamp(path: &Path) -> Result<FileStamp> {
+    let metadata = fs::metadata(path).with_context(|| format!("Cannot read the metadata of {}", path.display()))?;
+    let modified = chrono::DateTime::<chrono::Utc>::from(metadata.modified()?);
+    Ok(FileStamp { modified: modified.timestamp_micros(), size: metadata.len() as i64 })
+}
+
+/// A file as `_loaded_files` remembers it from the last load
+struct LoadedFile {
+    file: String,
+    stamp: FileStamp,
+    tables: Vec<String>,
+}
+
+fn create_loaded_files_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (file VARCHAR, modified_at TIMESTAMP, size BIGINT, tables JSON, loaded_at TIMESTAMP)",
+        LOADED_FILES_TABLE
+    ))?;
+    Ok(())
+}
+
+/// Everything `_loaded_files` holds, creating it on first use
+fn loaded_files(conn: &Connection) -> Result<Vec<LoadedFile>> {
+    create_loaded_files_table(conn)?;
+    let mut stmt = conn.prepare(&format!("SELECT file, modified_at, size, tables::VARCHAR FROM {}", LOADED_FILES_TABLE))?;
+    let rows = stmt.query_map([], |row| {
+        let modified = match row.get_ref(1)? {
+            ValueRef::Timestamp(unit, value) => to_micros(unit, value),
+            _ => 0,
+        };
+        Ok((row.get::<_, String>(0)?, modified, row.get::<_, i64>(2)?, row.get::<_, String>(3)?))
+    })?;
+    let mut loaded = Vec::new();
+    for row in rows {
+        let (file, modified, size, tables) = row?;
+        let tables = serde_json::from_str(&tables).with_context(|| format!("Cannot read the tables of {} in {}", file, LOADED_FILES_TABLE))?;
+        loaded.push(LoadedFile { file, stamp: FileStamp { modified, size }, tables });
+    }
+    Ok(loaded)
+}
+
+/// Brings `_loaded_files` up to date after an `--incremental` load: files that loaded are
+/// recorded with the stamp they had when the run started, and files that failed are forgotten
+/// so the next run tries them again. With `prune`, files that are gone from the folder lose
+/// their tables and their entry. Tables are recorded by their names in `schema_name` (`--schema-name`).
+fn record_loaded_files(
+    conn: &Connection, previous: &[LoadedFile], outcomes: &[FileOutcome], stamps: &HashMap<PathBuf, FileStamp>, present: &[PathBuf],
+    prune: bool, schema_name: Option<&str>,
+) -> Result<()> {
+    use duckdb::params;
+
+    // A --refresh run has dropped the tables along with the rest
+    create_loaded_files_table(conn)?;
+    create_loaded_sheets_table(conn)?;
+    let loaded_at = chrono::Local::now().naive_local().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+    let delete = format!("DELETE FROM {} WHERE file = ?", LOADED_FILES_TABLE);
+    let delete_sheets = format!("DELETE FROM {} WHERE file = ?", LOADED_SHEETS_TABLE);
+    for outcome in outcomes {
+        conn.execute(&delete, [&outcome.file])?;
+        conn.execute(&delete_sheets, [&outcome.file])?;
+        let Some(stamp) = stamps.get(Path::new(&outcome.file)).filter(|_| outcome.error.is_none()) else { continue };
+        let tables: Vec<String> = outcome.sheets.iter().map(|sheet| final_table(&sheet.table, schema_name)).collect();
+        conn.execute(
+            &format!("INSERT INTO {} VALUES (?, ?, ?, ?, CAST(? AS TIMESTAMP))", LOADED_FILES_TABLE),
+            params![outcome.file, Value::Timestamp(TimeUnit::Microsecond, stamp.modified), stamp.size, serde_json::json!(tables).to_string(), loaded_at],
+        )?;
+        for sheet in &outcome.sheets {
+            let Some(content_hash) = &sheet.content_hash else { continue };
+            conn.execute(
+                &format!("INSERT INTO {} VALUES (?, ?, ?, ?, ?, ?)", LOADED_SHEETS_TABLE),
+                params![outcome.file, sheet.sheet, final_table(&sheet.table, schema_name), content_hash, sheet.rows as i64, serde_json::json!(sheet.columns).to_string()],
+            )?;
+        }
+    }
+
+    if prune {
+        let present: HashSet<String> = present.iter().map(|path| path.display().to_string()).collect();
+        for gone in previous.iter().filter(|loaded| !present.contains(&loaded.file)) {
+            for table in &gone.tables {
+                let sql = format!("DROP TABLE IF EXISTS {}", quote_table(table));
+                debug!("{}", sql);
+                conn.execute(&sql, [])?;
+            }
+            conn.execute(&delete, [&gone.file])?;
+            conn.execute(&delete_sheets, [&gone.file])?;
+            info!("Dropped {} table(s) of {}, which is gone (--prune)", gone.tables.len(), gone.file);
+        }
+    }
+    Ok(())
+}
+
+/// A sheet as `_loaded_sheets` remembers it: the table it went to, the hash of its cells, and the
+/// rows and columns it loaded with, which a sheet kept as it was reports again
+#[derive(Clone, Debug, Default)]
+struct SheetRecord {
+    table: String,
+    content_hash: String,
+    rows: usize,
+    columns: Vec<(String, String)>,
+}
+
+fn create_loaded_sheets_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (file VARCHAR, sheet VARCHAR, table_name VARCHAR, content_hash VARCHAR, rows BIGINT, columns JSON)",
+        LOADED_SHEETS_TABLE
+    ))?;
+    Ok(())
+}
+
+/// Everything `_loaded_sheets` holds, by file and sheet, creating it on first use
+fn loaded_sheets(conn: &Connection) -> Result<HashMap<(String, String), SheetRecord>> {
+    create_loaded_sheets_table(conn)?;
+    let mut stmt = conn.prepare(&format!("SELECT file, sheet, table_name, content_hash, rows, columns::VARCHAR FROM {}", LOADED_SHEETS_TABLE))?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?, row.get::<_, i64>(4)?, row.get::<_, String>(5)?))
+    })?;
+    let mut sheets = HashMap::new();
+    for row in rows {
+        let (file, sheet, table, content_hash, rows, columns) = row?;
+        let columns: Vec<(String, String)> = serde_json::from_str(&columns)
+            .with_context(|| format!("Cannot read the columns of {} ({}) in {}", file, sheet, LOADED_SHEETS_TABLE))?;
+        sheets.insert((file, sheet), SheetRecord { table, content_hash, rows: rows as usize, columns });
+    }
+    Ok(sheets)
+}
+
+/// The options that decide which columns, types and values a sheet's table gets, written out
+/// for `--incremental` to hash along with the cells: the same cells loaded another way make a
+/// different table. Maps are sorted so the text comes out the same from run to run.
+fn shaping_options(table_name: &str, sheet_config: Option<&SheetConfig>, args: &Config) -> String {
+    format!("{:?}", (
+        sheet_config.map(|cfg| (cfg.header_row, &cfg.null_tokens, &cfg.columns, cfg.types.iter().collect::<BTreeMap<_, _>>())),
+        args.schema_overrides.columns_for(table_name).map(|types| types.iter().collect::<BTreeMap<_, _>>()),
+        (&args.columns, args.orientation, args.find_header, args.skip_rows, args.header_row, args.no_header,
+            args.header_rows, args.fill_header, args.header_style, args.identifier_case, args.max_identifier_length, args.ascii_only),
+        (args.infer_rows, args.load_rows, args.load_random, args.sample_from, args.type_strategy, args.all_text,
+            args.tight_ints, args.int_overflow, args.bigint_overflow, &args.suffix_types, &args.text_columns, &args.money_columns),
+        (args.money_scale, &args.format_map, args.detect_percent, args.number_locale, args.decimal_separator, args.thousands_separator,
+            &args.null_values, args.trim_null_values, &args.bool_strings, args.parse_dates, args.date_threshold, args.date_order),
+        (&args.timezone, args.on_bad_date, args.trim, args.normalize_whitespace, args.fix_encoding, args.error_handling,
+            args.on_bad_row, args.on_truncate, args.skip_blank_rows, args.keep_raw, args.flag_highlighted, &args.text_collation),
+        (&args.source_column, &args.source_sheet_column, &args.row_number_column, &args.load_timestamp_column),
+    ))
+}
+
+/// A stable hash of a sheet's cells (FNV-1a over each cell's position, kind and value) and of
+/// the [`shaping_options`] it loads with, as 16 hex digits. It has to come out the same from one
+/// build to the next, which std's hasher doesn't promise.
+fn range_hash(range: &Range<Data>, options: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash = (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    };
+    feed(options.as_bytes());
+    feed(&[0xff]);
+    let (row, column) = range.start().unwrap_or_default();
+    feed(&row.to_le_bytes());
+    feed(&column.to_le_bytes());
+    for (row, column, cell) in range.used_cells() {
+        feed(&(row as u64).to_le_bytes());
+        feed(&(column as u64).to_le_bytes());
+        match cell {
+            Data::Int(v) => { feed(b"i"); feed(&v.to_le_bytes()) }
+            Data::Float(v) => { feed(b"f"); feed(&v.to_bits().to_le_bytes()) }
+            Data::String(v) => { feed(b"s"); feed(v.as_bytes()) }
+            Data::Bool(v) => { feed(b"b"); feed(&[*v as u8]) }
+            Data::DateTime(v) => { feed(b"d"); feed(&v.as_f64().to_bits().to_le_bytes()) }
+            Data::DateTimeIso(v) => { feed(b"t"); feed(v.as_bytes()) }
+            Data::DurationIso(v) => { feed(b"u"); feed(v.as_bytes()) }
+            Data::Error(e) => { feed(b"e"); feed(e.to_string().as_bytes()) }
+            Data::Empty => feed(b"_"),
+        }
+        // Keeps "ab" + "c" apart from "a" + "bc"
+        feed(&[0xff]);
+    }
+    format!("{:016x}", hash)
+}
+
+/// The sheet as it was last loaded, when `--incremental` finds its cells hash the same as then and
+/// its table is still there under the same name; None means it loads again
+fn unchanged_sheet(
+    file_path: &Path, sheet_name: &str, table: &str, content_hash: &str, source_size: (usize, usize),
+    conn_mutex: &Arc<Mutex<Box<dyn Db>>>, args: &Config,
+) -> Result<Option<LoadedSheet>> {
+    // --watch appends each batch, so a sheet can't stand on what it held before
+    if args.append {
+        return Ok(None);
+    }
+    let key = (file_path.display().to_string(), sheet_name.to_string());
+    let Some(record) = args.loaded_sheets.get(&key) else { return Ok(None) };
+    let final_name = final_table(table, args.schema_name.as_deref());
+    if record.content_hash != content_hash || record.table != final_name {
+        return Ok(None);
+    }
+    let db = conn_mutex.lock().unwrap();
+    let Some(conn) = db.duckdb() else { return Ok(None) };
+    let (schema, name) = final_name.split_once('.').unwrap_or(("main", &final_name));
+    let present: i64 = conn.query_row(
+        "SELECT count(*) FROM information_schema.tables WHERE table_schema = ? AND table_name = ?",
+        [schema, name],
+        |row| row.get(0),
+    )?;
+    if present == 0 {
+        return Ok(None);
+    }
+    Ok(Some(LoadedSheet {
+        sheet: sheet_name.to_string(),
+        table: table.to_string(),
+        rows: record.rows,
+        columns: record.columns.clone(),
+        source_size,
+        expected_rows: record.rows,
+        content_hash: Some(content_hash.to_string()),
+        mixed_columns: Vec::new(),
+    }))
+}
+
+fn write_manifest(path: &str, manifest: &serde_json::Value) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(manifest)? + "\n")
+        .with_context(|| format!("Cannot write manifest {}", path))
+}
+
+/// The `--manifest` JSON, which `--json-report` builds on: the run's settings and totals, then
+/// every file with its sheets, tables, row counts and column types (or the error that stopped it).
+fn manifest_json(started_at: chrono::NaiveDateTime, outcomes: &[FileOutcome], elapsed: Duration, args: &Config) -> serde_json::Value {
+    // Files finish in whatever order the workers get to them; list them by path instead
+    let mut sorted: Vec<&FileOutcome> = outcomes.iter().collect();
+    sorted.sort_by(|a, b| a.file.cmp(&b.file));
+    let skipped = args.skipped_sheets.lock().unwrap();
+    let files: Vec<serde_json::Value> = sorted.into_iter().map(|outcome| {
+        let sheets: Vec<serde_json::Value> = outcome.sheets.iter().map(|sheet| {
+            let columns: Vec<serde_json::Value> = sheet.columns.iter()
+                .map(|(name, dtype)| serde_json::json!({ "name": name, "type": dtype }))
+                .collect();
+            serde_json::json!({
+                "sheet": sheet.sheet,
+                // Staged tables are reported under the name they get once swapped out of staging
+                "table": final_table(&sheet.table, args.schema_name.as_deref()),
+                "rows": sheet.rows,
+                "columns": columns,
+            })
+        }).collect();
+        serde_json::json!({
+            "file": outcome.file,
+            "elapsed_ms": outcome.duration.as_millis() as u64,
+            "error": outcome.error,
+            "sheets": sheets,
+            "skipped_sheets": skipped.iter()
+                .filter(|(file, _, _)| *file == outcome.file)
+                .map(|(_, sheet, error)| serde_json::json!({ "sheet": sheet, "error": error }))
+                .collect::<Vec<_>>(),
+        })
+    }).collect();
+    serde_json::json!({
+        "started_at": started_at.format("%Y-%m-%dT%H:%M:%S%.3f").to_string(),
+        "path": args.path,
+        "db": redacted_db(&args.db),
+        "elapsed_ms": elapsed.as_millis() as u64,
+        "files_total": outcomes.len(),
+        "files_failed": outcomes.iter().filter(|o| o.error.is_some()).count(),
+        "tables": outcomes.iter().map(|o| o.tables).sum::<usize>(),
+        "rows": outcomes.iter().map(|o| o.rows).sum::<usize>(),
+        "files": files,
+    })
+}
+
+/// Replaces the tables in `schema` (`main`, or `--schema-name`) with their staged copies inside
+/// a single transaction, so readers see either the old tables or the complete new load. With
+/// `refresh_all` (`--refresh-all`), tables there that were not part of this load are dropped as well.
+fn swap_in_staging(conn: &Connection, refresh_all: bool, schema: &str) -> Result<usize> {
+    let list_tables = |schema: &str| -> Result<Vec<String>> {
+        let mut stmt = conn.prepare(
+            "SELECT table_name FROM information_schema.tables WHERE table_schema = ? AND table_type = 'BASE TABLE'",
+        )?;
+        let tables = stmt.query_map([schema], |row| row.get(0))?.collect::<Result<_, _>>()?;
+        Ok(tables)
+    };
+    let staged = list_tables(STAGING_SCHEMA)?;
+
+    let mut swap = String::from("BEGIN TRANSACTION;\n");
+    if refresh_all {
+        for table in list_tables(schema)?.iter().filter(|t| !LOAD_LOG_TABLES.contains(&t.as_str())) {
+            swap.push_str(&format!("DROP TABLE IF EXISTS {}.\"{}\";\n", quote_identifier(schema), table));
+        }
+    }
+    for table in &staged {
+        swap.push_str(&format!(
+            "DROP TABLE IF EXISTS {m}.\"{t}\";\nCREATE TABLE {m}.\"{t}\" AS SELECT * FROM {s}.\"{t}\";\n",
+            m = quote_identifier(schema), t = table, s = STAGING_SCHEMA
+        ));
+    }
+    swap.push_str(&format!("DROP SCHEMA {} CASCADE;\nCOMMIT;", STAGING_SCHEMA));
+
+    debug!("{}", swap);
+    if let Err(e) = conn.execute_batch(&swap) {
+        let _ = conn.execute_batch("ROLLBACK");
+        return Err(e).with_context(|| format!("Swapping staged tables into {0} failed; {0} is untouched", schema));
+    }
+    Ok(staged.len())
+}
+
+/// Adds a column populated from a constant or expression. The table is found as `--index`
+/// finds it, case-insensitively and by its sanitized name, and the column type is whatever
+/// DuckDB describes the expression as over that table, so an empty table gets it too.
+fn add_column(conn: &Connection, add: &AddColumn) -> Result<()> {
+    let found: Option<(String, String)> = conn
+        .prepare("SELECT table_schema, table_name FROM information_schema.tables WHERE table_schema = current_schema() AND lower(table_name) IN (lower(?), lower(?))")?
+        .query_map([add.table.as_str(), sanitize_identifier(&add.table).as_str()], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .next()
+        .transpose()?;
+    let Some((schema, table_name)) = found else {
+        anyhow::bail!("Cannot add column {}.{}: there is no such table", add.table, add.column);
+    };
+    let table = format!("{}.{}", quote_identifier(&schema), quote_identifier(&table_name));
+    // Typed from the expression itself, so an empty (--schema-only) table gets the same column type
+    let data_type: String = conn
+        .query_row(&format!("DESCRIBE SELECT {} FROM {}", add.expr, table), [], |row| row.get(1))
+        .with_context(|| format!("Cannot evaluate --add-column {}.{}", table_name, add.column))?;
+    let data_type = if data_type == "NULL" { "VARCHAR".to_string() } else { data_type };
+
+    let column = quote_identifier(&add.column);
+    let sql = format!(
+        "BEGIN TRANSACTION;\nALTER TABLE {t} ADD COLUMN {c} {ty};\nUPDATE {t} SET {c} = {e};\nCOMMIT;",
+        t = table, c = column, ty = data_type, e = add.expr
+    );
+    debug!("{}", sql);
+    if let Err(e) = conn.execute_batch(&sql) {
+        let _ = conn.execute_batch("ROLLBACK");
+        return Err(e).with_context(|| format!("Cannot add column {}.{}", table_name, add.column));
+    }
+    info!("Added column {}.{} ({})", table_name, add.column, data_type);
+    Ok(())
+}
+
+/// Builds the `--index` indexes on the loaded tables in the current schema (`main`, or
+/// `--schema-name`). Tables and columns are matched case-insensitively (columns also by their
+/// sanitized name), and all of them are checked before the first index is built. Returns the
+/// CREATE INDEX statements, for `--ddl-out`.
+fn create_indexes(conn: &Connection, indexes: &[Indexes]) -> Result<Vec<String>> {
+    let mut statements = Vec::new();
+    for (table, columns) in indexes.iter().flat_map(|list| &list.0) {
+        let catalog: Vec<(String, String, String)> = conn
+            .prepare("SELECT table_schema, table_name, column_name FROM information_schema.columns WHERE table_schema = current_schema() AND lower(table_name) = lower(?) ORDER BY ordinal_position")?
+            .query_map([table], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+        let Some((schema, table_name, _)) = catalog.first() else {
+            anyhow::bail!("Cannot index {}: there is no such table", table);
+        };
+        let names: Vec<&str> = catalog.iter().map(|(_, _, column)| column.as_str()).collect();
+        let resolved = columns.iter()
+            .map(|column| {
+                names.iter().copied()
+                    .find(|name| name.eq_ignore_ascii_case(column) || name.eq_ignore_ascii_case(&sanitize_identifier(column)))
+                    .with_context(|| format!("Cannot index {}: it has no column {} (columns: {})", table_name, column, names.join(", ")))
+            })
+            .collect::<Result<Vec<&str>>>()?;
+        let index_name = sanitize_identifier(&format!("idx_{}_{}", table_name, resolved.join("_")));
+        let quoted: Vec<String> = resolved.iter().map(|column| quote_identifier(column)).collect();
+        // CREATE INDEX doesn't follow the search path to a --schema-name table
+        let sql = format!(
+            "CREATE INDEX IF NOT EXISTS {} ON {}.{} ({})",
+            quote_identifier(&index_name), quote_identifier(schema), quote_identifier(table_name), quoted.join(", ")
+        );
+        statements.push((format!("{} on {}({})", index_name, table_name, resolved.join(", ")), sql));
+    }
+    for (label, sql) in &statements {
+        debug!("{}", sql);
+        let start = Instant::now();
+        conn.execute_batch(sql).with_context(|| format!("Cannot build index {}", label))?;
+        info!("Built index {} in {:.2?}", label, start.elapsed());
+    }
+    Ok(statements.into_iter().map(|(_, sql)| sql).collect())
+}
+
+/// Writes the `--ddl-out` file: the `--schema-name` schema, then every table as it was first
+/// created (sorted by name, so the file only changes when the tables do), then the indexes
+fn write_ddl(path: &str, indexes: &[String], args: &Config) -> Result<()> {
+    let mut tables = args.ddl.lock().unwrap().clone();
+    tables.sort_by_key(|(table, _)| table.to_lowercase());
+    let mut sql = String::new();
+    if let Some(schema) = &args.schema_name {
+        sql.push_str(&format!("CREATE SCHEMA IF NOT EXISTS {};\n", quote_identifier(schema)));
+    }
+    for statement in tables.iter().map(|(_, statement)| statement).chain(indexes) {
+        sql.push_str(statement);
+        sql.push_str(";\n");
+    }
+    fs::write(path, sql).with_context(|| format!("Cannot write DDL file {}", path))?;
+    info!("Wrote {} table(s) and {} index(es) to {}", tables.len(), indexes.len(), path);
+    Ok(())
+}
+
+/// Splits a SQL script on `;` terminators, ignoring semicolons inside quotes and
+/// comments. Each statement is returned with the 1-based line it starts on.
+fn split_sql_statements(sql: &str) -> Vec<(usize, String)> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut line = 1;
+    let mut start_line = None;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            line += 1;
+        }
+        match c {
+            '\'' | '"' => {
+                start_line.get_or_insert(line);
+                current.push(c);
+                for q in chars.by_ref() {
+                    if q == '\n' {
+                        line += 1;
+                    }
+                    current.push(q);
+                    if q == c {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for q in chars.by_ref() {
+                    if q == '\n' {
+                        line += 1;
+                        current.push('\n');
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                for q in chars.by_ref() {
+                    if q == '\n' {
+                        line += 1;
+                        current.push('\n');
+                    }
+                    if prev == '*' && q == '/' {
+                        break;
+                    }
+                    prev = q;
+                }
+            }
+            ';' => {
+                if let Some(start) = start_line.take() {
+                    statements.push((start, current.trim().to_string()));
+                }
+                current.clear();
+            }
+            _ => {
+                if !c.is_whitespace() {
+                    start_line.get_or_insert(line);
+                }
+                current.push(c);
+            }
+        }
+    }
+    if let Some(start) = start_line {
+        statements.push((start, current.trim().to_string()));
+    }
+    statements
+}
+
+/// Executes every statement of a SQL script file in order, stopping at the first failure.
+/// Runs a `--sql-pre` or `--post-sql` hook: the statements given inline, or those of the file
+/// named after `@` (or, for a value that names an existing file, that file), in order,
+/// stopping at the first failure
+fn run_sql_hook(db: &dyn Db, hook: &str, flag: &str) -> Result<()> {
+    let path = hook.strip_prefix('@').or_else(|| Path::new(hook).is_file().then_some(hook));
+    let (source, sql) = match path {
+        Some(path) => (path, fs::read_to_string(path).with_context(|| format!("Cannot read SQL script {}", path))?),
+        None => (flag, hook.to_string()),
+    };
+    let statements = split_sql_statements(&sql);
+    for (line, statement) in &statements {
+        debug!("{}:{}: {}", source, line, statement);
+        db.execute_batch(statement)
+            .with_context(|| format!("{}:{}: statement failed: {}", source, line, statement))?;
+    }
+    info!("Ran {} statement(s) from {}", statements.len(), source);
+    Ok(())
+}
+
+/// Whether a statement produces a result set to print, going by its first keyword
+fn returns_rows(statement: &str) -> bool {
+    let keyword = statement.split(|c: char| c.is_whitespace() || c == '(').next().unwrap_or_default();
+    ["SELECT", "WITH", "FROM", "VALUES", "TABLE", "SHOW", "DESCRIBE", "SUMMARIZE", "EXPLAIN"]
+        .iter()
+        .any(|k| k.eq_ignore_ascii_case(keyword))
+}
+
+/// Runs a `--script` file statement by statement, stopping at the first failure: queries print
+/// (or export with `>>`) like `--query`, and everything else reports how many rows it changed
+fn run_query_script(conn: &Connection, path: &str, args: &Config) -> Result<()> {
+    let sql = fs::read_to_string(path).with_context(|| format!("Cannot read SQL script {}", path))?;
+    let statements = split_sql_statements(&sql);
+    for (line, statement) in &statements {
+        let context = || format!("{}:{}: statement failed: {}", path, line, statement);
+        if returns_rows(statement) || split_export_target(statement).1.is_some() {
+            run_query(conn, statement, args).with_context(context)?;
+        } else {
+            let changed = conn.execute(statement, []).with_context(context)?;
+            info!("{}:{}: {} row(s) changed", path, line, changed);
+        }
+    }
+    info!("Ran {} statement(s) from {}", statements.len(), path);
+    Ok(())
+}
+
+/// Writes a replayable logical dump of every table in the current schema (`main`, or
+/// `--schema-name`): a CREATE TABLE with the loaded column types followed by multi-row
+/// INSERTs. Numbers and booleans are written bare, everything else as quoted string literals.
+/// The loader's bookkeeping tables are left out unless `--export-internal`, and VARCHAR columns
+/// this run created with `--text-collation` keep their COLLATE.
+fn write_sql_dump(conn: &Connection, path: &str, args: &Config) -> Result<usize> {
+    use std::io::Write;
+
+    let mut out = std::io::BufWriter::new(fs::File::create(path).with_context(|| format!("Cannot create {}", path))?);
+    let mut stmt = conn.prepare(
+        "SELECT table_name, column_name, data_type FROM information_schema.columns \
+         WHERE table_schema = current_schema() ORDER BY table_name, ordinal_position",
+    )?;
+    let catalog: Vec<(String, String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut tables: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    for (table, column, data_type) in catalog {
+        if !args.export_internal && is_internal_table(&table) {
+            continue;
+        }
+        if tables.last().is_none_or(|(name, _)| *name != table) {
+            tables.push((table, Vec::new()));
+        }
+        tables.last_mut().unwrap().1.push((column, data_type));
+    }
+
+    let is_bare = |data_type: &str| {
+        matches!(data_type, "TINYINT" | "SMALLINT" | "INTEGER" | "BIGINT" | "HUGEINT" | "FLOAT" | "DOUBLE" | "BOOLEAN")
+            || data_type.starts_with("DECIMAL")
+    };
+    let collated = args.collated_columns.lock().unwrap();
+    for (table, columns) in &tables {
+        let defs: Vec<String> = columns.iter()
+            .map(|(name, dtype)| {
+                let collation = args.text_collation.as_deref().filter(|_| collated.contains(&(table.to_lowercase(), name.to_lowercase())));
+                column_def(name, dtype, collation)
+            })
+            .collect();
+        writeln!(out, "CREATE TABLE {} ({});", quote_identifier(table), defs.join(", "))?;
+
+        let select: Vec<String> = columns.iter().map(|(name, _)| format!("{}::VARCHAR", quote_identifier(name))).collect();
+        let mut rows_stmt = conn.prepare(&format!("SELECT {} FROM {}", select.join(", "), quote_identifier(table)))?;
+        let mut rows = rows_stmt.query([])?;
+        let mut batch = Vec::new();
+        loop {
+            let row = rows.next()?;
+            if let Some(row) = row {
+                let mut values = Vec::with_capacity(columns.len());
+                for (i, (_, dtype)) in columns.iter().enumerate() {
+                    let value: Option<String> = row.get(i)?;
+                    values.push(match value {
+                        None => "NULL".to_string(),
+                        Some(v) if is_bare(dtype) && !matches!(v.as_str(), "nan" | "inf" | "-inf") => v,
+                        Some(v) => format!("'{}'", v.replace('\'', "''")),
+                    });
+                }
+                batch.push(format!("({})", values.join(", ")));
+            }
+            if batch.len() >= 1000 || (row.is_none() && !batch.is_empty()) {
+                writeln!(out, "INSERT INTO {} VALUES\n{};", quote_identifier(table), batch.join(",\n"))?;
+                batch.clear();
+            }
+            if row.is_none() {
+                break;
+            }
+        }
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(tables.len())
+}
+
+/// One column's entry in the data dictionary or `--profile`
+struct ColumnProfile {
+    name: String,
+    data_type: String,
+    nulls: i64,
+    distinct: i64,
+    min: Option<String>,
+    max: Option<String>,
+    samples: Vec<String>,
+}
+
+impl ColumnProfile {
+    /// Scans one column of `table` (already quoted). Without `min_max` the smallest and largest
+    /// values are left out; `samples` is how many distinct values to keep as examples.
+    fn scan(conn: &Connection, table: &str, column: String, data_type: String, min_max: bool, samples: usize) -> Result<Self> {
+        let c = quote_identifier(&column);
+        let (min, max) = if min_max {
+            (format!("MIN({})::VARCHAR", c), format!("MAX({})::VARCHAR", c))
+        } else {
+            ("NULL::VARCHAR".to_string(), "NULL::VARCHAR".to_string())
+        };
+        let (nulls, distinct, min, max) = conn.query_row(
+            &format!("SELECT COUNT(*) - COUNT({c}), COUNT(DISTINCT {c}), {}, {} FROM {}", min, max, table, c = c),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        let samples = if samples == 0 {
+            Vec::new()
+        } else {
+            let mut stmt = conn.prepare(&format!("SELECT DISTINCT {c}::VARCHAR FROM {} WHERE {c} IS NOT NULL LIMIT {}", table, samples, c = c))?;
+            let samples = stmt.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+            samples
+        };
+        Ok(ColumnProfile { name: column, data_type, nulls, distinct, min, max, samples })
+    }
+}
+
+/// Whether a smallest and largest value say something about a column of this type: numbers,
+/// dates and times, but not text, booleans, blobs or nested values
+fn has_meaningful_range(data_type: &str) -> bool {
+    let base = data_type.split('(').next().unwrap_or_default().trim();
+    base.starts_with("DATE") || base.starts_with("TIME") || matches!(
+        base,
+        "TINYINT" | "SMALLINT" | "INTEGER" | "BIGINT" | "HUGEINT" | "UTINYINT" | "USMALLINT" | "UINTEGER" | "UBIGINT"
+            | "FLOAT" | "REAL" | "DOUBLE" | "DECIMAL"
+    )
+}
+
+/// `--profile`: writes each table's row count, then per column its type, the number of values,
+/// NULLs and distinct values, and the range of numeric, date and time columns
+fn print_profile(conn: &Connection, table: &str, out: &mut impl std::io::Write) -> Result<()> {
+    let quoted = quote_table(table);
+    let rows: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM {}", quoted), [], |row| row.get(0))?;
+    let mut stmt = conn.prepare(&format!("DESCRIBE {}", quoted))?;
+    let columns: Vec<(String, String)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+
+    let mut rendered = comfy_table::Table::new();
+    rendered.load_preset(comfy_table::presets::UTF8_FULL_CONDENSED)
+        .set_header(["column", "type", "values", "nulls", "distinct", "min", "max"]);
+    for (column, data_type) in columns {
+        let min_max = has_meaningful_range(&data_type);
+        let profile = ColumnProfile::scan(conn, &quoted, column, data_type, min_max, 0)?;
+        let bound = |value: Option<String>| match value {
+            _ if !min_max => String::new(),
+            Some(value) => value,
+            None => "NULL".to_string(),
+        };
+        rendered.add_row([
+            profile.name,
+            profile.data_type,
+            (rows - profile.nulls).to_string(),
+            profile.nulls.to_string(),
+            profile.distinct.to_string(),
+            bound(profile.min),
+            bound(profile.max),
+        ]);
+    }
+    writeln!(out, "{} ({} rows)", table, rows)?;
+    writeln!(out, "{}", rendered)?;
+    Ok(())
+}
+
+/// Profiles every table in the database and writes the result as a Markdown
+/// document, or as HTML when the path ends in `.html`/`.htm`.
+fn write_data_dictionary(conn: &Connection, path: &str) -> Result<()> {
+    let html = matches!(Path::new(path).extension().and_then(|e| e.to_str()), Some("html") | Some("htm"));
+
+    let mut stmt = conn.prepare(
+        "SELECT table_name, column_name, data_type FROM information_schema.columns \
+         WHERE table_schema = current_schema() ORDER BY table_name, ordinal_position",
+    )?;
+    let catalog: Vec<(String, String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut tables: Vec<(String, i64, Vec<ColumnProfile>)> = Vec::new();
+    for (table, column, data_type) in catalog {
+        if tables.last().is_none_or(|(name, _, _)| *name != table) {
+            let rows: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| row.get(0))?;
+            tables.push((table.clone(), rows, Vec::new()));
+        }
+        let profile = ColumnProfile::scan(conn, &quote_identifier(&table), column, data_type, true, 3)?;
+        tables.last_mut().unwrap().2.push(profile);
+    }
+
+    let mut doc = String::new();
+    if html {
+        doc.push_str("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Data Dictionary</title></head><body>\n<h1>Data Dictionary</h1>\n");
+    } else {
+        doc.push_str("# Data Dictionary\n");
+    }
+
+    let headers = ["Column", "Type", "Nulls", "Distinct", "Min", "Max", "Samples"];
+    for (table, rows, columns) in &tables {
+        if html {
+            doc.push_str(&format!("<h2>{}</h2>\n<p>{} rows</p>\n<table border=\"1\">\n<tr>", escape_html(table), rows));
+            for h in headers {
+                doc.push_str(&format!("<th>{}</th>", h));
+            }
+            doc.push_str("</tr>\n");
+        } else {
+            doc.push_str(&format!("\n## {}\n\n{} rows\n\n| {} |\n|{}\n", table, rows, headers.join(" | "), "---|".repeat(headers.len())));
+        }
+
+        for col in columns {
+            let cells = [
+                col.name.clone(),
+                col.data_type.clone(),
+                col.nulls.to_string(),
+                col.distinct.to_string(),
+                col.min.clone().unwrap_or_default(),
+                col.max.clone().unwrap_or_default(),
+                col.samples.join(", "),
+            ];
+            if html {
+                doc.push_str("<tr>");
+                for cell in &cells {
+                    doc.push_str(&format!("<td>{}</td>", escape_html(cell)));
+                }
+                doc.push_str("</tr>\n");
+            } else {
+                let cells: Vec<String> = cells.iter().map(|c| c.replace('|', "\\|").replace('\n', " ")).collect();
+                doc.push_str(&format!("| {} |\n", cells.join(" | ")));
+            }
+        }
+
+        if html {
+            doc.push_str("</table>\n");
+        }
+    }
+    if html {
+        doc.push_str("</body></html>\n");
+    }
+
+    fs::write(path, doc).with_context(|| format!("Cannot write data dictionary to {}", path))?;
+    Ok(())
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// What the loader needs to know about one cell format (`cellXfs` entry) of an xlsx workbook
+#[derive(Clone, Copy, Default)]
+struct CellStyle {
+    /// A non-default pattern or gradient fill
+    filled: bool,
+    /// A percentage number format, built in (`0%`, `0.00%`) or custom
+    percent: bool,
+    /// The `--format-map` type its number format maps to
+    mapped: Option<&'static str>,
+    /// The digits a zero-padded number format such as `00000` shows
+    padded: Option<usize>,
+}
+
+/// A cell (or, with `col` unset, a whole row) that names a cell format in the worksheet XML
+struct StyledCell {
+    /// 0-based sheet row
+    row: u32,
+    /// 0-based sheet column, `None` for a row-level format
+    col: Option<u32>,
+    xf: usize,
+}
+
+/// Reads one part (file) of an xlsx package, or `None` if the package has no such part
+fn read_zip_part<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, name: &str) -> Result<Option<String>> {
+    match archive.by_name(name) {
+        Ok(mut part) => {
+            let mut xml = String::new();
+            part.read_to_string(&mut xml)?;
+            Ok(Some(xml))
+        }
+        Err(zip::result::ZipError::FileNotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The value of an XML attribute by its local name
+fn xml_attr(e: &quick_xml::events::BytesStart, key: &[u8]) -> Option<String> {
+    e.attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == key)
+        .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+}
+
+/// Resolves a sheet name to its worksheet part (`xl/worksheets/sheet1.xml`) via the workbook relationships
+fn worksheet_part<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, sheet_name: &str) -> Result<Option<String>> {
+    use quick_xml::events::Event;
+
+    let mut sheet_rel = None;
+    if let Some(workbook) = read_zip_part(archive, "xl/workbook.xml")? {
+        let mut reader = quick_xml::Reader::from_str(&workbook);
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) | Event::Empty(e)
+                    if e.local_name().as_ref() == b"sheet" && xml_attr(&e, b"name").as_deref() == Some(sheet_name) =>
+                {
+                    sheet_rel = xml_attr(&e, b"id");
+                    break;
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+    }
+    match sheet_rel {
+        Some(id) => workbook_relationship(archive, "xl/_rels/workbook.xml.rels", &id),
+        None => Ok(None),
+    }
+}
+
+/// The part a workbook relationship (`rId3`) points at, such as `xl/worksheets/sheet1.xml`
+fn workbook_relationship<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, rels_part: &str, id: &str) -> Result<Option<String>> {
+    use quick_xml::events::Event;
+
+    let Some(rels) = read_zip_part(archive, rels_part)? else {
+        return Ok(None);
+    };
+    let mut reader = quick_xml::Reader::from_str(&rels);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e)
+                if e.local_name().as_ref() == b"Relationship" && xml_attr(&e, b"Id").as_deref() == Some(id) =>
+            {
+                return Ok(xml_attr(&e, b"Target").map(|target| match target.strip_prefix('/') {
+                    Some(absolute) => absolute.to_string(),
+                    None => format!("xl/{}", target),
+                }));
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// The (rows, columns) an A1-style range such as `A1:E10001` spans
+fn reference_size(reference: &str) -> Option<(u64, u64)> {
+    let (first, last) = reference.split_once(':').unwrap_or((reference, reference));
+    let ((top, left), (bottom, right)) = (cell_position(first)?, cell_position(last)?);
+    Some((bottom.checked_sub(top)? as u64 + 1, right.checked_sub(left)? as u64 + 1))
+}
+
+/// The size an xlsx worksheet declares in its `<dimension>` element, read from the start of the
+/// worksheet part without parsing its cells
+fn declared_dimension(file_path: &Path, sheet_name: &str, args: &Config) -> Result<Option<(u64, u64)>> {
+    use quick_xml::events::Event;
+
+    let mut archive = zip::ZipArchive::new(workbook_reader(file_path, args)?)?;
+    let Some(part) = worksheet_part(&mut archive, sheet_name)? else {
+        return Ok(None);
+    };
+    let mut reader = quick_xml::Reader::from_reader(BufReader::new(archive.by_name(&part)?));
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"dimension" => return Ok(xml_attr(&e, b"ref").and_then(|r| reference_size(&r))),
+                b"sheetData" => return Ok(None),
+                _ => {}
+            },
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// Rough peak memory for reading and loading a sheet of `rows` x `cols`: calamine collects the
+/// cells before laying them out in a grid that holds every cell, blank ones included;
+/// `--keep-raw` and `--orientation columns` add a second grid, and the loader keeps one slice per
+/// row. Text is counted only by its cell, so text-heavy sheets need more.
+fn sheet_footprint(rows: u64, cols: u64, args: &Config) -> u64 {
+    let grid = rows * cols * std::mem::size_of::<Data>() as u64;
+    let cells = rows * cols * std::mem::size_of::<calamine::Cell<Data>>() as u64;
+    let copies = 1 + (args.keep_raw || args.orientation == Orientation::Columns) as u64;
+    cells + grid * copies + rows * std::mem::size_of::<&[Data]>() as u64
+}
+
+/// Applies `--max-memory` to a sheet of `rows` x `cols`
+fn check_sheet_memory(sheet_label: &str, (rows, cols): (u64, u64), args: &Config) -> Result<()> {
+    let Some(limit) = args.max_memory else {
+        return Ok(());
+    };
+    let estimate = sheet_footprint(rows, cols, args);
+    if estimate <= limit {
+        return Ok(());
+    }
+    let problem = format!(
+        "{} ({} rows x {} columns) needs an estimated {}, more than --max-memory {}",
+        sheet_label, rows, cols, format_byte_size(estimate), format_byte_size(limit)
+    );
+    match args.on_memory_limit {
+        OnMemoryLimit::Warn => {
+            warn!("{}", problem);
+            Ok(())
+        }
+        OnMemoryLimit::Refuse => anyhow::bail!("{}; split the sheet or raise the limit", problem),
+    }
+}
+
+/// Reads an xlsx sheet's cell formats and which cells use them. calamine does not expose
+/// styles, so this reads `styles.xml` and the worksheet XML straight out of the zip container.
+/// A sheet whose worksheet part can't be found has no styled cells.
+fn read_cell_styles(file_path: &Path, sheet_name: &str, format_map: &[FormatRule], args: &Config) -> Result<(Vec<CellStyle>, Vec<StyledCell>)> {
+    let mut archive = zip::ZipArchive::new(workbook_reader(file_path, args)?)?;
+    let styles = read_styles(&mut archive, format_map)?;
+    if !styles.iter().any(|style| style.filled || style.percent || style.mapped.is_some() || style.padded.is_some()) {
+        return Ok((styles, Vec::new()));
+    }
+    let Some(mut styled) = StyledCells::open(&mut archive, sheet_name)? else {
+        return Ok((styles, Vec::new()));
+    };
+    let mut cells = Vec::new();
+    while let Some(cell) = styled.next_cell()? {
+        cells.push(cell);
+    }
+    Ok((styles, cells))
+}
+
+/// The cell formats of an xlsx package's `styles.xml`, in the order cells refer to them
+fn read_styles<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, format_map: &[FormatRule]) -> Result<Vec<CellStyle>> {
+    use quick_xml::events::Event;
+
+    let mut styles = Vec::new();
+    if let Some(xml) = read_zip_part(archive, "xl/styles.xml")? {
+        let mut fills: Vec<bool> = Vec::new();
+        // Built-in formats 9 and 10 are `0%` and `0.00%`; custom ones are listed in numFmts
+        let mut percent_formats: HashSet<u32> = HashSet::from([9, 10]);
+        let mut custom_codes: HashMap<u32, String> = HashMap::new();
+        let (mut in_fills, mut in_cell_xfs) = (false, false);
+        let mut reader = quick_xml::Reader::from_str(&xml);
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                    b"fills" => in_fills = true,
+                    b"cellXfs" => in_cell_xfs = true,
+                    b"numFmt" => {
+                        let id = xml_attr(&e, b"numFmtId").and_then(|v| v.parse().ok());
+                        if let (Some(id), Some(code)) = (id, xml_attr(&e, b"formatCode")) {
+                            // Codes quote literal text, which the attribute holds as `&quot;`
+                            let code = quick_xml::escape::unescape(&code).map_or_else(|_| code.clone(), |code| code.into_owned());
+                            if is_percent_format(&code) {
+                                percent_formats.insert(id);
+                            }
+                            custom_codes.insert(id, code);
+                        }
+                    }
+                    b"fill" if in_fills => fills.push(false),
+                    b"patternFill" if in_fills => {
+                        let pattern = xml_attr(&e, b"patternType").unwrap_or_else(|| "none".to_string());
+                        if pattern != "none" && pattern != "gray125" {
+                            if let Some(last) = fills.last_mut() {
+                                *last = true;
+                            }
+                        }
+                    }
+                    b"gradientFill" if in_fills => {
+                        if let Some(last) = fills.last_mut() {
+                            *last = true;
+                        }
+                    }
+                    b"xf" if in_cell_xfs => {
+                        let fill_id: usize = xml_attr(&e, b"fillId").and_then(|v| v.parse().ok()).unwrap_or(0);
+                        let format_id: u32 = xml_attr(&e, b"numFmtId").and_then(|v| v.parse().ok()).unwrap_or(0);
+                        let code = custom_codes.get(&format_id).map(String::as_str).or_else(|| builtin_format_code(format_id));
+                        styles.push(CellStyle {
+                            filled: fills.get(fill_id).copied().unwrap_or(false),
+                            percent: percent_formats.contains(&format_id),
+                            mapped: code.and_then(|code| mapped_format_type(format_map, code)),
+                            padded: code.and_then(zero_padded_width),
+                        });
+                    }
+                    _ => {}
+                },
+                Event::End(e) => match e.local_name().as_ref() {
+                    b"fills" => in_fills = false,
+                    b"cellXfs" => in_cell_xfs = false,
+                    _ => {}
+                },
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+    }
+    Ok(styles)
+}
+
+/// The cells of an xlsx worksheet, and its rows with a format of their own, that name a cell
+/// format, read from the worksheet XML one at a time in sheet order
+struct StyledCells<R> {
+    reader: quick_xml::Reader<R>,
+    buf: Vec<u8>,
+    /// The 1-based row being read and the 0-based column of its last cell
+    row: u32,
+    col: Option<u32>,
+}
+
+impl<'z> StyledCells<BufReader<zip::read::ZipFile<'z>>> {
+    /// Starts reading a sheet's worksheet part; None if the package has no part for the sheet
+    fn open<R: Read + Seek>(archive: &'z mut zip::ZipArchive<R>, sheet_name: &str) -> Result<Option<Self>> {
+        let Some(part) = worksheet_part(archive, sheet_name)? else {
+            return Ok(None);
+        };
+        match archive.by_name(&part) {
+            Ok(xml) => Ok(Some(StyledCells { reader: quick_xml::Reader::from_reader(BufReader::new(xml)), buf: Vec::new(), row: 0, col: None })),
+            Err(zip::result::ZipError::FileNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl<R: BufRead> StyledCells<R> {
+    fn next_cell(&mut self) -> Result<Option<StyledCell>> {
+        use quick_xml::events::Event;
+
+        loop {
+            self.buf.clear();
+            match self.reader.read_event_into(&mut self.buf)? {
+                Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                    b"row" => {
+                        self.row = xml_attr(&e, b"r").and_then(|r| r.parse::<u32>().ok()).map_or(self.row + 1, |r| r);
+                        self.col = None;
+                        let xf = xml_attr(&e, b"s").and_then(|s| s.parse().ok());
+                        if let Some(xf) = xf.filter(|_| xml_attr(&e, b"customFormat").is_some_and(|v| v == "1" || v == "true")) {
+                            return Ok(Some(StyledCell { row: self.row.saturating_sub(1), col: None, xf }));
+                        }
+                    }
+                    b"c" => {
+                        let reference = xml_attr(&e, b"r");
+                        let row = reference.as_deref()
+                            .and_then(|r| r.trim_start_matches(|c: char| c.is_ascii_alphabetic()).parse::<u32>().ok())
+                            .unwrap_or(self.row);
+                        let col = reference.as_deref()
+                            .map(|r| r.chars().take_while(char::is_ascii_alphabetic).fold(0, |n, c| n * 26 + (c.to_ascii_uppercase() as u32 - 'A' as u32 + 1)))
+                            .filter(|&n| n > 0)
+                            .map_or_else(|| self.col.map_or(0, |c| c + 1), |n| n - 1);
+                        self.col = Some(col);
+                        if let Some(xf) = xml_attr(&e, b"s").and_then(|s| s.parse().ok()) {
+                            return Ok(Some(StyledCell { row: row.saturating_sub(1), col: Some(col), xf }));
+                        }
+                    }
+                    _ => {}
+                },
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Whether a custom number format code shows values as a percentage: a `%` outside
+/// quoted text and not escaped with a backslash
+fn is_percent_format(code: &str) -> bool {
+    let (mut quoted, mut escaped) = (false, false);
+    for c in code.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            '%' if !quoted => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// The digits a number format shows when it is nothing but zeros, like `00000`: codes such as
+/// ZIPs stored as numbers, whose leading zeros only the format adds
+fn zero_padded_width(code: &str) -> Option<usize> {
+    let section = code.split(';').next().unwrap_or_default();
+    (section.len() > 1 && section.bytes().all(|b| b == b'0')).then_some(section.len())
+}
+
+/// Turns the whole numbers of an xlsx sheet's cells with a zero-padded format (see
+/// [`zero_padded_width`]) into the text the sheet shows, so 501 formatted `00000` loads as
+/// "00501". Like the other style readers it reads the file on disk, so piped workbooks keep their numbers.
+fn restore_padded_codes(file_path: &Path, sheet_name: &str, mut range: Range<Data>, args: &Config) -> Result<Range<Data>> {
+    let is_xlsx = file_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("xlsx"));
+    if !is_xlsx || args.stdin_data.is_some() {
+        return Ok(range);
+    }
+    let (styles, cells) = read_cell_styles(file_path, sheet_name, &[], args).with_context(|| format!("Reading the number formats of sheet {}", sheet_name))?;
+    for cell in &cells {
+        let (Some(col), Some(width)) = (cell.col, styles.get(cell.xf).and_then(|style| style.padded)) else {
+            continue;
+        };
+        if let Some(code) = range.get_value((cell.row, col)).and_then(|value| padded_code(value, width)) {
+            range.set_value((cell.row, col), code);
+        }
+    }
+    Ok(range)
+}
+
+/// The text a whole number shows under a zero-padded format `width` digits wide; None for
+/// other values
+fn padded_code(value: &Data, width: usize) -> Option<Data> {
+    let digits = match value {
+        Data::Int(v) if *v >= 0 => v.to_string(),
+        Data::Float(v) if *v >= 0.0 && *v < 1e15 && v.fract() == 0.0 => (*v as i64).to_string(),
+        _ => return None,
+    };
+    Some(Data::String(format!("{:0>width$}", digits)))
+}
+
+/// Returns the (0-based) rows of an xlsx sheet where any cell, or the row itself,
+/// uses a cell format with a non-default fill.
+fn highlighted_rows(file_path: &Path, sheet_name: &str, args: &Config) -> Result<HashSet<u32>> {
+    let (styles, cells) = read_cell_styles(file_path, sheet_name, &[], args)?;
+    Ok(cells.iter()
+        .filter(|cell| styles.get(cell.xf).is_some_and(|style| style.filled))
+        .map(|cell| cell.row)
+        .collect())
+}
+
+/// Returns the (0-based) columns of an xlsx sheet that hold cells with a percentage format
+fn percent_columns(file_path: &Path, sheet_name: &str, args: &Config) -> Result<HashSet<u32>> {
+    let (styles, cells) = read_cell_styles(file_path, sheet_name, &[], args)?;
+    Ok(cells.iter()
+        .filter(|cell| styles.get(cell.xf).is_some_and(|style| style.percent))
+        .filter_map(|cell| cell.col)
+        .collect())
+}
+
+/// Returns the (0-based) columns of an xlsx sheet whose data cells (in the given 0-based rows)
+/// use number formats that `--format-map` maps to one type. A column whose formats map to
+/// different types is left to inference.
+fn format_mapped_columns(file_path: &Path, sheet_name: &str, data_rows: std::ops::RangeInclusive<u32>, format_map: &[FormatRule], args: &Config) -> Result<HashMap<u32, &'static str>> {
+    let (styles, cells) = read_cell_styles(file_path, sheet_name, format_map, args)?;
+    let mut columns: HashMap<u32, Option<&'static str>> = HashMap::new();
+    for cell in cells.iter().filter(|cell| data_rows.contains(&cell.row)) {
+        let (Some(col), Some(dtype)) = (cell.col, styles.get(cell.xf).and_then(|style| style.mapped)) else {
+            continue;
+        };
+        let entry = columns.entry(col).or_insert(Some(dtype));
+        if *entry != Some(dtype) {
+            *entry = None;
+        }
+    }
+    Ok(columns.into_iter().filter_map(|(col, dtype)| Some((col, dtype?))).collect())
+}
+
+/// The records of an `.xlsb` part: a record type and a data length, both little-endian base-128
+/// numbers, then the data. Stops at the first record that runs past the end.
+fn xlsb_records(mut data: &[u8]) -> Vec<(u16, &[u8])> {
+    fn varint(data: &mut &[u8], max_bytes: usize) -> Option<usize> {
+        let mut value = 0;
+        for i in 0..max_bytes {
+            let (&byte, rest) = data.split_first()?;
+            *data = rest;
+            value |= ((byte & 0x7F) as usize) << (7 * i);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        Some(value)
+    }
+    let mut records = Vec::new();
+    while let (Some(typ), Some(len)) = (varint(&mut data, 2), varint(&mut data, 4)) {
+        if len > data.len() {
+            break;
+        }
+        let (record, rest) = data.split_at(len);
+        records.push((typ as u16, record));
+        data = rest;
+    }
+    records
+}
+
+/// An `.xlsb` wide string (a u32 character count, then UTF-16LE) and the bytes it takes up
+fn xlsb_wide_str(data: &[u8]) -> Option<(String, usize)> {
+    let len = u32::from_le_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let units: Vec<u16> = data.get(4..4 + len * 2)?.chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+    Some((String::from_utf16_lossy(&units), 4 + len * 2))
+}
+
+/// Whether a number format code shows a date or a time, and which: elapsed times like `[h]:mm`
+/// are durations, and any other d, m, y, h or s outside quoted text, escapes and `[...]` is a date
+fn date_format_kind(code: &str) -> Option<calamine::ExcelDateTimeType> {
+    let (mut quoted, mut escaped, mut bracket) = (false, false, None::<String>);
+    for c in code.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' | '_' => escaped = true,
+            '"' => quoted = !quoted,
+            _ if quoted => {}
+            ';' => break,
+            '[' => bracket = Some(String::new()),
+            ']' => {
+                let inside = bracket.take().unwrap_or_default().to_lowercase();
+                if !inside.is_empty() && inside.chars().all(|c| matches!(c, 'h' | 'm' | 's')) {
+                    return Some(calamine::ExcelDateTimeType::TimeDelta);
+                }
+            }
+            _ if bracket.is_some() => bracket.as_mut().unwrap().push(c),
+            'd' | 'm' | 'y' | 'h' | 's' | 'D' | 'M' | 'Y' | 'H' | 'S' => return Some(calamine::ExcelDateTimeType::DateTime),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The whole-number cells of an `.xlsb` sheet that carry a date or duration format, by
+/// (row, column) and read as dates. Excel saves whole numbers as RK integers, and calamine reads
+/// those as plain integers whatever their format, so a date without a time of day would
+/// otherwise load as its serial number.
+fn xlsb_integer_dates(file_path: &Path, sheet_name: &str, args: &Config) -> Result<Vec<((u32, u32), Data)>> {
+    let mut archive = zip::ZipArchive::new(workbook_reader(file_path, args)?)?;
+    let mut dates = Vec::new();
+    let Some(styles) = xlsb_date_styles(&mut archive, sheet_name)? else {
+        return Ok(dates);
+    };
+    let Some(mut cells) = XlsbIntegerDates::open(&mut archive, styles)? else {
+        return Ok(dates);
+    };
+    while let Some(date) = cells.next_date()? {
+        dates.push(date);
+    }
+    Ok(dates)
+}
+
+fn xlsb_u16(data: &[u8], at: usize) -> u16 {
+    data.get(at..at + 2).map_or(0, |b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn xlsb_u32(data: &[u8], at: usize) -> u32 {
+    data.get(at..at + 4).map_or(0, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// What finding an `.xlsb` sheet's whole-number dates takes: its part, the date kind of each
+/// cell format and whether the workbook counts dates from 1904
+struct XlsbDateStyles {
+    part: String,
+    kinds: Vec<Option<calamine::ExcelDateTimeType>>,
+    is_1904: bool,
+}
+
+/// Reads an `.xlsb` package's date formats for one sheet; None when the sheet has no part or
+/// no cell format shows a date
+fn xlsb_date_styles<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, sheet_name: &str) -> Result<Option<XlsbDateStyles>> {
+    // A missing part reads as no records
+    let read_part = |archive: &mut zip::ZipArchive<_>, name: &str| -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        match archive.by_name(name) {
+            Ok(mut part) => {
+                part.read_to_end(&mut data)?;
+            }
+            Err(zip::result::ZipError::FileNotFound) => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(data)
+    };
+
+    // Number formats 14-22, 45 and 47 are built-in dates and times, 46 is `[h]:mm:ss`
+    let mut kinds: HashMap<u16, calamine::ExcelDateTimeType> = (14..=22).chain([45, 47])
+        .map(|code| (code, calamine::ExcelDateTimeType::DateTime))
+        .chain([(46, calamine::ExcelDateTimeType::TimeDelta)])
+        .collect();
+    let mut styles = Vec::new();
+    let mut in_cell_xfs = false;
+    for (typ, data) in xlsb_records(&read_part(archive, "xl/styles.bin")?) {
+        match typ {
+            // BrtFmt: a custom number format
+            0x002C => {
+                if let Some(kind) = xlsb_wide_str(&data[data.len().min(2)..]).and_then(|(code, _)| date_format_kind(&code)) {
+                    kinds.insert(xlsb_u16(data, 0), kind);
+                }
+            }
+            0x0269 => in_cell_xfs = true,
+            0x026A => in_cell_xfs = false,
+            // BrtXF: a cell format, pointing at its number format
+            0x002F if in_cell_xfs => styles.push(kinds.get(&xlsb_u16(data, 2)).copied()),
+            _ => {}
+        }
+    }
+
+    let (mut is_1904, mut sheet_rel) = (false, None);
+    for (typ, data) in xlsb_records(&read_part(archive, "xl/workbook.bin")?) {
+        match typ {
+            // BrtWbProp
+            0x0099 => is_1904 = xlsb_u32(data, 0) & 1 != 0,
+            // BrtBundleSh: the sheet's relationship id, then its name
+            0x009C => {
+                let Some((rel, len)) = xlsb_wide_str(&data[data.len().min(8)..]) else { continue };
+                if xlsb_wide_str(&data[(8 + len).min(data.len())..]).is_some_and(|(name, _)| name == sheet_name) {
+                    sheet_rel = Some(rel);
+                }
+            }
+            _ => {}
+        }
+    }
+    let Some(part) = sheet_rel.map(|rel| workbook_relationship(archive, "xl/_rels/workbook.bin.rels", &rel)).transpose()?.flatten() else {
+        return Ok(None);
+    };
+    if styles.iter().all(Option::is_none) {
+        return Ok(None);
+    }
+    Ok(Some(XlsbDateStyles { part, kinds: styles, is_1904 }))
+}
+
+/// The records of an `.xlsb` part read one at a time from a stream, the way [`xlsb_records`]
+/// reads them from bytes
+struct XlsbRecordReader<R> {
+    reader: R,
+    /// The data of the record last read
+    data: Vec<u8>,
+}
+
+impl<R: Read> XlsbRecordReader<R> {
+    /// Reads the next record's data and returns its type; None at the end or at a record that
+    /// runs past it
+    fn next_record(&mut self) -> Result<Option<u16>> {
+        fn varint(reader: &mut impl Read, max_bytes: usize) -> Result<Option<usize>> {
+            let mut value = 0;
+            for i in 0..max_bytes {
+                let mut byte = [0];
+                if reader.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                value |= ((byte[0] & 0x7F) as usize) << (7 * i);
+                if byte[0] & 0x80 == 0 {
+                    break;
+                }
+            }
+            Ok(Some(value))
+        }
+        let (Some(typ), Some(len)) = (varint(&mut self.reader, 2)?, varint(&mut self.reader, 4)?) else {
+            return Ok(None);
+        };
+        self.data.clear();
+        (&mut self.reader).take(len as u64).read_to_end(&mut self.data)?;
+        Ok((self.data.len() == len).then_some(typ as u16))
+    }
+}
+
+/// The whole-number cells of an `.xlsb` sheet that carry a date or duration format, read as
+/// dates one at a time in sheet order (see [`xlsb_integer_dates`])
+struct XlsbIntegerDates<R> {
+    records: XlsbRecordReader<R>,
+    styles: XlsbDateStyles,
+    row: u32,
+}
+
+impl<'z> XlsbIntegerDates<BufReader<zip::read::ZipFile<'z>>> {
+    /// Starts reading the sheet part `styles` names; None if the package doesn't hold it
+    fn open<R: Read + Seek>(archive: &'z mut zip::ZipArchive<R>, styles: XlsbDateStyles) -> Result<Option<Self>> {
+        match archive.by_name(&styles.part) {
+            Ok(part) => Ok(Some(XlsbIntegerDates { records: XlsbRecordReader { reader: BufReader::new(part), data: Vec::new() }, styles, row: 0 })),
+            Err(zip::result::ZipError::FileNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl<R: Read> XlsbIntegerDates<R> {
+    fn next_date(&mut self) -> Result<Option<((u32, u32), Data)>> {
+        while let Some(typ) = self.records.next_record()? {
+            let data = &self.records.data;
+            match typ {
+                // BrtRowHdr
+                0x0000 => self.row = xlsb_u32(data, 0),
+                // BrtCellRk holding an integer (bit 1) that isn't scaled by 100 (bit 0)
+                0x0002 if xlsb_u32(data, 8) & 3 == 2 => {
+                    let style = (xlsb_u32(data, 4) & 0xFF_FFFF) as usize;
+                    if let Some(&Some(kind)) = self.styles.kinds.get(style) {
+                        let serial = (xlsb_u32(data, 8) as i32 >> 2) as f64;
+                        let date = Data::DateTime(calamine::ExcelDateTime::new(serial, kind, self.styles.is_1904));
+                        return Ok(Some(((self.row, xlsb_u32(data, 0)), date)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Cuts a sheet down to its `--range` block, if it has one (sheet names compare case-insensitively).
+/// The block keeps its top-left corner where the sheet is blank, so its first row is still the
+/// header, but ends at the sheet's last used row and column.
+fn restrict_to_range(range: Range<Data>, sheet_name: &str, args: &Config) -> Range<Data> {
+    let Some(block) = args.ranges.iter().find(|block| block.sheet.eq_ignore_ascii_case(sheet_name)) else {
+        return range;
+    };
+    let Some(used_end) = range.end() else {
+        return range;
+    };
+    let end = (block.end.0.min(used_end.0), block.end.1.min(used_end.1));
+    if end.0 < block.start.0 || end.1 < block.start.1 {
+        return Range::empty();
+    }
+    range.range(block.start, end)
+}
+
+/// With `--split-on-blank`, the tables stacked in a sheet: runs of rows that a blank row, or a
+/// row repeating the run's header, ends. Each block keeps its place in the sheet and is trimmed
+/// to the columns it uses. A sheet holding a single table comes back whole and unnumbered.
+fn sheet_parts(range: Range<Data>, args: &Config) -> Vec<(Option<usize>, Range<Data>)> {
+    let Some((row0, col0)) = range.start().filter(|_| args.split_on_blank) else {
+        return vec![(None, range)];
+    };
+    let rows: Vec<&[Data]> = range.rows().collect();
+    // (first, last) row indexes of each block
+    let mut blocks: Vec<(usize, usize)> = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, row) in rows.iter().enumerate() {
+        if is_blank_row(row) {
+            blocks.extend(start.take().map(|first| (first, i - 1)));
+        } else if let Some(first) = start {
+            if !args.no_header && i > first + 1 && *row == rows[first] {
+                blocks.push((first, i - 1));
+                start = Some(i);
+            }
+        } else {
+            start = Some(i);
+        }
+    }
+    blocks.extend(start.map(|first| (first, rows.len() - 1)));
+    if blocks.len() < 2 {
+        return vec![(None, range)];
+    }
+    blocks.iter().enumerate().map(|(n, &(first, last))| {
+        let used = |row: &&[Data]| row.iter().position(|cell| !matches!(cell, Data::Empty)).zip(row.iter().rposition(|cell| !matches!(cell, Data::Empty)));
+        let (left, right) = rows[first..=last].iter().filter_map(used)
+            .fold((usize::MAX, 0), |(left, right), (l, r)| (left.min(l), right.max(r)));
+        let block = range.range((row0 + first as u32, col0 + left as u32), (row0 + last as u32, col0 + right as u32));
+        (Some(n + 1), block)
+    }).collect()
+}
+
+/// The table names of one block of a sheet `--split-on-blank` splits: the sheet's own, with
+/// the block number after them. A shared `--single-table` target is left as it is.
+fn part_tables((table_name, target_table): (String, String), part: Option<usize>, args: &Config) -> (String, String) {
+    match part {
+        None => (table_name, target_table),
+        Some(n) if args.single_table.is_some() => (format!("{}_{}", table_name, n), target_table),
+        Some(n) => (format!("{}_{}", table_name, n), format!("{}_{}", target_table, n)),
+    }
+}
+
+/// Turns an `.xlsb` sheet's whole-number dates (see [`xlsb_integer_dates`]) back into dates
+fn restore_xlsb_dates(file_path: &Path, sheet_name: &str, mut range: Range<Data>, args: &Config) -> Result<Range<Data>> {
+    let is_xlsb = file_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("xlsb"));
+    if !is_xlsb {
+        return Ok(range);
+    }
+    let dates = xlsb_integer_dates(file_path, sheet_name, args).with_context(|| format!("Reading the date formats of sheet {}", sheet_name))?;
+    for (position, date) in dates {
+        if matches!(range.get_value(position), Some(Data::Int(_))) {
+            range.set_value(position, date);
+        }
+    }
+    Ok(range)
+}
+
+/// Builds the numbered file name for one export partition, e.g. `out.csv` -> `out_00003.csv`.
+fn partition_path(path: &str, index: usize) -> PathBuf {
+    let path = Path::new(path);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{:05}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}_{:05}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+/// Exports a query in fixed-size partitions using ORDER BY + LIMIT/OFFSET.
+/// Each partition is written to a `.partial` file and renamed once complete, so
+/// partitions that already exist from an earlier (failed) run are skipped on resume.
+fn export_partitioned(conn: &Connection, query: &str, path: &str, rows_per_part: usize, order_by: &str, retries: usize, csv_output: &CsvOutput) -> Result<usize> {
+    if rows_per_part == 0 {
+        anyhow::bail!("--export-partition-rows must be greater than 0");
+    }
+    let format = ExportFormat::from_path(path)?;
+    let total: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM ({})", query), [], |row| row.get(0))?;
+    let partitions = (total as usize).div_ceil(rows_per_part);
+
+    for index in 0..partitions {
+        let target = partition_path(path, index + 1);
+        if target.exists() {
+            info!("Skipping completed partition {}", target.display());
+            continue;
+        }
+
+        let mut partial = target.clone().into_os_string();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let copy_sql = format!(
+            "COPY (SELECT * FROM ({}) ORDER BY {} LIMIT {} OFFSET {}) TO '{}' {}",
+            query, order_by, rows_per_part, index * rows_per_part, partial.display(), format.copy_options(csv_output)
+        );
+
+        let mut attempt = 1;
+        while let Err(e) = conn.execute(&copy_sql, []) {
+            if attempt >= retries.max(1) {
+                return Err(e).with_context(|| format!("Exporting partition {} failed after {} attempt(s)", index + 1, attempt));
+            }
+            warn!("Partition {} failed ({}), retrying...", index + 1, e);
+            std::thread::sleep(std::time::Duration::from_millis(500 * attempt as u64));
+            attempt += 1;
+        }
+        if format.is_delimited() {
+            csv_output.apply(&partial)?;
+        }
+        fs::rename(&partial, &target)?;
+        info!("Wrote partition {}", target.display());
+    }
+
+    Ok(partitions)
+}
+
+/// Column layout inferred for one sheet
+struct SheetSchema {
+    names: Vec<String>,
+    types: Vec<&'static str>,
+    /// Number locale each column's text is read with, if any
+    locales: Vec<Option<NumberLocale>>,
+    /// Why each type was chosen; only filled in for `--explain-types` and `--verbose`
+    reasons: Vec<String>,
+    /// Whether each type was declared (config file or `--schema`) rather than inferred
+    declared: Vec<bool>,
+    /// Whether each column is VARCHAR only because its sampled values mixed types
+    mixed: Vec<bool>,
+}
+
+/// What a sheet's xlsx number formats say about its columns, keyed by lowercase (sanitized)
+/// column name because config projections reorder columns
+#[derive(Default)]
+struct ColumnFormats {
+    /// `--detect-percent`: columns whose cells carry a percentage format
+    percent: HashSet<String>,
+    /// `--format-map`: columns whose formats map to a type
+    mapped: HashMap<String, &'static str>,
+}
+
+/// Reads the number formats `--detect-percent` and `--format-map` type columns by; empty
+/// without either option or for files other than xlsx
+fn column_formats(file_path: &Path, sheet_name: &str, range: &Range<Data>, sheet_config: Option<&SheetConfig>, args: &Config) -> Result<ColumnFormats> {
+    let is_xlsx = file_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("xlsx"));
+    if !is_xlsx || (!args.detect_percent && args.format_map.is_empty()) {
+        return Ok(ColumnFormats::default());
+    }
+    if args.orientation == Orientation::Columns {
+        let flag = if args.detect_percent { "--detect-percent" } else { "--format-map" };
+        anyhow::bail!("{} reads formats per column, so it cannot be combined with --orientation columns", flag);
+    }
+    let header = select_header_row(locate_header(range.clone(), sheet_config, args), sheet_config.and_then(|config| config.header_row), args)?;
+    let (header_row, first_col) = header.start().unwrap_or((0, 0));
+    let names = header.rows().next().map(|row| unique_column_names(row, args.header_style, args.identifier_case, args.ascii_only, args.max_identifier_length)).unwrap_or_default();
+    let percent = if args.detect_percent { percent_columns(file_path, sheet_name, args)? } else { HashSet::new() };
+    let mapped = if args.format_map.is_empty() { HashMap::new() } else { format_mapped_columns(file_path, sheet_name, header_row + 1..=range.end().map_or(0, |(row, _)| row), &args.format_map, args)? };
+    let mut formats = ColumnFormats::default();
+    for (i, name) in names.into_iter().enumerate() {
+        let col = first_col + i as u32;
+        if percent.contains(&col) {
+            formats.percent.insert(name.to_lowercase());
+        }
+        if let Some(dtype) = mapped.get(&col) {
+            formats.mapped.insert(name.to_lowercase(), dtype);
+        }
+    }
+    Ok(formats)
+}
+
+/// Derives column names and types for a sheet from its header row and data rows,
+/// without touching the database.
+fn infer_sheet_schema(headers: &[Data], rows_data: &[&[Data]], table_name: &str, sheet_config: Option<&SheetConfig>, formats: &ColumnFormats, args: &Config) -> Result<SheetSchema> {
+    let mut column_names = Vec::new();
+    let mut column_types = Vec::new();
+    let mut column_locales = Vec::new();
+    let mut reasons = Vec::new();
+    let mut mixed = Vec::new();
+
+    // Infer types from a sample of data rows (head, tail or a random spread)
+    let sample = sample_rows(rows_data, args.infer_rows, args.sample_from);
+
+    column_names.extend(unique_column_names(headers, args.header_style, args.identifier_case, args.ascii_only, args.max_identifier_length));
+    // A plain text dump: nothing is inferred, declared or converted
+    if args.all_text {
+        let reasons = if args.explain_types || args.verbose { vec!["VARCHAR: --all-text".to_string(); headers.len()] } else { Vec::new() };
+        let width = headers.len();
+        return Ok(SheetSchema { names: column_names, types: vec!["VARCHAR"; width], locales: vec![None; width], reasons, declared: vec![false; width], mixed: vec![false; width] });
+    }
+    for i in 0..headers.len() {
+        let locale = resolve_column_locale(&sample, i, args.text_number_locale());
+        let (dtype, fitting, total) = match args.type_strategy {
+            TypeStrategy::Widen => (infer_column_type(&sample, i, locale), 0, 0),
+            TypeStrategy::Majority => majority_column_type(&sample, i, locale),
+        };
+        // Whole numbers past BIGINT only become HUGEINT on request; otherwise they keep their text
+        let dtype = match dtype {
+            "HUGEINT" if args.bigint_overflow == BigintOverflow::Text => "VARCHAR",
+            dtype => dtype,
+        };
+        column_types.push(dtype);
+        column_locales.push(locale);
+        mixed.push(dtype == "VARCHAR" && falls_back_to_text(&sample, i, locale));
+        if args.explain_types || args.verbose {
+            reasons.push(match fitting < total {
+                true => format!("{}: {} of {} sampled value(s) fit (--type-strategy majority)", dtype, fitting, total),
+                false if dtype == "VARCHAR" && infer_column_type(&sample, i, locale) == "HUGEINT" => {
+                    "VARCHAR: whole numbers past BIGINT's range (--bigint-overflow text)".to_string()
+                }
+                false => explain_column_type(&sample, i, locale),
+            });
+        }
+    }
+
+    // xlsx stores every number as a float, so whole-valued DOUBLE columns count as integer columns too
+    if args.tight_ints {
+        let sized_rows = if args.int_overflow == IntOverflow::Widen { rows_data } else { &sample };
+        for (i, dtype) in column_types.iter_mut().enumerate() {
+            if *dtype != "BIGINT" && *dtype != "DOUBLE" {
+                continue;
+            }
+            let values: Option<Vec<i64>> = sized_rows.iter()
+                .filter(|row| !matches!(row.get(i), None | Some(Data::Empty) | Some(Data::Error(_))))
+                .map(|row| cell_integer(row.get(i), column_locales[i]))
+                .collect();
+            let Some(values) = values.filter(|v| !v.is_empty()) else {
+                continue;
+            };
+            let (min, max) = values.iter().fold((i64::MAX, i64::MIN), |(lo, hi), &v| (v.min(lo), v.max(hi)));
+            *dtype = tightest_int_type(min, max);
+            if let Some(reason) = reasons.get_mut(i) {
+                *reason = format!("{}: whole values from {} to {} (--tight-ints)", dtype, min, max);
+            }
+
+            if args.int_overflow == IntOverflow::Error {
+                for (row_index, row) in rows_data.iter().enumerate() {
+                    if matches!(row.get(i), None | Some(Data::Empty) | Some(Data::Error(_))) {
+                        continue;
+                    }
+                    let fits = cell_integer(row.get(i), column_locales[i])
+                        .is_some_and(|v| tightest_int_type(min.min(v), max.max(v)) == *dtype);
+                    if !fits {
+                        anyhow::bail!(
+                            "Value {} in {}.{} (data row {}) does not fit {} sized from the sample; use --int-overflow widen",
+                            row[i], table_name, column_names[i], row_index + 1, dtype
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // Percentage-formatted numbers are fractions, so whole percentages (0%, 100%) must not make
+    // the column an integer one; columns inferred as text stay text
+    for (i, name) in column_names.iter().enumerate().filter(|(_, name)| formats.percent.contains(&name.to_lowercase())) {
+        let dtype = &mut column_types[i];
+        if matches!(*dtype, "TINYINT" | "SMALLINT" | "INTEGER" | "BIGINT" | "DOUBLE") || dtype.starts_with("DECIMAL") {
+            *dtype = "DOUBLE";
+            if let Some(reason) = reasons.get_mut(i) {
+                *reason = format!("DOUBLE: {} uses a percentage number format (--detect-percent)", name);
+            }
+        }
+    }
+
+    // The number formats the workbook's author chose say what a column holds; columns whose
+    // formats --format-map does not name keep their inferred type
+    for (i, name) in column_names.iter().enumerate() {
+        if let Some(dtype) = formats.mapped.get(&name.to_lowercase()) {
+            column_types[i] = dtype;
+            if let Some(reason) = reasons.get_mut(i) {
+                *reason = format!("{}: {} uses a number format named in --format-map", dtype, name);
+            }
+        }
+    }
+
+    // Header suffixes encode the team's naming convention and beat inference
+    if let Some(suffix_types) = &args.suffix_types {
+        for (i, name) in column_names.iter().enumerate() {
+            if let Some((suffix, dtype)) = suffix_types.type_for(name) {
+                column_types[i] = dtype;
+                if let Some(reason) = reasons.get_mut(i) {
+                    *reason = format!("{}: header ends in {} (--suffix-types)", dtype, suffix);
+                }
+            }
+        }
+    }
+
+    // Codes such as ZIPs keep their exact text; a table's own declarations below still win
+    for (i, name) in column_names.iter().enumerate() {
+        if args.text_columns.iter().any(|column| sanitize_identifier(column).eq_ignore_ascii_case(name)) {
+            column_types[i] = "VARCHAR";
+            if let Some(reason) = reasons.get_mut(i) {
+                *reason = "VARCHAR: named in --text-columns".to_string();
+            }
+        }
+    }
+
+    // Money stays exact to the cent rather than DOUBLE; a table's own declarations below still win
+    if !args.money_columns.is_empty() {
+        let money = parse_column_type(&format!("DECIMAL(18,{})", args.money_scale)).context("--money-scale is out of range")?;
+        for (i, name) in column_names.iter().enumerate() {
+            if args.money_columns.iter().any(|column| sanitize_identifier(column).eq_ignore_ascii_case(name)) {
+                column_types[i] = money;
+                if let Some(reason) = reasons.get_mut(i) {
+                    *reason = format!("{}: named in --money-columns", money);
+                }
+            }
+        }
+    }
+
+    // Types forced by the config file win over anything inferred
+    let mut declared = vec![false; column_names.len()];
+    for (column, dtype) in sheet_config.map(|c| &c.types).into_iter().flatten() {
+        let wanted = args.header_style.apply(sanitize_identifier(column));
+        let i = column_names.iter().position(|name| name.eq_ignore_ascii_case(&wanted))
+            .with_context(|| format!("Config sets a type for column '{}', which {} does not have", column, table_name))?;
+        column_types[i] = parse_column_type(dtype)
+            .with_context(|| format!("Unsupported type '{}' for {}.{}; expected DECIMAL(p,s) or one of {}", dtype, table_name, column_names[i], OVERRIDE_TYPES.join(", ")))?;
+        declared[i] = true;
+        if let Some(reason) = reasons.get_mut(i) {
+            *reason = format!("{}: set by the config file", column_types[i]);
+        }
+    }
+
+    // --schema declares types for whole tables and has the last word
+    for (column, dtype) in args.schema_overrides.columns_for(table_name).into_iter().flatten() {
+        let i = column_names.iter().position(|name| name.eq_ignore_ascii_case(column))
+            .with_context(|| format!("--schema sets a type for {}.{}, which the sheet does not have", table_name, column))?;
+        column_types[i] = dtype;
+        declared[i] = true;
+        if let Some(reason) = reasons.get_mut(i) {
+            *reason = format!("{}: declared in --schema", dtype);
+        }
+    }
+
+    // Naive date-times only mean an instant once a zone is given; dates stay dates
+    if let Some(zone) = &args.timezone {
+        for (i, dtype) in column_types.iter_mut().enumerate().filter(|(_, dtype)| **dtype == "TIMESTAMP") {
+            *dtype = "TIMESTAMPTZ";
+            if let Some(reason) = reasons.get_mut(i) {
+                *reason = format!("TIMESTAMPTZ: date-times read in {} (--timezone); {}", zone.name, reason);
+            }
+        }
+    }
+
+    // Columns something above set the type of are no longer silent fallbacks
+    for (i, flag) in mixed.iter_mut().enumerate() {
+        *flag &= column_types[i] == "VARCHAR" && !declared[i]
+            && !args.text_columns.iter().any(|column| sanitize_identifier(column).eq_ignore_ascii_case(&column_names[i]));
+    }
+    Ok(SheetSchema { names: column_names, types: column_types, locales: column_locales, reasons, declared, mixed })
+}
+
+/// Prints sheet names, dimensions and inferred column types for every file
+/// without creating a database connection.
+fn probe_files(files: &[PathBuf], root: &Path, format: ProbeFormat, args: &Config) -> Result<()> {
+    let report = probe_report(files, root, args)?;
+
+    match format {
+        ProbeFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        ProbeFormat::Table => {
+            for file in &report {
+                println!("{}", file["file"].as_str().unwrap_or_default());
+                if let Some(error) = file["error"].as_str() {
+                    println!("  error: {}", error);
+                }
+                for sheet in file["sheets"].as_array().into_iter().flatten() {
+                    if let Some(error) = sheet["error"].as_str() {
+                        println!("  {}: error: {}", sheet["sheet"].as_str().unwrap_or_default(), error);
+                        continue;
+                    }
+                    if let Some(reason) = sheet["skipped"].as_str() {
+                        println!("  {}: skipped: {}", sheet["sheet"].as_str().unwrap_or_default(), reason);
+                        continue;
+                    }
+                    println!(
+                        "  {} -> {} ({} rows x {} columns)",
+                        sheet["sheet"].as_str().unwrap_or_default(),
+                        sheet["table"].as_str().unwrap_or_default(),
+                        sheet["rows"],
+                        sheet["columns"]
+                    );
+                    for column in sheet["schema"].as_array().into_iter().flatten() {
+                        match column["reason"].as_str() {
+                            Some(reason) => println!("      {:<32} {}", column["name"].as_str().unwrap_or_default(), reason),
+                            None => println!("      {:<32} {}", column["name"].as_str().unwrap_or_default(), column["type"].as_str().unwrap_or_default()),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Sheets that sanitize to the same table name would overwrite each other when loaded;
+    // in --single-table and --append runs, and for --union-by tables, sharing a table is the point
+    if args.single_table.is_none() && !args.append {
+        let mut sources: Vec<(String, Vec<String>)> = Vec::new();
+        for file in &report {
+            for sheet in file["sheets"].as_array().into_iter().flatten() {
+                let Some(table) = sheet["table"].as_str() else { continue };
+                if args.union_tables.values().any(|union| union == unqualified_table(table)) {
+                    continue;
+                }
+                let source = format!("{} ({})", file["file"].as_str().unwrap_or_default(), sheet["sheet"].as_str().unwrap_or_default());
+                match sources.iter_mut().find(|(name, _)| name.eq_ignore_ascii_case(table)) {
+                    Some((_, list)) => list.push(source),
+                    None => sources.push((table.to_string(), vec![source])),
+                }
+            }
+        }
+        for (table, list) in sources.iter().filter(|(_, list)| list.len() > 1) {
+            warn!("table {} would be loaded from {}; see --on-collision", table, list.join(" and "));
+        }
+    }
+
+    if args.dry_run {
+        let planned: Vec<&serde_json::Value> = report.iter()
+            .flat_map(|file| file["sheets"].as_array().into_iter().flatten())
+            .filter(|sheet| sheet["table"].is_string())
+            .collect();
+        let rows: u64 = planned.iter().filter_map(|sheet| sheet["rows"].as_u64()).sum();
+        println!("Dry run: {} sheet(s) and {} row(s) would be loaded into {}; nothing was written", planned.len(), rows, redacted_db(&args.db));
+    }
+    Ok(())
+}
+
+/// One table's name, columns and row count, as a load would leave it or as the database holds it
+#[derive(Clone, Debug, Default)]
+struct TableShape {
+    name: String,
+    columns: Vec<(String, String)>,
+    rows: u64,
+}
+
+/// The data tables of one schema of a DuckDB database, row counts left at zero
+fn table_shapes(conn: &Connection, schema: &str) -> Result<Vec<TableShape>> {
+    let mut stmt = conn.prepare(
+        "SELECT table_name, column_name, data_type FROM information_schema.columns \
+         WHERE table_catalog = current_database() AND table_schema = ? ORDER BY table_name, ordinal_position",
+    )?;
+    let columns = stmt.query_map([schema], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?;
+    let mut shapes: Vec<TableShape> = Vec::new();
+    for column in columns {
+        let (table, name, dtype) = column?;
+        if is_internal_table(&table) {
+            continue;
+        }
+        match shapes.last_mut().filter(|shape| shape.name == table) {
+            Some(shape) => shape.columns.push((name, dtype)),
+            None => shapes.push(TableShape { name: table, columns: vec![(name, dtype)], rows: 0 }),
+        }
+    }
+    Ok(shapes)
+}
+
+/// `--compare`: the tables `planned` holds (as `explain_files` creates them, empty) against the
+/// ones in `--db`, with the would-be row counts of the `--dry-run` plan. Nothing is written.
+fn compare_files(files: &[PathBuf], root: &Path, planned: &Connection, args: &Config) -> Result<()> {
+    let schema = args.schema_name.as_deref().unwrap_or("main");
+    let mut loaded = table_shapes(planned, if args.staging { STAGING_SCHEMA } else { schema })?;
+    let mut rows: HashMap<String, u64> = HashMap::new();
+    for file in probe_report(files, root, args)? {
+        for sheet in file["sheets"].as_array().into_iter().flatten() {
+            if let (Some(table), Some(count)) = (sheet["table"].as_str(), sheet["rows"].as_u64()) {
+                *rows.entry(unqualified_table(table).to_lowercase()).or_default() += count;
+            }
+        }
+    }
+    for shape in &mut loaded {
+        shape.rows = rows.get(&shape.name.to_lowercase()).copied().unwrap_or(0);
+    }
+
+    // A database that doesn't exist yet holds no tables, and is not created just to say so
+    let local = motherduck_database(&args.db).is_none();
+    let existing = if local && !Path::new(&args.db).exists() {
+        Vec::new()
+    } else {
+        let conn = match local {
+            true => Connection::open_with_flags(&args.db, duckdb::Config::default().access_mode(duckdb::AccessMode::ReadOnly)?)
+                .with_context(|| format!("Cannot open {} read-only to compare with", args.db))?,
+            false => open_duckdb(&args.db)?,
+        };
+        let mut existing = table_shapes(&conn, schema)?;
+        for shape in &mut existing {
+            let sql = format!("SELECT count(*) FROM {}.{}", quote_identifier(schema), quote_identifier(&shape.name));
+            shape.rows = conn.query_row(&sql, [], |row| row.get::<_, i64>(0))? as u64;
+        }
+        existing
+    };
+
+    println!("Compared with {}; nothing was written", redacted_db(&args.db));
+    let (lines, summary) = compare_tables(&loaded, &existing, args.append);
+    for line in lines {
+        println!("  {}", line);
+    }
+    println!("{}", summary);
+    Ok(())
+}
+
+/// The `--compare` report: a line per table, marked `+` (new), `~` (changed), `=` (unchanged)
+/// or `-` (only in the database), then a count of each. Under `--append` the rows go on top of
+/// the ones already there.
+fn compare_tables(loaded: &[TableShape], existing: &[TableShape], append: bool) -> (Vec<String>, String) {
+    let find = |shapes: &'_ [TableShape], name: &str| shapes.iter().find(|shape| shape.name.eq_ignore_ascii_case(name)).cloned();
+    let mut names: Vec<&str> = loaded.iter().chain(existing).map(|shape| shape.name.as_str()).collect();
+    names.sort_by_key(|name| name.to_lowercase());
+    names.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+
+    let (mut lines, mut counts) = (Vec::new(), [0; 4]);
+    for name in names {
+        match (find(loaded, name), find(existing, name)) {
+            (Some(new), None) => {
+                counts[0] += 1;
+                lines.push(format!("+ {}: new table ({} row(s), {} column(s))", name, new.rows, new.columns.len()));
+            }
+            (Some(new), Some(old)) => {
+                let mut changes = Vec::new();
+                let rows = if append { old.rows + new.rows } else { new.rows };
+                if rows != old.rows {
+                    changes.push(format!("{} -> {} rows", old.rows, rows));
+                }
+                for (column, dtype) in &new.columns {
+                    match old.columns.iter().find(|(name, _)| name.eq_ignore_ascii_case(column)) {
+                        None => changes.push(format!("added {} {}", column, dtype)),
+                        Some((_, was)) if was != dtype => changes.push(format!("{} {} -> {}", column, was, dtype)),
+                        Some(_) => {}
+                    }
+                }
+                for (column, dtype) in old.columns.iter().filter(|(column, _)| !new.columns.iter().any(|(name, _)| name.eq_ignore_ascii_case(column))) {
+                    changes.push(format!("dropped {} {}", column, dtype));
+                }
+                if changes.is_empty() {
+                    counts[2] += 1;
+                    lines.push(format!("= {}: unchanged ({} row(s))", name, old.rows));
+                } else {
+                    counts[1] += 1;
+                    lines.push(format!("~ {}: {}", name, changes.join("; ")));
+                }
+            }
+            (None, Some(old)) => {
+                counts[3] += 1;
+                lines.push(format!("- {}: not produced by this load ({} row(s)); --refresh-all would drop it", name, old.rows));
+            }
+            (None, None) => {}
+        }
+    }
+    let summary = format!("{} new, {} changed, {} unchanged, {} only in the database", counts[0], counts[1], counts[2], counts[3]);
+    (lines, summary)
+}
+
+/// `--explain --dry-run`: loads each file into a throwaway in-memory database, stopping after
+/// each sheet's first chunk, so the statements printed are the ones a real load would run.
+/// The database is handed back for `--ddl-out` to build the indexes in.
+fn explain_files(files: &[PathBuf], root: &Path, args: &Config) -> Result<Connection> {
+    let conn = Connection::open_in_memory()?;
+    if args.staging {
+        conn.execute_batch(&format!("CREATE SCHEMA {}", STAGING_SCHEMA))?;
+    }
+    if let Some(schema) = &args.schema_name {
+        conn.execute_batch(&format!("CREATE SCHEMA {}", quote_identifier(schema)))?;
+    }
+    let db: Box<dyn Db> = Box::new(DuckBackend(conn.try_clone()?, InsertMode::Sql));
+    let conn_mutex = Arc::new(Mutex::new(db));
+    let progress = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+    for file_path in files {
+        if let Err(e) = process_excel_file(file_path, &table_prefix(file_path, root, args), &conn_mutex, &progress, args) {
+            error!("Error processing {}: {:#}", file_path.display(), e);
+        }
+    }
+    // With --schema-name the tables are found where create_indexes looks for them
+    if let Some(schema) = &args.schema_name {
+        conn.execute_batch(&format!("SET search_path = '{},main'", schema))?;
+    }
+    Ok(conn)
+}
+
+/// The sheets of every file with their target tables and inferred columns, as `--probe json` prints them
+fn probe_report(files: &[PathBuf], root: &Path, args: &Config) -> Result<Vec<serde_json::Value>> {
+    let mut report = Vec::new();
+    for file_path in files {
+        let prefix = table_prefix(file_path, root, args);
+        if is_json_lines(file_path) {
+            let sheet = probe_json_lines(file_path, &prefix, args).unwrap_or_else(|e| {
+                serde_json::json!({ "sheet": json_lines_sheet(file_path), "error": format!("{:#}", e) })
+            });
+            report.push(serde_json::json!({ "file": file_path.display().to_string(), "sheets": [sheet] }));
+            continue;
+        }
+        let mut workbook = match open_spreadsheet(file_path, args) {
+            Ok(wb) => wb,
+            Err(e) => {
+                report.push(serde_json::json!({ "file": file_path.display().to_string(), "error": e.to_string() }));
+                continue;
+            }
+        };
+
+        let mut sheets = Vec::new();
+        let all_sheets = workbook.sheet_names();
+        for sheet_name in select_sheets(&all_sheets, file_path, args) {
+            let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+            let sheet_config = args.settings.sheet_for(&file_name, &sheet_name);
+            let read = workbook.worksheet_range(&sheet_name).map_err(anyhow::Error::from)
+                .and_then(|range| restore_padded_codes(file_path, &sheet_name, range, args))
+                .and_then(|range| Ok(restrict_to_range(restore_xlsb_dates(file_path, &sheet_name, range, args)?, &sheet_name, args)));
+            let range = match read {
+                Ok(range) => range,
+                Err(e) => {
+                    sheets.push(serde_json::json!({ "sheet": sheet_name, "error": e.to_string() }));
+                    continue;
+                }
+            };
+            for (part, range) in sheet_parts(range, args) {
+                let prepared = column_formats(file_path, &sheet_name, &range, sheet_config, args)
+                    .and_then(|formats| Ok((prepare_range(range, &sheet_name, sheet_config, args)?.0, formats)));
+                let (range, formats) = match prepared {
+                    Ok(prepared) => prepared,
+                    Err(e) => {
+                        sheets.push(serde_json::json!({ "sheet": sheet_name, "error": e.to_string() }));
+                        continue;
+                    }
+                };
+                let (height, width) = range.get_size();
+                let (table_name, target_table) = part_tables(sheet_tables(&prefix, &sheet_name, &all_sheets, args), part, args);
+                let target_table = union_target(file_path, &sheet_name, args).unwrap_or(target_table);
+                let rows_count = height.saturating_sub(1);
+                if height == 0 {
+                    sheets.push(serde_json::json!({ "sheet": sheet_name, "skipped": "empty sheet" }));
+                    continue;
+                }
+                if let Some(min_rows) = args.min_rows.filter(|&min| rows_count < min) {
+                    sheets.push(serde_json::json!({ "sheet": sheet_name, "skipped": format!("{} data row(s), fewer than --min-rows {}", rows_count, min_rows) }));
+                    continue;
+                }
+                let mut rows = range.rows();
+                let columns = match rows.next() {
+                    Some(headers) => {
+                        let (rows_data, _) = sheet_data_rows(rows, args);
+                        let schema = infer_sheet_schema(headers, &rows_data, &table_name, sheet_config, &formats, args)?;
+                        schema.names.iter().zip(&schema.types).enumerate()
+                            .map(|(i, (name, dtype))| match schema.reasons.get(i) {
+                                Some(reason) => serde_json::json!({ "name": name, "type": dtype, "reason": reason }),
+                                None => serde_json::json!({ "name": name, "type": dtype }),
+                            })
+                            .collect()
+                    }
+                    None => Vec::new(),
+                };
+                sheets.push(serde_json::json!({
+                    "sheet": sheet_name,
+                    "table": target_table,
+                    "rows": args.load_rows.map_or(rows_count, |n| rows_count.min(n)),
+                    "columns": width,
+                    "schema": columns,
+                }));
+            }
+        }
+        report.push(serde_json::json!({ "file": file_path.display().to_string(), "sheets": sheets }));
+    }
+    Ok(report)
+}
+
+/// The tables loading `files` would (re)create, going by each workbook's sheet names and the
+/// naming options, for `--refresh` to drop beforehand. Workbooks that can't be opened are left to
+/// fail during the load; `--on-collision rename` tables (`<table>_2`) are replaced when they load.
+fn planned_tables(files: &[PathBuf], root: &Path, args: &Config) -> Vec<String> {
+    let mut tables = Vec::new();
+    for file_path in files {
+        let prefix = table_prefix(file_path, root, args);
+        if is_json_lines(file_path) {
+            tables.extend(Some(json_lines_table(&prefix, args)).filter(|table| table_selected(table, args)));
+            continue;
+        }
+        let mut workbook = match open_spreadsheet(file_path, args) {
+            Ok(workbook) => workbook,
+            Err(e) => {
+                debug!("Not refreshing the tables of {}: {}", file_path.display(), e);
+                continue;
+            }
+        };
+        let all_sheets = workbook.sheet_names();
+        for sheet_name in select_sheets(&all_sheets, file_path, args) {
+            let (table_name, target_table) = sheet_tables(&prefix, &sheet_name, &all_sheets, args);
+            if !table_selected(&table_name, args) {
+                continue;
+            }
+            if let Some(target) = union_target(file_path, &sheet_name, args) {
+                tables.push(target);
+                continue;
+            }
+            // Only the cells tell how many tables a sheet splits into
+            let parts = match args.split_on_blank.then(|| workbook.worksheet_range(&sheet_name)) {
+                Some(Ok(range)) => sheet_parts(restrict_to_range(range, &sheet_name, args), args).into_iter().map(|(part, _)| part).collect(),
+                _ => vec![None],
+            };
+            tables.extend(parts.into_iter().map(|part| part_tables((table_name.clone(), target_table.clone()), part, args).1));
+        }
+    }
+    let mut seen = HashSet::new();
+    tables.retain(|table| seen.insert(table.to_lowercase()));
+    tables
+}
+
+/// Expected row counts read from a `.ctl` sidecar file
+struct ControlTotals {
+    /// A bare number: rows expected across the whole file
+    total: Option<usize>,
+    /// `sheet=N` or `sheet,N` lines: rows expected per sheet
+    sheets: Vec<(String, usize)>,
+}
+
+fn read_control_file(path: &Path) -> Result<ControlTotals> {
+    let text = fs::read_to_string(path).with_context(|| format!("Cannot read control file {}", path.display()))?;
+    let mut totals = ControlTotals { total: None, sheets: Vec::new() };
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parse_count = |count: &str| {
+            count.trim().parse::<usize>()
+                .with_context(|| format!("{}:{}: expected a row count, got '{}'", path.display(), line_no + 1, count.trim()))
+        };
+        match line.split_once(['=', ',']) {
+            Some((sheet, count)) => totals.sheets.push((sheet.trim().to_string(), parse_count(count)?)),
+            None => totals.total = Some(parse_count(line)?),
+        }
+    }
+    Ok(totals)
+}
+
+/// Compares what was loaded from one workbook against its control file, describing every mismatch
+fn control_mismatches(totals: &ControlTotals, loaded: &[LoadedSheet]) -> Vec<String> {
+    let mut mismatches = Vec::new();
+    if let Some(expected) = totals.total {
+        let actual: usize = loaded.iter().map(|s| s.rows).sum();
+        if actual != expected {
+            mismatches.push(format!("expected {} rows in total, loaded {}", expected, actual));
+        }
+    }
+    for (sheet, expected) in &totals.sheets {
+        let actual = loaded.iter().find(|s| s.sheet.eq_ignore_ascii_case(sheet)).map_or(0, |s| s.rows);
+        if actual != *expected {
+            mismatches.push(format!("expected {} rows in sheet {}, loaded {}", expected, sheet, actual));
+        }
+    }
+    mismatches
+}
+
+/// Reserves up to `wanted` rows of the `--max-total-rows` budget and returns how many may be inserted
+fn claim_rows(args: &Config, wanted: usize) -> usize {
+    let Some(budget) = args.max_total_rows else {
+        return wanted;
+    };
+    let mut granted = 0;
+    let _ = args.rows_loaded.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |loaded| {
+        granted = wanted.min(budget.saturating_sub(loaded));
+        Some(loaded + granted)
+    });
+    granted
+}
+
+/// Registers `table` as written by `source` for this run and returns the table the sheet should
+/// load into, which differs from `table` when `--on-collision rename` had to pick a new name
+fn claim_table(args: &Config, table: &str, source: &str) -> Result<String> {
+    let mut claimed = args.tables_claimed.lock().unwrap();
+    let mut target = table.to_string();
+    if let Some(earlier) = claimed.get(&target.to_lowercase()) {
+        match args.on_collision {
+            OnCollision::Error => anyhow::bail!(
+                "Table {} from {} collides with the one from {}; pass --on-collision rename or overwrite to load both",
+                table, source, earlier
+            ),
+            OnCollision::Overwrite => warn!("{} replaces table {} loaded from {}", source, table, earlier),
+            OnCollision::Rename => {
+                let mut n = 2;
+                while claimed.contains_key(&format!("{}_{}", table, n).to_lowercase()) {
+                    n += 1;
+                }
+                target = format!("{}_{}", table, n);
+                info!("{} collides with {} on table {}; loading it into {}", source, earlier, table, target);
+            }
+        }
+    }
+    claimed.insert(target.to_lowercase(), source.to_string());
+    Ok(target)
+}
+
+/// Gives back rows claimed with [`claim_rows`] that didn't stay in the database after all
+fn release_rows(args: &Config, rows: usize) {
+    if args.max_total_rows.is_some() {
+        args.rows_loaded.fetch_sub(rows, Ordering::SeqCst);
+    }
+}
+
+fn budget_spent(args: &Config) -> bool {
+    args.max_total_rows.is_some_and(|budget| args.rows_loaded.load(Ordering::SeqCst) >= budget)
+}
+
+/// Column definition for CREATE TABLE, with `--text-collation` applied to text columns
+fn column_def(name: &str, dtype: &str, collation: Option<&str>) -> String {
+    match collation {
+        Some(collation) if dtype == "VARCHAR" => format!("{} {} COLLATE {}", quote_identifier(name), dtype, collation),
+        _ => format!("{} {}", quote_identifier(name), dtype),
+    }
+}
+
+/// A column list for INSERT, each name quoted
+fn quoted_columns(columns: &[(String, &str)]) -> String {
+    columns.iter().map(|(name, _)| quote_identifier(name)).collect::<Vec<_>>().join(", ")
+}
+
+/// CREATE TABLE for DuckDB; `replace` swaps out a table of the same name
+fn create_table_sql(table: &str, column_ddl: &[String], replace: bool) -> String {
+    let create = if replace { "CREATE OR REPLACE TABLE" } else { "CREATE TABLE" };
+    format!("{} {} ({})", create, quote_table(table), column_ddl.join(", "))
+}
+
+/// A value as a SQL literal, for the statements `--explain` prints
+fn sql_literal(value: &Value) -> String {
+    match ValueRef::from(value) {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Double(v) if !v.is_finite() => format!("'{}'", v),
+        v @ (ValueRef::Boolean(_) | ValueRef::BigInt(_) | ValueRef::Double(_)) => format_value(v),
+        v @ ValueRef::Timestamp(..) => format!("TIMESTAMP '{}'", format_value(v)),
+        v => format!("'{}'", format_value(v).replace('\'', "''")),
+    }
+}
+
+/// The INSERT a chunk of rows amounts to, with its values written out; only the first
+/// `EXPLAIN_ROWS` rows are shown. The Appender runs no SQL, but it writes the same rows.
+fn explain_insert(table: &str, columns: &[(String, &str)], values: &[Value]) -> String {
+    let rows: Vec<&[Value]> = values.chunks(columns.len().max(1)).collect();
+    let shown: Vec<String> = rows.iter().take(EXPLAIN_ROWS)
+        .map(|row| format!("  ({})", row.iter().map(sql_literal).collect::<Vec<_>>().join(", ")))
+        .collect();
+    let mut sql = format!("INSERT INTO {} ({}) VALUES\n{};", quote_table(table), quoted_columns(columns), shown.join(",\n"));
+    if rows.len() > EXPLAIN_ROWS {
+        sql.push_str(&format!("\n-- {} more row(s) in this chunk", rows.len() - EXPLAIN_ROWS));
+    }
+    sql
+}
+
+/// The operations a load needs from the database, so `main` can dispatch on `--backend`.
+/// Column types are always the loader's DuckDB type names; each backend maps them itself.
+trait Db: Send {
+    fn execute_batch(&self, sql: &str) -> Result<()>;
+    /// Drops every table except the load log
+    fn refresh(&self) -> Result<()>;
+    fn drop_table(&self, table: &str) -> Result<()>;
+    /// Creates `table`, replacing any existing table of that name
+    fn create_table(&self, table: &str, columns: &[(String, &str)], collation: Option<&str>) -> Result<()>;
+    /// Inserts rows whose values are laid out one row after another, `columns.len()` per row
+    fn insert_chunk(&self, table: &str, columns: &[(String, &str)], values: Vec<Value>) -> Result<()>;
+    /// The DuckDB connection behind the features only DuckDB supports
+    fn duckdb(&self) -> Option<&Connection>;
+    /// Another connection to the same database, for a writer working alongside this one
+    fn try_clone(&self) -> Option<Box<dyn Db>>;
+}
+
+/// Whether the Appender can take `value` for a `dtype` column as is. It panics on the value
+/// types it doesn't know, and a failed row leaves the rows before it appended.
+fn appends_as(value: &Value, dtype: &str) -> bool {
+    let numeric = matches!(dtype, "TINYINT" | "SMALLINT" | "INTEGER" | "BIGINT" | "HUGEINT" | "DOUBLE") || dtype.starts_with("DECIMAL");
+    match value {
+        Value::Null => true,
+        Value::Boolean(_) => dtype == "BOOLEAN",
+        Value::BigInt(_) | Value::Double(_) => numeric,
+        Value::HugeInt(_) => dtype == "HUGEINT",
+        // DATE columns get ISO text from cell_value
+        Value::Text(_) => matches!(dtype, "VARCHAR" | "JSON" | "DATE") || dtype.starts_with("DECIMAL"),
+        Value::Timestamp(..) => matches!(dtype, "TIMESTAMP" | "TIMESTAMPTZ"),
+        _ => false,
+    }
+}
+
+struct DuckBackend(Connection, InsertMode);
+
+impl DuckBackend {
+    /// Appends whole rows with the Appender, which needs a value for every table column in order
+    fn append_chunk(&self, table: &str, width: usize, values: &[Value]) -> duckdb::Result<()> {
+        let (schema, name) = table.split_once('.').unwrap_or(("main", table));
+        let mut appender = self.0.appender_to_db(name, schema)?;
+        for row in values.chunks(width) {
+            appender.append_row(duckdb::appender_params_from_iter(row))?;
+        }
+        // Dropping the appender flushes the rows into the table
+        Ok(())
+    }
+}
+
+impl Db for DuckBackend {
+    fn execute_batch(&self, sql: &str) -> Result<()> {
+        self.0.execute_batch(sql)?;
+        Ok(())
+    }
+
+    fn refresh(&self) -> Result<()> {
+        let mut stmt = self.0.prepare("SELECT name FROM sqlite_master WHERE type='table'")?;
+        let tables = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?;
+        for table in tables.iter().filter(|t| !LOAD_LOG_TABLES.contains(&t.as_str())) {
+            let sql = format!("DROP TABLE IF EXISTS {}", quote_identifier(table));
+            debug!("{}", sql);
+            self.0.execute(&sql, [])?;
+        }
+        Ok(())
+    }
+
+    fn drop_table(&self, table: &str) -> Result<()> {
+        let sql = format!("DROP TABLE IF EXISTS {}", quote_table(table));
+        debug!("{}", sql);
+        self.0.execute(&sql, [])?;
+        Ok(())
+    }
+
+    fn create_table(&self, table: &str, columns: &[(String, &str)], collation: Option<&str>) -> Result<()> {
+        let defs: Vec<String> = columns.iter().map(|(name, dtype)| column_def(name, dtype, collation)).collect();
+        let sql = create_table_sql(table, &defs, true);
+        debug!("{}", sql);
+        self.0.execute(&sql, [])?;
+        Ok(())
+    }
+
+    fn insert_chunk(&self, table: &str, columns: &[(String, &str)], values: Vec<Value>) -> Result<()> {
+        // Rows the appender already took stay behind when a later row fails, so a chunk is only
+        // appended when every value matches its column; anything else (text in a DOUBLE column)
+        // goes through INSERT, which casts it or reports it
+        let appendable = !columns.is_empty() && values.chunks(columns.len())
+            .all(|row| row.iter().zip(columns).all(|(value, (_, dtype))| appends_as(value, dtype)));
+        if self.1 == InsertMode::Appender && appendable {
+            return Ok(self.append_chunk(table, columns.len(), &values)?);
+        }
+
+        let row_placeholders = format!("({})", vec!["?"; columns.len()].join(", "));
+        let rows = values.len() / columns.len().max(1);
+        // Full chunks share one cached statement; only the last, shorter chunk prepares its own
+        let query = format!(
+            "INSERT INTO {} ({}) VALUES {}",
+            quote_table(table), quoted_columns(columns), vec![row_placeholders.as_str(); rows].join(", ")
+        );
+        let mut stmt = self.0.prepare_cached(&query)?;
+        stmt.execute(duckdb::params_from_iter(values))?;
+        Ok(())
+    }
+
+    fn duckdb(&self) -> Option<&Connection> {
+        Some(&self.0)
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn Db>> {
+        Some(Box::new(DuckBackend(self.0.try_clone().ok()?, self.1)))
+    }
+}
+
+struct SqliteBackend(rusqlite::Connection);
+
+impl SqliteBackend {
+    /// SQLite affinity for one of the loader's DuckDB types; dates and timestamps are stored as ISO text
+    fn affinity(dtype: &str) -> &'static str {
+        match dtype {
+            "BOOLEAN" | "TINYINT" | "SMALLINT" | "INTEGER" | "BIGINT" => "INTEGER",
+            "DOUBLE" => "REAL",
+            _ if dtype.starts_with("DECIMAL") => "REAL",
+            _ => "TEXT",
+        }
+    }
+
+    fn value(value: Value) -> Result<rusqlite::types::Value> {
+        use rusqlite::types::Value as Sqlite;
+        Ok(match value {
+            Value::Null => Sqlite::Null,
+            Value::Boolean(v) => Sqlite::Integer(v as i64),
+            Value::TinyInt(v) => Sqlite::Integer(v as i64),
+            Value::SmallInt(v) => Sqlite::Integer(v as i64),
+            Value::Int(v) => Sqlite::Integer(v as i64),
+            Value::BigInt(v) => Sqlite::Integer(v),
+            // Past i64 there is no SQLite integer to hold it, so it stays exact as text
+            Value::HugeInt(v) => Sqlite::Text(v.to_string()),
+            Value::Float(v) => Sqlite::Real(v as f64),
+            Value::Double(v) => Sqlite::Real(v),
+            Value::Text(v) => Sqlite::Text(v),
+            Value::Blob(v) => Sqlite::Blob(v),
+            Value::Timestamp(unit, v) => Sqlite::Text(format_value(ValueRef::Timestamp(unit, v))),
+            other => anyhow::bail!("Cannot store {:?} in SQLite", other),
+        })
+    }
+}
+
+impl Db for SqliteBackend {
+    fn execute_batch(&self, sql: &str) -> Result<()> {
+        self.0.execute_batch(sql)?;
+        Ok(())
+    }
+
+    fn refresh(&self) -> Result<()> {
+        let mut stmt = self.0.prepare("SELECT name FROM sqlite_master WHERE type='table'")?;
+        let tables = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?;
+        for table in tables.iter().filter(|t| !LOAD_LOG_TABLES.contains(&t.as_str())) {
+            let sql = format!("DROP TABLE IF EXISTS {}", quote_identifier(table));
+            debug!("{}", sql);
+            self.0.execute(&sql, [])?;
+        }
+        Ok(())
+    }
+
+    fn drop_table(&self, table: &str) -> Result<()> {
+        let sql = format!("DROP TABLE IF EXISTS {}", quote_table(table));
+        debug!("{}", sql);
+        self.0.execute(&sql, [])?;
+        Ok(())
+    }
+
+    fn create_table(&self, table: &str, columns: &[(String, &str)], collation: Option<&str>) -> Result<()> {
+        let defs: Vec<String> = columns.iter()
+            .map(|(name, dtype)| match collation {
+                Some(collation) if *dtype == "VARCHAR" => format!("{} TEXT COLLATE {}", quote_identifier(name), collation),
+                _ => format!("{} {}", quote_identifier(name), Self::affinity(dtype)),
+            })
+            .collect();
+        // SQLite has no CREATE OR REPLACE
+        let sql = format!("DROP TABLE IF EXISTS {0}; CREATE TABLE {0} ({1});", quote_table(table), defs.join(", "));
+        debug!("{}", sql);
+        self.0.execute_batch(&sql)?;
+        Ok(())
+    }
+
+    fn insert_chunk(&self, table: &str, columns: &[(String, &str)], values: Vec<Value>) -> Result<()> {
+        // One row per statement keeps wide sheets under SQLite's bound-parameter limit;
+        // the sheet's transaction keeps that as fast as a multi-row INSERT
+        let query = format!("INSERT INTO {} ({}) VALUES ({})", quote_table(table), quoted_columns(columns), vec!["?"; columns.len()].join(", "));
+        let mut stmt = self.0.prepare_cached(&query)?;
+        let values = values.into_iter().map(Self::value).collect::<Result<Vec<_>>>()?;
+        for row in values.chunks(columns.len().max(1)) {
+            stmt.execute(rusqlite::params_from_iter(row))?;
+        }
+        Ok(())
+    }
+
+    fn duckdb(&self) -> Option<&Connection> {
+        None
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn Db>> {
+        None
+    }
+}
+
+/// What a file's first bytes say it is, whatever its extension claims
+#[derive(Debug, PartialEq)]
+enum Container {
+    /// A workbook, named by the extension of the reader it needs
+    Workbook(&'static str),
+    /// Something no reader opens, described for the error message
+    Other(&'static str),
+}
+
+/// Reads a file's leading bytes to tell what it really is: an OLE2 compound file is an `.xls`
+/// (or an encrypted `.xlsx`), a zip archive an `.ods`, `.xlsb` or `.xlsx` by the entries it
+/// holds, and text or anything else no workbook at all. The reader is left at the start.
+fn sniff_container<RS: Read + Seek>(reader: &mut RS) -> Result<Container> {
+    let mut head = Vec::new();
+    reader.by_ref().take(512).read_to_end(&mut head)?;
+    reader.rewind()?;
+    if head.starts_with(&OLE2_MAGIC) {
+        return Ok(Container::Workbook(if is_encrypted_package(reader)? { "xlsx" } else { "xls" }));
+    }
+    if head.starts_with(b"PK\x03\x04") {
+        // A damaged archive is left to the xlsx reader, whose error says what is broken
+        let names: Option<HashSet<String>> = zip::ZipArchive::new(&mut *reader).ok().map(|zip| zip.file_names().map(str::to_string).collect());
+        reader.rewind()?;
+        return Ok(match names {
+            Some(names) if names.contains("xl/workbook.bin") => Container::Workbook("xlsb"),
+            Some(names) if names.contains("mimetype") && names.contains("content.xml") => Container::Workbook("ods"),
+            Some(names) if !names.contains("xl/workbook.xml") => Container::Other("a zip archive without a workbook in it"),
+            _ => Container::Workbook("xlsx"),
+        });
+    }
+    let text = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&head);
+    Ok(Container::Other(match text.iter().position(|b| !b.is_ascii_whitespace()).map(|i| &text[i..]) {
+        None if head.is_empty() => "an empty file",
+        _ if head.contains(&0) => "not a workbook",
+        Some(text) if text.starts_with(b"<") => "an HTML or XML file",
+        _ => "plain text, such as a CSV file",
+    }))
+}
+
+/// Picks the calamine reader for a workbook: the one its contents call for, which is normally the
+/// one its extension names (`.xlsb`, `.xls` and `.ods` get their own, anything else is read as
+/// `.xlsx`). A mislabeled workbook is read anyway, with a warning; a file that is no workbook fails.
+fn spreadsheet_from<RS: Read + Seek>(mut reader: RS, file_path: &Path) -> Result<Sheets<RS>> {
+    let ext = file_path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let claimed = match ext.as_str() {
+        "xls" | "ods" | "xlsb" => ext.as_str(),
+        _ => "xlsx",
+    };
+    let ext = match sniff_container(&mut reader)? {
+        Container::Workbook(actual) if actual != claimed => {
+            warn!("{} claims to be .{} but is actually an .{} workbook; reading it as one", file_path.display(), ext, actual);
+            actual
+        }
+        Container::Workbook(actual) => actual,
+        Container::Other(what) => anyhow::bail!(
+            "{} claims to be .{} but is actually {}; only xlsx, xlsm, xlsb, xls and ods workbooks (and JSON lines files) can be loaded",
+            file_path.display(), ext, what
+        ),
+    };
+    let protected = || anyhow::anyhow!("{}", PASSWORD_PROTECTED);
+    Ok(match ext {
+        "xls" => Sheets::Xls(Xls::new(reader).map_err(|e| match e {
+            calamine::XlsError::Password => anyhow::anyhow!("{}", XLS_ENCRYPTED),
+            e => e.into(),
+        })?),
+        "ods" => Sheets::Ods(Ods::new(reader).map_err(|e| match e {
+            calamine::OdsError::Password => anyhow::anyhow!("{}", ODS_ENCRYPTED),
+            e => e.into(),
+        })?),
+        "xlsb" => Sheets::Xlsb(Xlsb::new(reader).map_err(|e| match e {
+            calamine::XlsbError::Password => protected(),
+            e => e.into(),
+        })?),
+        _ => {
+            if is_encrypted_package(&mut reader)? {
+                return Err(protected());
+            }
+            // A damaged archive is left to the xlsx reader, whose error says what is broken
+            let protection = zip::ZipArchive::new(&mut reader).ok().map(|mut zip| workbook_protection(&mut zip)).transpose()?.unwrap_or_default();
+            reader.rewind()?;
+            if !protection.is_empty() {
+                info!("{}: ignoring the workbook's protection metadata ({}); its cells read as usual", file_path.display(), protection.join(", "));
+            }
+            Sheets::Xlsx(Xlsx::new(reader)?)
+        }
+    })
+}
+
+/// Why an `.xlsx` or `.xlsb` with an open password fails when no --password is given
+const PASSWORD_PROTECTED: &str = "the workbook is password-protected (encrypted); give its open password with --password or --password-env";
+
+/// Why an `.ods` with an open password fails; calamine can't decrypt one, and neither can we
+const ODS_ENCRYPTED: &str = "the .ods workbook is password-protected (encrypted), which is not supported; save a copy without an open password";
+
+/// Why an encrypted workbook fails to decrypt. The password isn't checked on its own, so a wrong
+/// one only shows once the result is no workbook.
+const WRONG_PASSWORD: &str = "cannot decrypt the workbook: wrong password or unsupported encryption";
+
+/// Excel encrypts an `.xls` whose structure is protected even when it opens without a password,
+/// so an encrypted `.xls` may only be protected
+const XLS_ENCRYPTED: &str = "the .xls workbook is encrypted, which is not supported; Excel also encrypts .xls files that only have a protected structure or a modify password, so if it opens without a password, remove the protection or save it as .xlsx";
+
+/// What protection an xlsx workbook declares in `xl/workbook.xml`: a modify password or read-only
+/// recommendation, and a locked structure. None of it stops the cells from being read.
+fn workbook_protection<R: Read + Seek>(archive: &mut zip::ZipArchive<R>) -> Result<Vec<&'static str>> {
+    use quick_xml::events::Event;
+
+    let Some(workbook) = read_zip_part(archive, "xl/workbook.xml")? else {
+        return Ok(Vec::new());
+    };
+    let set = |e: &quick_xml::events::BytesStart, keys: &[&[u8]]| keys.iter().any(|key| {
+        xml_attr(e, key).is_some_and(|value| !matches!(value.as_str(), "" | "0" | "false"))
+    });
+    let mut found = Vec::new();
+    let mut reader = quick_xml::Reader::from_str(&workbook);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"fileSharing" if set(&e, &[b"reservationPassword", b"hashValue"]) => found.push("modify password"),
+                b"fileSharing" if set(&e, &[b"readOnlyRecommended"]) => found.push("read-only recommended"),
+                b"workbookProtection" if set(&e, &[b"lockStructure", b"lockWindows", b"lockRevision", b"workbookPassword", b"workbookHashValue"]) => {
+                    found.push("locked structure")
+                }
+                // Both come before the sheet list
+                b"sheets" => break,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(found)
+}
+
+const OLE2_MAGIC: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+
+/// Whether OLE2 compound file bytes hold an `EncryptedPackage` stream
+fn holds_encrypted_package(data: &[u8]) -> bool {
+    let name: Vec<u8> = "EncryptedPackage".encode_utf16().flat_map(u16::to_le_bytes).collect();
+    data.starts_with(&OLE2_MAGIC) && data.windows(name.len()).any(|w| w == name)
+}
+
+/// Whether an `.xlsx` is really an OLE2 compound file holding an `EncryptedPackage` stream,
+/// which is how Excel saves a workbook that needs a password to open. Only files that start
+/// like one are read through; the reader is left at the start either way.
+fn is_encrypted_package<RS: Read + Seek>(reader: &mut RS) -> Result<bool> {
+    let mut data = Vec::new();
+    reader.by_ref().take(OLE2_MAGIC.len() as u64).read_to_end(&mut data)?;
+    if data == OLE2_MAGIC {
+        reader.read_to_end(&mut data)?;
+    }
+    reader.rewind()?;
+    Ok(holds_encrypted_package(&data))
+}
+
+/// Anything calamine can read a workbook from
+trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// The contents of a workbook saved with an open password, decrypted with --password, or `None`
+/// for a workbook that isn't encrypted or when no password is given. Deriving the key takes
+/// 100,000 rounds of SHA-512, so the result is kept for the other readers of the file until
+/// the file is loaded.
+fn decrypted_package(file_path: &Path, args: &Config) -> Result<Option<Arc<[u8]>>> {
+    let Some(password) = args.workbook_password()? else {
+        return Ok(None);
+    };
+    if let Some(package) = args.decrypted_packages.lock().unwrap().get(file_path) {
+        return Ok(Some(package.clone()));
+    }
+    let mut reader: Box<dyn ReadSeek> = match &args.stdin_data {
+        Some(data) => Box::new(Cursor::new(data.as_slice())),
+        None => Box::new(BufReader::new(fs::File::open(file_path)?)),
+    };
+    if !is_encrypted_package(&mut reader)? {
+        return Ok(None);
+    }
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let package: Arc<[u8]> = match office_crypto::decrypt_from_bytes(data, &password) {
+        Ok(package) if package.starts_with(b"PK\x03\x04") => package.into(),
+        Ok(_) => anyhow::bail!("{}", WRONG_PASSWORD),
+        Err(e) => anyhow::bail!("{} ({})", WRONG_PASSWORD, e),
+    };
+    args.decrypted_packages.lock().unwrap().insert(file_path.to_path_buf(), package.clone());
+    Ok(Some(package))
+}
+
+/// Reads a workbook's bytes: decrypted with --password when it is encrypted, from the buffered
+/// stdin contents with `--path -`, or from disk
+fn workbook_reader<'a>(file_path: &Path, args: &'a Config) -> Result<Box<dyn ReadSeek + 'a>> {
+    if let Some(package) = decrypted_package(file_path, args)? {
+        return Ok(Box::new(Cursor::new(package)));
+    }
+    Ok(match &args.stdin_data {
+        Some(data) => Box::new(Cursor::new(data.as_slice())),
+        None => Box::new(BufReader::new(fs::File::open(file_path)?)),
+    })
+}
+
+/// Opens a workbook from disk, or from the buffered stdin contents with `--path -`
+fn open_spreadsheet<'a>(file_path: &Path, args: &'a Config) -> Result<Sheets<Box<dyn ReadSeek + 'a>>> {
+    spreadsheet_from(workbook_reader(file_path, args)?, file_path)
+}
+
+/// The extension matching a workbook's magic bytes, for stdin input that has no file name:
+/// OLE2 compound files are `.xls` (or an encrypted `.xlsx`), and zip archives are `.ods` when
+/// their leading `mimetype` entry says so, `.xlsb` when they hold `xl/workbook.bin` and `.xlsx` otherwise
+fn workbook_extension(data: &[u8]) -> Result<&'static str> {
+    if data.starts_with(&OLE2_MAGIC) {
+        return Ok(if holds_encrypted_package(data) { "xlsx" } else { "xls" });
+    }
+    if data.starts_with(b"PK\x03\x04") {
+        let head = &data[..data.len().min(100)];
+        let is_ods = head.get(30..38) == Some(b"mimetype") && head.windows(24).any(|w| w == b"opendocument.spreadsheet");
+        if is_ods {
+            return Ok("ods");
+        }
+        let is_xlsb = data.windows(15).any(|w| w == b"xl/workbook.bin");
+        return Ok(if is_xlsb { "xlsb" } else { "xlsx" });
+    }
+    anyhow::bail!("stdin is not an xlsx, xlsb, xls or ods workbook")
+}
+
+/// Whether an error comes down to an I/O failure that may clear up on its own (a busy or
+/// locked file, a share timing out). A missing file or denied access won't, and neither will
+/// a file whose contents don't parse.
+fn is_transient_io_error(e: &anyhow::Error) -> bool {
+    use std::io::ErrorKind;
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION: another process has the file open on Windows
+    const SHARING_VIOLATIONS: [i32; 2] = [32, 33];
+    e.chain().filter_map(|cause| cause.downcast_ref::<std::io::Error>()).any(|io| {
+        matches!(io.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted | ErrorKind::TimedOut)
+            || (cfg!(windows) && io.raw_os_error().is_some_and(|code| SHARING_VIOLATIONS.contains(&code)))
+    })
+}
+
+/// Calls `open` again, up to `retries` times and waiting a little longer each time, while it
+/// fails with a transient I/O error. Missing files, denied access and files that open but
+/// don't parse fail on the first attempt.
+fn with_retries<T>(file_path: &Path, retries: usize, mut open: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 0;
+    loop {
+        match open() {
+            Err(e) if attempt < retries && is_transient_io_error(&e) => {
+                attempt += 1;
+                debug!("Opening {} failed ({:#}); retry {} of {}", file_path.display(), e, attempt, retries);
+                std::thread::sleep(Duration::from_millis(100 * attempt as u64));
+            }
+            result => return result,
+        }
+    }
+}
+
+fn process_excel_file(file_path: &Path, filename_prefix: &str, conn_mutex: &Arc<Mutex<Box<dyn Db>>>, progress: &MultiProgress, args: &Config) -> Result<Vec<LoadedSheet>> {
+    let loaded = if is_json_lines(file_path) {
+        if !table_selected(&json_lines_table(filename_prefix, args), args) {
+            return Ok(Vec::new());
+        }
+        args.write_turns.wait(file_path);
+        let sheet = load_json_lines(file_path, filename_prefix, conn_mutex, args)?;
+        if let Some(dir) = &args.output_parquet {
+            write_table_parquet(conn_mutex, &sheet.table, dir)?;
+        }
+        vec![sheet]
+    } else {
+        let loaded = open_and_load_workbook(file_path, filename_prefix, conn_mutex, progress, args);
+        args.decrypted_packages.lock().unwrap().remove(file_path);
+        loaded?
+    };
+
+    if args.control_files && !args.schema_only {
+        let totals = read_control_file(&file_path.with_extension("ctl"))?;
+        let mismatches = control_mismatches(&totals, &loaded);
+        if !mismatches.is_empty() {
+            // Take the file's rows back out so a failed control check leaves nothing half-trusted behind
+            let db = conn_mutex.lock().unwrap();
+            for sheet in &loaded {
+                match db.duckdb().filter(|_| args.single_table.is_some()) {
+                    Some(conn) => {
+                        let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+                        conn.execute(&format!("DELETE FROM {} WHERE _source_file = ?", quote_table(&sheet.table)), [file_name.as_ref()])?;
+                    }
+                    None => db.drop_table(&sheet.table)?,
+                }
+            }
+            anyhow::bail!("Control file check failed: {}", mismatches.join("; "));
+        }
+    }
+    if args.table_metadata {
+        store_table_metadata(conn_mutex.lock().unwrap().as_ref(), file_path, &loaded, args)?;
+    }
+    Ok(loaded)
+}
+
+/// Opens a workbook, memory-mapped with --mmap unless it has to be decrypted, and loads its sheets
+fn open_and_load_workbook(file_path: &Path, filename_prefix: &str, conn_mutex: &Arc<Mutex<Box<dyn Db>>>, progress: &MultiProgress, args: &Config) -> Result<Vec<LoadedSheet>> {
+    if args.mmap && decrypted_package(file_path, args).context("Cannot open file")?.is_none() {
+        let mmap = with_retries(file_path, args.retries, || {
+            let file = fs::File::open(file_path).context("Cannot open file")?;
+            // Safety: the mapping is read-only; as with any reader, the file must not be rewritten mid-load
+            unsafe { memmap2::Mmap::map(&file) }.context("Cannot memory-map file")
+        })?;
+        let mut workbook = spreadsheet_from(Cursor::new(&mmap[..]), file_path).context("Cannot open file")?;
+        load_workbook(&mut workbook, file_path, filename_prefix, conn_mutex, progress, args)
+    } else {
+        let mut workbook = with_retries(file_path, args.retries, || open_spreadsheet(file_path, args)).context("Cannot open file")?;
+        load_workbook(&mut workbook, file_path, filename_prefix, conn_mutex, progress, args)
+    }
+}
+
+/// `--output-parquet`: copies a freshly loaded table out to `dir/<table>.parquet` and drops it,
+/// so the in-memory database holds no more than the sheets being converted
+fn write_table_parquet(conn_mutex: &Arc<Mutex<Box<dyn Db>>>, table: &str, dir: &str) -> Result<()> {
+    let path = Path::new(dir).join(format!("{}.parquet", table));
+    let literal = path.display().to_string().replace('\'', "''");
+    let db = conn_mutex.lock().unwrap();
+    db.execute_batch(&format!("COPY {0} TO '{1}' (FORMAT PARQUET); DROP TABLE {0};", quote_table(table), literal))
+        .with_context(|| format!("Cannot write {}", path.display()))?;
+    info!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Records the header each of `table`'s columns came from in `_column_mapping`. A freshly
+/// created table replaces everything recorded for it; a table that sheets are merged into
+/// (`--single-table`, `--append`) only replaces the rows for these columns.
+fn store_column_mapping(db: &dyn Db, table: &str, headers: &[Data], names: &[String], fresh: bool) -> Result<()> {
+    db.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (table_name VARCHAR, column_index INTEGER, original_name VARCHAR, sanitized_name VARCHAR)",
+        COLUMN_MAPPING_TABLE
+    ))?;
+    let literal = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    let mut delete = format!("DELETE FROM {} WHERE table_name = {}", COLUMN_MAPPING_TABLE, literal(table));
+    if !fresh {
+        let listed: Vec<String> = names.iter().map(|name| literal(name)).collect();
+        delete.push_str(&format!(" AND sanitized_name IN ({})", listed.join(", ")));
+    }
+    db.execute_batch(&delete)?;
+
+    let columns: Vec<(String, &str)> = [("table_name", "VARCHAR"), ("column_index", "INTEGER"), ("original_name", "VARCHAR"), ("sanitized_name", "VARCHAR")]
+        .into_iter()
+        .map(|(name, dtype)| (name.to_string(), dtype))
+        .collect();
+    let values = names.iter().enumerate().flat_map(|(i, name)| {
+        let original = headers.get(i).map(|cell| cell.to_string()).unwrap_or_default();
+        [Value::Text(table.to_string()), Value::BigInt(i as i64 + 1), Value::Text(original), Value::Text(name.clone())]
+    });
+    db.insert_chunk(COLUMN_MAPPING_TABLE, &columns, values.collect())
+}
+
+/// Records where each of a file's `loaded` tables came from in `_table_metadata`. A table of its
+/// own replaces everything recorded for it; a table that sheets are merged into (`--single-table`,
+/// `--append`, `--union-by`) keeps a row per source and only replaces this sheet's.
+fn store_table_metadata(db: &dyn Db, file_path: &Path, loaded: &[LoadedSheet], args: &Config) -> Result<()> {
+    db.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (table_name VARCHAR, source_file VARCHAR, sheet_name VARCHAR, source_rows BIGINT, source_columns BIGINT, file_modified_at TIMESTAMP, loaded_at TIMESTAMP)",
+        TABLE_METADATA_TABLE
+    ))?;
+    let literal = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    let source_file = file_path.display().to_string();
+    // Piped input has no file to stamp
+    let modified = match args.stdin_data {
+        Some(_) => Value::Null,
+        None => file_stamp(file_path).map_or(Value::Null, |stamp| Value::Timestamp(TimeUnit::Microsecond, stamp.modified)),
+    };
+    let loaded_at = Value::Timestamp(TimeUnit::Microsecond, chrono::Local::now().naive_local().and_utc().timestamp_micros());
+    let mut values = Vec::new();
+    for sheet in loaded {
+        let table = final_table(&sheet.table, args.schema_name.as_deref());
+        let merged = args.single_table.is_some() || args.append || union_target(file_path, &sheet.sheet, args).is_some();
+        let mut delete = format!("DELETE FROM {} WHERE table_name = {}", TABLE_METADATA_TABLE, literal(&table));
+        if merged {
+            delete.push_str(&format!(" AND source_file = {} AND sheet_name = {}", literal(&source_file), literal(&sheet.sheet)));
+        }
+        db.execute_batch(&delete)?;
+        values.extend([
+            Value::Text(table),
+            Value::Text(source_file.clone()),
+            Value::Text(sheet.sheet.clone()),
+            Value::BigInt(sheet.source_size.0 as i64),
+            Value::BigInt(sheet.source_size.1 as i64),
+            modified.clone(),
+            loaded_at.clone(),
+        ]);
+    }
+    let columns: Vec<(String, &str)> = [
+        ("table_name", "VARCHAR"), ("source_file", "VARCHAR"), ("sheet_name", "VARCHAR"), ("source_rows", "BIGINT"),
+        ("source_columns", "BIGINT"), ("file_modified_at", "TIMESTAMP"), ("loaded_at", "TIMESTAMP"),
+    ]
+        .into_iter()
+        .map(|(name, dtype)| (name.to_string(), dtype))
+        .collect();
+    if values.is_empty() {
+        return Ok(());
+    }
+    db.insert_chunk(TABLE_METADATA_TABLE, &columns, values)
+}
+
+/// A sheet that made it into the database, with the table it went to, how many rows it brought
+/// and the columns it was given
+struct LoadedSheet {
+    sheet: String,
+    table: String,
+    rows: usize,
+    columns: Vec<(String, String)>,
+    /// The (rows, columns) the sheet spanned as read, header and all, before any rows were skipped
+    source_size: (usize, usize),
+    /// Data rows that should have loaded: all of them, or the `--load-rows` sample, less blank rows
+    /// `--skip-blank-rows` dropped and rows past the `--max-total-rows` budget. Rows set aside under
+    /// `--on-bad-row` still count, so `--verify` can report them.
+    expected_rows: usize,
+    /// The hash of the sheet's cells that `--incremental` records, for sheets read from a workbook
+    content_hash: Option<String>,
+    /// Columns that became VARCHAR only because their sampled values mixed types (`--type-summary`)
+    mixed_columns: Vec<String>,
+}
+
+/// What a JSON lines file is called where a workbook would name its sheet
+fn json_lines_sheet(file_path: &Path) -> String {
+    file_path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+/// The table a JSON lines file loads into: its file name with `--tables-prefix` and
+/// `--identifier-case` applied, in the staging schema or `--schema-name`, as a workbook's sheets get theirs
+fn json_lines_table(filename_prefix: &str, args: &Config) -> String {
+    let table = table_identifier(match &args.tables_prefix {
+        Some(prefix) => format!("{}_{}", sanitize_identifier(prefix), filename_prefix.trim_start_matches('_')),
+        None => filename_prefix.to_string(),
+    }, args);
+    qualify_table(&table, args)
+}
+
+/// Checks that every non-blank line is a JSON object, since DuckDB would otherwise fail
+/// without saying where, and returns how many there are
+fn check_json_lines(text: &str) -> Result<usize> {
+    let mut rows = 0;
+    let mut problems = Vec::new();
+    for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(serde_json::Value::Object(_)) => rows += 1,
+            Ok(other) => {
+                let kind = match other {
+                    serde_json::Value::Array(_) => "an array",
+                    serde_json::Value::String(_) => "a string",
+                    serde_json::Value::Number(_) => "a number",
+                    serde_json::Value::Bool(_) => "a boolean",
+                    _ => "null",
+                };
+                problems.push(format!("line {}: expected a JSON object, found {}", i + 1, kind));
+            }
+            Err(e) => {
+                // serde counts lines within the one line it was given
+                let message = e.to_string();
+                let message = message.rsplit_once(" at line ").map_or(message.as_str(), |(message, _)| message);
+                problems.push(format!("line {}, column {}: invalid JSON ({})", i + 1, e.column(), message));
+            }
+        }
+    }
+    if !problems.is_empty() {
+        let more = problems.len().saturating_sub(5);
+        problems.truncate(5);
+        let more = if more > 0 { format!("; and {} more", more) } else { String::new() };
+        anyhow::bail!("{}{}", problems.join("; "), more);
+    }
+    Ok(rows)
+}
+
+/// The `read_json_auto` call DuckDB loads a JSON lines file with
+fn read_json_sql(file_path: &Path) -> String {
+    format!("read_json_auto('{}', format = 'newline_delimited')", file_path.display().to_string().replace('\'', "''"))
+}
+
+/// The columns DuckDB infers for a JSON lines file, with nested objects and arrays as JSON
+/// columns, and the SELECT list that loads them that way
+fn json_lines_columns(conn: &Connection, file_path: &Path) -> Result<(Vec<(String, String)>, String)> {
+    let mut stmt = conn.prepare(&format!("DESCRIBE SELECT * FROM {}", read_json_sql(file_path)))?;
+    let inferred: Vec<(String, String)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<Result<_, _>>()?;
+    // STRUCT and list values can't be read back through the duckdb crate, so --query and the
+    // exports couldn't show them
+    let mut columns = Vec::new();
+    let mut select = Vec::new();
+    for (name, dtype) in inferred {
+        let nested = dtype.ends_with(']') || dtype.starts_with("STRUCT") || dtype.starts_with("MAP");
+        select.push(match nested {
+            true => format!("to_json({0}) AS {0}", quote_identifier(&name)),
+            false => quote_identifier(&name),
+        });
+        columns.push((name, if nested { "JSON".to_string() } else { dtype }));
+    }
+    Ok((columns, select.join(", ")))
+}
+
+/// A JSON lines file's `--probe` entry, with the columns DuckDB would infer
+fn probe_json_lines(file_path: &Path, filename_prefix: &str, args: &Config) -> Result<serde_json::Value> {
+    let rows = check_json_lines(&fs::read_to_string(file_path).context("Cannot open file")?)?;
+    let (columns, _) = json_lines_columns(&Connection::open_in_memory()?, file_path)?;
+    let columns: Vec<serde_json::Value> = columns.iter().map(|(name, dtype)| serde_json::json!({ "name": name, "type": dtype })).collect();
+    Ok(serde_json::json!({
+        "sheet": json_lines_sheet(file_path),
+        "table": json_lines_table(filename_prefix, args),
+        "rows": rows,
+        "columns": columns.len(),
+        "schema": columns,
+    }))
+}
+
+/// Loads a `.jsonl`/`.ndjson` file into a table named after it with DuckDB's `read_json_auto`,
+/// which infers the column types. Under `--watch`, files that arrive or change after the first
+/// load add their lines to that table, as workbooks add their rows.
+fn load_json_lines(file_path: &Path, filename_prefix: &str, conn_mutex: &Arc<Mutex<Box<dyn Db>>>, args: &Config) -> Result<LoadedSheet> {
+    let watching = args.watch && args.append;
+    if args.single_table.is_some() || (args.append && !watching) {
+        anyhow::bail!("JSON lines files load into a table of their own, so they cannot be used with --single-table or --append");
+    }
+    let text = with_retries(file_path, args.retries, || fs::read_to_string(file_path).context("Cannot open file"))?;
+    let total = check_json_lines(&text)?;
+    let sheet = json_lines_sheet(file_path);
+    let table = match watching {
+        true => json_lines_table(filename_prefix, args),
+        false => claim_table(args, &json_lines_table(filename_prefix, args), &file_path.display().to_string())?,
+    };
+
+    let db = conn_mutex.lock().unwrap();
+    let conn = db.duckdb().context("JSON lines files are read by DuckDB, so they need --backend duckdb")?;
+    let (mut columns, mut select) = json_lines_columns(conn, file_path)?;
+    if let Some(column) = &args.load_timestamp_column {
+        let column = sanitize_identifier(column);
+        if columns.iter().any(|(name, _)| name.eq_ignore_ascii_case(&column)) {
+            anyhow::bail!("Source column {} clashes with a column of {}", column, table);
+        }
+        select.push_str(&format!(", {} AS {}", sql_literal(&args.load_timestamp()), quote_identifier(&column)));
+        columns.push((column, "TIMESTAMP".to_string()));
+    }
+    // --schema-only and --max-total-rows cut the file short; otherwise every line loads
+    let rows = if args.schema_only { 0 } else { claim_rows(args, total) };
+    let limit = if rows < total { format!(" LIMIT {}", rows) } else { String::new() };
+    let sql = match watching {
+        // Matched up by key, so a file whose keys come in another order still lines up
+        true => format!(
+            "CREATE TABLE IF NOT EXISTS {0} AS SELECT {1} FROM {2} LIMIT 0; INSERT INTO {0} BY NAME SELECT {1} FROM {2}{3}",
+            quote_table(&table), select, read_json_sql(file_path), limit,
+        ),
+        false => format!("CREATE OR REPLACE TABLE {} AS SELECT {} FROM {}{}", quote_table(&table), select, read_json_sql(file_path), limit),
+    };
+    if args.explain {
+        println!("-- {} ({})\n{};", table, sheet, sql);
+    }
+    debug!("{}", sql);
+    if let Err(e) = conn.execute_batch(&sql) {
+        release_rows(args, rows);
+        return Err(e.into());
+    }
+    let source_size = (total, columns.len());
+    Ok(LoadedSheet { sheet, table, rows, columns, source_size, expected_rows: rows, content_hash: None, mixed_columns: Vec::new() })
+}
+
+/// Applies `--sheets`, `--exclude-sheets` and `--sheet-regex` to a workbook's sheet names,
+/// warning about any `--sheets` entry the workbook doesn't have.
+fn select_sheets(names: &[String], file_path: &Path, args: &Config) -> Vec<String> {
+    for wanted in &args.sheets {
+        if !names.iter().any(|name| name.eq_ignore_ascii_case(wanted.trim())) {
+            args.write_turns.wait(file_path);
+            warn!("{} has no sheet named {}", file_path.display(), wanted.trim());
+        }
+    }
+    names
+        .iter()
+        .filter(|name| args.sheets.is_empty() || args.sheets.iter().any(|s| name.eq_ignore_ascii_case(s.trim())))
+        .filter(|name| !args.exclude_sheets.iter().any(|s| name.eq_ignore_ascii_case(s.trim())))
+        .filter(|name| args.sheet_regex.as_ref().is_none_or(|re| re.is_match(name)))
+        .cloned()
+        .collect()
+}
+
+/// `--table-filter`: whether a table, named without its schema, should load
+fn table_selected(table: &str, args: &Config) -> bool {
+    let table = unqualified_table(table);
+    let selected = args.table_filter.as_ref().is_none_or(|re| re.is_match(table));
+    if !selected {
+        debug!("Skipping table {}: it doesn't match --table-filter", table);
+    }
+    selected
+}
+
+fn load_workbook<RS: Read + Seek>(workbook: &mut Sheets<RS>, file_path: &Path, filename_prefix: &str, conn_mutex: &Arc<Mutex<Box<dyn Db>>>, progress: &MultiProgress, args: &Config) -> Result<Vec<LoadedSheet>> {
+    let all_sheets = workbook.sheet_names();
+    let sheets: Vec<String> = select_sheets(&all_sheets, file_path, args)
+        .into_iter()
+        .filter(|sheet_name| table_selected(&sheet_tables(filename_prefix, sheet_name, &all_sheets, args).0, args))
+        .collect();
+    let source = SheetSource { file_path, filename_prefix, all_sheets: &all_sheets };
+    // calamine reads one sheet at a time, so --parallel-sheets reads a batch of sheets (one per
+    // thread) and then loads the batch in parallel, holding no more than a batch in memory
+    let batch_size = if args.parallel_sheets { rayon::current_num_threads().max(1) } else { 1 };
+    let mut loaded = Vec::new();
+    // Under --sorted the first batch is read before the file waits its turn to log or write
+    let turn = || args.write_turns.wait(file_path);
+
+    for batch in sheets.chunks(batch_size) {
+        let mut ranges = Vec::new();
+        for sheet_name in batch {
+            if budget_spent(args) {
+                break;
+            }
+            if args.interrupted() {
+                anyhow::bail!("Interrupted before sheet {}", sheet_name);
+            }
+            let sheet_label = format!("{} sheet {}", file_path.display(), sheet_name);
+            // A sheet is streamed where it can be, holding a chunk of its rows at a time; one that
+            // ends within its first rows is read whole on the way, and one the stream can't follow
+            // (or that it can't read) is read whole below
+            let (mut read, mut claimed) = (None, None);
+            if batch_size == 1 && streams_sheet(file_path, sheet_name, args) {
+                let mut fix_archive = None;
+                let opened = SheetStream::open(workbook, file_path, sheet_name, &mut fix_archive, args);
+                match opened {
+                    Ok(Some((head, Some(stream)))) => {
+                        turn();
+                        match load_sheet(&source, sheet_name, None, SheetCells::Stream(head, Box::new(stream)), conn_mutex, progress, args).map_err(|e| e.downcast::<StreamFallback>()) {
+                            Ok(sheet) => {
+                                loaded.extend(sheet);
+                                continue;
+                            }
+                            Err(Ok(fallback)) => {
+                                info!("{}: {}; reading the sheet whole", sheet_label, fallback.reason);
+                                claimed = fallback.table;
+                            }
+                            Err(Err(e)) => return Err(e),
+                        }
+                    }
+                    Ok(Some((range, None))) => read = Some(range),
+                    Ok(None) | Err(_) => {}
+                }
+            }
+            // xlsx sheets declare their size up front, so they are checked before calamine reads them
+            // whole; other formats can only be measured once read
+            let is_zipped_xlsx = file_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("xlsx") || ext.eq_ignore_ascii_case("xlsm"));
+            let declared = match args.max_memory {
+                Some(_) if is_zipped_xlsx && read.is_none() => declared_dimension(file_path, sheet_name, args).ok().flatten(),
+                _ => None,
+            };
+            if let Some(size) = declared {
+                turn();
+                check_sheet_memory(&sheet_label, size, args)?;
+            }
+            let range = read.map_or_else(|| {
+                workbook.worksheet_range(sheet_name).map_err(anyhow::Error::from)
+                    .and_then(|range| restore_xlsb_dates(file_path, sheet_name, range, args))
+                    .and_then(|range| restore_padded_codes(file_path, sheet_name, range, args))
+            }, Ok);
+            let range = match range {
+                Ok(range) => restrict_to_range(range, sheet_name, args),
+                Err(e) if args.strict => return Err(e).with_context(|| format!("Cannot read sheet {} (--strict)", sheet_name)),
+                // A damaged sheet doesn't take the rest of the workbook down with it
+                Err(e) => {
+                    turn();
+                    warn!("Skipping {}: cannot read it: {}", sheet_label, e);
+                    args.skipped_sheets.lock().unwrap().push((file_path.display().to_string(), sheet_name.clone(), e.to_string()));
+                    continue;
+                }
+            };
+            if declared.is_none() && args.max_memory.is_some() {
+                turn();
+                let (height, width) = range.get_size();
+                check_sheet_memory(&sheet_label, (height as u64, width as u64), args)?;
+            }
+            let parts = sheet_parts(range, args);
+            if parts.len() > 1 {
+                info!("{}: {} tables separated by blank or repeated header rows (--split-on-blank)", sheet_label, parts.len());
+            }
+            ranges.extend(parts.into_iter().map(|(part, range)| (sheet_name.as_str(), part, range, claimed.clone())));
+        }
+
+        turn();
+        if ranges.len() > 1 {
+            // Each sheet gets a connection of its own where the backend allows, so one sheet's
+            // transaction doesn't hold up the rest
+            let results: Vec<Result<Option<LoadedSheet>>> = ranges.into_par_iter().map(|(sheet_name, part, range, claimed)| {
+                let own_db = conn_mutex.lock().unwrap().try_clone().map(|db| Arc::new(Mutex::new(db)));
+                load_sheet(&source, sheet_name, part, SheetCells::Whole(range, claimed), own_db.as_ref().unwrap_or(conn_mutex), progress, args)
+            }).collect();
+            for result in results {
+                loaded.extend(result?);
+            }
+        } else {
+            for (sheet_name, part, range, claimed) in ranges {
+                loaded.extend(load_sheet(&source, sheet_name, part, SheetCells::Whole(range, claimed), conn_mutex, progress, args)?);
+            }
+        }
+    }
+
+    Ok(loaded)
+}
+
+/// Whether a sheet is streamed ([`SheetStream`]) rather than read whole: loading it must need
+/// no more than its first rows before inserting them, and must store each later row as read.
+/// Options that look through, reshape or sample the whole sheet have it read whole, and so does
+/// `--parallel-sheets`, which reads a batch of sheets up front.
+fn streams_sheet(file_path: &Path, sheet_name: &str, args: &Config) -> bool {
+    let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    let for_sheet = |sheet: &str| sheet.eq_ignore_ascii_case(sheet_name);
+    !args.parallel_sheets
+        && args.settings.sheet_for(&file_name, sheet_name).is_none()
+        && !args.ranges.iter().any(|block| for_sheet(&block.sheet))
+        && !args.columns.iter().any(|selection| for_sheet(&selection.sheet))
+        && !args.split_on_blank
+        && !args.incremental
+        && !args.detect_percent
+        && args.format_map.is_empty()
+        && !args.fix_encoding
+        && args.orientation == Orientation::Rows
+        && !args.find_header
+        && args.skip_rows == 0
+        && args.header_row == 0
+        && args.header_rows == 1
+        && !args.fill_header
+        && !args.no_header
+        && !args.trim
+        && !args.normalize_whitespace
+        && args.null_values.is_empty()
+        && matches!(args.error_handling, ErrorHandling::Null)
+        && args.bool_strings.is_none()
+        && !args.parse_dates
+        && args.min_rows.is_none()
+        && !args.load_random
+        && !args.tight_ints
+        && matches!(args.sample_from, SampleFrom::Head)
+        && !args.explain
+        && !args.explain_types
+        && args.ddl_out.is_none()
+}
+
+/// The cells [`load_sheet`] loads a sheet from
+enum SheetCells<'a> {
+    /// The whole sheet, and the table an attempt to stream it already claimed
+    Whole(Range<Data>, Option<String>),
+    /// The first rows of a sheet, and the rest of it to come a row at a time
+    Stream(Range<Data>, Box<SheetStream<'a>>),
+}
+
+/// Reads a sheet's cells one at a time, in the order the file holds them, with their positions
+type CellReader<'a> = Box<dyn FnMut() -> Result<Option<((u32, u32), Data)>> + 'a>;
+
+/// An xlsx or xlsb sheet read a row at a time through calamine's cell reader, so loading it holds
+/// a chunk of rows rather than every cell. Its first rows (the head) are read whole, to infer the
+/// column types from. calamine sizes a sheet it reads whole by all of its cells, so the stream
+/// only follows a sheet whose cells come in order and within the columns of its head, and gives
+/// up on any other with a [`StreamFallback`].
+struct SheetStream<'a> {
+    next_cell: CellReader<'a>,
+    fixes: CellFixes<'a>,
+    /// The first cell of the rows not handed out yet, and whether the sheet has no more
+    pending: Option<((u32, u32), Data)>,
+    ended: bool,
+    /// The first cell and the last one read, which the next must follow
+    first: Option<(u32, u32)>,
+    last: Option<(u32, u32)>,
+    /// The columns of the head, once it is read
+    columns: Option<(u32, u32)>,
+    /// The sheet row handed out next
+    next_row: u32,
+    /// The data rows an xlsx sheet declares it holds, for the progress bar
+    declared_rows: Option<usize>,
+}
+
+impl<'a> SheetStream<'a> {
+    /// Starts reading an xlsx or xlsb sheet, reading its header and enough rows to infer the
+    /// column types and fill a chunk. Returns those rows and the stream of the rest, or no stream
+    /// for a sheet that ends within them; None for other formats.
+    fn open<'r: 'a, RS: Read + Seek + 'a>(
+        workbook: &'a mut Sheets<RS>,
+        file_path: &Path,
+        sheet_name: &str,
+        fix_archive: &'a mut Option<zip::ZipArchive<Box<dyn ReadSeek + 'r>>>,
+        args: &'r Config,
+    ) -> Result<Option<(Range<Data>, Option<SheetStream<'a>>)>> {
+        let (next_cell, declared): (CellReader, _) = match workbook {
+            Sheets::Xlsx(xlsx) => {
+                let mut cells = xlsx.worksheet_cells_reader(sheet_name)?;
+                let declared = declared_dimension(file_path, sheet_name, args).ok().flatten();
+                (Box::new(move || Ok(cells.next_cell()?.map(|cell| (cell.get_position(), Data::from(cell.get_value().clone()))))), declared)
+            }
+            Sheets::Xlsb(xlsb) => {
+                let mut cells = xlsb.worksheet_cells_reader(sheet_name)?;
+                (Box::new(move || Ok(cells.next_cell()?.map(|cell| (cell.get_position(), Data::from(cell.get_value().clone()))))), None)
+            }
+            _ => return Ok(None),
+        };
+        let mut stream = SheetStream {
+            next_cell,
+            fixes: CellFixes::open(file_path, sheet_name, fix_archive, args)?,
+            pending: None,
+            ended: false,
+            first: None,
+            last: None,
+            columns: None,
+            next_row: 0,
+            declared_rows: declared.map(|(rows, _)| rows.saturating_sub(1) as usize),
+        };
+
+        // Whole chunks of data rows, so the sheet is chunked as it would be read whole
+        let chunk_size = args.chunk_size.max(1);
+        let head_rows = args.infer_rows.max(1).div_ceil(chunk_size) * chunk_size;
+        let head_rows = args.load_rows.map_or(head_rows, |n| head_rows.min(n.max(1)));
+        let mut head = Vec::new();
+        while let Some((position, value)) = stream.read_cell()? {
+            let first_row = stream.first.get_or_insert(position).0;
+            if (position.0 - first_row) as usize > head_rows {
+                stream.pending = Some((position, value));
+                break;
+            }
+            head.push(calamine::Cell::new(position, value));
+        }
+        let head = Range::from_sparse(head);
+        match (head.start(), head.end()) {
+            (Some(start), Some(end)) if stream.pending.is_some() => {
+                stream.columns = Some((start.1, end.1));
+                stream.next_row = end.0 + 1;
+                Ok(Some((head, Some(stream))))
+            }
+            _ => Ok(Some((head, None))),
+        }
+    }
+
+    /// The next cell with a value, fixed up. Fails with a [`StreamFallback`] once the sheet
+    /// leaves the order or the columns the stream follows.
+    fn read_cell(&mut self) -> Result<Option<((u32, u32), Data)>> {
+        let fallback = |reason: String| anyhow::Error::from(StreamFallback { reason, table: None });
+        while !self.ended {
+            let Some((position, value)) = (self.next_cell)().map_err(|e| fallback(format!("cannot read it a row at a time: {:#}", e)))? else {
+                self.ended = true;
+                break;
+            };
+            if value == Data::Empty {
+                continue;
+            }
+            if self.last.is_some_and(|last| position <= last) {
+                return Err(fallback(format!("its cells are out of order at row {}", position.0 + 1)));
+            }
+            if self.columns.is_some_and(|(left, right)| position.1 < left || position.1 > right) {
+                return Err(fallback(format!("row {} has cells outside the columns of its first rows", position.0 + 1)));
+            }
+            self.last = Some(position);
+            let value = self.fixes.fix(position, value).map_err(|e| fallback(format!("{:#}", e)))?;
+            return Ok(Some((position, value)));
+        }
+        Ok(None)
+    }
+
+    /// The next row past the head, or None at the end of the sheet; rows without cells come
+    /// back blank, as they sit in a whole sheet
+    fn next_row(&mut self) -> Result<Option<Vec<Data>>> {
+        if self.pending.is_none() {
+            self.pending = self.read_cell()?;
+        }
+        let (Some(_), Some((left, right))) = (&self.pending, self.columns) else {
+            return Ok(None);
+        };
+        let mut row = vec![Data::Empty; (right - left + 1) as usize];
+        while let Some(((_, col), value)) = self.pending.take_if(|((row, _), _)| *row == self.next_row) {
+            row[(col - left) as usize] = value;
+            self.pending = self.read_cell()?;
+        }
+        self.next_row += 1;
+        Ok(Some(row))
+    }
+
+    /// Reads up to `n` more rows into `rows`, in place of the ones it held
+    fn read_rows(&mut self, rows: &mut Vec<Vec<Data>>, n: usize) -> Result<()> {
+        rows.clear();
+        while rows.len() < n {
+            match self.next_row()? {
+                Some(row) => rows.push(row),
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the rest of the sheet without keeping it, so rows left unloaded (past `--load-rows`,
+    /// the row budget or an `--schema-only` header) are still held to the stream's columns
+    fn finish(&mut self) -> Result<()> {
+        self.pending = None;
+        while self.read_cell()?.is_some() {}
+        Ok(())
+    }
+
+    /// The (rows, columns) of the whole sheet, as [`Range::get_size`] has them, once it is finished
+    fn size(&self) -> (usize, usize) {
+        match (self.first, self.last, self.columns) {
+            (Some(first), Some(last), Some((left, right))) => ((last.0 - first.0 + 1) as usize, (right - left + 1) as usize),
+            _ => (0, 0),
+        }
+    }
+}
+
+/// What [`restore_xlsb_dates`] and [`restore_padded_codes`] do to a whole sheet, done a cell at a
+/// time as a [`SheetStream`] reads it: a second reader walks the sheet's part alongside calamine's
+enum CellFixes<'z> {
+    None,
+    PaddedCodes {
+        styles: Vec<CellStyle>,
+        cells: StyledCells<BufReader<zip::read::ZipFile<'z>>>,
+        next: Option<StyledCell>,
+    },
+    XlsbDates {
+        dates: XlsbIntegerDates<BufReader<zip::read::ZipFile<'z>>>,
+        next: Option<((u32, u32), Data)>,
+    },
+}
+
+impl<'z> CellFixes<'z> {
+    /// The fixes a sheet's cells need, read through `archive`, which is opened on the workbook
+    /// when there are any. They go by the file's extension, as the whole-sheet fixes do.
+    fn open<'r: 'z>(file_path: &Path, sheet_name: &str, archive: &'z mut Option<zip::ZipArchive<Box<dyn ReadSeek + 'r>>>, args: &'r Config) -> Result<Self> {
+        let is_ext = |wanted: &str| file_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(wanted));
+        let padded = is_ext("xlsx") && args.stdin_data.is_none();
+        if !padded && !is_ext("xlsb") {
+            return Ok(CellFixes::None);
+        }
+        let archive = archive.insert(zip::ZipArchive::new(workbook_reader(file_path, args)?)?);
+        if padded {
+            let styles = read_styles(archive, &[])?;
+            if styles.iter().all(|style| style.padded.is_none()) {
+                return Ok(CellFixes::None);
+            }
+            let Some(mut cells) = StyledCells::open(archive, sheet_name)? else {
+                return Ok(CellFixes::None);
+            };
+            let next = cells.next_cell()?;
+            return Ok(CellFixes::PaddedCodes { styles, cells, next });
+        }
+        let Some(styles) = xlsb_date_styles(archive, sheet_name)? else {
+            return Ok(CellFixes::None);
+        };
+        let Some(mut dates) = XlsbIntegerDates::open(archive, styles)? else {
+            return Ok(CellFixes::None);
+        };
+        let next = dates.next_date()?;
+        Ok(CellFixes::XlsbDates { dates, next })
+    }
+
+    /// The value of the cell at `position`, the cells before it having been fixed already
+    fn fix(&mut self, position: (u32, u32), value: Data) -> Result<Data> {
+        match self {
+            CellFixes::None => Ok(value),
+            CellFixes::PaddedCodes { styles, cells, next } => {
+                // Row formats, and the formats of cells calamine found no value in, are passed over
+                while let Some(cell) = next.as_ref().filter(|cell| cell.col.is_none_or(|col| (cell.row, col) < position)) {
+                    let passed = cell.col.map(|col| (cell.row, col));
+                    *next = cells.next_cell()?;
+                    if let Some(passed) = passed.filter(|&passed| next.as_ref().is_some_and(|cell| cell.col.is_some_and(|col| (cell.row, col) <= passed))) {
+                        anyhow::bail!("the formats of its cells are out of order at row {}", passed.0 + 1);
+                    }
+                }
+                let width = next.as_ref()
+                    .filter(|cell| cell.row == position.0 && cell.col == Some(position.1))
+                    .and_then(|cell| styles.get(cell.xf)?.padded);
+                Ok(width.and_then(|width| padded_code(&value, width)).unwrap_or(value))
+            }
+            CellFixes::XlsbDates { dates, next } => {
+                while let Some(&(passed, _)) = next.as_ref().filter(|(at, _)| *at < position) {
+                    *next = dates.next_date()?;
+                    if next.as_ref().is_some_and(|(at, _)| *at <= passed) {
+                        anyhow::bail!("its dates are out of order at row {}", passed.0 + 1);
+                    }
+                }
+                match next {
+                    Some((at, date)) if *at == position && matches!(value, Data::Int(_)) => Ok(date.clone()),
+                    _ => Ok(value),
+                }
+            }
+        }
+    }
+}
+
+/// A sheet a [`SheetStream`] could not follow to its end. Nothing it loaded stays: `load_workbook`
+/// reads the sheet whole and loads it again, into the table it claimed.
+struct StreamFallback {
+    reason: String,
+    table: Option<String>,
+}
+
+impl std::fmt::Display for StreamFallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl std::fmt::Debug for StreamFallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "streaming the sheet gave up: {}", self.reason)
+    }
+}
+
+impl std::error::Error for StreamFallback {}
+
+/// The workbook a sheet is loaded from: its path, the table-name prefix it gives its sheets, and
+/// the names of all its sheets, which `{index}` and `--single-sheet-file-name` go by
+struct SheetSource<'a> {
+    file_path: &'a Path,
+    filename_prefix: &'a str,
+    all_sheets: &'a [String],
+}
+
+/// The columns an empty sheet is created with under `--include-empty-sheets`: those its `[[sheet]]`
+/// section lists, else the ones its `types` or `--schema` give a type, alphabetically
+fn declared_columns(table_name: &str, sheet_config: Option<&SheetConfig>, args: &Config) -> Option<Vec<String>> {
+    if let Some(columns) = sheet_config.and_then(|cfg| cfg.columns.clone()).filter(|c| !c.is_empty()) {
+        return Some(columns);
+    }
+    let mut columns: Vec<String> = match sheet_config.filter(|cfg| !cfg.types.is_empty()) {
+        Some(cfg) => cfg.types.keys().cloned().collect(),
+        None => args.schema_overrides.columns_for(table_name)?.keys().cloned().collect(),
+    };
+    columns.sort();
+    (!columns.is_empty()).then_some(columns)
+}
+
+/// A one-row range holding just the given header cells
+fn header_range(columns: Vec<String>) -> Range<Data> {
+    let mut range = Range::new((0, 0), (0, columns.len() as u32 - 1));
+    for (col, name) in columns.into_iter().enumerate() {
+        range.set_value((0, col as u32), Data::String(name));
+    }
+    range
+}
+
+/// Loads one sheet's cells into its table, in a transaction of its own. Returns None for a sheet
+/// that was skipped (empty, below `--min-rows`, or left out of a merged table). `part` numbers
+/// the block of a sheet `--split-on-blank` split. A streamed sheet is inferred from its head and
+/// then loaded a chunk at a time; one the stream gives up on fails with a [`StreamFallback`].
+fn load_sheet(source: &SheetSource, sheet_name: &str, part: Option<usize>, cells: SheetCells, conn_mutex: &Arc<Mutex<Box<dyn Db>>>, progress: &MultiProgress, args: &Config) -> Result<Option<LoadedSheet>> {
+    let SheetSource { file_path, filename_prefix, all_sheets } = *source;
+    let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    let sheet_config = args.settings.sheet_for(&file_name, sheet_name);
+    let (table_name, mut target_table) = part_tables(sheet_tables(filename_prefix, sheet_name, all_sheets, args), part, args);
+    let (range, mut stream, claimed) = match cells {
+        SheetCells::Whole(range, claimed) => (range, None, claimed),
+        SheetCells::Stream(head, stream) => (head, Some(stream), None),
+    };
+    let range = if range.is_empty() && args.include_empty_sheets && !args.no_header {
+        match declared_columns(&table_name, sheet_config, args) {
+            Some(columns) => {
+                info!("{} has no cells; creating it from its declared columns: {}", table_name, columns.join(", "));
+                header_range(columns)
+            }
+            None => {
+                warn!("Skipping {}: the sheet is empty and no columns are declared for it in a [[sheet]] section or --schema", table_name);
+                return Ok(None);
+            }
+        }
+    } else {
+        range
+    };
+    // A streamed sheet is measured once it is read to its end
+    let mut source_size = range.get_size();
+    // Hashed as read, before any option reshapes the cells, together with the options that will
+    let content_hash = args.incremental.then(|| range_hash(&range, &shaping_options(&table_name, sheet_config, args)));
+    let formats = column_formats(file_path, sheet_name, &range, sheet_config, args)
+        .with_context(|| format!("Reading number formats of sheet {}", sheet_name))?;
+    let (range, raw_range) = prepare_range(range, sheet_name, sheet_config, args).with_context(|| format!("Reading sheet {}", sheet_name))?;
+    // Raw rows line up with data rows; both skip the header row
+    let raw_rows: Vec<&[Data]> = raw_range.as_ref().map_or_else(Vec::new, |raw| raw.rows().skip(1).collect());
+    // Sheets merged into a shared table (--single-table or a --union-by group) carry their source
+    let union_table = union_target(file_path, sheet_name, args);
+    let merged = args.single_table.is_some() || union_table.is_some();
+    if let Some(table) = union_table {
+        target_table = table;
+    }
+    
+    // Get headers
+    let mut rows = range.rows();
+    let headers = if let Some(h) = rows.next() {
+        h
+    } else {
+        info!("Skipping {}: the sheet is empty", table_name);
+        return Ok(None);
+    };
+    if !args.no_header && looks_like_data(headers) {
+        warn!("The header row of {} holds only numbers; if the sheet has no header, load it with --no-header", table_name);
+    }
+
+    // --min-rows goes by the sheet, not by the --load-rows sample
+    let sheet_rows = range.height().saturating_sub(1);
+    if let Some(min_rows) = args.min_rows.filter(|&min| sheet_rows < min) {
+        info!("Skipping {}: {} data row(s), fewer than --min-rows {}", table_name, sheet_rows, min_rows);
+        return Ok(None);
+    }
+    let (rows_data, sampled) = sheet_data_rows(rows, args);
+    // Where a loaded row sits among the sheet's data rows, which a --load-random sample skips through
+    let data_index = |i: usize| sampled.as_ref().map_or(i, |picked| picked[i]);
+    match claimed {
+        Some(table) => target_table = table,
+        None if !merged && !args.append => target_table = claim_table(args, &target_table, &format!("{} ({})", file_path.display(), sheet_name))?,
+        None => {}
+    }
+    if let Some(hash) = &content_hash {
+        if let Some(kept) = unchanged_sheet(file_path, sheet_name, &target_table, hash, source_size, conn_mutex, args)? {
+            info!("Keeping {}: the sheet's cells are unchanged since the last load (--incremental)", kept.table);
+            return Ok(Some(kept));
+        }
+    }
+
+    let schema = infer_sheet_schema(headers, &rows_data, &table_name, sheet_config, &formats, args)?;
+    // Data running past the labelled columns still loads, under the generated names
+    let unlabelled: Vec<&str> = headers.iter().zip(&schema.names)
+        .filter(|(cell, _)| cell.to_string().trim().is_empty())
+        .map(|(_, name)| name.as_str())
+        .collect();
+    if !unlabelled.is_empty() && !args.no_header {
+        info!("{}: {} column(s) without a header loaded as {}", table_name, unlabelled.len(), unlabelled.join(", "));
+    }
+    for (name, reason) in schema.names.iter().zip(&schema.reasons) {
+        debug!("{}.{}: {}", table_name, name, reason);
+    }
+    if args.explain_types {
+        let lines: Vec<String> = schema.names.iter().zip(&schema.reasons)
+            .map(|(name, reason)| format!("  {}.{}: {}", table_name, name, reason))
+            .collect();
+        println!("Column types for {}:\n{}", table_name, lines.join("\n"));
+    }
+    // Absolute (0-based) sheet row of the first data row, used to line rows up with style info
+    // (--no-header moved the rows down one to make room for its header)
+    let first_data_row = range.start().map_or(1, |(row, _)| row + 1) - args.no_header as u32;
+    let source_label = format!("{} sheet {}", file_path.display(), sheet_name);
+    // Values that lose precision; a streamed sheet's later rows are checked as they are read
+    let mut truncated = 0;
+    if !args.schema_only {
+        check_truncation(&rows_data, &data_index, &schema, &table_name, args.on_truncate, &mut truncated)?;
+        // Otherwise the rows that don't fit are set aside one by one while inserting
+        if args.on_bad_row == OnBadRow::Abort {
+            check_declared_types(&rows_data, &data_index, &schema, first_data_row, &source_label)?;
+        }
+        if stream.is_none() {
+            warn_truncated(truncated, &table_name);
+        }
+    }
+    let mixed_columns: Vec<String> = schema.names.iter().zip(&schema.mixed).filter(|(_, mixed)| **mixed).map(|(name, _)| name.clone()).collect();
+    let (column_names, column_types, column_locales) = (&schema.names, &schema.types, &schema.locales);
+
+    for required in args.require_columns.iter().filter(|r| r.table.eq_ignore_ascii_case(&table_name)) {
+        let missing: Vec<&str> = required.columns.iter()
+            .filter(|c| !column_names.iter().any(|name| name.eq_ignore_ascii_case(c)))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!("Table {} is missing required column(s): {}", table_name, missing.join(", "));
+        }
+    }
+
+    if args.flag_highlighted && args.orientation == Orientation::Columns {
+        anyhow::bail!("--flag-highlighted marks rows, so it cannot be combined with --orientation columns");
+    }
+    if args.row_number_column.is_some() && args.orientation == Orientation::Columns {
+        anyhow::bail!("--row-number-column numbers sheet rows, so it cannot be combined with --orientation columns");
+    }
+    let is_xlsx = file_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("xlsx"));
+    if args.flag_highlighted && !is_xlsx {
+        anyhow::bail!("--flag-highlighted reads xlsx cell styles, so it cannot be used on {}", file_path.display());
+    }
+    let highlighted = if args.flag_highlighted {
+        Some(highlighted_rows(file_path, sheet_name, args).context("Cannot read cell styles")?)
+    } else {
+        None
+    };
+
+    // A big sheet can take minutes on its own, so show its rows moving rather than a stalled file
+    // bar; a streamed xlsx sheet goes by the size it declares
+    let bar_rows = stream.as_ref().and_then(|stream| stream.declared_rows).map_or(rows_data.len(), |rows| args.load_rows.map_or(rows, |n| rows.min(n)));
+    let row_bar = (bar_rows > ROW_PROGRESS_MIN_ROWS && !args.schema_only).then(|| {
+        let bar = progress.add(ProgressBar::new(bar_rows as u64));
+        bar.set_style(ProgressStyle::default_bar()
+            .template("  {msg} [{bar:30.cyan/blue}] {pos}/{len} rows ({eta})")
+            .unwrap()
+            .progress_chars("#>-"));
+        bar.set_message(table_name.clone());
+        bar
+    });
+
+    // The connection stays locked for the whole sheet so its transaction holds only this
+    // sheet's writes; a sheet that fails part-way is rolled back and leaves no partial table
+    let db = conn_mutex.lock().unwrap();
+    // From this chunk on, rows are tried on `probe` before the sheet's transaction gets them
+    let mut checked_from: Option<usize> = None;
+    let mut probe: Option<DuckBackend> = None;
+    let written = loop {
+        db.execute_batch("BEGIN TRANSACTION")?;
+        // Rows this attempt took from the --max-total-rows budget, one per row it inserts
+        let mut claimed = 0;
+        // Rows written and rows that should have been, or None when the sheet was skipped
+        let attempt = (|| -> Result<Option<(usize, usize)>> {
+            // Create Table
+            let mut schema_cols: Vec<String> = column_names.iter().zip(column_types.iter())
+                .map(|(name, dtype)| column_def(name, dtype, args.text_collation.as_deref()))
+                .collect();
+            if highlighted.is_some() {
+                schema_cols.push(column_def("_highlighted", "BOOLEAN", None));
+            }
+            if args.keep_raw {
+                schema_cols.push(column_def("_raw", "JSON", None));
+            }
+
+            let mut insert_columns: Vec<(String, &str)> = column_names.iter().cloned().zip(column_types.iter().copied()).collect();
+            if highlighted.is_some() {
+                insert_columns.push(("_highlighted".to_string(), "BOOLEAN"));
+            }
+            if args.keep_raw {
+                insert_columns.push(("_raw".to_string(), "JSON"));
+            }
+            // Provenance columns go last, each row carrying the same file and sheet name and load time
+            let provenance: Vec<(String, &str, Value)> = [
+                (&args.source_column, "VARCHAR", Value::Text(file_name.to_string())),
+                (&args.source_sheet_column, "VARCHAR", Value::Text(sheet_name.to_string())),
+                (&args.load_timestamp_column, "TIMESTAMP", args.load_timestamp()),
+            ]
+                .into_iter()
+                .filter_map(|(column, dtype, value)| Some((sanitize_identifier(column.as_deref()?), dtype, value)))
+                .collect();
+            for (column, dtype, _) in &provenance {
+                if insert_columns.iter().any(|(name, _)| name.eq_ignore_ascii_case(column)) {
+                    anyhow::bail!("Source column {} clashes with a column of {}", column, table_name);
+                }
+                insert_columns.push((column.clone(), dtype));
+                schema_cols.push(column_def(column, dtype, args.text_collation.as_deref()));
+            }
+            let row_number_column = args.row_number_column.as_deref().map(sanitize_identifier);
+            if let Some(column) = &row_number_column {
+                if insert_columns.iter().any(|(name, _)| name.eq_ignore_ascii_case(column)) {
+                    anyhow::bail!("Row number column {} clashes with a column of {}", column, table_name);
+                }
+                insert_columns.push((column.clone(), "INTEGER"));
+                schema_cols.push(column_def(column, "INTEGER", None));
+            }
+            if let (Some(_), Some(_)) = (&args.text_collation, &args.sql_dump) {
+                // Every VARCHAR column so far got the collation; the merged source columns below don't
+                let table = final_table(&target_table, args.schema_name.as_deref()).to_lowercase();
+                let mut collated = args.collated_columns.lock().unwrap();
+                for (name, _) in insert_columns.iter().filter(|(_, dtype)| *dtype == "VARCHAR") {
+                    collated.insert((table.clone(), name.to_lowercase()));
+                }
+            }
+            let sheet_label = format!("{} ({})", table_name, sheet_name);
+            if let Some(probe) = &probe {
+                probe.execute_batch(&format!("CREATE OR REPLACE TEMP TABLE {} ({})", ROW_CHECK_TABLE, schema_cols.join(", ")))?;
+            }
+
+            // main rejects --single-table, --union-by and --append up front for backends other than DuckDB
+            let duckdb = db.duckdb();
+            if merged && duckdb.is_some() {
+                insert_columns.splice(0..0, [("_source_file".to_string(), "VARCHAR"), ("_source_sheet".to_string(), "VARCHAR")]);
+                schema_cols.splice(0..0, [column_def("_source_file", "VARCHAR", None), column_def("_source_sheet", "VARCHAR", None)]);
+            }
+            if args.explain {
+                // Merged and appended tables are only created by the first sheet that reaches them
+                println!("-- {}\n{};", sheet_label, create_table_sql(&target_table, &schema_cols, !merged && !args.append));
+            }
+            if args.ddl_out.is_some() {
+                // Staged tables are written under the name they are swapped in as
+                let table = final_table(&target_table, args.schema_name.as_deref());
+                let mut ddl = args.ddl.lock().unwrap();
+                match ddl.iter_mut().find(|(name, _)| name.eq_ignore_ascii_case(&table)) {
+                    // --on-collision overwrite replaces the table with the later sheet's
+                    Some((_, statement)) if !merged && !args.append => *statement = create_table_sql(&table, &schema_cols, true),
+                    Some(_) => {}
+                    None => {
+                        let statement = create_table_sql(&table, &schema_cols, !merged && !args.append);
+                        ddl.push((table, statement));
+                    }
+                }
+            }
+            if let Some(conn) = duckdb.filter(|_| merged) {
+                if !prepare_single_table(conn, &target_table, &insert_columns, &schema_cols, args.single_table_widen, &sheet_label)? {
+                    return Ok(None);
+                }
+            } else if let Some(conn) = duckdb.filter(|_| args.append) {
+                prepare_append_table(conn, &target_table, &insert_columns, &schema_cols, &sheet_label)?;
+            } else {
+                db.create_table(&target_table, &insert_columns, args.text_collation.as_deref())?;
+            }
+
+            if args.store_metadata {
+                let table = final_table(&target_table, args.schema_name.as_deref());
+                let fresh = !merged && !args.append;
+                store_column_mapping(db.as_ref(), &table, headers, column_names, fresh)?;
+            }
+
+            // Schema discovery only: the table exists with its inferred types but stays empty
+            if args.schema_only {
+                return Ok(Some((0, 0)));
+            }
+
+            // Merged rows lead with the file and sheet they came from
+            let source_values = match merged {
+                true => vec![Value::Text(file_name.to_string()), Value::Text(sheet_name.to_string())],
+                false => Vec::new(),
+            };
+            let row_width = insert_columns.len();
+
+            // Insert data in chunks through the backend (DuckDB's Appender or batched, parameterized INSERTs)
+            let mut inserted = 0;
+            let mut offered = 0;
+            // Rows left out under --on-bad-row: their 1-based sheet row, the reason, and the row as JSON
+            let mut bad_rows: Vec<(usize, String, String)> = Vec::new();
+            let mut blank_rows = 0;
+            let mut explained_insert = false;
+            let chunk_size = args.chunk_size;
+            let mut head_chunks = rows_data.chunks(chunk_size);
+            // The rows of a streamed sheet past its head, read a chunk at a time
+            let mut streamed: Vec<Vec<Data>> = Vec::new();
+            // Where the chunk's first row sits among the loaded rows
+            let mut first_row = 0;
+            for chunk_index in 0.. {
+                let chunk: Vec<&[Data]> = match (head_chunks.next(), stream.as_mut()) {
+                    (Some(chunk), _) => chunk.to_vec(),
+                    (None, Some(stream)) => {
+                        // --load-rows counts the head's rows too
+                        stream.read_rows(&mut streamed, args.load_rows.map_or(chunk_size, |n| chunk_size.min(n.saturating_sub(first_row))))?;
+                        let chunk: Vec<&[Data]> = streamed.iter().map(Vec::as_slice).collect();
+                        let data_index = |i: usize| first_row + i;
+                        check_truncation(&chunk, &data_index, &schema, &table_name, args.on_truncate, &mut truncated)?;
+                        if args.on_bad_row == OnBadRow::Abort {
+                            check_declared_types(&chunk, &data_index, &schema, first_data_row, &source_label)?;
+                        }
+                        chunk
+                    }
+                    (None, None) => break,
+                };
+                if chunk.is_empty() {
+                    break;
+                }
+                if args.interrupted() {
+                    anyhow::bail!("Interrupted; the rows of {} were rolled back", sheet_label);
+                }
+                // Rows of the chunk looked at before the budget ran out
+                let mut taken = chunk.len();
+
+                let mut values: Vec<Value> = Vec::with_capacity(chunk.len() * row_width);
+                // The 1-based sheet row and raw cells behind each row of `values`
+                let mut value_rows: Vec<(usize, &[Data])> = Vec::with_capacity(chunk.len());
+                for (row_index, row) in chunk.iter().enumerate() {
+                    let data_row = data_index(first_row + row_index);
+                    if args.skip_blank_rows && is_blank_row(row) {
+                        blank_rows += 1;
+                        continue;
+                    }
+                    if args.on_bad_row != OnBadRow::Abort && !args.all_text {
+                        if let Some(problem) = misfit_cell(row, column_names, column_types, column_locales) {
+                            let raw_row = raw_rows.get(data_row).copied().unwrap_or(row);
+                            bad_rows.push((first_data_row as usize + data_row + 1, problem, raw_row_json(raw_row)));
+                            if args.on_bad_row != OnBadRow::Null {
+                                continue;
+                            }
+                        }
+                    }
+                    if claim_rows(args, 1) == 0 {
+                        taken = row_index;
+                        break;
+                    }
+                    claimed += 1;
+                    inserted += 1;
+                    let raw_row = raw_rows.get(data_row).copied().unwrap_or(row);
+                    value_rows.push((first_data_row as usize + data_row + 1, raw_row));
+                    values.extend(source_values.iter().cloned());
+                    values.extend((0..column_types.len()).map(|i| match row.get(i) {
+                        Some(cell) if args.all_text => cell_text(cell).map_or(Value::Null, Value::Text),
+                        Some(cell) if args.on_bad_row == OnBadRow::Null && !cell_fits(cell, column_types[i], column_locales[i]) => Value::Null,
+                        Some(cell) => match &args.timezone {
+                            Some(zone) if column_types[i] == "TIMESTAMPTZ" => zoned_cell_value(cell, zone),
+                            _ => cell_value(cell, column_types[i], column_locales[i]),
+                        },
+                        None => Value::Null, // Pad with NULLs if row is short
+                    }));
+                    if let Some(highlighted) = &highlighted {
+                        let sheet_row = first_data_row + data_row as u32;
+                        values.push(Value::Boolean(highlighted.contains(&sheet_row)));
+                    }
+                    if args.keep_raw {
+                        values.push(Value::Text(raw_row_json(raw_row)));
+                    }
+                    values.extend(provenance.iter().map(|(_, _, value)| value.clone()));
+                    if row_number_column.is_some() {
+                        // Blank rows --skip-blank-rows leaves out still count, so numbers match the sheet
+                        let sheet_row = first_data_row + data_row as u32 + 1;
+                        values.push(Value::Int(sheet_row as i32));
+                    }
+                }
+
+                let checked = probe.as_ref().filter(|_| checked_from.is_some_and(|from| chunk_index >= from));
+                if let Some(probe) = checked {
+                    let (kept, refused) = insertable_rows(probe, &insert_columns, values);
+                    inserted -= refused.len();
+                    claimed -= refused.len();
+                    release_rows(args, refused.len());
+                    bad_rows.extend(refused.into_iter().map(|(i, error)| (value_rows[i].0, error, raw_row_json(value_rows[i].1))));
+                    values = kept;
+                }
+                if !values.is_empty() {
+                    if args.explain && !explained_insert {
+                        println!("{}", explain_insert(&target_table, &insert_columns, &values));
+                        explained_insert = true;
+                    }
+                    match db.insert_chunk(&target_table, &insert_columns, values) {
+                        // A stream can't be read again, so the sheet is read whole to be checked row by row
+                        Err(error) if stream.is_some() && matches!(args.on_bad_row, OnBadRow::Skip | OnBadRow::RejectFile) => {
+                            let reason = format!("the database refused chunk {} ({:#})", chunk_index + 1, error);
+                            return Err(StreamFallback { reason, table: None }.into());
+                        }
+                        // The failed insert aborted the transaction, so the sheet starts over
+                        Err(error) if checked.is_none() && matches!(args.on_bad_row, OnBadRow::Skip | OnBadRow::RejectFile) => {
+                            return Err(RefusedChunk { chunk: chunk_index, error }.into());
+                        }
+                        result => result?,
+                    }
+                }
+                offered += taken;
+                first_row += chunk.len();
+                if let Some(bar) = &row_bar {
+                    bar.inc(taken as u64);
+                }
+                // An --explain dry run loads a throwaway database and needs no more than the first chunk
+                if taken < chunk.len() || (explained_insert && args.dry_run) {
+                    break;
+                }
+            }
+            if stream.is_some() {
+                warn_truncated(truncated, &table_name);
+            }
+            if blank_rows > 0 {
+                info!("{}: skipped {} blank row(s)", sheet_label, blank_rows);
+            }
+            // Rows the database refused were found after the chunk's other bad rows
+            bad_rows.sort_by_key(|(row, _, _)| *row);
+            if let Some((row, problem, _)) = bad_rows.first() {
+                let action = match args.on_bad_row {
+                    OnBadRow::RejectFile => format!("wrote {} row(s) to {}", bad_rows.len(), args.rejects_file),
+                    OnBadRow::Null => format!("set NULL in {} row(s) for values", bad_rows.len()),
+                    _ => format!("skipped {} row(s)", bad_rows.len()),
+                };
+                warn!("{}: {} that didn't fit their columns, the first at row {}: {}", sheet_label, action, row, problem);
+            }
+            if args.on_bad_row == OnBadRow::RejectFile {
+                args.rejected_rows.lock().unwrap().extend(bad_rows.into_iter().map(|(row, error, values)| RejectedRow {
+                    file: file_path.display().to_string(),
+                    sheet: sheet_name.to_string(),
+                    row,
+                    error,
+                    values,
+                }));
+            }
+            Ok(Some((inserted, offered - blank_rows)))
+        })();
+        // Rows left unloaded are read too, so the table can't miss columns the sheet has
+        let attempt = attempt.and_then(|written| match stream.as_mut() {
+            Some(stream) if written.is_some() => stream.finish().map(|_| written),
+            _ => Ok(written),
+        });
+        let attempt = match attempt {
+            Ok(written) => db.execute_batch("COMMIT").map(|_| written),
+            Err(e) => {
+                let _ = db.execute_batch("ROLLBACK");
+                Err(e)
+            }
+        };
+        if attempt.is_err() {
+            // Nothing the attempt inserted stayed, so neither does its share of the budget
+            release_rows(args, claimed);
+        }
+        let attempt = match attempt.map_err(|e| e.downcast::<RefusedChunk>()) {
+            Err(Ok(refused)) => {
+                // Rows are tried on a connection of their own, outside the transaction they'd abort
+                probe = probe.or_else(|| Some(DuckBackend(db.duckdb()?.try_clone().ok()?, InsertMode::Sql)));
+                if probe.is_none() || checked_from.is_some() {
+                    Err(refused.error)
+                } else {
+                    warn!(
+                        "{}: the database refused chunk {} ({:#}); loading the sheet again with its rows checked one at a time",
+                        table_name, refused.chunk + 1, refused.error
+                    );
+                    if let Some(bar) = &row_bar {
+                        bar.set_position(0);
+                    }
+                    checked_from = Some(refused.chunk);
+                    continue;
+                }
+            }
+            Err(Err(e)) => Err(e),
+            Ok(written) => Ok(written),
+        };
+        break attempt;
+    };
+    drop(probe);
+    drop(db);
+
+    if let Some(bar) = row_bar {
+        bar.finish_and_clear();
+    }
+    let written = written.map_err(|e| match e.downcast::<StreamFallback>() {
+        Ok(fallback) => StreamFallback { table: Some(target_table.clone()), ..fallback }.into(),
+        Err(e) => e,
+    });
+    let Some((rows, expected_rows)) = written? else { return Ok(None) };
+    if let Some(stream) = &stream {
+        source_size = stream.size();
+    }
+    if let Some(dir) = &args.output_parquet {
+        write_table_parquet(conn_mutex, &target_table, dir)?;
+    }
+    let columns = schema.names.into_iter().zip(schema.types.into_iter().map(String::from)).collect();
+    Ok(Some(LoadedSheet { sheet: sheet_name.to_string(), table: target_table, rows, columns, source_size, expected_rows, content_hash, mixed_columns }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_with_quotes_and_control_characters_round_trips() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/quoted_text.xlsx"));
+        let notes = [
+            "it's a \\ \"quoted\"\nline\t'' -- not a comment; DROP TABLE t;",
+            "C:\\temp\\ and an escaped \\' quote",
+            "'); DELETE FROM t; --",
+        ];
+        for mode in [InsertMode::Appender, InsertMode::Sql] {
+            let (conn, _) = load_fixture_with(&path, "quoted", &Config::parse_from(["excel_loader_rs"]), mode).unwrap();
+            let stored: Vec<String> = conn
+                .prepare("SELECT note FROM quoted_Data ORDER BY id").unwrap()
+                .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+            assert_eq!(stored, notes, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn collecting_skips_lock_empty_and_non_excel_files() {
+        let dir = std::env::temp_dir().join(format!("collect_excel_files_{}", std::process::id()));
+        fs::create_dir_all(dir.join("2024")).unwrap();
+        for (name, contents) in [("book.xlsx", "x"), ("old.xls", "x"), ("fast.xlsb", "x"), ("~$book.xlsx", "x"), ("empty.xlsx", ""), ("notes.txt", "x"), ("2024/nested.xlsx", "x")] {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        let names = |recursive| {
+            let mut names: Vec<String> = collect_excel_files(&dir, recursive).unwrap().iter()
+                .map(|path| path.strip_prefix(&dir).unwrap().display().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(false), ["book.xlsx", "fast.xlsb", "old.xls"]);
+        assert_eq!(names(true), ["2024/nested.xlsx", "book.xlsx", "fast.xlsb", "old.xls"]);
+        let matched: Vec<PathBuf> = collect_excel_files(&dir.join("**/*.xlsx"), false).unwrap();
+        assert_eq!(matched, [dir.join("2024/nested.xlsx"), dir.join("book.xlsx")]);
+        assert_eq!(glob_root(&dir.join("**/*.xlsx")), dir);
+        let err = collect_excel_files(&dir.join("2023-*.xlsx"), false).unwrap_err();
+        assert!(err.to_string().starts_with("No files matched"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Loads a workbook into a fresh in-memory database as `excel_loader_rs FLAGS` would
+    fn load_fixture(path: &Path, prefix: &str, flags: &[&str]) -> Connection {
+        let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+        load_fixture_with(path, prefix, &args, InsertMode::Appender).unwrap().0
+    }
+
+    // For tests that check the loaded sheets, the error, an insert mode or what the run left in `args`
+    fn load_fixture_with(path: &Path, prefix: &str, args: &Config, mode: InsertMode) -> Result<(Connection, Vec<LoadedSheet>)> {
+        let conn = Connection::open_in_memory().unwrap();
+        let db: Box<dyn Db> = Box::new(DuckBackend(conn.try_clone().unwrap(), mode));
+        let progress = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let loaded = process_excel_file(path, prefix, &Arc::new(Mutex::new(db)), &progress, args)?;
+        Ok((conn, loaded))
+    }
+
+    #[test]
+    fn skip_blank_rows_drops_only_fully_empty_rows() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/gaps.xlsx"));
+        let load = |flags: &[&str]| -> Vec<(Option<String>, Option<i64>)> {
+            let conn = load_fixture(&path, "gaps", flags);
+            let mut stmt = conn.prepare("SELECT region, sales FROM gaps_Data").unwrap();
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(Result::unwrap).collect();
+            rows
+        };
+        assert_eq!(load(&[]).len(), 7);
+        // The row holding only a sales figure stays
+        assert_eq!(load(&["--skip-blank-rows"]), [
+            (Some("North".to_string()), Some(10)),
+            (Some("South".to_string()), Some(20)),
+            (None, Some(5)),
+            (Some("East".to_string()), Some(30)),
+        ]);
+    }
+
+    #[test]
+    fn refresh_plans_only_the_tables_the_run_loads() {
+        let data = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let files = [data.join("gaps.xlsx"), data.join("events.jsonl")];
+        let planned = |flags: &[&str]| planned_tables(&files, &data, &Config::parse_from([&["excel_loader_rs"], flags].concat()));
+        assert_eq!(planned(&[]), ["gaps_Data", "events"]);
+        assert_eq!(planned(&["--tables-prefix", "raw", "--sheets", "Nope"]), ["raw_events"]);
+        assert_eq!(planned(&["--single-table", "everything"]), ["everything", "events"]);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--refresh", "--refresh-all"]).is_err());
+    }
+
+    #[test]
+    fn parallel_sheets_load_the_same_tables_in_sheet_order() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/many_sheets.xlsx"));
+        // Four threads, so the sheets load in batches of four even on a one-core machine
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let load = |flags: &[&str]| -> (Vec<(String, usize)>, i64) {
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            let (conn, loaded) = pool.install(|| load_fixture_with(&path, "many", &args, InsertMode::Appender)).unwrap();
+            let total = conn.query_row("SELECT sum(amount)::BIGINT FROM many_Region6", [], |row| row.get(0)).unwrap();
+            (loaded.into_iter().map(|sheet| (sheet.table, sheet.rows)).collect(), total)
+        };
+        let serial = load(&[]);
+        assert_eq!(serial.0.len(), 6);
+        assert_eq!(serial.0[5], ("many_Region6".to_string(), 8));
+        assert_eq!(load(&["--parallel-sheets"]), serial);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--parallel-sheets", "--single-table", "all"]).is_err());
+    }
+
+    #[test]
+    fn count_only_counts_data_rows_after_the_header_options() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/gaps.xlsx"));
+        let count = |flags: &[&str]| count_sheet_rows(&path, &Config::parse_from([&["excel_loader_rs"], flags].concat())).unwrap();
+        assert_eq!(count(&[]), [("Data".to_string(), 7)]);
+        assert_eq!(count(&["--skip-blank-rows"]), [("Data".to_string(), 4)]);
+        assert_eq!(count(&["--skip-rows", "2"]), [("Data".to_string(), 5)]);
+        assert_eq!(count(&["--exclude-sheets", "Data"]), []);
+    }
+
+    #[test]
+    fn table_filter_matches_the_final_table_names() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/many_sheets.xlsx"));
+        let tables = |flags: &[&str]| -> Vec<String> {
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            load_fixture_with(&path, "many", &args, InsertMode::Appender).unwrap().1.into_iter().map(|sheet| sheet.table).collect()
+        };
+        assert_eq!(tables(&["--table-filter", "^many_Region[12]$"]), ["many_Region1", "many_Region2"]);
+        // The filter sees the prefixed, lower-cased name rather than the sheet name
+        assert_eq!(tables(&["--tables-prefix", "raw", "--identifier-case", "lower", "--table-filter", "^raw_many_region6$"]), ["raw_many_region6"]);
+        assert!(tables(&["--table-filter", "^Region"]).is_empty());
+        assert!(Config::try_parse_from(["excel_loader_rs", "--table-filter", "(unclosed"]).is_err());
+    }
+
+    #[test]
+    fn output_parquet_writes_each_sheet_with_its_column_types() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/complex_data.xlsx"));
+        let dir = std::env::temp_dir().join(format!("output_parquet_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let args = Config::parse_from(["excel_loader_rs", "--tight-ints", "--output-parquet", dir.to_str().unwrap()]);
+        let (conn, loaded) = load_fixture_with(&path, "complex_data", &args, InsertMode::Appender).unwrap();
+        assert_eq!(loaded.len(), 2);
+
+        // The tables went out as Parquet and were dropped
+        let left: i64 = conn.query_row("SELECT count(*) FROM information_schema.tables", [], |row| row.get(0)).unwrap();
+        assert_eq!(left, 0);
+        for sheet in &loaded {
+            let file = dir.join(format!("{}.parquet", sheet.table));
+            let mut stmt = conn.prepare(&format!("DESCRIBE SELECT * FROM read_parquet('{}')", file.display())).unwrap();
+            let columns: Vec<(String, String)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(Result::unwrap).collect();
+            assert_eq!(columns, sheet.columns);
+            let rows: usize = conn.query_row(&format!("SELECT count(*) FROM read_parquet('{}')", file.display()), [], |row| row.get(0)).unwrap();
+            assert_eq!(rows, sheet.rows);
+        }
+        assert!(loaded[0].columns.iter().any(|(_, dtype)| dtype == "SMALLINT"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(Config::try_parse_from(["excel_loader_rs", "--output-parquet", "out", "--query", "SELECT 1"]).is_err());
+    }
+
+    #[test]
+    fn unreadable_sheets_are_reported_or_fail_the_file_with_strict() {
+        // The Notes sheet's XML is cut off half-way
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/damaged.xlsx"));
+        let conn = Connection::open_in_memory().unwrap();
+        let args = Config::parse_from(["excel_loader_rs"]);
+        let stats = load_excel_into_db(std::slice::from_ref(&path), &args, &conn).unwrap();
+        assert_eq!((stats.tables, stats.rows), (1, 2));
+        assert_eq!(stats.skipped_sheets.len(), 1);
+        let (file, sheet, error) = &stats.skipped_sheets[0];
+        assert_eq!((file.as_str(), sheet.as_str()), (path.to_str().unwrap(), "Notes"));
+        assert!(error.contains("Unexpected end of xml"), "{}", error);
+
+        let conn = Connection::open_in_memory().unwrap();
+        let stats = load_excel_into_db(std::slice::from_ref(&path), &Config::parse_from(["excel_loader_rs", "--strict"]), &conn).unwrap();
+        assert!(stats.skipped_sheets.is_empty());
+        assert_eq!(stats.failures.len(), 1);
+        assert!(stats.failures[0].1.contains("Cannot read sheet Notes (--strict)"), "{}", stats.failures[0].1);
+    }
+
+    #[test]
+    fn max_memory_warns_or_refuses_sheets_over_the_estimate() {
+        assert_eq!(parse_byte_size("512MB"), Ok(512 << 20));
+        assert_eq!(parse_byte_size("1.5g"), Ok(3 << 29));
+        assert_eq!(parse_byte_size("2 GiB"), Ok(2 << 30));
+        assert_eq!(parse_byte_size("4096"), Ok(4096));
+        assert!(parse_byte_size("lots").is_err());
+        assert_eq!(format_byte_size(3 << 29), "1.5 GB");
+        assert_eq!(reference_size("B2:E10001"), Some((10000, 4)));
+
+        let users = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/users.xlsx"));
+        assert_eq!(declared_dimension(&users, "Sheet1", &Config::parse_from(["excel_loader_rs"])).unwrap(), Some((4, 3)));
+        // The xlsx is checked from its declared size, the xls once read
+        for (file, prefix) in [("users.xlsx", "users"), ("legacy.xls", "legacy")] {
+            let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data")).join(file);
+            let load = |flags: &[&str]| {
+                let args = Config::parse_from([&["excel_loader_rs", "--max-memory", "100B"], flags].concat());
+                load_fixture_with(&path, prefix, &args, InsertMode::Appender).map(|(_, loaded)| loaded)
+            };
+            assert_eq!(load(&[]).unwrap().len(), 1, "{}", file);
+            let err = load(&["--on-memory-limit", "refuse"]).err().unwrap();
+            assert!(format!("{:#}", err).contains("more than --max-memory 100 bytes"), "{:#}", err);
+        }
+        assert!(Config::try_parse_from(["excel_loader_rs", "--on-memory-limit", "refuse"]).is_err());
+    }
+
+    // The header and rows of a table print_query renders, cell by cell
+    fn table_cells(rendered: &str) -> Vec<Vec<&str>> {
+        rendered.lines()
+            .filter(|line| line.starts_with('│'))
+            .map(|line| line.trim_matches('│').split('┆').map(str::trim).collect())
+            .collect()
+    }
+
+    #[test]
+    fn show_schema_lists_tables_and_describes_one() {
+        let conn = Connection::open_in_memory().unwrap();
+        let shown = |table: Option<&str>| {
+            let mut out = Vec::new();
+            show_schema(&conn, table, &mut out).map(|_| String::from_utf8(out).unwrap())
+        };
+        assert_eq!(shown(None).unwrap(), "The database holds no tables.\n");
+        conn.execute_batch("CREATE TABLE orders_Sheet1 (id BIGINT, \"Order\" VARCHAR); INSERT INTO orders_Sheet1 VALUES (1, 'a'), (2, 'b');").unwrap();
+        let listed = shown(None).unwrap();
+        assert_eq!(table_cells(&listed), [["table_schema", "table_name", "columns", "rows"], ["main", "orders_Sheet1", "2", "2"]]);
+        assert!(listed.ends_with("Query returned 1 rows.\n"), "{}", listed);
+        let described = shown(Some("ORDERS_SHEET1")).unwrap();
+        assert_eq!(table_cells(&described), [["column_name", "data_type", "is_nullable"], ["id", "BIGINT", "YES"], ["Order", "VARCHAR", "YES"]]);
+        assert_eq!(shown(Some("main.orders_sheet1")).unwrap(), described);
+        let err = shown(Some("missing")).unwrap_err();
+        assert!(err.to_string().starts_with("No table named missing"), "{}", err);
+    }
+
+    #[test]
+    fn benchmark_loads_every_strategy_from_a_clean_slate() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/users.xlsx"));
+        // Without the reset between runs, the second load would collide with the first one's tables
+        let mut args = Config::parse_from(["excel_loader_rs", "--benchmark", "--benchmark-runs", "2"]);
+        let mut out = Vec::new();
+        benchmark_files(&[path], &mut args, &mut out).unwrap();
+        let rendered = String::from_utf8(out).unwrap();
+        let rows = table_cells(&rendered);
+        assert_eq!(rows[0], ["Inserts", "Writes", "Tables", "Rows", "Seconds", "Rows/s"]);
+        let strategies: Vec<(&str, &str, &str, &str)> = rows[1..].iter().map(|row| (row[0], row[1], row[2], row[3])).collect();
+        assert_eq!(strategies, [
+            ("Appender", "shared connection", "1", "3"),
+            ("Appender", "parallel", "1", "3"),
+            ("SQL", "shared connection", "1", "3"),
+            ("SQL", "parallel", "1", "3"),
+        ]);
+        for row in &rows[1..] {
+            assert!(row[4].parse::<f64>().is_ok() && row[5].parse::<f64>().is_ok(), "{:?}", row);
+        }
+        assert!(Config::try_parse_from(["excel_loader_rs", "--benchmark", "--parallel-writes"]).is_err());
+        assert!(Config::try_parse_from(["excel_loader_rs", "--benchmark-runs", "3"]).is_err());
+    }
+
+    #[test]
+    fn source_columns_tag_rows_with_file_and_sheet() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/shares.xlsx"));
+        for mode in [InsertMode::Appender, InsertMode::Sql] {
+            let args = Config::parse_from(["excel_loader_rs", "--source-column", "source file", "--source-sheet-column", "sheet"]);
+            let (conn, _) = load_fixture_with(&path, "shares", &args, mode).unwrap();
+            let tags: Vec<(String, String)> = conn
+                .prepare("SELECT DISTINCT source_file, sheet FROM shares_Data").unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(Result::unwrap).collect();
+            assert_eq!(tags, [("shares.xlsx".to_string(), "Data".to_string())], "{:?}", mode);
+        }
+
+        // A name the sheet already uses is refused rather than shadowed
+        let args = Config::parse_from(["excel_loader_rs", "--source-column", "Price"]);
+        let err = load_fixture_with(&path, "shares", &args, InsertMode::Appender).err().expect("the clashing source column is refused");
+        assert!(format!("{:#}", err).contains("clashes with a column of shares_Data"), "{:#}", err);
+    }
+
+    #[test]
+    fn union_by_schema_merges_sheets_with_matching_columns() {
+        let root = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let files = [root.join("monthly_jan.xlsx"), root.join("monthly_feb.xlsx")];
+        let mut args = Config::parse_from(["excel_loader_rs", "--union-by", "schema"]);
+        args.union_tables = plan_union_tables(&files, &root, &args).unwrap();
+        // Both Sales sheets match; the Notes sheets differ, so each keeps its own table
+        let mut planned: Vec<(String, &str)> = args.union_tables.iter()
+            .map(|((file, sheet), table)| (format!("{} {}", file.file_name().unwrap().to_string_lossy(), sheet), table.as_str()))
+            .collect();
+        planned.sort();
+        assert_eq!(planned, [("monthly_feb.xlsx Sales".to_string(), "Sales"), ("monthly_jan.xlsx Sales".to_string(), "Sales")]);
+
+        let db: Box<dyn Db> = Box::new(DuckBackend(Connection::open_in_memory().unwrap(), InsertMode::Appender));
+        let conn_mutex = Arc::new(Mutex::new(db));
+        let progress = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        for file in &files {
+            process_excel_file(file, &table_prefix(file, &root, &args), &conn_mutex, &progress, &args).unwrap();
+        }
+        let db = conn_mutex.lock().unwrap();
+        let conn = db.duckdb().unwrap();
+        let merged: Vec<(String, String)> = conn
+            .prepare("SELECT _source_file, region FROM Sales ORDER BY _source_file DESC, region").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(Result::unwrap).collect();
+        let expected = [("monthly_jan.xlsx", "North"), ("monthly_jan.xlsx", "South"), ("monthly_feb.xlsx", "North")];
+        assert_eq!(merged, expected.map(|(file, region)| (file.to_string(), region.to_string())));
+        let notes: i64 = conn
+            .query_row("SELECT count(*) FROM information_schema.tables WHERE table_name IN ('monthly_jan_Notes', 'monthly_feb_Notes')", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(notes, 2);
+    }
+
+    #[test]
+    fn only_io_errors_are_retried() {
+        let path = Path::new("flaky.xlsx");
+        let mut attempts = 0;
+        let opened = with_retries(path, 3, || {
+            attempts += 1;
+            match attempts {
+                1 | 2 => Err(anyhow::Error::from(std::io::Error::new(std::io::ErrorKind::TimedOut, "share went away")).context("Cannot open file")),
+                _ => Ok("workbook"),
+            }
+        });
+        assert_eq!((opened.unwrap(), attempts), ("workbook", 3));
+
+        // A file that isn't a workbook fails on the first attempt
+        let mut attempts = 0;
+        let garbage = std::env::temp_dir().join(format!("not_a_workbook_{}.xlsx", std::process::id()));
+        fs::write(&garbage, b"plain text, not a zip archive").unwrap();
+        let args = Config::parse_from(["excel_loader_rs"]);
+        let err = with_retries(&garbage, 3, || {
+            attempts += 1;
+            open_spreadsheet(&garbage, &args)
+        });
+        fs::remove_file(&garbage).unwrap();
+        assert!(err.is_err());
+        assert_eq!(attempts, 1);
+
+        // Neither a missing file nor denied access clears up by waiting
+        for kind in [std::io::ErrorKind::NotFound, std::io::ErrorKind::PermissionDenied] {
+            let mut attempts = 0;
+            let err = with_retries(path, 3, || -> Result<()> {
+                attempts += 1;
+                Err(anyhow::Error::from(std::io::Error::new(kind, "no")).context("Cannot open file"))
+            });
+            assert!(err.is_err());
+            assert_eq!(attempts, 1, "{:?}", kind);
+        }
+
+        // Retries run out, and the last error is what's reported
+        let mut attempts = 0;
+        let err = with_retries(path, 2, || -> Result<()> {
+            attempts += 1;
+            Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "still gone").into())
+        });
+        assert_eq!((format!("{:#}", err.unwrap_err()), attempts), ("still gone".to_string(), 3));
+    }
+
+    #[test]
+    fn bad_rows_abort_skip_or_go_to_the_rejects_file() {
+        // Two sampled rows make amount a DOUBLE; the text further down doesn't fit it
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/bad_rows.xlsx"));
+        let load = |flags: &[&str]| {
+            let args = Config::parse_from([&["excel_loader_rs", "--infer-rows", "2"], flags].concat());
+            let loaded = load_fixture_with(&path, "bad_rows", &args, InsertMode::Appender).map(|(_, loaded)| loaded);
+            (loaded, args)
+        };
+
+        let (loaded, _) = load(&[]);
+        assert!(loaded.is_err(), "abort is the default");
+        let (loaded, args) = load(&["--on-bad-row", "skip"]);
+        assert_eq!(loaded.unwrap()[0].rows, 3);
+        assert!(args.rejected_rows.lock().unwrap().is_empty());
+
+        let (loaded, args) = load(&["--on-bad-row", "reject-file"]);
+        assert_eq!(loaded.unwrap()[0].rows, 3);
+        let rejected = args.rejected_rows.into_inner().unwrap();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!((rejected[0].sheet.as_str(), rejected[0].row), ("Data", 4));
+        assert_eq!(rejected[0].error, "value 'oops, late' in column amount cannot be loaded as DOUBLE");
+
+        let csv_path = std::env::temp_dir().join(format!("rejects_{}.csv", std::process::id()));
+        write_rejects(csv_path.to_str().unwrap(), &rejected).unwrap();
+        let csv = fs::read_to_string(&csv_path).unwrap();
+        fs::remove_file(&csv_path).unwrap();
+        let expected = format!(
+            "file,sheet,row,error,values\n{},Data,4,\"value 'oops, late' in column amount cannot be loaded as DOUBLE\",\"[3.0,\"\"oops, late\"\"]\"\n",
+            path.display()
+        );
+        assert_eq!(csv, expected);
+    }
+
+    #[test]
+    fn small_chunk_sizes_load_every_row() {
+        // Three rows in chunks of two leaves a short final batch
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/legacy.xls"));
+        for mode in [InsertMode::Appender, InsertMode::Sql] {
+            let args = Config::parse_from(["excel_loader_rs", "--chunk-size", "2"]);
+            let (conn, loaded) = load_fixture_with(&path, "legacy", &args, mode).unwrap();
+            assert_eq!(loaded[0].rows, 3, "{:?}", mode);
+            let ids: Vec<i64> = conn
+                .prepare("SELECT id FROM legacy_Legacy ORDER BY id").unwrap()
+                .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+            assert_eq!(ids, [1, 2, 3], "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn legacy_xls_workbooks_load_through_the_xls_reader() {
+        // legacy.xls is a BIFF8 workbook in an OLE2 container, not an xlsx renamed
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/legacy.xls"));
+        assert_eq!(workbook_extension(&fs::read(&path).unwrap()).unwrap(), "xls");
+        let (conn, loaded) = load_fixture_with(&path, "legacy", &Config::default(), InsertMode::Appender).unwrap();
+        assert_eq!((loaded[0].table.as_str(), loaded[0].rows), ("legacy_Legacy", 3));
+        let (name, amount): (String, f64) = conn
+            .query_row("SELECT name, amount FROM legacy_Legacy WHERE id = 3", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((name.as_str(), amount), ("gamma", -3.25));
+    }
+
+    #[test]
+    fn ods_dates_load_typed_and_durations_as_interval_text() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/shifts.ods"));
+        let (conn, loaded) = load_fixture_with(&path, "shifts", &Config::default(), InsertMode::Appender).unwrap();
+        let types: Vec<&str> = loaded[0].columns.iter().map(|(_, dtype)| dtype.as_str()).collect();
+        assert_eq!(types, ["VARCHAR", "DATE", "TIMESTAMP", "VARCHAR"]);
+        let (day, started, minutes): (String, String, i64) = conn
+            .query_row(
+                "SELECT day::VARCHAR, started::VARCHAR, epoch(length::INTERVAL)::BIGINT // 60 FROM shifts_Shifts WHERE worker = 'Ben'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((day.as_str(), started.as_str(), minutes), ("2024-03-02", "2024-03-02 22:15:30.5", 36 * 60));
+    }
+
+    #[test]
+    fn incremental_loads_remember_files_and_prune_the_gone() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE kept_Data (a INTEGER); CREATE TABLE gone_Data (a INTEGER);").unwrap();
+        let kept = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/legacy.xls"));
+        let gone = PathBuf::from("reports/gone.xlsx");
+        let outcome = |path: &Path, table: &str| FileOutcome {
+            file: path.display().to_string(), tables: 1, rows: 0, duration: Duration::ZERO, error: None,
+            sheets: vec![LoadedSheet { sheet: "Data".to_string(), table: table.to_string(), rows: 0, columns: Vec::new(), source_size: (1, 1), expected_rows: 0, content_hash: None, mixed_columns: Vec::new() }],
+        };
+        let stamps = HashMap::from([(kept.clone(), file_stamp(&kept).unwrap()), (gone.clone(), FileStamp { modified: 1, size: 2 })]);
+        record_loaded_files(&conn, &[], &[outcome(&kept, "kept_Data"), outcome(&gone, "gone_Data")], &stamps, &[kept.clone(), gone.clone()], true, None).unwrap();
+
+        let previous = loaded_files(&conn).unwrap();
+        let entry = previous.iter().find(|loaded| loaded.file == kept.display().to_string()).unwrap();
+        assert_eq!((entry.stamp, entry.tables.as_slice()), (stamps[&kept], ["kept_Data".to_string()].as_slice()));
+
+        // The next run finds gone.xlsx deleted; only --prune drops its table
+        record_loaded_files(&conn, &previous, &[], &stamps, std::slice::from_ref(&kept), false, None).unwrap();
+        assert_eq!(loaded_files(&conn).unwrap().len(), 2);
+        record_loaded_files(&conn, &previous, &[], &stamps, std::slice::from_ref(&kept), true, None).unwrap();
+        let files: Vec<String> = loaded_files(&conn).unwrap().into_iter().map(|loaded| loaded.file).collect();
+        assert_eq!(files, [kept.display().to_string()]);
+        let tables: i64 = conn.query_row("SELECT count(*) FROM information_schema.tables WHERE table_name LIKE '%_Data'", [], |row| row.get(0)).unwrap();
+        assert_eq!(tables, 1);
+    }
+
+    #[test]
+    fn explained_statements_run_as_printed() {
+        let columns = vec![("id".to_string(), "BIGINT"), ("note".to_string(), "VARCHAR"), ("at".to_string(), "TIMESTAMP"), ("ok".to_string(), "BOOLEAN")];
+        let defs: Vec<String> = columns.iter().map(|(name, dtype)| column_def(name, dtype, None)).collect();
+        let mut values = Vec::new();
+        for i in 0..7 {
+            values.extend([Value::BigInt(i), Value::Text(format!("it's {}", i)), Value::Timestamp(TimeUnit::Microsecond, 1_700_000_000_500_000), Value::Null]);
+        }
+        let insert = explain_insert("Explained", &columns, &values);
+        assert!(insert.ends_with("-- 2 more row(s) in this chunk"), "{}", insert);
+        assert!(insert.contains("(0, 'it''s 0', TIMESTAMP '2023-11-14 22:13:20.500', NULL)"), "{}", insert);
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(&format!("{};\n{}", create_table_sql("Explained", &defs, true), insert)).unwrap();
+        let (rows, note): (i64, String) = conn
+            .query_row("SELECT count(*), max(note) FROM \"Explained\"", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((rows, note.as_str()), (5, "it's 4"));
+    }
+
+    #[test]
+    fn password_protected_workbooks_say_so() {
+        // An OLE2 container with EncryptionInfo and EncryptedPackage streams, as Excel saves an xlsx with an open
+        // password; it sits in a subfolder so loading test_data itself still succeeds
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/unreadable/encrypted.xlsx"));
+        let args = Config::parse_from(["excel_loader_rs"]);
+        let err = open_spreadsheet(&path, &args).err().expect("encrypted workbook opened");
+        assert_eq!(err.to_string(), PASSWORD_PROTECTED);
+        // Its EncryptionInfo is empty, so no password decrypts it
+        let args = Config::parse_from(["excel_loader_rs", "--password", "secret"]);
+        let err = open_spreadsheet(&path, &args).err().expect("encrypted workbook opened");
+        assert!(err.to_string().starts_with(WRONG_PASSWORD), "{}", err);
+
+        // Piped in, it is still told apart from an .xls
+        assert_eq!(workbook_extension(&fs::read(&path).unwrap()).unwrap(), "xlsx");
+        let legacy = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/legacy.xls")).unwrap();
+        assert_eq!(workbook_extension(&legacy).unwrap(), "xls");
+    }
+
+    #[test]
+    fn json_lines_files_load_into_a_table_named_after_them() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/events.jsonl"));
+        let (conn, loaded) = load_fixture_with(&path, "events", &Config::default(), InsertMode::Appender).unwrap();
+        assert_eq!((loaded[0].table.as_str(), loaded[0].rows), ("events", 3));
+        let types: Vec<&str> = loaded[0].columns.iter().map(|(_, dtype)| dtype.as_str()).collect();
+        assert_eq!(types, ["BIGINT", "VARCHAR", "TIMESTAMP", "JSON", "JSON"]);
+        let plans: String = conn
+            .query_row("SELECT string_agg(user->>'plan', ',' ORDER BY id) FROM events", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(plans, "free,pro,free");
+
+        // Blank lines are fine; anything but an object is reported by line
+        assert_eq!(check_json_lines("{\"a\": 1}\n\n{\"a\": 2}\n").unwrap(), 2);
+        let err = check_json_lines("{\"a\": 1}\n[1, 2]\n{\"a\" 3}\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: expected a JSON object, found an array; line 3, column 6: invalid JSON (expected `:`)");
+    }
+
+    #[test]
+    fn workbooks_on_the_1904_date_system_load_the_right_dates() {
+        // Saved with date1904 set, as older Mac Excel did; serial 0 is 1904-01-01, not 1899-12-31
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/mac_1904.xlsx"));
+        let dates: Vec<String> = load_fixture(&path, "mac", &[])
+            .prepare("SELECT at::VARCHAR FROM mac_Data ORDER BY at").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(dates, ["1904-01-01 00:00:00", "2027-03-16 12:00:00"]);
+
+        let cell = Data::DateTime(calamine::ExcelDateTime::new(45000.5, calamine::ExcelDateTimeType::DateTime, true));
+        assert_eq!(cell_text(&cell).as_deref(), Some("2027-03-16 12:00:00"));
+    }
+
+    #[test]
+    fn xlsb_workbooks_load_with_their_dates() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/ledger.xlsb"));
+        // calamine hands back the whole-day dates, which xlsb stores as RK integers, as plain numbers
+        let args = Config::parse_from(["excel_loader_rs"]);
+        let raw = open_spreadsheet(&path, &args).unwrap().worksheet_range("Ledger").unwrap();
+        assert_eq!(raw.get_value((1, 1)), Some(&Data::Int(45292)));
+
+        let conn = load_fixture(&path, "ledger", &[]);
+        let types: Vec<String> = conn
+            .prepare("SELECT data_type FROM information_schema.columns WHERE table_name = 'ledger_Ledger' ORDER BY ordinal_position").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(types, ["BIGINT", "DATE", "TIMESTAMP", "DOUBLE", "BOOLEAN", "VARCHAR"]);
+        let (booked, settled): (String, String) = conn
+            .query_row("SELECT booked::VARCHAR, settled_at::VARCHAR FROM ledger_Ledger WHERE id = 3", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((booked.as_str(), settled.as_str()), ("2024-02-29", "2024-03-01 18:00:00"));
+
+        assert_eq!(workbook_extension(&fs::read(&path).unwrap()).unwrap(), "xlsb");
+        assert!(date_format_kind("yyyy-mm-dd").is_some());
+        assert_eq!(date_format_kind("[h]:mm"), Some(calamine::ExcelDateTimeType::TimeDelta));
+        assert_eq!(date_format_kind("0.00 \"days\""), None);
+    }
+
+    #[test]
+    fn iso_durations_become_duckdb_interval_text() {
+        assert_eq!(iso_duration_interval("PT1H30M").as_deref(), Some("1 hours 30 minutes"));
+        assert_eq!(iso_duration_interval("PT01H30M00S").as_deref(), Some("1 hours 30 minutes"));
+        assert_eq!(iso_duration_interval("PT36H").as_deref(), Some("36 hours"));
+        assert_eq!(iso_duration_interval("P1Y2M3DT4H5M6.5S").as_deref(), Some("1 years 2 months 3 days 4 hours 5 minutes 6 seconds 500000 microseconds"));
+        assert_eq!(iso_duration_interval("P2W").as_deref(), Some("14 days"));
+        assert_eq!(iso_duration_interval("-PT1M45S").as_deref(), Some("-1 minutes -45 seconds"));
+        assert_eq!(iso_duration_interval("PT0S").as_deref(), Some("0 seconds"));
+        for bad in ["", "P", "PT", "1H30M", "PTH", "P1H", "PT1X", "PT1H30"] {
+            assert_eq!(iso_duration_interval(bad), None, "{}", bad);
+        }
+
+        // DuckDB reads the text back as the same interval
+        let conn = Connection::open_in_memory().unwrap();
+        let same: bool = conn
+            .query_row(
+                "SELECT ?::INTERVAL = INTERVAL 14 MONTH + INTERVAL 3 DAY + to_microseconds(14706500000)",
+                [iso_duration_interval("P1Y2M3DT4H5M6.5S")],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(same);
+    }
+
+    #[test]
+    fn ods_workbooks_load_like_xlsx() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/inventory.ods"));
+        let (conn, loaded) = load_fixture_with(&path, "inventory", &Config::default(), InsertMode::Appender).unwrap();
+        assert_eq!((loaded[0].table.as_str(), loaded[0].rows), ("inventory_Stock", 3));
+        let (name, qty): (String, i64) = conn
+            .query_row("SELECT name, qty FROM inventory_Stock WHERE sku = 'B-2'", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((name.as_str(), qty), ("Gadget", 3));
+    }
+
+    #[test]
+    fn skip_rows_then_header_row_picks_the_header() {
+        let mut range = Range::new((0, 0), (4, 0));
+        for (row, text) in ["Quarterly report", "", "id", "1", "2"].into_iter().enumerate() {
+            if !text.is_empty() {
+                range.set_value((row as u32, 0), Data::String(text.to_string()));
+            }
+        }
+        let args = Config::parse_from(["excel_loader_rs", "--skip-rows", "1", "--header-row", "1"]);
+        let picked = select_header_row(range.clone(), None, &args).unwrap();
+        assert_eq!(picked.rows().next().unwrap()[0], Data::String("id".to_string()));
+        assert_eq!(picked.height(), 3);
+        // A config section's header_row replaces --header-row and counts the same way, after --skip-rows
+        let args = Config::parse_from(["excel_loader_rs", "--skip-rows", "1", "--header-row", "2"]);
+        let picked = select_header_row(range.clone(), Some(1), &args).unwrap();
+        assert_eq!((&picked.rows().next().unwrap()[0], picked.height()), (&Data::String("id".to_string()), 3));
+        let args = Config::parse_from(["excel_loader_rs"]);
+        assert_eq!(select_header_row(range.clone(), Some(2), &args).unwrap().height(), 3);
+        let err = select_header_row(range, Some(5), &args).unwrap_err();
+        assert!(err.to_string().contains("header_row in --config 5"), "{}", err);
+    }
+
+    #[test]
+    fn table_names_follow_folder_single_table_and_staging() {
+        let prefix = table_prefix(Path::new("/in/2024/north/sales.xlsx"), Path::new("/in"), &Config::default());
+        assert_eq!(prefix, "_2024_north_sales");
+        assert_eq!(table_prefix(Path::new("/in/sales.xlsx"), Path::new("/in"), &Config::default()), "sales");
+        let args = Config::parse_from(["excel_loader_rs"]);
+        let sheets = ["Q1 Data".to_string()];
+        assert_eq!(sheet_tables(&prefix, "Q1 Data", &sheets, &args), ("_2024_north_sales_Q1_Data".to_string(), "_2024_north_sales_Q1_Data".to_string()));
+        let args = Config::parse_from(["excel_loader_rs", "--single-table", "all sales", "--staging"]);
+        assert_eq!(sheet_tables("sales", "Q1", &sheets, &args).1, "_staging.all_sales");
+    }
+
+    #[test]
+    fn name_templates_and_prefixes_shape_table_names() {
+        let one = ["Sheet1".to_string()];
+        let two = ["Summary".to_string(), "2024 Detail".to_string()];
+        let name = |flags: &[&str], sheet: &str, sheets: &[String]| {
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            sheet_tables("sales", sheet, sheets, &args).0
+        };
+        assert_eq!(name(&["--name-template", "raw_{file}_{sheet}"], "Summary", &two), "raw_sales_Summary");
+        assert_eq!(name(&["--name-template", "{sheet}"], "2024 Detail", &two), "_2024_Detail");
+        assert_eq!(name(&["--name-template", "{file}-{index}"], "2024 Detail", &two), "sales_2");
+        assert_eq!(name(&["--tables-prefix", "raw"], "2024 Detail", &two), "raw_sales__2024_Detail");
+        // Only workbooks with a single sheet lose the sheet part, template or not
+        assert_eq!(name(&["--single-sheet-file-name"], "Sheet1", &one), "sales");
+        assert_eq!(name(&["--single-sheet-file-name"], "Summary", &two), "sales_Summary");
+        assert_eq!(name(&["--single-sheet-file-name", "--name-template", "raw_{file}_{sheet}"], "Sheet1", &one), "raw_sales");
+        assert_eq!(name(&["--single-sheet-file-name", "--name-template", "{sheet}"], "Sheet1", &one), "sales");
+        assert_eq!(name(&["--identifier-case", "lower"], "2024 Detail", &two), "sales__2024_detail");
+        assert_eq!(name(&["--identifier-case", "upper", "--tables-prefix", "raw"], "Summary", &two), "RAW_SALES_SUMMARY");
+        assert!(parse_name_template("{file}_{tab}").unwrap_err().contains("{tab}"));
+        assert!(parse_name_template("fixed").is_err());
+    }
+
+    #[test]
+    fn colliding_tables_error_or_get_a_counter() {
+        let args = Config::parse_from(["excel_loader_rs"]);
+        assert_eq!(claim_table(&args, "sales_Data", "a/sales.xlsx (Data)").unwrap(), "sales_Data");
+        let err = claim_table(&args, "SALES_data", "b/sales.xlsx (data)").unwrap_err().to_string();
+        assert!(err.contains("a/sales.xlsx (Data)") && err.contains("b/sales.xlsx (data)"), "{}", err);
+
+        let args = Config::parse_from(["excel_loader_rs", "--on-collision", "rename"]);
+        let names: Vec<String> = ["a", "b", "c"].iter().map(|source| claim_table(&args, "sales_Data", source).unwrap()).collect();
+        assert_eq!(names, ["sales_Data", "sales_Data_2", "sales_Data_3"]);
+    }
+
+    #[test]
+    fn sheet_filters_compose_case_insensitively() {
+        let names: Vec<String> = ["Sheet1", "Data", "Notes", "Data_2023"].iter().map(|s| s.to_string()).collect();
+        let args = Config::parse_from(["excel_loader_rs", "--sheets", "sheet1,DATA,Missing"]);
+        assert_eq!(select_sheets(&names, Path::new("a.xlsx"), &args), ["Sheet1", "Data"]);
+        let args = Config::parse_from(["excel_loader_rs", "--exclude-sheets", "notes", "--sheet-regex", "^Data"]);
+        assert_eq!(select_sheets(&names, Path::new("a.xlsx"), &args), ["Data", "Data_2023"]);
+    }
+
+    #[test]
+    fn null_values_blank_matching_data_cells() {
+        let mut range = Range::new((0, 0), (4, 0));
+        for (row, text) in ["N/A", "N/A", " - ", "-", "5"].into_iter().enumerate() {
+            range.set_value((row as u32, 0), Data::String(text.to_string()));
+        }
+        let cells = |args: &[&str]| -> Vec<Data> {
+            let args = Config::parse_from([&["excel_loader_rs"], args].concat());
+            prepare_range(range.clone(), "Sheet1", None, &args).unwrap().0.rows().map(|row| row[0].clone()).collect()
+        };
+        let text = |t: &str| Data::String(t.to_string());
+        // The header is never blanked, and matches are exact unless trimmed
+        assert_eq!(cells(&["--null-values", "N/A,-"]), [text("N/A"), Data::Empty, text(" - "), Data::Empty, text("5")]);
+        assert_eq!(cells(&["--null-values", "N/A,-", "--trim-null-values"]), [text("N/A"), Data::Empty, Data::Empty, Data::Empty, text("5")]);
+    }
+
+    #[test]
+    fn boolean_columns_survive_blanks_and_text_spellings() {
+        let rows: [[Data; 3]; 4] = [
+            ["done".into(), "approved".into(), "note".into()],
+            [Data::Empty, "Yes".into(), "yes".into()],
+            [Data::Bool(true), " no ".into(), "call back".into()],
+            [Data::Bool(false), Data::Empty, "No".into()],
+        ];
+        let mut range = Range::new((0, 0), (3, 2));
+        for (row, cells) in rows.iter().enumerate() {
+            for (col, cell) in cells.iter().enumerate() {
+                range.set_value((row as u32, col as u32), cell.clone());
+            }
+        }
+        let types = |args: &[&str]| -> Vec<String> {
+            let args = Config::parse_from([&["excel_loader_rs"], args].concat());
+            infer_schema(range.clone(), "flags", &args).unwrap().into_iter().map(|(_, dtype)| dtype).collect()
+        };
+        // A leading blank doesn't stop a column of booleans typing as BOOLEAN
+        assert_eq!(types(&[]), ["BOOLEAN", "VARCHAR", "VARCHAR"]);
+        // Mapped spellings convert, but not in a column that also holds other text
+        assert_eq!(types(&["--bool-strings", "Yes=true,No=false"]), ["BOOLEAN", "BOOLEAN", "VARCHAR"]);
+
+        let args = Config::parse_from(["excel_loader_rs", "--bool-strings", "Yes=true,No=false"]);
+        let (prepared, _) = prepare_range(range.clone(), "Sheet1", None, &args).unwrap();
+        let approved: Vec<Data> = prepared.rows().map(|row| row[1].clone()).collect();
+        assert_eq!(approved, ["approved".into(), Data::Bool(true), Data::Bool(false), Data::Empty]);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--bool-strings", "Yes=maybe"]).is_err());
+    }
+
+    #[test]
+    fn date_text_columns_type_as_dates_with_parse_dates() {
+        let rows: [[Data; 4]; 5] = [
+            ["shipped".into(), "billed".into(), "logged".into(), "note".into()],
+            ["2024-03-01".into(), "03/04/2024".into(), "2024-03-01 08:30".into(), "2024-03-01".into()],
+            ["2024-03-02".into(), "25/12/2024".into(), "2024-03-01T09:15:30".into(), "call back".into()],
+            [Data::Empty, "01/02/2024".into(), "2024-03-02".into(), "later".into()],
+            ["2024-03-04".into(), "01/01/2024".into(), "2024-03-03 17:00:00".into(), Data::Empty],
+        ];
+        let mut range = Range::new((0, 0), (4, 3));
+        for (row, cells) in rows.iter().enumerate() {
+            for (col, cell) in cells.iter().enumerate() {
+                range.set_value((row as u32, col as u32), cell.clone());
+            }
+        }
+        let types = |args: &[&str]| -> Vec<String> {
+            let args = Config::parse_from([&["excel_loader_rs"], args].concat());
+            infer_schema(range.clone(), "dates", &args).unwrap().into_iter().map(|(_, dtype)| dtype).collect()
+        };
+        assert_eq!(types(&[]), ["VARCHAR", "VARCHAR", "VARCHAR", "VARCHAR"]);
+        // 25/12 isn't a month-first date, so the default order leaves billed at 3 of 4 dates
+        assert_eq!(types(&["--parse-dates"]), ["DATE", "VARCHAR", "TIMESTAMP", "VARCHAR"]);
+        assert_eq!(types(&["--parse-dates", "--date-threshold", "0.75"]), ["DATE", "DATE", "TIMESTAMP", "VARCHAR"]);
+        assert_eq!(types(&["--parse-dates", "--date-order", "dmy"]), ["DATE", "DATE", "TIMESTAMP", "VARCHAR"]);
+
+        let prepare = |args: &[&str]| prepare_range(range.clone(), "Sheet1", None, &Config::parse_from([&["excel_loader_rs"], args].concat())).map(|(r, _)| r);
+        let billed: Vec<Data> = prepare(&["--parse-dates", "--date-threshold", "0.75"]).unwrap().rows().map(|row| row[1].clone()).collect();
+        assert_eq!(billed, ["billed".into(), Data::DateTimeIso("2024-03-04".into()), Data::Empty, Data::DateTimeIso("2024-01-02".into()), Data::DateTimeIso("2024-01-01".into())]);
+        let err = prepare(&["--parse-dates", "--date-threshold", "0.75", "--on-bad-date", "error"]).unwrap_err();
+        assert!(err.to_string().contains("Column 'billed' holds dates, but row 3 has '25/12/2024'"), "{}", err);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--date-order", "dmy"]).is_err());
+    }
+
+    #[test]
+    fn timezone_reads_local_times_deterministically_across_dst() {
+        let micros = |text: &str| chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap().and_utc().timestamp_micros();
+        let rule = parse_posix_rule("EST5EDT,M3.2.0,M11.1.0").unwrap();
+        assert_eq!(rule.std, -5 * 3600);
+        let new_york = TimeZone { name: "America/New_York".into(), transitions: Vec::new(), initial: rule.std, rule: Some(rule) };
+        let utc = |local: &str| new_york.to_utc_micros(micros(local));
+        assert_eq!(utc("2024-01-15 12:00"), micros("2024-01-15 17:00"));
+        assert_eq!(utc("2024-07-01 12:00"), micros("2024-07-01 16:00"));
+        // Skipped when the clocks go forward: read as 03:30 daylight time
+        assert_eq!(utc("2024-03-10 02:30"), micros("2024-03-10 07:30"));
+        // Repeated when they go back: the first, daylight-time 01:30
+        assert_eq!(utc("2024-11-03 01:30"), micros("2024-11-03 05:30"));
+
+        // Daylight saving time spans the new year south of the equator
+        let sydney = parse_posix_rule("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(sydney.offset_at(micros("2025-01-01 00:00") / 1_000_000), 11 * 3600);
+        assert_eq!(sydney.offset_at(micros("2025-07-01 00:00") / 1_000_000), 10 * 3600);
+
+        let offset = |text: &str| parse_timezone(text).map(|zone| zone.initial);
+        assert_eq!(offset("utc"), Ok(0));
+        assert_eq!(offset("+05:30"), Ok(5 * 3600 + 1800));
+        assert_eq!(offset("UTC-8"), Ok(-8 * 3600));
+        assert!(parse_timezone("../etc/passwd").is_err());
+
+        assert_eq!(zoned_cell_value(&excel_date(45361.104166666664), &new_york), Value::Timestamp(TimeUnit::Microsecond, micros("2024-03-10 07:30")));
+    }
+
+    #[test]
+    fn error_cells_load_as_null_text_or_fail() {
+        let mut range = Range::new((0, 0), (3, 1));
+        range.set_value((0, 0), Data::String("ratio".into()));
+        range.set_value((0, 1), Data::String("lookup".into()));
+        range.set_value((1, 0), Data::Float(0.5));
+        range.set_value((2, 0), Data::Error(calamine::CellErrorType::Div0));
+        range.set_value((3, 0), Data::Float(1.5));
+        range.set_value((1, 1), Data::String("a".into()));
+        range.set_value((3, 1), Data::Error(calamine::CellErrorType::NA));
+        let prepare = |mode: &str| prepare_range(range.clone(), "Sheet1", None, &Config::parse_from(["excel_loader_rs", "--error-handling", mode])).map(|(r, _)| r);
+        let types = |mode: &str| -> Vec<String> {
+            let args = Config::parse_from(["excel_loader_rs", "--error-handling", mode]);
+            infer_schema(range.clone(), "errors", &args).unwrap().into_iter().map(|(_, dtype)| dtype).collect()
+        };
+
+        // The default loads errors as NULL and leaves the column numeric
+        assert_eq!(types("null"), ["DOUBLE", "VARCHAR"]);
+        assert_eq!(cell_value(&Data::Error(calamine::CellErrorType::Div0), "DOUBLE", None), Value::Null);
+        assert_eq!(prepare("null").unwrap().get((2, 0)), Some(&Data::Error(calamine::CellErrorType::Div0)));
+
+        assert_eq!(types("text"), ["VARCHAR", "VARCHAR"]);
+        let text = prepare("text").unwrap();
+        assert_eq!(text.get((2, 0)), Some(&Data::String("#DIV/0!".into())));
+        assert_eq!(text.get((3, 1)), Some(&Data::String("#N/A".into())));
+
+        let err = prepare("fail").unwrap_err();
+        assert_eq!(err.to_string(), "Column 'ratio', row 3 holds the error #DIV/0! (--error-handling fail)");
+    }
+
+    #[test]
+    fn no_header_keeps_the_first_row_as_data() {
+        // A headerless block starting at B3, below a title row
+        let mut range = Range::new((0, 1), (3, 2));
+        range.set_value((0, 1), Data::String("Readings".into()));
+        for row in 2..=3 {
+            range.set_value((row, 1), Data::Float(row as f64));
+            range.set_value((row, 2), Data::Float(row as f64 * 1.5));
+        }
+        let args = Config::parse_from(["excel_loader_rs", "--no-header", "--skip-rows", "2"]);
+        let (range, _) = prepare_range(range, "Sheet1", None, &args).unwrap();
+        let rows: Vec<&[Data]> = range.rows().collect();
+        assert_eq!(rows[0], [Data::String("column_1".into()), Data::String("column_2".into())]);
+        assert_eq!(rows[1..], [&[Data::Float(2.0), Data::Float(3.0)][..], &[Data::Float(3.0), Data::Float(4.5)][..]]);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--no-header", "--header-row", "1"]).is_err());
+
+        assert!(looks_like_data(&[Data::Float(1.0), Data::Empty, Data::Int(3)]));
+        assert!(!looks_like_data(&[Data::Float(2024.0), Data::String("total".into())]));
+        assert!(!looks_like_data(&[Data::Empty]));
+    }
+
+    #[test]
+    fn trim_and_normalize_whitespace_clean_text_cells() {
+        let mut range = Range::new((0, 0), (5, 0));
+        for (row, text) in [" name ", "  Acme Corp\t", "\u{a0}Acme\u{a0}Corp\u{a0}", "Acme \t  Corp", " \u{a0}\t", "Acme"].into_iter().enumerate() {
+            range.set_value((row as u32, 0), Data::String(text.to_string()));
+        }
+        let cells = |args: &[&str]| -> Vec<Data> {
+            let args = Config::parse_from([&["excel_loader_rs"], args].concat());
+            prepare_range(range.clone(), "Sheet1", None, &args).unwrap().0.rows().map(|row| row[0].clone()).collect()
+        };
+        let text = |t: &str| Data::String(t.to_string());
+        // The header is left alone, and whitespace-only cells become NULL
+        assert_eq!(cells(&["--trim"]), [text(" name "), text("Acme Corp"), text("Acme\u{a0}Corp"), text("Acme \t  Corp"), Data::Empty, text("Acme")]);
+        assert_eq!(cells(&["--normalize-whitespace"]), [text(" name "), text("Acme Corp"), text("Acme Corp"), text("Acme Corp"), Data::Empty, text("Acme")]);
+        assert_eq!(cells(&["--trim", "--null-values", "-"])[4], Data::Empty);
+    }
+
+    #[test]
+    fn byte_order_marks_are_stripped_and_mojibake_repaired_on_request() {
+        let mut range = Range::new((0, 0), (3, 1));
+        for (row, cells) in [["\u{feff}Name", "City"], ["\u{feff}Acme", "CafÃ© Zürich"], ["donâ€™t", "\u{feff}"], ["plain", "Café"]].iter().enumerate() {
+            for (col, text) in cells.iter().enumerate() {
+                range.set_value((row as u32, col as u32), Data::String(text.to_string()));
+            }
+        }
+        let rows = |flags: &[&str]| -> Vec<Vec<Data>> {
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            prepare_range(range.clone(), "Sheet1", None, &args).unwrap().0.rows().map(<[Data]>::to_vec).collect()
+        };
+        let text = |t: &str| Data::String(t.to_string());
+        let plain = rows(&[]);
+        assert_eq!(plain[0], [text("Name"), text("City")]);
+        assert_eq!(unique_column_names(&plain[0], HeaderStyle::AsIs, IdentifierCase::Preserve, false, None), ["Name", "City"]);
+        assert_eq!(plain[1], [text("Acme"), text("CafÃ© Zürich")]);
+        assert_eq!(plain[2], [text("donâ€™t"), Data::Empty]);
+        let fixed = rows(&["--fix-encoding"]);
+        // Text that isn't double-encoded UTF-8 (a lone `ü` or `é`) is left as it is
+        assert_eq!(fixed[1], [text("Acme"), text("CafÃ© Zürich")]);
+        assert_eq!(fixed[2][0], text("don’t"));
+        assert_eq!(fixed[3], [text("plain"), text("Café")]);
+        assert_eq!(fix_mojibake("CafÃ©").as_deref(), Some("Café"));
+        assert_eq!(fix_mojibake("â‚¬5").as_deref(), Some("€5"));
+    }
+
+    fn header_row(cells: &[&str]) -> Vec<Data> {
+        cells.iter().map(|text| if text.is_empty() { Data::Empty } else { Data::String(text.to_string()) }).collect()
+    }
+
+    #[test]
+    fn colliding_headers_get_numbered_suffixes() {
+        let names = unique_column_names(&header_row(&["Total $", "Total %", "total", "Total_2", "Id"]), HeaderStyle::AsIs, IdentifierCase::Preserve, false, None);
+        assert_eq!(names, ["Total", "Total_2", "total_3", "Total_2_2", "Id"]);
+    }
+
+    #[test]
+    fn case_folded_headers_are_numbered_as_duplicates() {
+        let headers = header_row(&["Total", "total", "Region", ""]);
+        assert_eq!(unique_column_names(&headers, HeaderStyle::AsIs, IdentifierCase::Lower, false, None), ["total", "total_2", "region", "column_4"]);
+        assert_eq!(unique_column_names(&headers, HeaderStyle::AsIs, IdentifierCase::Upper, false, None), ["TOTAL", "TOTAL_2", "REGION", "COLUMN_4"]);
+    }
+
+    #[test]
+    fn empty_headers_are_named_by_position() {
+        let names = unique_column_names(&header_row(&["id", "", "name", "  ", "column_2"]), HeaderStyle::AsIs, IdentifierCase::Preserve, false, None);
+        assert_eq!(names, ["id", "column_2", "name", "column_4", "column_2_2"]);
+    }
+
+    #[test]
+    fn leading_digits_get_an_underscore() {
+        assert_eq!(sanitize_identifier("2024"), "_2024");
+        assert_eq!(sanitize_identifier(" 1st place"), "_1st_place");
+        assert_eq!(sanitize_identifier("Q1 2024"), "Q1_2024");
+    }
+
+    #[test]
+    fn symbol_and_non_ascii_names_still_make_identifiers() {
+        assert_eq!(sanitize_identifier("###"), "");
+        assert_eq!(sanitize_identifier("Café Größe"), "Café_Größe");
+        assert_eq!(name_identifier("Café Größe", true), "Cafe_Grosse");
+        assert_eq!(name_identifier("売上 2024", true), "_2024");
+        assert_eq!(name_identifier("売上", true), "");
+
+        let args = Config::parse_from(["excel_loader_rs", "--ascii-only"]);
+        let prefix = table_prefix(Path::new("/in/###.xlsx"), Path::new("/in"), &args);
+        assert_eq!(prefix, "file");
+        let sheets = ["Données".to_string(), "€€€".to_string()];
+        assert_eq!(sheet_tables(&prefix, "Données", &sheets, &args).0, "file_Donnees");
+        assert_eq!(sheet_tables(&prefix, "€€€", &sheets, &args).0, "file_sheet_2");
+        let headers = header_row(&["Prix €", "売上", "Año"]);
+        assert_eq!(unique_column_names(&headers, HeaderStyle::AsIs, IdentifierCase::Preserve, true, None), ["Prix", "column_2", "Ano"]);
+        assert_eq!(unique_column_names(&headers, HeaderStyle::AsIs, IdentifierCase::Preserve, false, None), ["Prix", "売上", "Año"]);
+    }
+
+    #[test]
+    fn reserved_words_work_as_table_and_column_names() {
+        let db = DuckBackend(Connection::open_in_memory().unwrap(), InsertMode::Sql);
+        let columns = [("Order".to_string(), "BIGINT"), ("select".to_string(), "VARCHAR"), (sanitize_identifier("2024"), "DOUBLE")];
+        db.create_table("Group", &columns, Some("nocase")).unwrap();
+        db.insert_chunk("Group", &columns, vec![Value::BigInt(1), Value::Text("x".to_string()), Value::Double(2.5)]).unwrap();
+        let stored: (i64, String, f64) = db.0.query_row("SELECT \"Order\", \"select\", _2024 FROM \"Group\"", [], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        }).unwrap();
+        assert_eq!(stored, (1, "x".to_string(), 2.5));
+        db.drop_table("Group").unwrap();
+    }
+
+    fn stored_rows(db: &DuckBackend, table: &str) -> Vec<Vec<String>> {
+        let mut stmt = db.0.prepare(&format!("SELECT * FROM {}", table)).unwrap();
+        let mut rows = stmt.query([]).unwrap();
+        let width = rows.as_ref().map_or(0, |stmt| stmt.column_count());
+        let mut stored = Vec::new();
+        while let Some(row) = rows.next().unwrap() {
+            stored.push((0..width).map(|i| format_value(row.get_ref(i).unwrap())).collect());
+        }
+        stored
+    }
+
+    #[test]
+    fn appender_and_sql_inserts_store_the_same_rows() {
+        let columns = [("n", "BIGINT"), ("x", "DOUBLE"), ("s", "VARCHAR"), ("d", "DATE"), ("at", "TIMESTAMP"), ("ok", "BOOLEAN")];
+        let typed: Vec<(String, &str)> = columns.iter().map(|(name, dtype)| (name.to_string(), *dtype)).collect();
+        let rows = [
+            [Data::Float(1.0), Data::Float(2.5), Data::String("a".to_string()), excel_date(45351.0), excel_date(45351.5), Data::Bool(true)],
+            [Data::Empty, Data::Int(3), Data::Float(7.0), Data::Empty, Data::Empty, Data::String("no".to_string())],
+        ];
+        let stored: Vec<_> = [InsertMode::Appender, InsertMode::Sql].into_iter()
+            .map(|mode| {
+                let db = DuckBackend(Connection::open_in_memory().unwrap(), mode);
+                db.create_table("t", &typed, None).unwrap();
+                let values = rows.iter().flat_map(|row| row.iter().zip(&columns).map(|(cell, (_, dtype))| cell_value(cell, dtype, None))).collect();
+                db.insert_chunk("t", &typed, values).unwrap();
+                stored_rows(&db, "t")
+            })
+            .collect();
+        assert_eq!(stored[0].len(), 2);
+        assert_eq!(stored[0], stored[1]);
+    }
+
+    #[test]
+    fn non_finite_floats_load_as_null_and_large_ones_round_trip() {
+        for mode in [InsertMode::Appender, InsertMode::Sql] {
+            let db = DuckBackend(Connection::open_in_memory().unwrap(), mode);
+            let columns = [("x".to_string(), "DOUBLE")];
+            db.create_table("t", &columns, None).unwrap();
+            let cells = [Data::Float(0.1 + 0.2), Data::Float(f64::NAN), Data::Float(1e308), Data::Float(f64::NEG_INFINITY)];
+            db.insert_chunk("t", &columns, cells.iter().map(|cell| cell_value(cell, "DOUBLE", None)).collect()).unwrap();
+            let mut stmt = db.0.prepare("SELECT x FROM t").unwrap();
+            let stored: Vec<Option<f64>> = stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+            assert_eq!(stored, [Some(0.1 + 0.2), None, Some(1e308), None]);
+        }
+    }
+
+    #[test]
+    fn column_mapping_is_replaced_on_reload() {
+        let db = DuckBackend(Connection::open_in_memory().unwrap(), InsertMode::Appender);
+        let headers = header_row(&["Q1 Revenue ($)", "", "id"]);
+        let names = unique_column_names(&headers, HeaderStyle::AsIs, IdentifierCase::Preserve, false, None);
+        store_column_mapping(&db, "sales_Sheet1", &headers, &names, true).unwrap();
+        store_column_mapping(&db, "sales_Sheet1", &headers[..1], &names[..1], true).unwrap();
+        assert_eq!(stored_rows(&db, COLUMN_MAPPING_TABLE), [["sales_Sheet1", "1", "Q1 Revenue ($)", "Q1_Revenue"]]);
+        // Sheets merged into a table keep the columns other sheets recorded
+        store_column_mapping(&db, "sales_Sheet1", &headers[2..], &names[2..], false).unwrap();
+        assert_eq!(stored_rows(&db, COLUMN_MAPPING_TABLE).len(), 2);
+    }
+
+    #[test]
+    fn append_keeps_compatible_tables_and_names_the_mismatched_column() {
+        let conn = Connection::open_in_memory().unwrap();
+        let prepare = |columns: &[(&str, &str)]| {
+            let columns: Vec<(String, &str)> = columns.iter().map(|(name, dtype)| (name.to_string(), *dtype)).collect();
+            let ddl: Vec<String> = columns.iter().map(|(name, dtype)| column_def(name, dtype, None)).collect();
+            prepare_append_table(&conn, "sales", &columns, &ddl, "sales_Jan (Jan)")
+        };
+        prepare(&[("id", "BIGINT"), ("amount", "DOUBLE")]).unwrap();
+        conn.execute("INSERT INTO sales VALUES (1, 2.5)", []).unwrap();
+        // Narrower ints fit, new columns are added and the existing rows stay
+        prepare(&[("id", "INTEGER"), ("region", "VARCHAR")]).unwrap();
+        let (rows, width): (i64, i64) = conn.query_row(
+            "SELECT (SELECT count(*) FROM sales), (SELECT count(*) FROM information_schema.columns WHERE table_name = 'sales')",
+            [], |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!((rows, width), (1, 3));
+        let err = prepare(&[("amount", "VARCHAR")]).unwrap_err().to_string();
+        assert!(err.contains("column amount is DOUBLE in the table but VARCHAR in the sheet"), "{}", err);
+    }
+
+    #[test]
+    fn text_in_a_number_column_falls_back_to_insert() {
+        let db = DuckBackend(Connection::open_in_memory().unwrap(), InsertMode::Appender);
+        let columns = [("x".to_string(), "DOUBLE")];
+        db.create_table("t", &columns, None).unwrap();
+        db.insert_chunk("t", &columns, vec![Value::Double(1.0), Value::Text("2.5".to_string())]).unwrap();
+        // A chunk INSERT can't cast either fails as a whole
+        assert!(db.insert_chunk("t", &columns, vec![Value::Double(3.0), Value::Text("not a number".to_string())]).is_err());
+        assert_eq!(stored_rows(&db, "t"), [["1".to_string()], ["2.5".to_string()]]);
+    }
+
+    #[test]
+    fn export_format_follows_the_extension() {
+        assert_eq!(ExportFormat::from_path("out.csv").unwrap(), ExportFormat::Csv);
+        assert_eq!(ExportFormat::from_path("out.TSV").unwrap(), ExportFormat::Tsv);
+        assert_eq!(ExportFormat::from_path("dir/out.parquet").unwrap(), ExportFormat::Parquet);
+        assert_eq!(ExportFormat::from_path("out.json").unwrap(), ExportFormat::Json);
+        assert!(ExportFormat::from_path("out.xlsx").is_err());
+        assert!(ExportFormat::from_path("out").is_err());
+    }
+
+    #[test]
+    fn csv_exports_take_the_delimiter_header_and_quote_options() {
+        let dir = std::env::temp_dir().join(format!("csv_options_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE t AS SELECT * FROM (VALUES (1, 'a;b'), (2, 'c')) v(id, label)").unwrap();
+        let export = |file: &str, flags: &[&str]| -> String {
+            let path = dir.join(file);
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            args.validate().unwrap();
+            run_query(&conn, &format!("SELECT * FROM t ORDER BY id >> {}", path.display()), &args).unwrap();
+            fs::read_to_string(path).unwrap()
+        };
+        assert_eq!(export("plain.csv", &[]), "id,label\n1,a;b\n2,c\n");
+        assert_eq!(export("plain.tsv", &[]), "id\tlabel\n1\ta;b\n2\tc\n");
+        assert_eq!(export("semi.csv", &["--csv-delimiter", ";", "--csv-no-header", "--csv-quote", "'"]), "1;'a;b'\n2;c\n");
+        assert_eq!(export("tab.csv", &["--csv-delimiter", "\\t"]), "id\tlabel\n1\ta;b\n2\tc\n");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(Config::try_parse_from(["excel_loader_rs", "--csv-delimiter", ";;"]).is_err());
+        let clash = Config::parse_from(["excel_loader_rs", "--csv-delimiter", "\""]);
+        assert!(clash.validate().is_err());
+    }
+
+    #[test]
+    fn query_scripts_run_statement_by_statement() {
+        let dir = std::env::temp_dir().join(format!("query_script_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let export = dir.join("totals.csv");
+        let script = dir.join("report.sql");
+        fs::write(&script, format!(
+            "CREATE TABLE t AS SELECT * FROM range(3) r(n);\n-- add one more\nINSERT INTO t VALUES (10);\n\
+             SELECT sum(n) AS total FROM t;\nSELECT count(*) AS n FROM t >> {};\n\nSELECT missing FROM t;\n",
+            export.display()
+        )).unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        let args = Config::parse_from(["excel_loader_rs", "--query", "SELECT 1", "--query", "SELECT 2"]);
+        assert_eq!(args.query, ["SELECT 1", "SELECT 2"]);
+        let err = run_query_script(&conn, &script.display().to_string(), &args).unwrap_err();
+        // Everything before the failing statement ran, and the error names its line
+        assert!(format!("{:#}", err).contains("report.sql:7: statement failed: SELECT missing FROM t"), "{:#}", err);
+        assert_eq!(fs::read_to_string(&export).unwrap(), "n\n4\n");
+        assert!(returns_rows("with x as (select 1) select * from x") && !returns_rows("CREATE VIEW v AS SELECT 1"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn indexes_are_checked_then_built_after_load() {
+        let parsed = parse_indexes("orders.id, orders.(Region, placed at)").unwrap().0;
+        assert_eq!(parsed, [
+            ("orders".to_string(), vec!["id".to_string()]),
+            ("orders".to_string(), vec!["Region".to_string(), "placed at".to_string()]),
+        ]);
+        assert!(parse_indexes("orders").is_err());
+        assert!(parse_indexes("orders.(id,)").is_err());
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE Orders (id BIGINT, region VARCHAR, placed_at TIMESTAMP)").unwrap();
+        let err = create_indexes(&conn, &[parse_indexes("orders.id,orders.total").unwrap()]).unwrap_err();
+        assert_eq!(err.to_string(), "Cannot index Orders: it has no column total (columns: id, region, placed_at)");
+        assert!(create_indexes(&conn, &[parse_indexes("invoices.id").unwrap()]).is_err());
+        // Nothing was built before the bad column was found
+        let count = |conn: &Connection| -> i64 { conn.query_row("SELECT count(*) FROM duckdb_indexes()", [], |row| row.get(0)).unwrap() };
+        assert_eq!(count(&conn), 0);
+
+        let indexes = [parse_indexes("orders.id,orders.(region,placed at)").unwrap()];
+        create_indexes(&conn, &indexes).unwrap();
+        // Building them again (an --append run) leaves the existing ones alone
+        create_indexes(&conn, &indexes).unwrap();
+        let names: Vec<String> = conn.prepare("SELECT index_name FROM duckdb_indexes() ORDER BY index_name").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(names, ["idx_Orders_id", "idx_Orders_region_placed_at"]);
+    }
+
+    #[test]
+    fn sqlite_backend_maps_types_to_affinities() {
+        let db = SqliteBackend(rusqlite::Connection::open_in_memory().unwrap());
+        let columns = [("n".to_string(), "BIGINT"), ("x".to_string(), "DOUBLE"), ("ok".to_string(), "BOOLEAN"), ("at".to_string(), "TIMESTAMP")];
+        db.create_table("t", &columns, None).unwrap();
+        let values = vec![
+            cell_value(&Data::Float(7.0), "BIGINT", None),
+            cell_value(&Data::Float(1.5), "DOUBLE", None),
+            cell_value(&Data::Bool(true), "BOOLEAN", None),
+            timestamp("2024-02-29 13:45:30"),
+        ];
+        db.insert_chunk("t", &columns, values).unwrap();
+        let stored = db.0.query_row("SELECT typeof(n), typeof(x), ok, at FROM t", [], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, String>(3)?))
+        }).unwrap();
+        assert_eq!(stored, ("integer".to_string(), "real".to_string(), 1, "2024-02-29 13:45:30".to_string()));
+    }
+
+    fn excel_date(serial: f64) -> Data {
+        Data::DateTime(calamine::ExcelDateTime::new(serial, calamine::ExcelDateTimeType::DateTime, false))
+    }
+
+    fn timestamp(text: &str) -> Value {
+        let dt = chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap();
+        Value::Timestamp(TimeUnit::Microsecond, dt.and_utc().timestamp_micros())
+    }
+
+    #[test]
+    fn datetime_serials_bind_as_timestamps() {
+        assert_eq!(cell_value(&excel_date(44197.0), "TIMESTAMP", None), timestamp("2021-01-01 00:00:00"));
+        assert_eq!(cell_value(&excel_date(44197.75), "TIMESTAMP", None), timestamp("2021-01-01 18:00:00"));
+        assert_eq!(cell_value(&excel_date(44197.0), "DATE", None), Value::Text("2021-01-01".to_string()));
+    }
+
+    #[test]
+    fn serials_around_the_1900_leap_year_bug() {
+        // Excel counts a 1900-02-29 that never existed: serial 61 is March 1st, serials before 60 are shifted by a day
+        assert_eq!(cell_value(&excel_date(1.0), "DATE", None), Value::Text("1900-01-01".to_string()));
+        assert_eq!(cell_value(&excel_date(59.0), "DATE", None), Value::Text("1900-02-28".to_string()));
+        assert_eq!(cell_value(&excel_date(61.0), "DATE", None), Value::Text("1900-03-01".to_string()));
+        // Serial 60 is the phantom 1900-02-29 itself; it folds onto the 28th rather than being dropped
+        assert_eq!(cell_value(&excel_date(60.0), "DATE", None), Value::Text("1900-02-28".to_string()));
+        assert_eq!(cell_value(&excel_date(60.5), "TIMESTAMP", None), timestamp("1900-02-28 12:00:00"));
+        assert_eq!(cell_text(&excel_date(60.0)).as_deref(), Some("1900-02-28"));
+    }
+
+    #[test]
+    fn whole_day_serials_infer_date_and_times_widen_to_timestamp() {
+        assert_eq!(infer(&column(vec![excel_date(44197.0), excel_date(44198.0)])), "DATE");
+        assert_eq!(infer(&column(vec![excel_date(44197.0), excel_date(44198.5)])), "TIMESTAMP");
+    }
+
+    #[test]
+    fn schema_file_declares_types_and_rejects_values_that_do_not_fit() {
+        let dir = std::env::temp_dir().join(format!("schema_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("schema.toml"), "customers.\"ZIP Code\" = \"varchar\"\n\n[Customers]\nid = \"INTEGER\"\n").unwrap();
+        fs::write(dir.join("schema.json"), r#"{"customers.zip_code": "VARCHAR", "customers": {"id": "INTEGER"}}"#).unwrap();
+        fs::write(dir.join("bad.toml"), "customers = \"VARCHAR\"\n").unwrap();
+        let toml = SchemaOverrides::load(&dir.join("schema.toml")).unwrap();
+        let json = SchemaOverrides::load(&dir.join("schema.json")).unwrap();
+        let bad = SchemaOverrides::load(&dir.join("bad.toml")).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(toml.0, json.0);
+        assert!(bad.to_string().contains("should be table.column"), "{}", bad);
+
+        let mut args = Config::parse_from(["excel_loader_rs"]);
+        args.schema_overrides = toml;
+        let headers = [Data::String("id".into()), Data::String("ZIP Code".into())];
+        let rows = [
+            vec![Data::Float(1.0), Data::Float(2134.0)],
+            vec![Data::String("2".into()), Data::Float(501.0)],
+            vec![Data::String("n/a".into()), Data::Float(10001.0)],
+        ];
+        let rows_data: Vec<&[Data]> = rows.iter().map(Vec::as_slice).collect();
+        let schema = infer_sheet_schema(&headers, &rows_data, "customers", None, &ColumnFormats::default(), &args).unwrap();
+        assert_eq!(schema.types, ["INTEGER", "VARCHAR"]);
+        assert_eq!(schema.declared, [true, true]);
+        check_declared_types(&rows_data[..2], &|i| i, &schema, 0, "customers.xlsx sheet Data").unwrap();
+        let err = check_declared_types(&rows_data, &|i| i, &schema, 1, "customers.xlsx sheet Data").unwrap_err();
+        assert_eq!(err.to_string(), "customers.xlsx sheet Data, row 4: value 'n/a' in column id cannot be loaded as the declared INTEGER");
+        // In a --load-random sample the row is named by where it sits in the sheet, not in the sample
+        let sampled = [1, 6, 40];
+        let err = check_declared_types(&rows_data, &|i| sampled[i], &schema, 1, "customers.xlsx sheet Data").unwrap_err();
+        assert!(err.to_string().starts_with("customers.xlsx sheet Data, row 42: value 'n/a'"), "{}", err);
+
+        assert!(!cell_fits(&Data::Float(300.0), "TINYINT", None));
+        assert!(!cell_fits(&Data::Float(1234.5), "DECIMAL(5,2)", None));
+        assert!(cell_fits(&Data::String("2024-05-01".into()), "DATE", None));
+    }
+
+    #[test]
+    fn load_rows_loads_the_first_or_a_random_n_data_rows() {
+        let rows: Vec<Vec<Data>> = (0..10).map(|i| vec![Data::Int(i)]).collect();
+        let loaded = |flags: &[&str]| -> Vec<i64> {
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            sheet_data_rows(rows.iter().map(Vec::as_slice), &args).0.iter()
+                .map(|row| match row[0] { Data::Int(v) => v, _ => unreachable!() })
+                .collect()
+        };
+        assert_eq!(loaded(&[]).len(), 10);
+        assert_eq!(loaded(&["--load-rows", "3"]), [0, 1, 2]);
+        assert_eq!(loaded(&["--load-rows", "20"]).len(), 10);
+        let random = loaded(&["--load-rows", "4", "--load-random"]);
+        assert_eq!(random.len(), 4);
+        assert!(random.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", random);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--load-random"]).is_err());
+        // A random sample says where each of its rows came from
+        let args = Config::parse_from(["excel_loader_rs", "--load-rows", "4", "--load-random"]);
+        let (picked, indexes) = sheet_data_rows(rows.iter().map(Vec::as_slice), &args);
+        let values: Vec<usize> = picked.iter().map(|row| match row[0] { Data::Int(v) => v as usize, _ => unreachable!() }).collect();
+        assert_eq!(indexes.unwrap(), values);
+        // --sample-rows meant --infer-rows to some and --load-rows to others, so it is refused
+        let removed = Config::parse_from(["excel_loader_rs", "--sample-rows", "7"]);
+        assert_eq!(removed.infer_rows, 100);
+        let err = format!("{:#}", removed.validate().unwrap_err());
+        assert!(err.contains("--load-rows 7") && err.contains("--infer-rows 7"), "{}", err);
+        assert!(Config::parse_from(["excel_loader_rs", "--sample-random"]).validate().is_err());
+    }
+
+    #[test]
+    fn load_summary_totals_files_tables_and_throughput() {
+        let outcome = |tables, rows, error: Option<&str>| FileOutcome {
+            file: "f.xlsx".to_string(), tables, rows, duration: Duration::ZERO, error: error.map(str::to_string), sheets: Vec::new(),
+        };
+        let outcomes = [outcome(2, 1500, None), outcome(1, 500, None), outcome(0, 0, Some("Cannot open"))];
+        assert_eq!(
+            load_summary(&outcomes, Duration::from_millis(500)),
+            "Loaded 2000 row(s) into 3 table(s) from 2 of 3 file(s) in 500.00ms (4000 rows/s)"
+        );
+        assert!(load_summary(&[], Duration::ZERO).ends_with("(n/a rows/s)"));
+    }
+
+    #[test]
+    fn manifest_lists_files_sheets_and_errors() {
+        let path = std::env::temp_dir().join(format!("manifest_{}.json", std::process::id()));
+        let args = Config::parse_from(["excel_loader_rs", "--staging", "--db", "out.duckdb"]);
+        let loaded = LoadedSheet {
+            sheet: "Data".to_string(),
+            table: format!("{}.sales_Data", STAGING_SCHEMA),
+            rows: 2,
+            columns: vec![("id".to_string(), "BIGINT".to_string()), ("zip".to_string(), "VARCHAR".to_string())],
+            source_size: (3, 2),
+            expected_rows: 2,
+            content_hash: None,
+            mixed_columns: Vec::new(),
+        };
+        let outcomes = [
+            FileOutcome { file: "sales.xlsx".to_string(), tables: 1, rows: 2, duration: Duration::from_millis(7), error: None, sheets: vec![loaded] },
+            FileOutcome { file: "broken.xlsx".to_string(), tables: 0, rows: 0, duration: Duration::ZERO, error: Some("Cannot open".to_string()), sheets: Vec::new() },
+        ];
+        let started_at = chrono::NaiveDate::from_ymd_opt(2024, 5, 1).unwrap().and_hms_opt(9, 30, 0).unwrap();
+        write_manifest(path.to_str().unwrap(), &manifest_json(started_at, &outcomes, Duration::from_millis(20), &args)).unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(manifest["started_at"], "2024-05-01T09:30:00.000");
+        assert_eq!((manifest["files_total"].as_u64(), manifest["files_failed"].as_u64(), manifest["rows"].as_u64()), (Some(2), Some(1), Some(2)));
+        assert_eq!(manifest["files"][0]["file"], "broken.xlsx");
+        assert_eq!(manifest["files"][0]["error"], "Cannot open");
+        let sheet = &manifest["files"][1]["sheets"][0];
+        assert_eq!(sheet["table"], "sales_Data");
+        assert_eq!(sheet["columns"][1], serde_json::json!({ "name": "zip", "type": "VARCHAR" }));
+    }
+
+    #[test]
+    fn currency_symbols_and_percent_signs_parse_as_numbers() {
+        let en = |text| parse_locale_number(text, NumberLocale::En);
+        assert_eq!(en("$1,234.50"), Some(LocaleNumber::Float(1234.5)));
+        assert_eq!(en("-$5"), Some(LocaleNumber::Int(-5)));
+        assert_eq!(en("$-5"), Some(LocaleNumber::Int(-5)));
+        assert_eq!(en("15%"), Some(LocaleNumber::Float(0.15)));
+        assert_eq!(parse_locale_number("1.234,50 €", NumberLocale::De), Some(LocaleNumber::Float(1234.5)));
+        assert_eq!(en("$"), None);
+        assert_eq!(en("USD 5"), None);
+    }
+
+    #[test]
+    fn separator_options_read_european_number_text() {
+        let locale = |flags: &[&str]| Config::parse_from([&["excel_loader_rs"], flags].concat()).text_number_locale();
+        let de = locale(&["--decimal-separator", ","]).unwrap();
+        assert_eq!(de, NumberLocale::Custom { group: '.', decimal: ',' });
+        assert_eq!(locale(&["--thousands-separator", "."]), Some(de));
+        assert_eq!(parse_locale_number("1.234,56", de), Some(LocaleNumber::Float(1234.56)));
+        assert_eq!(parse_locale_number("€ 1.234.567", de), Some(LocaleNumber::Int(1_234_567)));
+        assert_eq!(parse_locale_number("1,234.56", de), None);
+        let spaced = locale(&["--decimal-separator", ".", "--thousands-separator", "_"]).unwrap();
+        assert_eq!(parse_locale_number("$1_000.5", spaced), Some(LocaleNumber::Float(1000.5)));
+
+        // One stray word keeps the column text; the numbers load as DOUBLE otherwise
+        let rows = column(vec![Data::String("1.234,56".into()), Data::String("7,5 €".into())]);
+        let rows: Vec<&[Data]> = rows.iter().map(Vec::as_slice).collect();
+        assert_eq!(infer_column_type(&rows, 0, Some(de)), "DOUBLE");
+        assert_eq!(cell_value(&Data::String("1.234,56".into()), "DOUBLE", Some(de)), Value::Double(1234.56));
+        let mixed = column(vec![Data::String("1.234,56".into()), Data::String("n/a".into())]);
+        let mixed: Vec<&[Data]> = mixed.iter().map(Vec::as_slice).collect();
+        assert_eq!(infer_column_type(&mixed, 0, Some(de)), "VARCHAR");
+
+        let invalid = |flags: &[&str]| Config::parse_from([&["excel_loader_rs"], flags].concat()).validate().is_err();
+        assert!(invalid(&["--decimal-separator", ".", "--thousands-separator", "."]));
+        assert!(invalid(&["--decimal-separator", "5"]));
+        assert!(Config::try_parse_from(["excel_loader_rs", "--decimal-separator", ",", "--number-locale", "de"]).is_err());
+    }
+
+    #[test]
+    fn table_metadata_records_where_each_table_came_from() {
+        let test_data = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let progress = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let load = |files: &[&str], flags: &[&str]| -> Vec<Vec<String>> {
+            let db: Box<dyn Db> = Box::new(DuckBackend(Connection::open_in_memory().unwrap(), InsertMode::Appender));
+            let conn_mutex = Arc::new(Mutex::new(db));
+            // Loading twice, as two runs would, replaces the rows rather than adding to them
+            for _ in 0..2 {
+                let args = Config::parse_from([&["excel_loader_rs", "--table-metadata"], flags].concat());
+                for file in files {
+                    let path = test_data.join(file);
+                    process_excel_file(&path, path.file_stem().unwrap().to_str().unwrap(), &conn_mutex, &progress, &args).unwrap();
+                }
+            }
+            let db = conn_mutex.lock().unwrap();
+            let mut stmt = db.duckdb().unwrap().prepare(&format!(
+                "SELECT table_name, regexp_extract(source_file, '[^/]+$'), sheet_name, source_rows::VARCHAR, source_columns::VARCHAR, \
+                 (file_modified_at IS NOT NULL AND loaded_at IS NOT NULL)::VARCHAR FROM {} ORDER BY ALL",
+                TABLE_METADATA_TABLE
+            )).unwrap();
+            let rows = stmt.query_map([], |row| (0..6).map(|i| row.get(i)).collect::<Result<Vec<String>, _>>()).unwrap();
+            rows.collect::<Result<_, _>>().unwrap()
+        };
+        let many = load(&["many_sheets.xlsx"], &[]);
+        assert_eq!(many.len(), 6);
+        assert_eq!(many[5], ["many_sheets_Region6", "many_sheets.xlsx", "Region6", "9", "3", "true"]);
+        // A merged table keeps one row per file and sheet it was loaded from
+        let merged = load(&["monthly_jan.xlsx", "monthly_feb.xlsx"], &["--single-table", "sales", "--sheets", "Sales"]);
+        let sources: Vec<(&str, &str)> = merged.iter().map(|row| (row[0].as_str(), row[1].as_str())).collect();
+        assert_eq!(sources, [("sales", "monthly_feb.xlsx"), ("sales", "monthly_jan.xlsx")]);
+    }
+
+    #[test]
+    fn failed_files_fail_the_run_unless_ignored() {
+        let test_data = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let files = [test_data.join("unreadable/encrypted.xlsx"), test_data.join("users.xlsx"), test_data.join("legacy.xls")];
+        let progress = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let outcomes = |flags: &[&str]| -> Vec<FileOutcome> {
+            let db: Box<dyn Db> = Box::new(DuckBackend(Connection::open_in_memory().unwrap(), InsertMode::Appender));
+            let conn_mutex = Arc::new(Mutex::new(db));
+            let args = Config::parse_from([&["excel_loader_rs", "--threads", "1"], flags].concat());
+            load_files(&files, &test_data, &conn_mutex, InsertMode::Appender, &progress, &args).unwrap()
+        };
+        let all = outcomes(&[]);
+        assert_eq!(all.len(), 3);
+        assert_eq!(all.iter().filter(|outcome| outcome.error.is_some()).count(), 1);
+        // On one thread the failing first file stops the other two from starting
+        assert_eq!(outcomes(&["--fail-fast"]).len(), 1);
+
+        assert!(failure_result(0, 3, 3, false).is_ok());
+        assert_eq!(failure_result(1, 2, 3, false).unwrap_err().to_string(), "1 of 3 file(s) failed to load");
+        assert!(failure_result(1, 2, 3, true).is_ok());
+        assert_eq!(exit_code(&failure_result(1, 2, 3, false).unwrap_err()), EXIT_PARTIAL_FAILURE);
+        // --fail-fast stopped before anything loaded
+        assert_eq!(exit_code(&failure_result(1, 0, 3, false).unwrap_err()), EXIT_FAILURE);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--ignore-errors", "--fail-fast"]).is_err());
+    }
+
+    #[test]
+    fn dedup_on_keeps_the_first_or_last_row_per_key() {
+        let dedup = |keys: &[&str], keep: DedupKeep| -> (Option<usize>, Vec<String>) {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE sales (_source_file VARCHAR, id BIGINT, amount DOUBLE); \
+                 INSERT INTO sales VALUES ('jan.xlsx', 1, 10), ('jan.xlsx', 2, 20), ('feb.xlsx', 2, 20), ('feb.xlsx', 1, 15);",
+            ).unwrap();
+            let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+            let removed = dedup_table(&conn, "sales", &keys, keep).unwrap();
+            let mut stmt = conn.prepare("SELECT _source_file || ':' || id || '=' || amount FROM sales ORDER BY rowid").unwrap();
+            let rows = stmt.query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap();
+            (removed, rows)
+        };
+        assert_eq!(dedup(&["ID"], DedupKeep::First), (Some(2), vec!["jan.xlsx:1=10.0".to_string(), "jan.xlsx:2=20.0".to_string()]));
+        assert_eq!(dedup(&["id"], DedupKeep::Last), (Some(2), vec!["feb.xlsx:2=20.0".to_string(), "feb.xlsx:1=15.0".to_string()]));
+        // Whole rows match across files, since the source column isn't compared
+        assert_eq!(dedup(&["*"], DedupKeep::First).0, Some(1));
+        assert_eq!(dedup(&["id", "region"], DedupKeep::First), (None, vec![
+            "jan.xlsx:1=10.0".to_string(), "jan.xlsx:2=20.0".to_string(), "feb.xlsx:2=20.0".to_string(), "feb.xlsx:1=15.0".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn progress_is_weighted_by_input_bytes() {
+        let test_data = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let mut args = Config::parse_from(["excel_loader_rs"]);
+        let big = test_data.join("data_50k.xlsx");
+        assert_eq!(input_size(&big, &args), fs::metadata(&big).unwrap().len());
+        assert!(input_size(&big, &args) > 100 * input_size(&test_data.join("users.xlsx"), &args));
+        assert_eq!(input_size(&test_data.join("missing.xlsx"), &args), 1);
+        args.stdin_data = Some(vec![0; 42]);
+        assert_eq!(input_size(Path::new("stdin.xlsx"), &args), 42);
+    }
+
+    #[test]
+    fn leading_zero_codes_and_text_columns_keep_their_exact_text() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/accounts.xlsx"));
+        let load = |flags: &[&str]| -> (Vec<String>, Vec<String>) {
+            let conn = load_fixture(&path, "accounts", flags);
+            let types = conn
+                .prepare("SELECT data_type FROM information_schema.columns WHERE table_name = 'accounts_Data' ORDER BY ordinal_position").unwrap()
+                .query_map([], |row| row.get(0)).unwrap()
+                .collect::<Result<_, _>>().unwrap();
+            let first = conn
+                .query_row("SELECT zip::VARCHAR, account_id::VARCHAR, phone::VARCHAR, balance::VARCHAR FROM accounts_Data LIMIT 1", [], |row| {
+                    Ok(vec![row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?])
+                })
+                .unwrap();
+            (types, first)
+        };
+        // Reading text as numbers would otherwise turn 00123 into 123
+        let (types, first) = load(&["--number-locale", "en"]);
+        assert_eq!(types, ["VARCHAR", "DOUBLE", "VARCHAR", "DOUBLE"]);
+        assert_eq!(first, ["00123", "1001.0", "0712345678", "10.5"]);
+        let (types, first) = load(&["--text-columns", "Account_ID,balance"]);
+        assert_eq!(types, ["VARCHAR", "VARCHAR", "VARCHAR", "VARCHAR"]);
+        assert_eq!(first, ["00123", "1001", "0712345678", "10.5"]);
+        assert_eq!(parse_locale_number("0.5", NumberLocale::En), Some(LocaleNumber::Float(0.5)));
+        assert_eq!(parse_locale_number("0", NumberLocale::En), Some(LocaleNumber::Int(0)));
+    }
+
+    #[test]
+    fn stdin_workbooks_are_recognised_by_their_bytes() {
+        let test_data = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let ods = fs::read(test_data.join("inventory.ods")).unwrap();
+        assert_eq!(workbook_extension(&ods).unwrap(), "ods");
+        assert_eq!(workbook_extension(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1, 0]).unwrap(), "xls");
+        assert!(workbook_extension(b"id,name\n1,a\n").is_err());
+
+        let mut args = Config::parse_from(["excel_loader_rs", "--path", "-"]);
+        args.stdin_data = Some(fs::read(test_data.join("ragged.xlsx")).unwrap());
+        assert_eq!(workbook_extension(args.stdin_data.as_ref().unwrap()).unwrap(), "xlsx");
+        // The path only names the workbook; nothing is read from disk
+        let path = PathBuf::from("piped.xlsx");
+        let (_, loaded) = load_fixture_with(&path, &table_prefix(&path, Path::new(""), &args), &args, InsertMode::Appender).unwrap();
+        assert_eq!((loaded[0].table.as_str(), loaded[0].rows), ("piped_Data", 2));
+    }
+
+    #[test]
+    fn all_text_loads_every_cell_as_its_text() {
+        assert_eq!(cell_text(&Data::Float(2.0)).as_deref(), Some("2"));
+        assert_eq!(cell_text(&Data::Float(0.1)).as_deref(), Some("0.1"));
+        assert_eq!(cell_text(&Data::Bool(true)).as_deref(), Some("true"));
+        assert_eq!(cell_text(&excel_date(44197.0)).as_deref(), Some("2021-01-01"));
+        assert_eq!(cell_text(&excel_date(44197.75)).as_deref(), Some("2021-01-01 18:00:00"));
+        assert_eq!(cell_text(&Data::Error(calamine::CellErrorType::Div0)), None);
+        assert_eq!(cell_text(&Data::Empty), None);
+
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/shares.xlsx"));
+        let conn = load_fixture(&path, "shares", &["--all-text"]);
+        let types: Vec<String> = conn.prepare("SELECT DISTINCT data_type FROM information_schema.columns WHERE table_name = 'shares_Data'").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(types, ["VARCHAR"]);
+        let first: (String, String, String) = conn
+            .query_row("SELECT id, share, price FROM shares_Data WHERE id = '1'", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap();
+        assert_eq!(first, ("1".to_string(), "0".to_string(), "$1,234.50".to_string()));
+    }
+
+    #[test]
+    fn cells_past_the_header_load_into_generated_columns() {
+        // ragged.xlsx: header `id | name`, then `1 | a | x | 9` and `2 | b`
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/ragged.xlsx"));
+        let conn = load_fixture(&path, "ragged", &[]);
+        let long: (String, Option<String>, Option<f64>) = conn
+            .query_row("SELECT name, column_3, column_4 FROM ragged_Data WHERE id = 1", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap();
+        assert_eq!(long, ("a".to_string(), Some("x".to_string()), Some(9.0)));
+        let short: (Option<String>, Option<f64>) = conn
+            .query_row("SELECT column_3, column_4 FROM ragged_Data WHERE id = 2", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(short, (None, None));
+    }
+
+    #[test]
+    fn percent_formatted_columns_load_as_double() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/shares.xlsx"));
+        let conn = load_fixture(&path, "shares", &["--detect-percent", "--tight-ints", "--number-locale", "en"]);
+        let mut stmt = conn
+            .prepare("SELECT data_type FROM information_schema.columns WHERE table_name = 'shares_Data' ORDER BY ordinal_position")
+            .unwrap();
+        let types: Vec<String> = stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        // Whole percentages (0%, 100%) stay fractions, and "$1,234.50" text is a number
+        assert_eq!(types, ["TINYINT", "DOUBLE", "DOUBLE"]);
+    }
+
+    #[test]
+    fn numeric_text_binds_as_number_only_outside_varchar_columns() {
+        let text = Data::String("1.234,5".to_string());
+        assert_eq!(cell_value(&text, "DOUBLE", Some(NumberLocale::De)), Value::Double(1234.5));
+        assert_eq!(cell_value(&text, "VARCHAR", Some(NumberLocale::De)), Value::Text("1.234,5".to_string()));
+        assert_eq!(cell_value(&Data::Empty, "DOUBLE", None), Value::Null);
+    }
+
+    fn column(cells: Vec<Data>) -> Vec<Vec<Data>> {
+        cells.into_iter().map(|cell| vec![cell]).collect()
+    }
+
+    fn infer(rows: &[Vec<Data>]) -> &'static str {
+        let rows: Vec<&[Data]> = rows.iter().map(Vec::as_slice).collect();
+        infer_column_type(&rows, 0, None)
+    }
+
+    #[test]
+    fn integer_then_float_widens_to_double() {
+        assert_eq!(infer(&column(vec![Data::Int(1), Data::Float(2.5), Data::Int(3)])), "DOUBLE");
+    }
+
+    #[test]
+    fn any_string_makes_varchar() {
+        assert_eq!(infer(&column(vec![Data::Int(1), Data::Float(2.5), Data::String("n/a".into())])), "VARCHAR");
+        assert_eq!(infer(&column(vec![Data::Bool(true), Data::Int(1)])), "VARCHAR");
+    }
+
+    #[test]
+    fn empty_and_error_cells_are_ignored() {
+        let rows = column(vec![Data::Empty, Data::Int(4), Data::Error(calamine::CellErrorType::Div0), Data::Int(5)]);
+        assert_eq!(infer(&rows), "BIGINT");
+    }
+
+    #[test]
+    fn all_empty_column_is_varchar() {
+        assert_eq!(infer(&column(vec![Data::Empty, Data::Empty])), "VARCHAR");
+        assert_eq!(infer(&[]), "VARCHAR");
+    }
+
+    #[test]
+    fn majority_strategy_types_by_the_most_common_kind() {
+        let majority = |cells: Vec<Data>| {
+            let rows = column(cells);
+            let rows: Vec<&[Data]> = rows.iter().map(Vec::as_slice).collect();
+            majority_column_type(&rows, 0, None)
+        };
+        let text = || Data::String("n/a".into());
+        assert_eq!(majority(vec![Data::Int(1), Data::Int(2), text(), Data::Empty, Data::Int(3)]), ("BIGINT", 3, 4));
+        assert_eq!(majority(vec![Data::Int(1), Data::Float(2.5), text()]), ("DOUBLE", 2, 3));
+        // Ties and mostly-text columns fall back to the widening rules
+        assert_eq!(majority(vec![Data::Int(1), Data::Bool(true)]), ("VARCHAR", 2, 2));
+        assert_eq!(majority(vec![Data::Int(1), text(), text()]), ("VARCHAR", 3, 3));
+        assert_eq!(majority(vec![Data::Int(1), Data::Int(2)]), ("BIGINT", 2, 2));
+
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/bad_rows.xlsx"));
+        let conn = load_fixture(&path, "bad_rows", &["--type-strategy", "majority", "--on-bad-row", "null"]);
+        let (dtype, nulls): (String, i64) = conn.query_row(
+            "SELECT typeof(amount), count(*) FILTER (WHERE amount IS NULL) FROM bad_rows_Data GROUP BY ALL LIMIT 1", [], |r| Ok((r.get(0)?, r.get(1)?)),
+        ).unwrap();
+        assert_eq!((dtype.as_str(), nulls), ("DOUBLE", 1));
+    }
+
+    #[test]
+    fn short_rows_count_as_empty() {
+        let rows: Vec<Vec<Data>> = vec![vec![Data::Int(1), Data::Float(1.5)], vec![Data::Int(2)]];
+        let rows: Vec<&[Data]> = rows.iter().map(Vec::as_slice).collect();
+        assert_eq!(infer_column_type(&rows, 1, None), "DOUBLE");
+    }
+
+    #[test]
+    fn inference_only_sees_the_sampled_rows() {
+        let rows = column(vec![Data::Int(1), Data::Int(2), Data::String("late text".into())]);
+        let rows: Vec<&[Data]> = rows.iter().map(Vec::as_slice).collect();
+        assert_eq!(infer_column_type(&sample_rows(&rows, 2, SampleFrom::Head), 0, None), "BIGINT");
+        assert_eq!(infer_column_type(&sample_rows(&rows, 3, SampleFrom::Head), 0, None), "VARCHAR");
+    }
+
+    #[test]
+    fn query_format_prints_json_and_csv() {
+        let conn = Connection::open_in_memory().unwrap();
+        let query = "SELECT * FROM (VALUES (2, 'b, \"quoted\"', 1.5, true), (1, NULL, NULL, false)) t(id, name, score, ok)";
+        let write = |format| {
+            let mut out = Vec::new();
+            let count = write_query(&conn, query, format, &mut out).unwrap();
+            (count, String::from_utf8(out).unwrap())
+        };
+
+        let (count, json) = write(QueryFormat::Json);
+        assert_eq!(count, 2);
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, serde_json::json!([
+            {"id": 2, "name": "b, \"quoted\"", "score": 1.5, "ok": true},
+            {"id": 1, "name": null, "score": null, "ok": false},
+        ]));
+        // Keys follow the query's column order
+        assert!(json.find("\"id\"").unwrap() < json.find("\"ok\"").unwrap());
+
+        let (_, csv) = write(QueryFormat::Csv);
+        assert_eq!(csv, "id,name,score,ok\n2,\"b, \"\"quoted\"\"\",1.5,true\n1,,,false\n");
+        assert_eq!(write_query(&conn, "SELECT 1 AS x WHERE false", QueryFormat::Json, &mut Vec::new()).unwrap(), 0);
+    }
+
+    #[test]
+    fn grouped_headers_flatten_into_group_subcolumn_names() {
+        let grid = [
+            ["Sales report", "", "", "", ""],
+            ["Region", "Q1", "", "Q2", ""],
+            ["", "Sales", "Units", "Sales", "Units"],
+            ["North", "10", "1", "20", "2"],
+        ];
+        let mut range = Range::new((0, 0), (3, 4));
+        for (row, cells) in grid.iter().enumerate() {
+            for (col, text) in cells.iter().enumerate().filter(|(_, text)| !text.is_empty()) {
+                range.set_value((row as u32, col as u32), Data::String(text.to_string()));
+            }
+        }
+        let names = |flags: &[&str]| {
+            let args = Config::parse_from([&["excel_loader_rs", "--skip-rows", "1"], flags].concat());
+            let picked = select_header_row(range.clone(), None, &args).unwrap();
+            (unique_column_names(picked.rows().next().unwrap(), HeaderStyle::AsIs, IdentifierCase::Preserve, false, None), picked.height())
+        };
+        assert_eq!(names(&["--header-rows", "2", "--fill-header"]), (
+            ["Region", "Q1_Sales", "Q1_Units", "Q2_Sales", "Q2_Units"].map(String::from).to_vec(), 2,
+        ));
+        // Without filling, the subcolumns under a merged group lose its label
+        assert_eq!(names(&["--header-rows", "2"]).0, ["Region", "Q1_Sales", "Units", "Q2_Sales", "Units_2"]);
+        assert_eq!(names(&["--fill-header"]).0, ["Region", "Q1", "Q1_2", "Q2", "Q2_2"]);
+        assert!(select_header_row(range.clone(), None, &Config::parse_from(["excel_loader_rs", "--header-rows", "5"])).is_err());
+    }
+
+    #[test]
+    fn schema_name_keeps_each_import_in_its_own_schema() {
+        let db = std::env::temp_dir().join(format!("schema_name_{}.duckdb", std::process::id()));
+        let _ = fs::remove_file(&db);
+        let load = |file: &str, flags: &[&str]| {
+            let path = format!("{}/../test_data/{}", env!("CARGO_MANIFEST_DIR"), file);
+            run(Config::parse_from([&["excel_loader_rs", "--quiet", "--path", &path, "--db", db.to_str().unwrap()], flags].concat())).unwrap();
+        };
+        load("accounts.xlsx", &["--schema-name", "raw"]);
+        load("accounts.xlsx", &["--schema-name", "archive", "--staging"]);
+        load("users.xlsx", &["--schema-name", "raw", "--refresh-all"]);
+
+        let conn = Connection::open(&db).unwrap();
+        let tables: Vec<String> = conn
+            .prepare("SELECT table_schema || '.' || table_name FROM information_schema.tables WHERE table_name NOT LIKE '\\_%' ESCAPE '\\' ORDER BY 1").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        // --refresh-all cleared raw only; the staged import was swapped into archive, not main
+        let users: Vec<String> = tables.iter().filter(|table| table.starts_with("raw.")).cloned().collect();
+        assert!(!users.is_empty() && users.iter().all(|table| table.starts_with("raw.users_")), "{:?}", tables);
+        assert_eq!(tables.iter().filter(|table| !table.starts_with("raw.")).collect::<Vec<_>>(), ["archive.accounts_Data"]);
+        drop(conn);
+        fs::remove_file(&db).unwrap();
+        assert!(Config::try_parse_from(["excel_loader_rs", "--schema-name", "my schema"]).is_err());
+    }
+
+    #[test]
+    fn sorted_loads_report_and_resolve_collisions_in_path_order() {
+        let test_data = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let dir = std::env::temp_dir().join(format!("sorted_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Both names sanitize to the table a_b_Data; "a b" sorts first
+        for name in ["a b.xlsx", "a_b.xlsx"] {
+            fs::copy(test_data.join("accounts.xlsx"), dir.join(name)).unwrap();
+        }
+        let files = [dir.join("a_b.xlsx"), test_data.join("users.xlsx"), dir.join("a b.xlsx"), test_data.join("legacy.xls")];
+        let progress = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        for _ in 0..3 {
+            let db: Box<dyn Db> = Box::new(DuckBackend(Connection::open_in_memory().unwrap(), InsertMode::Appender));
+            let conn_mutex = Arc::new(Mutex::new(db));
+            let args = Config::parse_from(["excel_loader_rs", "--sorted", "--threads", "3", "--parallel-sheets", "--on-collision", "rename"]);
+            let outcomes = load_files(&files, &dir, &conn_mutex, InsertMode::Appender, &progress, &args).unwrap();
+            let mut sorted = files.to_vec();
+            sorted.sort();
+            assert_eq!(outcomes.iter().map(|outcome| PathBuf::from(&outcome.file)).collect::<Vec<_>>(), sorted);
+            let tables: Vec<(&str, &str)> = outcomes.iter()
+                .filter(|outcome| outcome.file.starts_with(dir.to_str().unwrap()))
+                .map(|outcome| (outcome.file.rsplit('/').next().unwrap(), outcome.sheets[0].table.as_str()))
+                .collect();
+            assert_eq!(tables, [("a b.xlsx", "a_b_Data"), ("a_b.xlsx", "a_b_Data_2")]);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn range_loads_only_the_given_block_of_a_sheet() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/report_block.xlsx"));
+        let args = Config::parse_from(["excel_loader_rs", "--range", "report!$B$3:D5"]);
+        let (_, loaded) = load_fixture_with(&path, "report_block", &args, InsertMode::Appender).unwrap();
+        let shapes: Vec<(&str, usize, Vec<&str>)> = loaded.iter()
+            .map(|sheet| (sheet.table.as_str(), sheet.rows, sheet.columns.iter().map(|(name, _)| name.as_str()).collect()))
+            .collect();
+        // The notes around the block are left out; the sheet without a range loads whole
+        assert_eq!(shapes, [
+            ("report_block_Report", 2, vec!["region", "sales", "units"]),
+            ("report_block_Other", 2, vec!["id", "name"]),
+        ]);
+
+        assert_eq!(parse_sheet_range("'Q1 ''24'!A1:XFD1048576").unwrap(), SheetRange { sheet: "Q1 '24".to_string(), start: (0, 0), end: (1_048_575, 16_383) });
+        for bad in ["B5:F200", "!B5:F200", "Sheet1!B5", "Sheet1!F200:B5", "Sheet1!B0:F2", "Sheet1!5B:F2", "Sheet1!XFE1:XFE2"] {
+            assert!(parse_sheet_range(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn verify_reports_skipped_rows_and_tables_that_lost_rows() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/bad_rows.xlsx"));
+        let verify = |flags: &[&str], tamper: &str| {
+            // The fourth row's text doesn't fit the DOUBLE column two sampled rows give amount
+            let args = Config::parse_from([&["excel_loader_rs", "--infer-rows", "2", "--on-bad-row", "skip", "--verify"], flags].concat());
+            let (conn, sheets) = load_fixture_with(&path, "bad_rows", &args, InsertMode::Appender).unwrap();
+            let outcome = FileOutcome { file: "bad_rows.xlsx".to_string(), tables: 1, rows: 3, duration: Duration::ZERO, error: None, sheets };
+            conn.execute_batch(tamper).unwrap();
+            verify_load(&conn, &[outcome], &args).unwrap()
+        };
+        assert_eq!(verify(&[], ""), ["bad_rows.xlsx sheet Data: 1 of 4 row(s) did not load (--verify-max-skipped 0)"]);
+        assert!(verify(&["--verify-max-skipped", "1"], "").is_empty());
+        assert_eq!(
+            verify(&["--verify-max-skipped", "1"], "DELETE FROM bad_rows_Data WHERE rowid = 0"),
+            ["Table bad_rows_Data holds 2 row(s), but 3 were inserted"],
+        );
+        assert!(Config::try_parse_from(["excel_loader_rs", "--verify-max-skipped", "2"]).is_err());
+    }
+
+    #[test]
+    fn watch_loads_files_once_two_scans_agree() {
+        let stamp = |size| FileStamp { modified: 1_000, size };
+        let (old, new) = (PathBuf::from("old.xlsx"), PathBuf::from("new.xlsx"));
+        let mut seen = HashMap::from([(old.clone(), stamp(10))]);
+        let mut pending = HashMap::new();
+        let mut scan = |files: &[(&PathBuf, i64)]| {
+            let stamped: Vec<(PathBuf, FileStamp)> = files.iter().map(|(file, size)| ((*file).clone(), stamp(*size))).collect();
+            settled_files(&stamped, &mut seen, &mut pending)
+        };
+        // A copy in progress keeps growing, so it waits until it holds still
+        assert!(scan(&[(&old, 10), (&new, 5)]).is_empty());
+        assert!(scan(&[(&old, 10), (&new, 8)]).is_empty());
+        assert_eq!(scan(&[(&old, 10), (&new, 8)]), [PathBuf::from("new.xlsx")]);
+        assert!(scan(&[(&old, 10), (&new, 8)]).is_empty());
+        // A loaded file that changes loads again once it settles
+        assert!(scan(&[(&old, 12), (&new, 8)]).is_empty());
+        assert_eq!(scan(&[(&old, 12), (&new, 8)]), [PathBuf::from("old.xlsx")]);
+        // One deleted mid-copy is forgotten rather than loaded when it comes back
+        let other = PathBuf::from("other.xlsx");
+        assert!(scan(&[(&other, 3)]).is_empty());
+        assert!(scan(&[]).is_empty());
+        assert!(scan(&[(&other, 3)]).is_empty());
+        assert!(Config::try_parse_from(["excel_loader_rs", "--watch", "--repl"]).is_err());
+        assert!(Config::try_parse_from(["excel_loader_rs", "--watch-interval", "5"]).is_err());
+    }
+
+    #[test]
+    fn memory_db_loads_without_writing_a_file() {
+        let dump = std::env::temp_dir().join(format!("memory_db_{}.sql", std::process::id()));
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/users.xlsx");
+        run(Config::parse_from(["excel_loader_rs", "--quiet", "--path", path, "--db", ":memory:", "--sql-dump", dump.to_str().unwrap()])).unwrap();
+        let sql = fs::read_to_string(&dump).unwrap();
+        fs::remove_file(&dump).unwrap();
+        assert!(sql.contains("CREATE TABLE") && sql.contains("INSERT INTO"), "{}", sql);
+        assert!(!Path::new(MEMORY_DB).exists());
+    }
+
+    #[test]
+    fn ddl_out_writes_sorted_create_statements_with_or_without_loading() {
+        let tmp = std::env::temp_dir();
+        let (db, ddl) = (tmp.join(format!("ddl_out_{}.duckdb", std::process::id())), tmp.join(format!("ddl_out_{}.sql", std::process::id())));
+        let _ = fs::remove_file(&db);
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/monthly_*.xlsx");
+        let ddl_of = |flags: &[&str]| {
+            let base = ["excel_loader_rs", "--quiet", "--path", path, "--db", db.to_str().unwrap(), "--ddl-out", ddl.to_str().unwrap()];
+            run(Config::parse_from([&base[..], flags].concat())).unwrap();
+            fs::read_to_string(&ddl).unwrap()
+        };
+        let dry = ddl_of(&["--dry-run", "--index", "monthly_jan_Sales.region"]);
+        assert!(!db.exists());
+        let loaded = ddl_of(&["--index", "monthly_jan_Sales.region"]);
+        assert_eq!(dry, loaded);
+        let lines: Vec<&str> = loaded.lines().collect();
+        assert_eq!(lines, [
+            r#"CREATE OR REPLACE TABLE "monthly_feb_Notes" ("note" VARCHAR, "author" VARCHAR);"#,
+            r#"CREATE OR REPLACE TABLE "monthly_feb_Sales" ("region" VARCHAR, "units" DOUBLE, "revenue" DOUBLE);"#,
+            r#"CREATE OR REPLACE TABLE "monthly_jan_Notes" ("note" VARCHAR);"#,
+            r#"CREATE OR REPLACE TABLE "monthly_jan_Sales" ("region" VARCHAR, "units" DOUBLE, "revenue" DOUBLE);"#,
+            r#"CREATE INDEX IF NOT EXISTS "idx_monthly_jan_Sales_region" ON "main"."monthly_jan_Sales" ("region");"#,
+        ]);
+        fs::remove_file(&db).unwrap();
+        fs::remove_file(&ddl).unwrap();
+    }
+
+    #[test]
+    fn json_report_carries_the_exit_code_and_each_file_status() {
+        let dir = std::env::temp_dir().join(format!("json_report_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for file in ["users.xlsx", "damaged.xlsx"] {
+            fs::copy(format!("{}/../test_data/{}", env!("CARGO_MANIFEST_DIR"), file), dir.join(file)).unwrap();
+        }
+        let report = |flags: &[&str]| {
+            let mut args = Config::parse_from([&["excel_loader_rs", "--quiet", "--db", ":memory:", "--json-report", "--path", dir.to_str().unwrap()], flags].concat());
+            let start = Instant::now();
+            let result = run_steps(&mut args);
+            run_report(&result, start.elapsed(), &args)
+        };
+        let partial = report(&["--strict"]);
+        assert_eq!((partial["status"].as_str(), partial["exit_code"].as_u64()), (Some("partial"), Some(EXIT_PARTIAL_FAILURE as u64)));
+        assert_eq!(partial["error"], "1 of 2 file(s) failed to load");
+        let statuses: Vec<(&str, &str)> = partial["files"].as_array().unwrap().iter()
+            .map(|file| (file["file"].as_str().unwrap().rsplit('/').next().unwrap(), file["status"].as_str().unwrap()))
+            .collect();
+        assert_eq!(statuses, [("damaged.xlsx", "failed"), ("users.xlsx", "ok")]);
+        assert_eq!(report(&[])["status"], "ok");
+        let bad = report(&["--chunk-size", "0"]);
+        assert_eq!((bad["status"].as_str(), bad["exit_code"].as_u64()), (Some("bad_args"), Some(EXIT_BAD_ARGS as u64)));
+        assert!(bad.get("files").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn load_timestamp_column_is_the_same_in_every_table_of_a_run() {
+        let dir = std::env::temp_dir().join(format!("load_timestamp_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for file in ["users.xlsx", "orders.xlsx", "events.jsonl"] {
+            fs::copy(format!("{}/../test_data/{}", env!("CARGO_MANIFEST_DIR"), file), dir.join(file)).unwrap();
+        }
+        let db = dir.join("load.duckdb");
+        run(Config::parse_from(["excel_loader_rs", "--quiet", "--path", dir.to_str().unwrap(), "--db", db.to_str().unwrap(), "--load-timestamp-column", "loaded at"])).unwrap();
+
+        let conn = Connection::open(&db).unwrap();
+        let stamps: Vec<String> = conn
+            .prepare("SELECT DISTINCT loaded_at::VARCHAR FROM (SELECT loaded_at FROM users_Sheet1 UNION ALL SELECT loaded_at FROM orders_Sheet1 UNION ALL SELECT loaded_at FROM events)").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        let started: String = conn.query_row("SELECT started_at::VARCHAR FROM _load_runs", [], |row| row.get(0)).unwrap();
+        assert_eq!(stamps, [started]);
+        let dtype: String = conn.query_row("SELECT data_type FROM information_schema.columns WHERE table_name = 'events' AND column_name = 'loaded_at'", [], |row| row.get(0)).unwrap();
+        assert_eq!(dtype, "TIMESTAMP");
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sql_hooks_run_around_the_load_and_post_failures_keep_the_tables() {
+        let db = std::env::temp_dir().join(format!("sql_hooks_{}.duckdb", std::process::id()));
+        let script = std::env::temp_dir().join(format!("sql_hooks_{}.sql", std::process::id()));
+        let _ = fs::remove_file(&db);
+        fs::write(&script, "CREATE VIEW user_count AS SELECT count(*) AS n FROM users_Sheet1;\nSELECT missing FROM user_count;\n").unwrap();
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/users.xlsx");
+        let load = |pre: &str, post: &str| {
+            run(Config::parse_from(["excel_loader_rs", "--quiet", "--path", path, "--db", db.to_str().unwrap(), "--sql-pre", pre, "--post-sql", post]))
+        };
+        let tables = || -> Vec<String> {
+            Connection::open(&db).unwrap()
+                .prepare("SELECT table_name FROM information_schema.tables WHERE table_name NOT LIKE '\\_%' ESCAPE '\\' ORDER BY 1").unwrap()
+                .query_map([], |row| row.get(0)).unwrap()
+                .collect::<Result<_, _>>().unwrap()
+        };
+
+        // A plain path to a script file works as well as `@file`
+        let err = load("CREATE TABLE pre_marker AS SELECT 1 AS x", script.to_str().unwrap()).unwrap_err();
+        assert!(format!("{:#}", err).contains(":2: statement failed: SELECT missing FROM user_count"), "{:#}", err);
+        assert_eq!(tables(), ["pre_marker", "user_count", "users_Sheet1"]);
+        let alias = Config::parse_from(["excel_loader_rs", "--sql-post", "@views.sql"]);
+        assert_eq!(alias.post_sql.as_deref(), Some("@views.sql"));
+
+        fs::remove_file(&db).unwrap();
+        let err = load("SELEC 1", "SELECT 1").unwrap_err();
+        assert!(format!("{:#}", err).starts_with("--sql-pre failed; nothing was loaded: --sql-pre:1: statement failed"), "{:#}", err);
+        assert!(tables().is_empty());
+        fs::remove_file(&db).unwrap();
+        fs::remove_file(&script).unwrap();
+    }
+
+    #[test]
+    fn query_output_renders_decimals_blobs_and_nested_types() {
+        let conn = Connection::open_in_memory().unwrap();
+        let query = "SELECT sum(x) AS total, sum(x) * 1000000000000000000000 AS wide, '\\xAA\\x01'::BLOB AS small, \
+            repeat('a', 20)::BLOB AS big, [1, 2] AS list, {'k': 'v'} AS item, INTERVAL 2 DAY AS span, TIME '08:30:00' AS at \
+            FROM (VALUES (0.1::DECIMAL(10,2)), (0.2)) t(x);";
+        let mut out = Vec::new();
+        write_query(&conn, query, QueryFormat::Csv, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), format!(
+            "total,wide,small,big,list,item,span,at\n0.30,300000000000000000000.00,\\xAA\\x01,{}... (20 bytes),\"[1, 2]\",{{'k': v}},2 days,08:30:00\n",
+            "\\x61".repeat(BLOB_PREVIEW_BYTES),
+        ));
+
+        let mut out = Vec::new();
+        write_query(&conn, query, QueryFormat::Json, &mut out).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&String::from_utf8(out).unwrap()).unwrap();
+        // A short sum stays a number; one too long for a double stays exact as text
+        assert_eq!(parsed[0]["total"], serde_json::json!(0.3));
+        assert_eq!(parsed[0]["wide"], serde_json::json!("300000000000000000000.00"));
+        assert_eq!(parsed[0]["list"], serde_json::json!("[1, 2]"));
+        // Statements DuckDB can't describe still run as written
+        assert_eq!(renderable_query(&conn, "CREATE TABLE t (x INTEGER)").0, "CREATE TABLE t (x INTEGER)");
+    }
+
+    #[test]
+    fn incremental_rebuilds_only_sheets_whose_cells_changed() {
+        let dir = std::env::temp_dir().join(format!("sheet_hashes_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("in")).unwrap();
+        let workbook = dir.join("in/monthly_jan.xlsx");
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/monthly_jan.xlsx"), &workbook).unwrap();
+        let db = dir.join("out.duckdb");
+        let load = |flags: &[&str]| run(Config::parse_from([&[
+            "excel_loader_rs", "--quiet", "--incremental", "--path", dir.join("in").to_str().unwrap(), "--db", db.to_str().unwrap(),
+        ][..], flags].concat())).unwrap();
+        load(&[]);
+
+        // Mark both tables, then make Notes look edited and touch the file so it is read again
+        let conn = Connection::open(&db).unwrap();
+        conn.execute_batch(&format!(
+            "INSERT INTO monthly_jan_Sales (region) VALUES ('marker'); INSERT INTO monthly_jan_Notes VALUES ('marker');
+             UPDATE {} SET content_hash = 'edited' WHERE sheet = 'Notes';",
+            LOADED_SHEETS_TABLE
+        )).unwrap();
+        drop(conn);
+        let touch = |ahead: u64| {
+            let later = std::time::SystemTime::now() + Duration::from_secs(ahead);
+            fs::File::options().write(true).open(&workbook).unwrap().set_modified(later).unwrap();
+        };
+        touch(60);
+        load(&[]);
+
+        let conn = Connection::open(&db).unwrap();
+        let markers = |table: &str| -> i64 {
+            conn.query_row(&format!("SELECT count(*) FROM {} WHERE {} = 'marker'", table, if table.ends_with("Sales") { "region" } else { "note" }), [], |row| row.get(0)).unwrap()
+        };
+        // Sales hashed the same and kept its table; Notes was rebuilt from the sheet
+        assert_eq!((markers("monthly_jan_Sales"), markers("monthly_jan_Notes")), (1, 0));
+        let hashes: Vec<String> = conn.prepare(&format!("SELECT content_hash FROM {} ORDER BY sheet", LOADED_SHEETS_TABLE)).unwrap()
+            .query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap();
+        assert!(hashes.iter().all(|hash| hash.len() == 16), "{:?}", hashes);
+        drop(conn);
+
+        // The same cells loaded with other options make another table, so Sales is rebuilt
+        Connection::open(&db).unwrap().execute_batch("INSERT INTO monthly_jan_Sales (region) VALUES ('marker')").unwrap();
+        touch(120);
+        load(&["--columns", "Sales:region"]);
+        let conn = Connection::open(&db).unwrap();
+        let (markers, columns): (i64, i64) = conn.query_row(
+            "SELECT (SELECT count(*) FROM monthly_jan_Sales WHERE region = 'marker'),
+                    (SELECT count(*) FROM information_schema.columns WHERE table_name = 'monthly_jan_Sales')",
+            [], |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!((markers, columns), (0, 1));
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn interrupted_runs_load_nothing_more_and_exit_with_their_own_code() {
+        let db = std::env::temp_dir().join(format!("interrupted_{}.duckdb", std::process::id()));
+        let _ = fs::remove_file(&db);
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/monthly_*.xlsx");
+        let args = Config::parse_from(["excel_loader_rs", "--quiet", "--path", path, "--db", db.to_str().unwrap(), "--query", "SELECT 1"]);
+        args.interrupt_flag().store(true, Ordering::Relaxed);
+        let error = run(args).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_INTERRUPTED);
+        assert!(error.to_string().starts_with("Interrupted after 0 of 2 file(s)"), "{}", error);
+
+        // The run is still logged, and the database opens cleanly with no tables half-written
+        let conn = Connection::open(&db).unwrap();
+        let tables: Vec<String> = conn.prepare("SELECT table_name FROM information_schema.tables ORDER BY table_name").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(tables, ["_load_run_files", "_load_runs"]);
+        drop(conn);
+        fs::remove_file(&db).unwrap();
+    }
+
+    #[test]
+    fn whole_numbers_past_bigint_load_exactly_as_hugeint_or_text() {
+        let en = NumberLocale::En;
+        assert_eq!(parse_locale_number("12,345,678,901,234,567,890", en), Some(LocaleNumber::Huge(12_345_678_901_234_567_890)));
+        let headers = [Data::String("account".into())];
+        let rows = column(vec![Data::String("12345678901234567890".into()), Data::String("42".into())]);
+        let rows: Vec<&[Data]> = rows.iter().map(Vec::as_slice).collect();
+        let types = |flags: &[&str]| {
+            let args = Config::parse_from([&["excel_loader_rs", "--number-locale", "en"], flags].concat());
+            infer_sheet_schema(&headers, &rows, "accounts", None, &ColumnFormats::default(), &args).unwrap().types
+        };
+        assert_eq!(types(&[]), ["VARCHAR"]);
+        assert_eq!(types(&["--bigint-overflow", "hugeint"]), ["HUGEINT"]);
+        assert_eq!(cell_value(&Data::String("12345678901234567890".into()), "VARCHAR", Some(en)), Value::Text("12345678901234567890".into()));
+
+        // Both write paths store the HUGEINT exactly, next to ordinary whole numbers
+        for mode in [InsertMode::Appender, InsertMode::Sql] {
+            let db = DuckBackend(Connection::open_in_memory().unwrap(), mode);
+            let columns = [("account".to_string(), "HUGEINT")];
+            db.create_table("accounts", &columns, None).unwrap();
+            let values = rows.iter().map(|row| cell_value(&row[0], "HUGEINT", Some(en))).collect();
+            db.insert_chunk("accounts", &columns, values).unwrap();
+            assert_eq!(stored_rows(&db, "accounts"), [["12345678901234567890"], ["42"]]);
+        }
+        // --tight-ints no longer squeezes a whole float past i64 into i64::MAX
+        assert_eq!(cell_integer(Some(&Data::Float(1e20)), None), None);
+    }
+
+    #[test]
+    fn empty_sheets_take_their_header_from_declared_columns() {
+        let mut args = Config::parse_from(["excel_loader_rs", "--include-empty-sheets"]);
+        let settings: Settings = toml::from_str(concat!(
+            "[[sheet]]\nsheet = \"Notes\"\ncolumns = [\"Note\", \"Author\"]\n\n",
+            "[[sheet]]\nsheet = \"Budget\"\ntypes = { Due = \"DATE\", Amount = \"DOUBLE\" }\n",
+        )).unwrap();
+        let notes = settings.sheet_for("book.xlsx", "notes");
+        let budget = settings.sheet_for("book.xlsx", "Budget");
+        assert_eq!(declared_columns("book_notes", notes, &args).unwrap(), ["Note", "Author"]);
+        assert_eq!(declared_columns("book_budget", budget, &args).unwrap(), ["Amount", "Due"]);
+        assert_eq!(declared_columns("book_other", None, &args), None);
+
+        args.schema_overrides = SchemaOverrides(HashMap::from([
+            ("book_other".to_string(), HashMap::from([("id".to_string(), "INTEGER")])),
+        ]));
+        let columns = declared_columns("book_other", None, &args).unwrap();
+        let range = header_range(columns);
+        assert_eq!(range.get_size(), (1, 1));
+        assert_eq!(range.rows().next().unwrap(), [Data::String("id".into())]);
+    }
+
+    #[test]
+    fn columns_flag_keeps_the_selected_columns_by_name_or_letter() {
+        // A block starting at column B: B holds id, C name, D amount
+        let mut range = Range::new((0, 1), (2, 3));
+        for (col, header) in ["id", "Name", "Amount"].into_iter().enumerate() {
+            range.set_value((0, col as u32 + 1), Data::String(header.into()));
+        }
+        for row in 1..=2 {
+            range.set_value((row, 1), Data::Float(row as f64));
+            range.set_value((row, 2), Data::String(format!("n{}", row)));
+            range.set_value((row, 3), Data::Float(row as f64 * 2.5));
+        }
+        let prepare = |selection: &str| {
+            let args = Config::parse_from(["excel_loader_rs", "--columns", selection]);
+            prepare_range(range.clone(), "Sheet1", None, &args).map(|(r, _)| r.rows().map(<[Data]>::to_vec).collect::<Vec<_>>())
+        };
+        let rows = prepare("sheet1:D,name").unwrap();
+        assert_eq!(rows[0], [Data::String("Amount".into()), Data::String("Name".into())]);
+        assert_eq!(rows[2], [Data::Float(5.0), Data::String("n2".into())]);
+        // Selections for other sheets leave this one whole
+        assert_eq!(prepare("Other:A").unwrap()[0].len(), 3);
+
+        let err = prepare("Sheet1:id,amout").unwrap_err();
+        assert_eq!(err.to_string(), "Projected column 'amout' is not in the header row (id, Name, Amount)");
+        let err = prepare("Sheet1:A").unwrap_err();
+        assert!(err.to_string().contains("nor a used column of the sheet"), "{}", err);
+        assert!(parse_sheet_columns("Sheet1").is_err());
+        assert!(parse_sheet_columns("Sheet1:id,,name").is_err());
+    }
+
+    #[test]
+    fn max_identifier_length_cuts_long_names_and_keeps_them_apart() {
+        let long = "quarterly_revenue_by_region_and_product_line_excluding_returns";
+        let fitted = fit_identifier(format!("{}_2024", long), Some(32));
+        assert_eq!(fitted.chars().count(), 32);
+        assert!(fitted.starts_with("quarterly_revenue_by_re_"), "{}", fitted);
+        assert_eq!(fitted, fit_identifier(format!("{}_2024", long), Some(32)));
+        assert_ne!(fitted, fit_identifier(format!("{}_2025", long), Some(32)));
+        assert_eq!(fit_identifier("short".to_string(), Some(32)), "short");
+        assert_eq!(fit_identifier(long.to_string(), None), long);
+
+        // Headers that only differ past the limit, and a repeated one, still get distinct names
+        let headers: Vec<Data> = [format!("{}_2024", long), format!("{}_2025", long), format!("{}_2024", long)]
+            .into_iter().map(Data::String).collect();
+        let names = unique_column_names(&headers, HeaderStyle::AsIs, IdentifierCase::Upper, false, Some(32));
+        assert!(names.iter().all(|name| name.chars().count() <= 32 && name == &name.to_uppercase()), "{:?}", names);
+        assert_eq!(names.iter().collect::<HashSet<_>>().len(), 3, "{:?}", names);
+
+        let args = Config::parse_from(["excel_loader_rs", "--max-identifier-length", "20"]);
+        let sheets = ["Monthly Summary Report".to_string()];
+        let (table, _) = sheet_tables("regional_sales_2024", &sheets[0], &sheets, &args);
+        assert_eq!(table.chars().count(), 20);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--max-identifier-length", "8"]).is_err());
+    }
+
+    #[test]
+    fn export_all_copies_every_data_table_to_its_own_file() {
+        let dir = std::env::temp_dir().join(format!("export_all_{}", std::process::id()));
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE orders (id INTEGER, amount DOUBLE); INSERT INTO orders VALUES (1, 2.5), (2, 4.0);
+             CREATE TABLE customers (name VARCHAR); INSERT INTO customers VALUES ('Ann');
+             CREATE TABLE _load_runs (run_id INTEGER); INSERT INTO _load_runs VALUES (1);",
+        ).unwrap();
+        let export = |flags: &[&str]| {
+            let args = Config::parse_from([&["excel_loader_rs", "--export-all", dir.to_str().unwrap()], flags].concat());
+            export_all_tables(&conn, dir.to_str().unwrap(), &args).unwrap()
+        };
+
+        let exports = export(&["--export-jobs", "2"]);
+        let summary: Vec<(&str, usize)> = exports.iter().map(|e| (e.table.as_str(), e.rows)).collect();
+        assert_eq!(summary, [("customers", 1), ("orders", 2)]);
+        assert_eq!(fs::read_to_string(dir.join("orders.csv")).unwrap(), "id,amount\n1,2.5\n2,4.0\n");
+        assert_eq!(exports[1].bytes, fs::metadata(dir.join("orders.csv")).unwrap().len());
+        assert!(!dir.join("_load_runs.csv").exists());
+
+        let exports = export(&["--export-format", "parquet", "--export-internal"]);
+        assert_eq!(exports.iter().map(|e| e.table.as_str()).collect::<Vec<_>>(), ["_load_runs", "customers", "orders"]);
+        let rows: i64 = conn.query_row(&format!("SELECT count(*) FROM '{}'", dir.join("orders.parquet").display()), [], |row| row.get(0)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rows, 2);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--export-format", "csv"]).is_err());
+    }
+
+    #[test]
+    fn header_style_gives_every_source_the_same_column_names() {
+        let headers = header_row(&["First Name", "firstName", "HTTPServer", "Q1Sales", "2024 total", "address2", "Ünit_Price"]);
+        let snake = unique_column_names(&headers, HeaderStyle::SnakeCase, IdentifierCase::Preserve, false, None);
+        assert_eq!(snake, ["first_name", "first_name_2", "http_server", "q1_sales", "_2024_total", "address2", "ünit_price"]);
+        let camel = unique_column_names(&headers, HeaderStyle::CamelCase, IdentifierCase::Preserve, false, None);
+        assert_eq!(camel, ["firstName", "firstName_2", "httpServer", "q1Sales", "_2024Total", "address2", "ünitPrice"]);
+        let as_is = unique_column_names(&headers[..2], HeaderStyle::AsIs, IdentifierCase::Preserve, false, None);
+        assert_eq!(as_is, ["First_Name", "firstName"]);
+        let args = Config::parse_from(["excel_loader_rs", "--normalize-headers", "snake_case"]);
+        assert_eq!(args.header_style, HeaderStyle::SnakeCase);
+    }
+
+    #[test]
+    fn config_file_options_sit_between_the_command_line_and_the_defaults() {
+        let dir = std::env::temp_dir().join(format!("config_options_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("loader.toml");
+        fs::write(&path, concat!(
+            "db = \"team.duckdb\"\nrefresh = true\nchunk-size = 250\nsheets = [\"Data\", \"Summary\"]\nnull_values = [\"N/A\"]\n",
+            "\n[[sheet]]\nsheet = \"Data\"\nheader_row = 2\n",
+        )).unwrap();
+        fs::write(dir.join("bad.toml"), "refesh = true\n").unwrap();
+        let parse = |args: &[&str]| Config::try_parse_with_config([&["excel_loader_rs"], args].concat());
+        let config = path.to_str().unwrap();
+
+        let args = parse(&["--config", config]).unwrap();
+        assert_eq!((args.db.as_str(), args.refresh, args.chunk_size), ("team.duckdb", true, 250));
+        assert_eq!(args.sheets, ["Data", "Summary"]);
+        assert_eq!(args.null_values, ["N/A"]);
+        // The command line wins, option by option, and options neither sets keep their defaults
+        let args = parse(&["--config", config, "--db", "mine.duckdb", "--sheets", "Totals", "--chunk-size", "10"]).unwrap();
+        assert_eq!((args.db.as_str(), args.refresh, args.chunk_size), ("mine.duckdb", true, 10));
+        assert_eq!(args.sheets, ["Totals"]);
+        assert_eq!(args.path, "test_data");
+        assert_eq!(parse(&[]).unwrap().db, "rust_speedup.duckdb");
+
+        // A flag given on the command line also wins over a file option it conflicts with, whichever side declares the conflict
+        fs::write(dir.join("skip.toml"), "skip_rows = 3\n").unwrap();
+        let args = parse(&["--config", dir.join("skip.toml").to_str().unwrap(), "--split-on-blank"]).unwrap();
+        assert_eq!((args.split_on_blank, args.skip_rows), (true, 0));
+        fs::write(dir.join("split.toml"), "split-on-blank = true\n").unwrap();
+        let args = parse(&["--config", dir.join("split.toml").to_str().unwrap(), "--skip-rows", "2"]).unwrap();
+        assert_eq!((args.split_on_blank, args.skip_rows), (false, 2));
+
+        // The parsed file comes along, so running doesn't read it again
+        let mut args = parse(&["--config", config]).unwrap();
+        fs::remove_file(&path).unwrap();
+        args.read_option_files().unwrap();
+        assert_eq!(args.settings.sheet[0].header_row, Some(2));
+
+        let bad = dir.join("bad.toml");
+        let err = parse(&["--config", bad.to_str().unwrap()]).unwrap_err();
+        assert!(err.to_string().contains("sets 'refesh', which is not an option"), "{}", err);
+        // A Config parsed without the file's options refuses them rather than ignoring them
+        let mut args = Config::parse_from(["excel_loader_rs", "--config", bad.to_str().unwrap()]);
+        let err = args.read_option_files().unwrap_err();
+        assert!(err.to_string().contains("sets 'refesh', which is not an option"), "{}", err);
+        let mut args = Config::parse_from(["excel_loader_rs", "--config", dir.join("skip.toml").to_str().unwrap()]);
+        let err = args.read_option_files().unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(err.to_string().contains("only applied by Config::try_parse_with_config"), "{}", err);
+    }
+
+    #[test]
+    fn profile_skips_ranges_that_mean_nothing_and_copes_with_every_type() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE t (n INTEGER, d DATE, s VARCHAR, b BOOLEAN, l INTEGER[], r STRUCT(x INTEGER), blob BLOB, i INTERVAL);
+             INSERT INTO t VALUES (3, DATE '2024-01-02', 'x', true, [1, 2], {'x': 1}, '\\xAA'::BLOB, INTERVAL 1 DAY),
+                                  (NULL, DATE '2023-05-06', 'y', false, NULL, NULL, NULL, NULL), (7, NULL, 'x', true, [1], {'x': 2}, NULL, NULL);",
+        ).unwrap();
+        let n = ColumnProfile::scan(&conn, "t", "n".to_string(), "INTEGER".to_string(), true, 0).unwrap();
+        assert_eq!((n.nulls, n.distinct, n.min.as_deref(), n.max.as_deref()), (1, 2, Some("3"), Some("7")));
+        let s = ColumnProfile::scan(&conn, "t", "s".to_string(), "VARCHAR".to_string(), false, 3).unwrap();
+        assert_eq!((s.nulls, s.distinct, s.min, s.samples.len()), (0, 2, None, 2));
+
+        let ranged = ["INTEGER", "DECIMAL(18,3)", "DOUBLE", "DATE", "TIMESTAMP WITH TIME ZONE", "TIME", "HUGEINT"];
+        assert!(ranged.iter().all(|data_type| has_meaningful_range(data_type)));
+        let unranged = ["VARCHAR", "BOOLEAN", "BLOB", "INTEGER[]", "STRUCT(x INTEGER)", "INTERVAL", "MAP(VARCHAR, INTEGER)"];
+        assert!(!unranged.iter().any(|data_type| has_meaningful_range(data_type)));
+        let mut out = Vec::new();
+        print_profile(&conn, "t", &mut out).unwrap();
+        let profile = String::from_utf8(out).unwrap();
+        assert!(profile.starts_with("t (3 rows)\n"), "{}", profile);
+        let rows = table_cells(&profile);
+        assert_eq!(rows[0], ["column", "type", "values", "nulls", "distinct", "min", "max"]);
+        assert_eq!(rows[1], ["n", "INTEGER", "2", "1", "2", "3", "7"]);
+        assert_eq!(rows[2], ["d", "DATE", "2", "1", "2", "2023-05-06", "2024-01-02"]);
+        // No range for text, nested values or blobs
+        assert_eq!(rows[3], ["s", "VARCHAR", "3", "0", "2", "", ""]);
+        assert_eq!(rows[7], ["blob", "BLOB", "1", "2", "1", "", ""]);
+        assert_eq!(rows.len(), 9);
+    }
+
+    #[test]
+    fn find_header_locates_a_table_below_a_title_and_right_of_blank_columns() {
+        // A title in A1, a note in A3, and the table itself at D5:F7 with a stray total in H7
+        let mut range = Range::new((0, 0), (6, 7));
+        range.set_value((0, 0), Data::String("Quarterly report".into()));
+        range.set_value((2, 0), Data::String("Prepared by finance".into()));
+        range.set_value((2, 1), Data::String("   ".into()));
+        for (col, header) in ["Region", "Units", "Revenue"].into_iter().enumerate() {
+            range.set_value((4, 3 + col as u32), Data::String(header.into()));
+        }
+        for row in 5..=6 {
+            range.set_value((row, 3), Data::String(format!("r{}", row)));
+            range.set_value((row, 4), Data::Float(row as f64));
+            range.set_value((row, 5), Data::Float(row as f64 * 10.0));
+        }
+        range.set_value((6, 7), Data::Float(999.0));
+
+        let args = Config::parse_from(["excel_loader_rs", "--find-header"]);
+        let (found, _) = prepare_range(range.clone(), "Sheet1", None, &args).unwrap();
+        let rows: Vec<&[Data]> = found.rows().collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], [Data::String("Region".into()), Data::String("Units".into()), Data::String("Revenue".into())]);
+        assert_eq!(rows[2], [Data::String("r6".into()), Data::Float(6.0), Data::Float(60.0)]);
+        assert_eq!(found.start(), Some((4, 3)));
+
+        // Without the option the title row is the header
+        let (whole, _) = prepare_range(range.clone(), "Sheet1", None, &Config::parse_from(["excel_loader_rs"])).unwrap();
+        assert_eq!(whole.get_size(), (7, 8));
+
+        // A header with a gap still beats the title, and keeps the column under the gap and those past it
+        let mut gappy = range;
+        for col in 3..=5 {
+            gappy.set_value((4, col), Data::Empty);
+        }
+        gappy.set_value((4, 3), Data::String("Region".into()));
+        gappy.set_value((4, 5), Data::String("Revenue".into()));
+        gappy.set_value((4, 6), Data::String("Notes".into()));
+        let (found, _) = prepare_range(gappy, "Sheet1", None, &args).unwrap();
+        assert_eq!(found.start(), Some((4, 3)));
+        let header: Vec<String> = found.rows().next().unwrap().iter().map(|cell| cell.to_string()).collect();
+        assert_eq!(header, ["Region", "", "Revenue", "Notes"]);
+        assert_eq!(found.rows().nth(2).unwrap()[..3], [Data::String("r6".into()), Data::Float(6.0), Data::Float(60.0)]);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--find-header", "--skip-rows", "2"]).is_err());
+    }
+
+    #[test]
+    fn row_number_column_gives_each_row_its_sheet_row() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/accounts.xlsx"));
+        // The header is row 1; skipping two rows without a header starts at row 3
+        let with_header: (&[&str], &[i32]) = (&["--row-number-column", "excel row"], &[2, 3, 4]);
+        let skipping_two_rows: (&[&str], &[i32]) = (&["--row-number-column", "excel_row", "--no-header", "--skip-rows", "2"], &[3, 4]);
+        for (flags, expected) in [with_header, skipping_two_rows] {
+            let numbers: Vec<i32> = load_fixture(&path, "accounts", flags)
+                .prepare("SELECT excel_row FROM accounts_Data ORDER BY excel_row").unwrap()
+                .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+            assert_eq!(numbers, expected, "{:?}", flags);
+        }
+        assert!(Config::try_parse_from(["excel_loader_rs", "--row-number-column", "n", "--load-rows", "5", "--load-random"]).is_err());
+    }
+
+    #[test]
+    fn mislabeled_files_open_with_the_reader_their_contents_need() {
+        let dir = std::env::temp_dir().join(format!("mislabeled_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let data = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let args = Config::parse_from(["excel_loader_rs"]);
+        // Each real workbook under a name that claims another format
+        for (source, sheet, mislabeled) in [
+            ("legacy.xls", "Legacy", "legacy.xlsx"),
+            ("users.xlsx", "Sheet1", "users.xls"),
+            ("ledger.xlsb", "Ledger", "ledger.xlsx"),
+            ("inventory.ods", "Stock", "inventory.xls"),
+        ] {
+            let path = dir.join(mislabeled);
+            fs::copy(data.join(source), &path).unwrap();
+            let mut workbook = open_spreadsheet(&path, &args).unwrap_or_else(|e| panic!("{}: {:#}", mislabeled, e));
+            assert!(workbook.sheet_names().contains(&sheet.to_string()), "{}: {:?}", mislabeled, workbook.sheet_names());
+            assert!(workbook.worksheet_range(sheet).is_ok(), "{}", mislabeled);
+        }
+
+        // Files that are no workbook at all say what they are
+        for (name, contents, what) in [
+            ("export.xlsx", &b"id,name\n1,Ann\n"[..], "plain text, such as a CSV file"),
+            ("report.xls", b"\xEF\xBB\xBF <html><table><tr><td>1</td></tr></table></html>", "an HTML or XML file"),
+            ("blank.xlsx", b"", "an empty file"),
+        ] {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            let err = open_spreadsheet(&path, &args).err().unwrap_or_else(|| panic!("{} opened", name));
+            let ext = name.rsplit('.').next().unwrap();
+            assert!(err.to_string().ends_with(&format!("claims to be .{} but is actually {}; only xlsx, xlsm, xlsb, xls and ods workbooks (and JSON lines files) can be loaded", ext, what)), "{}", err);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(sniff_container(&mut Cursor::new(&b"PK\x03\x04"[..])).unwrap(), Container::Workbook("xlsx"));
+    }
+
+    #[test]
+    fn type_summary_counts_types_and_mixed_fallbacks() {
+        let rows = [vec![Data::Float(1.0), Data::String("a".into()), Data::Int(3)], vec![Data::String("n/a".into()), Data::String("b".into()), Data::Empty]];
+        let sample: Vec<&[Data]> = rows.iter().map(Vec::as_slice).collect();
+        assert!(falls_back_to_text(&sample, 0, None));
+        assert!(!falls_back_to_text(&sample, 1, None));
+        assert!(!falls_back_to_text(&sample, 2, None));
+
+        let sheet = |table: &str, columns: &[(&str, &str)], mixed: &[&str]| LoadedSheet {
+            sheet: "Data".to_string(),
+            table: table.to_string(),
+            rows: 2,
+            columns: columns.iter().map(|(name, dtype)| (name.to_string(), dtype.to_string())).collect(),
+            source_size: (3, columns.len()),
+            expected_rows: 2,
+            content_hash: None,
+            mixed_columns: mixed.iter().map(|c| c.to_string()).collect(),
+        };
+        let outcomes = [FileOutcome {
+            file: "sales.xlsx".to_string(), tables: 2, rows: 4, duration: Duration::ZERO, error: None,
+            sheets: vec![
+                sheet("sales_Data", &[("id", "BIGINT"), ("amount", "VARCHAR"), ("note", "VARCHAR")], &["amount"]),
+                sheet("sales_Rates", &[("rate", "DOUBLE"), ("code", "VARCHAR")], &[]),
+            ],
+        }];
+        let summary = type_summary(&outcomes);
+        assert!(summary.starts_with("Column types across 2 table(s):"));
+        let varchar = summary.find("VARCHAR ┆ 3").unwrap();
+        assert!(varchar < summary.find("BIGINT  ┆ 1").unwrap());
+        assert!(summary.find("BIGINT  ┆ 1").unwrap() < summary.find("DOUBLE  ┆ 1").unwrap());
+        assert!(summary.ends_with("Fell back to VARCHAR due to mixed types: 1 (sales_Data.amount)"));
+    }
+
+    #[test]
+    fn protected_but_readable_workbooks_load() {
+        // users.xlsx with a modify password and a locked structure added, as Excel writes them
+        let source = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/users.xlsx"));
+        let mut original = zip::ZipArchive::new(fs::File::open(&source).unwrap()).unwrap();
+        // openpyxl writes an empty <workbookProtection/>, which protects nothing
+        assert!(workbook_protection(&mut original).unwrap().is_empty());
+        let mut protected = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for i in 0..original.len() {
+            let mut part = original.by_index(i).unwrap();
+            let name = part.name().to_string();
+            let mut xml = String::new();
+            part.read_to_string(&mut xml).unwrap();
+            if name == "xl/workbook.xml" {
+                xml = xml.replacen("<bookViews>", r#"<fileSharing userName="ops" hashValue="abc=" saltValue="def=" spinCount="100000"/><workbookProtection lockStructure="1"/><bookViews>"#, 1);
+            }
+            protected.start_file(name, zip::write::FileOptions::default()).unwrap();
+            std::io::Write::write_all(&mut protected, xml.as_bytes()).unwrap();
+        }
+        let data = protected.finish().unwrap().into_inner();
+        assert_eq!(workbook_protection(&mut zip::ZipArchive::new(Cursor::new(&data)).unwrap()).unwrap(), ["modify password", "locked structure"]);
+
+        let dir = std::env::temp_dir().join(format!("protected_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("users.xlsx");
+        fs::write(&path, &data).unwrap();
+        let args = Config::parse_from(["excel_loader_rs", "--path", dir.to_str().unwrap(), "--db", ":memory:"]);
+        let sheets = open_spreadsheet(&path, &args).unwrap().sheet_names();
+        assert_eq!(sheets, ["Sheet1"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn max_files_takes_the_first_files_in_order() {
+        let dir = std::env::temp_dir().join(format!("max_files_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let start = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        // Sizes and times run against the names, so each order picks differently
+        for (i, name) in ["a.xlsx", "b.xlsx", "c.xlsx", "d.xlsx"].iter().enumerate() {
+            let file = fs::File::create(dir.join(name)).unwrap();
+            file.set_len(40 - i as u64 * 10).unwrap();
+            file.set_modified(start + Duration::from_secs(if i == 0 { 50 } else { 40 - i as u64 * 10 })).unwrap();
+        }
+        let names = |order, max| -> Vec<String> {
+            let files = collect_excel_files(&dir, false).unwrap();
+            first_files(files, order, max).unwrap().iter().map(|f| f.file_name().unwrap().to_string_lossy().into_owned()).collect()
+        };
+        assert_eq!(names(FileOrder::Name, 2), ["a.xlsx", "b.xlsx"]);
+        assert_eq!(names(FileOrder::Size, 2), ["d.xlsx", "c.xlsx"]);
+        assert_eq!(names(FileOrder::Mtime, 3), ["d.xlsx", "c.xlsx", "b.xlsx"]);
+        assert_eq!(names(FileOrder::Name, 10).len(), 4);
+
+        assert_eq!(Config::parse_from(["excel_loader_rs", "--max-files", "5"]).order, FileOrder::Name);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--order", "size"]).is_err());
+        assert!(Config::try_parse_from(["excel_loader_rs", "--max-files", "0"]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn money_columns_sum_exactly_to_the_cent() {
+        assert_eq!(decimal_text(1.005, 2), "1.01");
+        assert_eq!(decimal_text(0.1 + 0.2, 2), "0.30");
+        assert_eq!(decimal_text(-2.675, 2), "-2.68");
+        assert_eq!(decimal_text(9.995, 2), "10.00");
+        assert_eq!(decimal_text(-0.001, 2), "0.00");
+        assert_eq!(decimal_text(1234.5, 0), "1235");
+        assert_eq!(decimal_text(1e-7, 3), "0.000");
+
+        let args = Config::parse_from(["excel_loader_rs", "--money-columns", "Amount", "--money-scale", "2"]);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--money-scale", "2"]).is_err());
+        let mut amounts = vec![0.1; 10];
+        amounts.extend([0.2; 10]);
+        amounts.extend([19.99, 19.99, 19.99, 1.005, 2.675]);
+        let rows: Vec<Vec<Data>> = amounts.iter().map(|v| vec![Data::String("x".into()), Data::Float(*v)]).collect();
+        let rows_data: Vec<&[Data]> = rows.iter().map(Vec::as_slice).collect();
+        let schema = infer_sheet_schema(&header_row(&["item", "amount"]), &rows_data, "ledger", None, &ColumnFormats::default(), &args).unwrap();
+        assert_eq!(schema.types, ["VARCHAR", "DECIMAL(18,2)"]);
+
+        let db = DuckBackend(Connection::open_in_memory().unwrap(), InsertMode::Appender);
+        let columns: Vec<(String, &str)> = schema.names.iter().cloned().zip(schema.types.iter().copied()).collect();
+        db.create_table("ledger", &columns, None).unwrap();
+        let values = rows.iter().flatten().zip(schema.types.iter().cycle()).map(|(cell, dtype)| cell_value(cell, dtype, None)).collect();
+        db.insert_chunk("ledger", &columns, values).unwrap();
+        let total: String = db.0.query_row("SELECT sum(amount)::VARCHAR FROM ledger", [], |row| row.get(0)).unwrap();
+        assert_eq!(total, "66.66");
+    }
+
+    #[test]
+    fn progress_bars_hide_when_asked_and_draw_when_forced() {
+        let target = |flags: &[&str]| progress_target(&Config::parse_from([&["excel_loader_rs"], flags].concat()));
+        assert!(target(&["--quiet"]).is_hidden());
+        assert!(target(&["--no-progress"]).is_hidden());
+        assert!(!target(&["--force-progress"]).is_hidden());
+        assert!(Config::try_parse_from(["excel_loader_rs", "--force-progress", "--no-progress"]).is_err());
+        assert!(Config::try_parse_from(["excel_loader_rs", "--force-progress", "--quiet"]).is_err());
+    }
+
+    #[test]
+    fn motherduck_and_remote_db_targets() {
+        assert_eq!(motherduck_database("md:sales"), Some("sales"));
+        assert_eq!(motherduck_database("MotherDuck:sales?motherduck_token=abc"), Some("sales"));
+        assert_eq!(motherduck_database("md:"), Some(""));
+        assert_eq!(motherduck_database("mdata.duckdb"), None);
+        assert_eq!(motherduck_database("C:\\data\\out.duckdb"), None);
+
+        assert!(has_motherduck_token("md:sales?motherduck_token=abc", None));
+        assert!(has_motherduck_token("md:sales", Some("abc")));
+        assert!(!has_motherduck_token("md:sales?motherduck_token=", Some("")));
+        assert_eq!(redacted_db("md:sales?saas_mode=true&motherduck_token=abc"), "md:sales?saas_mode=true&motherduck_token=***");
+
+        let err = open_duckdb("md:?motherduck_token=abc").err().unwrap();
+        assert_eq!(err.to_string(), "--db md:?motherduck_token=*** names no MotherDuck database; use md:<database>, such as md:my_db");
+        let err = open_duckdb("https://example.com/warehouse.duckdb").err().unwrap();
+        assert!(err.to_string().starts_with("--db https://example.com/warehouse.duckdb is a remote https file"), "{}", err);
+    }
+
+    #[test]
+    fn a_second_run_on_the_same_database_waits_or_stops() {
+        let db = std::env::temp_dir().join(format!("run_lock_{}.duckdb", std::process::id()));
+        let db = db.to_str().unwrap();
+        let first = acquire_run_lock(db, Duration::ZERO, || false).unwrap().expect("a file database is locked");
+        let holder = fs::read_to_string(format!("{}.lock", db)).unwrap();
+        assert!(holder.starts_with(&format!("pid {} since ", std::process::id())), "{}", holder);
+
+        let err = acquire_run_lock(db, Duration::ZERO, || false).err().unwrap();
+        assert_eq!(err.to_string(), format!("Another load is in progress on {} ({}); wait for it with --lock-wait <seconds>, or run again once it finishes", db, holder));
+        let started = Instant::now();
+        let err = acquire_run_lock(db, Duration::from_millis(300), || false).err().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert!(err.to_string().ends_with("gave up after waiting 300ms (--lock-wait)"), "{}", err);
+
+        drop(first);
+        assert!(acquire_run_lock(db, Duration::ZERO, || false).unwrap().is_some());
+        assert!(acquire_run_lock(MEMORY_DB, Duration::ZERO, || false).unwrap().is_none());
+        fs::remove_file(format!("{}.lock", db)).unwrap();
+    }
+
+    #[test]
+    fn format_map_types_columns_by_number_format() {
+        assert_eq!(format_class(r##""$"#,##0.00"##), Some(FormatClass::Currency));
+        assert_eq!(format_class("[$€-407] #,##0"), Some(FormatClass::Currency));
+        assert_eq!(format_class("h:mm AM/PM"), Some(FormatClass::Time));
+        assert_eq!(format_class("m/d/yy h:mm"), Some(FormatClass::Datetime));
+        assert_eq!(format_class("mmm-yy"), Some(FormatClass::Date));
+        assert_eq!(format_class("00000"), None);
+        // Locale tags name no symbol, so these stay dates and times
+        assert_eq!(format_class("[$-409]m/d/yy"), Some(FormatClass::Date));
+        assert_eq!(format_class("[$-409]m/d/yy h:mm AM/PM"), Some(FormatClass::Datetime));
+        assert_eq!(format_class("[$-F800]dddd, mmmm dd, yyyy"), Some(FormatClass::Date));
+        assert_eq!(format_class(r"\$0.00"), Some(FormatClass::Currency));
+
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/formats.xlsx"));
+        let conn = load_fixture(&path, "formats", &["--format-map", "currency=DECIMAL(18,2)", "--format-map", "date=TIMESTAMP", "--format-map", "00000=INTEGER"]);
+        let mut stmt = conn
+            .prepare("SELECT data_type FROM information_schema.columns WHERE table_name = 'formats_Data' ORDER BY ordinal_position")
+            .unwrap();
+        let types: Vec<String> = stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        // The percent column has no rule, so it keeps its inferred type
+        assert_eq!(types, ["DOUBLE", "DECIMAL(18,2)", "DOUBLE", "TIMESTAMP", "INTEGER", "VARCHAR"]);
+        let total: String = conn.query_row("SELECT SUM(price)::VARCHAR FROM formats_Data", [], |row| row.get(0)).unwrap();
+        assert_eq!(total, "25.10");
+    }
+
+    #[test]
+    fn split_on_blank_returns_each_stacked_table() {
+        // Orders at A1:B3, a blank row, customers at A5:C6, then the customers header repeated at A7
+        let mut range = Range::new((0, 0), (8, 2));
+        let text = |s: &str| Data::String(s.into());
+        for (row, cells) in [
+            (0, vec![text("order"), text("total")]),
+            (1, vec![text("o1"), Data::Float(5.0)]),
+            (2, vec![text("o2"), Data::Float(7.5)]),
+            (4, vec![text("name"), text("city"), text("since")]),
+            (5, vec![text("Ann"), text("Oslo"), Data::Int(2019)]),
+            (6, vec![text("name"), text("city"), text("since")]),
+            (7, vec![text("Bo"), text("Rome"), Data::Int(2021)]),
+            (8, vec![text("Cy"), text("Lima"), Data::Int(2022)]),
+        ] {
+            for (col, cell) in cells.into_iter().enumerate() {
+                range.set_value((row, col as u32), cell);
+            }
+        }
+        let args = Config::parse_from(["excel_loader_rs", "--split-on-blank"]);
+        let parts = sheet_parts(range.clone(), &args);
+        let blocks: Vec<_> = parts.iter().map(|(part, block)| (*part, block.start(), block.get_size())).collect();
+        assert_eq!(blocks, [(Some(1), Some((0, 0)), (3, 2)), (Some(2), Some((4, 0)), (2, 3)), (Some(3), Some((6, 0)), (3, 3))]);
+        let tables = part_tables(("book_Sheet1".to_string(), "book_Sheet1".to_string()), Some(2), &args);
+        assert_eq!(tables, ("book_Sheet1_2".to_string(), "book_Sheet1_2".to_string()));
+
+        // A single table, or a run without the option, comes back untouched
+        let single = range.range((0, 0), (3, 2));
+        assert_eq!(sheet_parts(single.clone(), &args).len(), 1);
+        assert_eq!(sheet_parts(single, &args)[0].0, None);
+        let whole = sheet_parts(range.clone(), &Config::parse_from(["excel_loader_rs"]));
+        // Options that pick the header from the whole sheet don't fit blocks split from it
+        for flags in [&["--find-header"][..], &["--skip-rows", "2"], &["--header-row", "1"]] {
+            assert!(Config::try_parse_from([&["excel_loader_rs", "--split-on-blank"], flags].concat()).is_err(), "{:?}", flags);
+        }
+        let columns = Config::parse_from(["excel_loader_rs", "--split-on-blank", "--orientation", "columns"]);
+        assert!(format!("{:#}", columns.validate().unwrap_err()).contains("--orientation columns"));
+        let mut sectioned = Config::parse_from(["excel_loader_rs", "--split-on-blank"]);
+        sectioned.settings = toml::from_str("[[sheet]]\nheader_row = 3\n").unwrap();
+        assert!(format!("{:#}", sectioned.validate().unwrap_err()).contains("header_row"));
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0].1.get_size(), range.get_size());
+    }
+
+    #[test]
+    fn compare_reports_new_changed_and_leftover_tables() {
+        let shape = |name: &str, columns: &[(&str, &str)], rows| TableShape {
+            name: name.to_string(),
+            columns: columns.iter().map(|(name, dtype)| (name.to_string(), dtype.to_string())).collect(),
+            rows,
+        };
+        let loaded = [
+            shape("orders", &[("id", "INTEGER"), ("total", "DOUBLE"), ("note", "VARCHAR")], 12),
+            shape("Users", &[("id", "INTEGER")], 3),
+            shape("events", &[("at", "TIMESTAMP")], 5),
+        ];
+        let existing = [
+            shape("orders", &[("id", "INTEGER"), ("total", "VARCHAR"), ("fax", "VARCHAR")], 10),
+            shape("users", &[("id", "INTEGER")], 3),
+            shape("old_report", &[("x", "INTEGER")], 1),
+        ];
+        let (lines, summary) = compare_tables(&loaded, &existing, false);
+        assert_eq!(lines, [
+            "+ events: new table (5 row(s), 1 column(s))",
+            "- old_report: not produced by this load (1 row(s)); --refresh-all would drop it",
+            "~ orders: 10 -> 12 rows; total VARCHAR -> DOUBLE; added note VARCHAR; dropped fax VARCHAR",
+            "= Users: unchanged (3 row(s))",
+        ]);
+        assert_eq!(summary, "1 new, 1 changed, 1 unchanged, 1 only in the database");
+
+        // Appended rows add to the ones already there
+        let (lines, _) = compare_tables(&loaded[1..2], &existing[1..2], true);
+        assert_eq!(lines, ["~ Users: 3 -> 6 rows"]);
+    }
+
+    #[test]
+    fn export_flags_build_a_checked_copy_clause() {
+        let options = |path: &str, flags: &[&str]| {
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            export_copy_options(path, &CsvOutput::from_args(&args), &args).map_err(|e| e.to_string())
+        };
+        assert_eq!(options("out.csv.gz", &[]).unwrap(), (ExportFormat::Csv, "(HEADER true, DELIMITER ',', COMPRESSION 'gzip')".to_string()));
+        assert_eq!(options("out.parquet", &["--export-compression", "none"]).unwrap().1, "(FORMAT PARQUET, COMPRESSION 'uncompressed')");
+        let dir = std::env::temp_dir().join(format!("partitioned_{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        assert_eq!(
+            options(dir, &["--export-partition-by", "region,year", "--export-format", "parquet"]).unwrap(),
+            (ExportFormat::Parquet, r#"(FORMAT PARQUET, PARTITION_BY ("region", "year"))"#.to_string())
+        );
+
+        // Combinations DuckDB can't honour fail before the COPY runs
+        assert!(options("out.csv", &["--export-partition-by", "region"]).unwrap_err().contains("must name a folder"));
+        assert!(options("out.csv.gz", &["--export-compression", "zstd"]).is_err());
+        assert!(options("out.parquet.gz", &[]).is_err());
+        assert!(options("out.csv", &["--export-compression", "gzip", "--csv-crlf"]).is_err());
+        assert!(Config::try_parse_from(["excel_loader_rs", "--export-overwrite"]).is_err());
+        assert!(Config::try_parse_from(["excel_loader_rs", "--export-format", "json"]).is_err());
+    }
+
+    #[test]
+    fn decimal_types_are_interned_once() {
+        let first = parse_column_type("decimal( 12 , 3 )").unwrap();
+        let again = parse_column_type("DECIMAL(12,3)").unwrap();
+        assert_eq!(first, "DECIMAL(12,3)");
+        assert!(std::ptr::eq(first, again));
+        assert_eq!(parse_column_type("varchar"), Some("VARCHAR"));
+        assert_eq!(parse_column_type("DECIMAL(3,4)"), None);
+    }
+
+    #[test]
+    fn export_suffix_only_matches_a_trailing_path() {
+        assert_eq!(split_export_target("SELECT * FROM t >> out.csv"), ("SELECT * FROM t", Some("out.csv")));
+        assert_eq!(split_export_target("SELECT * FROM t >> exports/sales ;"), ("SELECT * FROM t", Some("exports/sales")));
+        for sql in ["SELECT a >> 2 FROM t", "SELECT '>>' AS x", "SELECT 'a >> b.csv'", "SELECT doc->>'plan' FROM t",
+                    "SELECT 8 >> 1.5", "SELECT x >> y FROM t"] {
+            assert_eq!(split_export_target(sql), (sql, None), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn watch_appends_the_lines_of_json_lines_files_that_change() {
+        let dir = std::env::temp_dir().join(format!("watch_jsonl_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("in")).unwrap();
+        let events = dir.join("in/events.jsonl");
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/events.jsonl"), &events).unwrap();
+        let db = dir.join("out.duckdb");
+        let args = Config::parse_from([
+            "excel_loader_rs", "--quiet", "--watch", "--watch-interval", "1", "--path", dir.join("in").to_str().unwrap(), "--db", db.to_str().unwrap(),
+        ]);
+        let stop = args.interrupt_flag();
+        let watcher = std::thread::spawn(move || run(args));
+
+        // Saved again with a fourth line once the first load is done
+        std::thread::sleep(Duration::from_millis(1500));
+        let mut more = fs::read_to_string(&events).unwrap();
+        more.push_str("{\"id\": 4, \"event\": \"churn\", \"at\": \"2024-03-09 08:00:00\", \"user\": {\"name\": \"Ana\", \"plan\": \"pro\"}, \"tags\": []}\n");
+        fs::write(&events, more).unwrap();
+        std::thread::sleep(Duration::from_millis(3500));
+        stop.store(true, Ordering::Relaxed);
+        watcher.join().unwrap().unwrap();
+
+        let conn = Connection::open(&db).unwrap();
+        let (rows, churned): (i64, i64) = conn.query_row(
+            "SELECT count(*), count(*) FILTER (WHERE event = 'churn') FROM events", [], |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        // The three first lines load once at the start and again with the fourth
+        assert_eq!((rows, churned), (7, 1));
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rows_the_database_refuses_are_set_aside_one_by_one() {
+        // 99.995 passes the range check but rounds to 100.00, which DECIMAL(4,2) can't hold
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/refused_rows.xlsx"));
+        let config = std::env::temp_dir().join(format!("refused_rows_{}.toml", std::process::id()));
+        fs::write(&config, "[[sheet]]\ntypes = { amount = \"DECIMAL(4,2)\" }\n").unwrap();
+        for (mode, chunk_size) in [(InsertMode::Appender, "1000"), (InsertMode::Sql, "2")] {
+            let mut args = Config::parse_from([
+                "excel_loader_rs", "--config", config.to_str().unwrap(), "--on-bad-row", "reject-file", "--chunk-size", chunk_size,
+            ]);
+            args.read_option_files().unwrap();
+            let (conn, loaded) = load_fixture_with(&path, "refused_rows", &args, mode).unwrap();
+            assert_eq!(loaded[0].rows, 3, "{:?}", mode);
+            let rejected: Vec<(usize, String)> = args.rejected_rows.into_inner().unwrap().into_iter().map(|r| (r.row, r.error)).collect();
+            assert_eq!(rejected, [
+                (4, "Conversion Error: Could not convert string \"100.00\" to DECIMAL(4,2)".to_string()),
+                (6, "value 'oops, late' in column amount cannot be loaded as DECIMAL(4,2)".to_string()),
+            ], "{:?}", mode);
+            let ids: String = conn
+                .query_row("SELECT string_agg(id::BIGINT::VARCHAR, ',' ORDER BY id) FROM refused_rows_Data", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(ids, "1,2,4", "{:?}", mode);
+        }
+        fs::remove_file(&config).unwrap();
+    }
+
+    #[test]
+    fn zero_padded_number_formats_load_as_the_codes_the_sheet_shows() {
+        assert_eq!(zero_padded_width("00000"), Some(5));
+        assert_eq!(zero_padded_width("000000;-000000"), Some(6));
+        assert_eq!(zero_padded_width("0"), None);
+        assert_eq!(zero_padded_width("0.00"), None);
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/zip_codes.xlsx"));
+        let conn = load_fixture(&path, "zips", &[]);
+        let types: Vec<String> = conn
+            .prepare("SELECT data_type FROM information_schema.columns WHERE table_name = 'zips_Data' ORDER BY ordinal_position").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(types, ["VARCHAR", "VARCHAR", "DOUBLE"]);
+        let zips: Vec<String> = conn.prepare("SELECT zip FROM zips_Data").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap();
+        assert_eq!(zips, ["00501", "02134", "90210"]);
+    }
+
+    // The measurement behind --parallel-writes staying opt-in. Slow, so run it on purpose:
+    // cargo test --release parallel_writes_against -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn parallel_writes_against_the_shared_connection_on_fifty_files() {
+        let fixture = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/data_10k.xlsx"));
+        let dir = std::env::temp_dir().join(format!("parallel_writes_bench_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<PathBuf> = (1..=50)
+            .map(|i| {
+                let copy = dir.join(format!("data_{:02}.xlsx", i));
+                fs::copy(&fixture, &copy).unwrap();
+                copy
+            })
+            .collect();
+        let mut args = Config::parse_from(["excel_loader_rs", "--benchmark"]);
+        args.path = dir.to_string_lossy().into_owned();
+        let mut out = Vec::new();
+        benchmark_files(&files, &mut args, &mut out).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let rendered = String::from_utf8(out).unwrap();
+        println!("{}", rendered);
+        for row in &table_cells(&rendered)[1..] {
+            assert_eq!((row[2], row[3]), ("50", "500000"), "{:?}", row);
+        }
+    }
+
+    // The measurement behind the --mmap numbers in the README. Slow, so run it on purpose:
+    // cargo test --release mmap_reads_against -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn mmap_reads_against_the_buffered_reader_on_the_largest_fixture() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/data_1mil.xlsx"));
+        let args = Config::parse_from(["excel_loader_rs"]);
+        fn read_first_sheet<RS: Read + Seek>(workbook: &mut Sheets<RS>) -> (usize, usize) {
+            let sheet = workbook.sheet_names()[0].clone();
+            workbook.worksheet_range(&sheet).unwrap().get_size()
+        }
+        let (mut buffered, mut mapped) = (Duration::MAX, Duration::MAX);
+        for _ in 0..3 {
+            let start = Instant::now();
+            let size = read_first_sheet(&mut open_spreadsheet(&path, &args).unwrap());
+            buffered = buffered.min(start.elapsed());
+
+            let start = Instant::now();
+            let file = fs::File::open(&path).unwrap();
+            let mmap = unsafe { memmap2::Mmap::map(&file) }.unwrap();
+            assert_eq!(read_first_sheet(&mut spreadsheet_from(Cursor::new(&mmap[..]), &path).unwrap()), size);
+            mapped = mapped.min(start.elapsed());
+        }
+        println!("buffered reader: {:.2?}, --mmap: {:.2?}", buffered, mapped);
+    }
+
+    // The measurement behind the Appender being the default. Slow, so run it on purpose:
+    // cargo test --release appender_against -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn appender_against_sql_inserts_on_data_50k() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/data_50k.xlsx"));
+        let conn = Connection::open_in_memory().unwrap();
+        let progress = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+        let mut tables = Vec::new();
+        for mode in [InsertMode::Appender, InsertMode::Sql] {
+            let mut fastest = Duration::MAX;
+            for run in 1..=3 {
+                let db: Box<dyn Db> = Box::new(DuckBackend(conn.try_clone().unwrap(), mode));
+                let start = Instant::now();
+                let loaded = process_excel_file(&path, &format!("{:?}_{}", mode, run), &Arc::new(Mutex::new(db)), &progress, &Config::default()).unwrap();
+                fastest = fastest.min(start.elapsed());
+                assert_eq!(loaded[0].rows, 50_000, "{:?}", mode);
+                tables.push(loaded[0].table.clone());
+            }
+            println!("{:?}: {:.2?} ({:.0} rows/s)", mode, fastest, 50_000.0 / fastest.as_secs_f64());
+        }
+        // Both paths have to produce the same table, not just a faster one
+        let (appender, sql) = (quote_table(&tables[0]), quote_table(&tables[3]));
+        for (a, b) in [(&appender, &sql), (&sql, &appender)] {
+            let extra: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM (SELECT * FROM {} EXCEPT ALL SELECT * FROM {})", a, b), [], |row| row.get(0)).unwrap();
+            assert_eq!(extra, 0, "{} has rows {} lacks", a, b);
+        }
+    }
+
+    #[test]
+    fn config_header_row_picks_the_row_header_row_does() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/report_block.xlsx"));
+        let config = std::env::temp_dir().join(format!("header_row_{}.toml", std::process::id()));
+        fs::write(&config, "[[sheet]]\nsheet = \"Report\"\nheader_row = 1\n").unwrap();
+        let columns = |args: &Config| {
+            let (conn, _) = load_fixture_with(&path, "report", args, InsertMode::Appender).unwrap();
+            let names: Vec<String> = conn
+                .prepare("SELECT column_name FROM information_schema.columns WHERE table_name = 'report_Report' ORDER BY ordinal_position").unwrap()
+                .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+            names
+        };
+        let from_flags = columns(&Config::parse_from(["excel_loader_rs", "--skip-rows", "1", "--header-row", "1"]));
+        let from_config = columns(&Config::try_parse_with_config(["excel_loader_rs", "--config", config.to_str().unwrap(), "--skip-rows", "1"]).unwrap());
+        fs::remove_file(&config).unwrap();
+        assert_eq!(from_flags, ["column_1", "region", "sales", "units"]);
+        assert_eq!(from_config, from_flags);
+    }
+
+    #[test]
+    fn sql_dump_replays_the_loaded_tables_with_their_collation() {
+        let dump = std::env::temp_dir().join(format!("sql_dump_{}.sql", std::process::id()));
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/users.xlsx");
+        let dump_of = |flags: &[&str]| {
+            let base = ["excel_loader_rs", "--quiet", "--path", path, "--db", ":memory:", "--sql-dump", dump.to_str().unwrap()];
+            run(Config::parse_from([&base[..], flags].concat())).unwrap();
+            fs::read_to_string(&dump).unwrap()
+        };
+        let sql = dump_of(&["--text-collation", "nocase"]);
+        assert!(sql.contains(r#"CREATE TABLE "users_Sheet1" ("id" DOUBLE, "name" VARCHAR COLLATE nocase, "email" VARCHAR COLLATE nocase);"#), "{}", sql);
+        assert!(!sql.contains(LOAD_LOG_TABLES[0]), "{}", sql);
+        let replayed = Connection::open_in_memory().unwrap();
+        replayed.execute_batch(&sql).unwrap();
+        // Upper-cased names still match, so the collation came through
+        let matched: i64 = replayed.query_row("SELECT COUNT(*) FROM users_Sheet1 WHERE name = upper(name)", [], |row| row.get(0)).unwrap();
+        assert_eq!(matched, 3);
+
+        let sql = dump_of(&["--export-internal"]);
+        assert!(sql.contains(&format!("CREATE TABLE {}", quote_identifier(LOAD_LOG_TABLES[0]))), "{}", sql);
+        assert!(sql.contains(r#""name" VARCHAR,"#), "{}", sql);
+        fs::remove_file(&dump).unwrap();
+        assert!(Config::parse_from(["excel_loader_rs", "--export-internal"]).validate().is_err());
+    }
+
+    #[test]
+    fn add_column_finds_the_table_and_types_the_expression_even_when_empty() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/users.xlsx"));
+        let column_types = |conn: &Connection| -> Vec<(String, String)> {
+            conn.prepare("SELECT column_name, data_type FROM information_schema.columns WHERE table_name = 'users_Sheet1' ORDER BY ordinal_position").unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(Result::unwrap).collect()
+        };
+        for flags in [&[][..], &["--schema-only"][..]] {
+            let conn = load_fixture(&path, "users", flags);
+            for add in ["users Sheet1.loaded on=current_date", "USERS_SHEET1.batch=1", "users_Sheet1.label='it''s'"] {
+                add_column(&conn, &parse_add_column(add).unwrap()).unwrap();
+            }
+            let types = column_types(&conn);
+            assert_eq!(types[3..].iter().map(|(name, dtype)| (name.as_str(), dtype.as_str())).collect::<Vec<_>>(),
+                [("loaded_on", "DATE"), ("batch", "INTEGER"), ("label", "VARCHAR")], "{:?}", flags);
+            let rows: i64 = conn.query_row("SELECT COUNT(*) FROM users_Sheet1 WHERE batch = 1 AND label = 'it''s'", [], |row| row.get(0)).unwrap();
+            assert_eq!(rows, if flags.is_empty() { 3 } else { 0 });
+        }
+        let conn = load_fixture(&path, "users", &[]);
+        let err = add_column(&conn, &parse_add_column("orders.x=1").unwrap()).unwrap_err();
+        assert!(err.to_string().contains("there is no such table"), "{}", err);
+    }
+
+    #[test]
+    fn max_total_rows_counts_only_rows_that_stay_inserted() {
+        let data = concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/");
+        // refused_rows.xlsx fails part-way: its third row passes the checks but DECIMAL(4,2) can't hold it
+        let config = std::env::temp_dir().join(format!("row_budget_{}.toml", std::process::id()));
+        fs::write(&config, "[[sheet]]\nfile = \"refused_rows.xlsx\"\nnull_tokens = [\"oops, late\"]\ntypes = { amount = \"DECIMAL(4,2)\" }\n").unwrap();
+        let mut args = Config::parse_from(["excel_loader_rs", "--config", config.to_str().unwrap(), "--max-total-rows", "7", "--chunk-size", "1", "--sorted"]);
+        args.path = data.to_string();
+        args.read_option_files().unwrap();
+        let files: Vec<PathBuf> = ["legacy.xls", "refused_rows.xlsx", "users.xlsx", "zip_codes.xlsx"].iter().map(|name| Path::new(data).join(name)).collect();
+        let conn = Connection::open_in_memory().unwrap();
+        let stats = load_excel_into_db(&files, &args, &conn).unwrap();
+        fs::remove_file(&config).unwrap();
+        assert_eq!(stats.failures.len(), 1, "{:?}", stats.failures);
+        assert!(stats.failures[0].0.ends_with("refused_rows.xlsx"), "{:?}", stats.failures);
+        // The failed file's two rows were rolled back, so the files after it get them
+        let count = |table: &str| conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get::<_, i64>(0)).unwrap();
+        assert_eq!((count("legacy_Legacy"), count("users_Sheet1"), count("zip_codes_Data")), (3, 3, 1));
+        assert_eq!(stats.rows, 7);
+
+        // Blank rows --skip-blank-rows leaves out don't use up the budget
+        let gaps = PathBuf::from(format!("{}gaps.xlsx", data));
+        let conn = load_fixture(&gaps, "gaps", &["--skip-blank-rows", "--max-total-rows", "4"]);
+        let loaded: i64 = conn.query_row("SELECT COUNT(*) FROM gaps_Data", [], |row| row.get(0)).unwrap();
+        assert_eq!(loaded, 4);
+    }
+
+    #[test]
+    fn password_opens_encrypted_workbooks() {
+        // users.xlsx saved with the open password "secret" (agile encryption, as current Excel saves it)
+        let test_data = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let encrypted = test_data.join("unreadable/encrypted_users.xlsx");
+        let rows = |conn: &Connection| -> Vec<String> {
+            let mut stmt = conn.prepare("SELECT t::VARCHAR FROM users_Sheet1 t ORDER BY 1").unwrap();
+            let rows = stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+            rows
+        };
+        let expected = rows(&load_fixture(&test_data.join("users.xlsx"), "users", &[]));
+        assert_eq!(expected.len(), 3);
+        assert_eq!(rows(&load_fixture(&encrypted, "users", &["--password", "secret"])), expected);
+        // Mapping the file doesn't apply to a workbook that has to be decrypted first
+        assert_eq!(rows(&load_fixture(&encrypted, "users", &["--password", "secret", "--mmap"])), expected);
+        std::env::set_var("EXCEL_LOADER_TEST_PASSWORD", "secret");
+        assert_eq!(rows(&load_fixture(&encrypted, "users", &["--password-env", "EXCEL_LOADER_TEST_PASSWORD"])), expected);
+        // Workbooks that aren't encrypted ignore the password
+        assert_eq!(rows(&load_fixture(&test_data.join("users.xlsx"), "users", &["--password", "secret"])), expected);
+
+        let load = |flags: &[&str]| {
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            format!("{:#}", load_fixture_with(&encrypted, "users", &args, InsertMode::Appender).err().expect("encrypted workbook loaded"))
+        };
+        assert!(load(&[]).ends_with(PASSWORD_PROTECTED), "{}", load(&[]));
+        assert!(load(&["--password", "Secret"]).ends_with(WRONG_PASSWORD), "{}", load(&["--password", "Secret"]));
+        let unset = Config::parse_from(["excel_loader_rs", "--password-env", "EXCEL_LOADER_TEST_NO_SUCH_VAR"]).validate().unwrap_err();
+        assert_eq!(unset.to_string(), "--password-env EXCEL_LOADER_TEST_NO_SUCH_VAR: the environment variable is not set");
+    }
+
+    #[test]
+    fn streamed_sheets_load_the_same_tables_as_whole_ones() {
+        let dir = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        // The database refuses a value of refused_rows.xlsx's third chunk, which streaming can't retry
+        let schema = std::env::temp_dir().join(format!("streamed_schema_{}.toml", std::process::id()));
+        fs::write(&schema, "t_Data.amount = \"DECIMAL(4,2)\"\n").unwrap();
+        // Small chunks stream each sheet past a head of a few rows; --parallel-sheets reads it whole
+        let cases: [(&str, &[&str]); 11] = [
+            ("data_10k.xlsx", &[]),
+            ("data_10k.xlsx", &["--load-rows", "7"]),
+            ("data_10k.xlsx", &["--max-total-rows", "11"]),
+            ("data_10k.xlsx", &["--schema-only"]),
+            ("gaps.xlsx", &["--keep-raw"]),
+            ("zip_codes.xlsx", &[]),
+            ("ledger.xlsb", &[]),
+            ("bad_rows.xlsx", &["--on-bad-row", "skip"]),
+            ("refused_rows.xlsx", &["--on-bad-row", "skip"]),
+            ("refused_rows.xlsx", &["--on-bad-row", "skip", "--schema", schema.to_str().unwrap()]),
+            ("widening.xlsx", &[]),
+        ];
+        for (fixture, flags) in cases {
+            let load = |whole: bool| {
+                let mut argv = vec!["excel_loader_rs", "--chunk-size", "2", "--infer-rows", "2"];
+                argv.extend(flags);
+                if whole {
+                    argv.push("--parallel-sheets");
+                }
+                let mut args = Config::parse_from(argv);
+                args.read_option_files().unwrap();
+                let (conn, loaded) = load_fixture_with(&dir.join(fixture), "t", &args, InsertMode::Appender).unwrap();
+                loaded.into_iter().map(|sheet| {
+                    let mut stmt = conn.prepare(&format!("SELECT t::VARCHAR FROM {} t ORDER BY 1", quote_identifier(&sheet.table))).unwrap();
+                    let rows: Vec<String> = stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+                    (sheet.table, sheet.rows, sheet.expected_rows, sheet.source_size, sheet.columns, rows)
+                }).collect::<Vec<_>>()
+            };
+            let streamed = load(false);
+            assert!(!streamed.is_empty(), "{} {:?}", fixture, flags);
+            assert_eq!(streamed, load(true), "{} {:?}", fixture, flags);
+        }
+        fs::remove_file(&schema).unwrap();
+    }
+
+    #[test]
+    fn sheet_stream_reads_past_its_head_and_gives_up_on_wider_rows() {
+        let dir = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let args = Config::parse_from(["excel_loader_rs", "--chunk-size", "2", "--infer-rows", "1"]);
+        let stream_rows = |fixture: &str| -> Result<(Range<Data>, Vec<Vec<Data>>)> {
+            let path = dir.join(fixture);
+            let mut workbook = open_spreadsheet(&path, &args)?;
+            let mut fix_archive = None;
+            let (head, stream) = SheetStream::open(&mut workbook, &path, "Data", &mut fix_archive, &args)?.unwrap();
+            let mut rows = Vec::new();
+            let mut stream = stream.unwrap();
+            while let Some(row) = stream.next_row()? {
+                rows.push(row);
+            }
+            Ok((head, rows))
+        };
+
+        // The head is the header and two data rows, the second without cells; the rows after it
+        // that have no cells come back blank
+        let (head, rows) = stream_rows("gaps.xlsx").unwrap();
+        let whole = open_spreadsheet(&dir.join("gaps.xlsx"), &args).unwrap().worksheet_range("Data").unwrap();
+        assert_eq!(head.height(), 2);
+        assert_eq!(rows.len(), 6);
+        let streamed: Vec<Vec<Data>> = head.rows().map(<[Data]>::to_vec).chain(rows).collect();
+        assert_eq!(streamed, whole.rows().map(<[Data]>::to_vec).collect::<Vec<_>>());
+
+        let err = stream_rows("widening.xlsx").unwrap_err();
+        let fallback = err.downcast_ref::<StreamFallback>().unwrap();
+        assert_eq!(fallback.reason, "row 10 has cells outside the columns of its first rows");
+
+        // A sheet that ends within the head is read whole on the way
+        let path = dir.join("bad_rows.xlsx");
+        let mut workbook = open_spreadsheet(&path, &args).unwrap();
+        let args = Config::parse_from(["excel_loader_rs"]);
+        let mut fix_archive = None;
+        let (head, stream) = SheetStream::open(&mut workbook, &path, "Data", &mut fix_archive, &args).unwrap().unwrap();
+        assert!(stream.is_none());
+        assert_eq!(head.get_size(), (5, 2));
+    }
+
+    #[test]
+    fn schema_only_creates_the_typed_tables_without_rows() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/complex_data.xlsx"));
+        let load = |flags: &[&str]| {
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            load_fixture_with(&path, "complex_data", &args, InsertMode::Appender).unwrap()
+        };
+        let (_, full) = load(&[]);
+        let (conn, empty) = load(&["--schema-only"]);
+        assert_eq!(empty.len(), full.len());
+        for (empty, full) in empty.iter().zip(&full) {
+            assert_eq!((&empty.table, &empty.columns, empty.rows), (&full.table, &full.columns, 0));
+            assert!(full.rows > 0);
+            let stored: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM {}", quote_identifier(&empty.table)), [], |row| row.get(0)).unwrap();
+            assert_eq!(stored, 0, "{}", empty.table);
+        }
+    }
+
+    #[test]
+    fn partitioned_exports_resume_by_skipping_written_partitions() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/users.xlsx"));
+        let conn = load_fixture(&path, "users", &[]);
+        let dir = std::env::temp_dir().join(format!("partitioned_export_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let query = format!("SELECT id::INTEGER AS id, name FROM users_Sheet1 >> {}", dir.join("users.csv").display());
+        let args = Config::parse_from(["excel_loader_rs", "--export-partition-rows", "2", "--export-order-by", "id"]);
+        run_query(&conn, &query, &args).unwrap();
+        let part = |n: usize| fs::read_to_string(dir.join(format!("users_{:05}.csv", n))).unwrap();
+        assert_eq!(part(1).lines().count(), 3);
+        assert_eq!(part(2).lines().count(), 2);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        // A rerun after a failure writes only the partitions that are missing
+        fs::write(dir.join("users_00001.csv"), "kept\n").unwrap();
+        let second = part(2);
+        fs::remove_file(dir.join("users_00002.csv")).unwrap();
+        run_query(&conn, &query, &args).unwrap();
+        assert_eq!((part(1), part(2)), ("kept\n".to_string(), second));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let unordered = Config::parse_from(["excel_loader_rs", "--export-partition-rows", "2"]);
+        let err = run_query(&conn, &query, &unordered).unwrap_err();
+        assert!(err.to_string().contains("requires --export-order-by"), "{}", err);
+    }
+
+    #[test]
+    fn sample_from_tail_or_random_types_columns_from_other_rows() {
+        // bad_rows.xlsx's amounts are numbers apart from the third of four, "oops, late"
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/bad_rows.xlsx"));
+        let amount = |infer_rows: &str, flags: &[&str]| -> (String, usize) {
+            let args = Config::parse_from([&["excel_loader_rs", "--infer-rows", infer_rows, "--on-bad-row", "null"], flags].concat());
+            let (_, loaded) = load_fixture_with(&path, "bad_rows", &args, InsertMode::Appender).unwrap();
+            (loaded[0].columns[1].1.clone(), loaded[0].rows)
+        };
+        assert_eq!(amount("2", &[]), ("DOUBLE".to_string(), 4));
+        assert_eq!(amount("2", &["--sample-from", "tail"]), ("VARCHAR".to_string(), 4));
+        assert_eq!(amount("4", &["--sample-from", "random"]), ("VARCHAR".to_string(), 4));
+
+        // A random sample keeps sheet order
+        let rows: Vec<Vec<Data>> = (0..50).map(|i| vec![Data::Int(i)]).collect();
+        let rows: Vec<&[Data]> = rows.iter().map(Vec::as_slice).collect();
+        let picked: Vec<i64> = sample_rows(&rows, 10, SampleFrom::Random).iter().map(|row| match row[0] { Data::Int(i) => i, _ => unreachable!() }).collect();
+        assert_eq!(picked.len(), 10);
+        assert!(picked.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", picked);
+    }
+
+    #[test]
+    fn require_columns_fails_tables_missing_a_named_column() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/orders.xlsx"));
+        let load = |flags: &[&str]| {
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            load_fixture_with(&path, "orders", &args, InsertMode::Appender)
+        };
+        let (_, loaded) = load(&["--require-columns", "ORDERS_SHEET1=id, Amount", "--require-columns", "users_Sheet1=email"]).unwrap();
+        assert_eq!(loaded[0].rows, 4);
+        let err = load(&["--require-columns", "orders_Sheet1=id,customer,amount,region"]).err().unwrap();
+        assert!(format!("{:#}", err).contains("Table orders_Sheet1 is missing required column(s): customer, region"), "{:#}", err);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--require-columns", "orders_Sheet1="]).is_err());
+    }
+
+    #[test]
+    fn sheet_regex_picks_matching_sheets_in_every_file() {
+        let data = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let files = [data.join("many_sheets.xlsx"), data.join("report_block.xlsx"), data.join("users.xlsx")];
+        let args = Config::parse_from(["excel_loader_rs", "--sorted", "--sheet-regex", "^(Region[13]|Other)$"]);
+        let conn = Connection::open_in_memory().unwrap();
+        let stats = load_excel_into_db(&files, &args, &conn).unwrap();
+        assert!(stats.failures.is_empty(), "{:?}", stats.failures);
+        let tables: Vec<String> = conn.prepare("SELECT table_name FROM information_schema.tables WHERE table_name NOT LIKE '\\_%' ESCAPE '\\' ORDER BY 1").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(tables, ["many_sheets_Region1", "many_sheets_Region3", "report_block_Other"]);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--sheet-regex", "(Region"]).is_err());
+    }
+
+    #[test]
+    fn text_collation_makes_every_text_column_compare_by_it() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/users.xlsx"));
+        let matches = |flags: &[&str]| -> i64 {
+            let conn = load_fixture(&path, "users", &[&["--source-column", "source_file"], flags].concat());
+            conn.query_row("SELECT COUNT(*) FROM users_Sheet1 WHERE name = 'ALICE' AND email = 'ALICE@EXAMPLE.COM' AND source_file = 'USERS.XLSX'", [], |row| row.get(0)).unwrap()
+        };
+        assert_eq!(matches(&[]), 0);
+        assert_eq!(matches(&["--text-collation", "NOCASE"]), 1);
+        let conn = load_fixture(&path, "users", &["--text-collation", "nocase"]);
+        // The column's collation wins over the uncollated result of upper()
+        let joined: i64 = conn.query_row("SELECT COUNT(*) FROM users_Sheet1 a JOIN users_Sheet1 b ON a.name = upper(b.name)", [], |row| row.get(0)).unwrap();
+        assert_eq!(joined, 3);
+        assert!(Config::try_parse_from(["excel_loader_rs", "--text-collation", "nocase; DROP TABLE x"]).is_err());
+    }
+
+    #[test]
+    fn data_dictionary_documents_each_table_as_markdown_or_html() {
+        let data = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let conn = load_fixture(&data.join("orders.xlsx"), "orders", &[]);
+        let dir = std::env::temp_dir().join(format!("data_dictionary_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let markdown = dir.join("dictionary.md");
+        write_data_dictionary(&conn, markdown.to_str().unwrap()).unwrap();
+        let doc = fs::read_to_string(&markdown).unwrap();
+        assert!(doc.contains("## orders_Sheet1\n\n4 rows\n\n| Column | Type | Nulls | Distinct | Min | Max | Samples |"), "{}", doc);
+        assert!(doc.contains("| user_id | DOUBLE | 0 | 3 | 1.0 | 3.0 | 1.0, 2.0, 3.0 |"), "{}", doc);
+        assert!(doc.contains("| product_name | VARCHAR | 0 | 3 | Widget A | Widget C |"), "{}", doc);
+
+        // Cell text is escaped for HTML
+        let conn = load_fixture(&data.join("quoted_text.xlsx"), "quoted", &[]);
+        let html = dir.join("dictionary.html");
+        write_data_dictionary(&conn, html.to_str().unwrap()).unwrap();
+        let doc = fs::read_to_string(&html).unwrap();
+        assert!(doc.starts_with("<!DOCTYPE html>") && doc.ends_with("</body></html>\n"), "{}", doc);
+        assert!(doc.contains("<h2>quoted_Data</h2>\n<p>3 rows</p>"), "{}", doc);
+        assert!(doc.contains("&quot;quoted&quot;") && !doc.contains("\"quoted\""), "{}", doc);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn flag_highlighted_marks_rows_with_a_filled_cell() {
+        // Bob's email and Dana's id are filled yellow; Charlie's name has a fill of "none"
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/highlighted.xlsx"));
+        // A one-row head streams the rest of the sheet, which must line up with the same styles
+        for flags in [&[][..], &["--chunk-size", "1", "--infer-rows", "1"][..]] {
+            let conn = load_fixture(&path, "highlighted", &[&["--flag-highlighted"], flags].concat());
+            let flagged: Vec<(String, bool)> = conn.prepare("SELECT name, _highlighted FROM highlighted_Sheet1 ORDER BY id").unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(Result::unwrap).collect();
+            assert_eq!(flagged, [("Alice".to_string(), false), ("Bob".to_string(), true), ("Charlie".to_string(), false), ("Dana".to_string(), true)], "{:?}", flags);
+        }
+        let ods = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/inventory.ods"));
+        let err = load_fixture_with(&ods, "inventory", &Config::parse_from(["excel_loader_rs", "--flag-highlighted"]), InsertMode::Appender).err().unwrap();
+        assert!(format!("{:#}", err).contains("--flag-highlighted reads xlsx cell styles"), "{:#}", err);
+    }
+
+    #[test]
+    fn number_locale_types_separated_number_text_as_numbers() {
+        // shares.xlsx's prices are text: "$1,234.50", "$99.00" and "-€5"
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/shares.xlsx"));
+        let prices = |flags: &[&str]| -> (String, Vec<String>) {
+            let conn = load_fixture(&path, "shares", flags);
+            let dtype = conn.query_row("SELECT data_type FROM information_schema.columns WHERE table_name = 'shares_Data' AND column_name = 'price'", [], |row| row.get(0)).unwrap();
+            let prices = conn.prepare("SELECT price::VARCHAR FROM shares_Data ORDER BY id").unwrap()
+                .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+            (dtype, prices)
+        };
+        let numbers = ("DOUBLE".to_string(), vec!["1234.5".to_string(), "99.0".to_string(), "-5.0".to_string()]);
+        assert_eq!(prices(&["--number-locale", "en"]), numbers);
+        assert_eq!(prices(&["--number-locale", "auto"]), numbers);
+        // Read the German way the first price is malformed, so the column stays text
+        assert_eq!(prices(&["--number-locale", "de"]).0, "VARCHAR");
+        assert_eq!(prices(&[]), ("VARCHAR".to_string(), vec!["$1,234.50".to_string(), "$99.00".to_string(), "-€5".to_string()]));
+    }
+
+    #[test]
+    fn staging_swaps_a_clean_load_in_and_leaves_main_alone_when_a_file_fails() {
+        let dir = std::env::temp_dir().join(format!("staging_{}", std::process::id()));
+        let (good, bad) = (dir.join("good"), dir.join("bad"));
+        fs::create_dir_all(&good).unwrap();
+        fs::create_dir_all(&bad).unwrap();
+        let data = concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data");
+        for (folder, file) in [(&good, "users.xlsx"), (&good, "orders.xlsx"), (&bad, "users.xlsx"), (&bad, "unreadable/encrypted.xlsx")] {
+            fs::copy(Path::new(data).join(file), folder.join(Path::new(file).file_name().unwrap())).unwrap();
+        }
+        let db = dir.join("served.duckdb");
+        let load = |folder: &Path| run(Config::parse_from(["excel_loader_rs", "--quiet", "--staging", "--path", folder.to_str().unwrap(), "--db", db.to_str().unwrap()]));
+        let state = || -> (i64, i64, i64) {
+            let conn = Connection::open(&db).unwrap();
+            conn.query_row(
+                "SELECT (SELECT count(*) FROM users_Sheet1), (SELECT count(*) FROM orders_Sheet1), \
+                 (SELECT count(*) FROM information_schema.schemata WHERE schema_name = '_staging')",
+                [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            ).unwrap()
+        };
+        load(&good).unwrap();
+        assert_eq!(state(), (3, 4, 0));
+
+        // The encrypted workbook fails, so the users.xlsx staged beside it never replaces main's table
+        Connection::open(&db).unwrap().execute_batch("INSERT INTO users_Sheet1 (id, name) VALUES (99, 'served')").unwrap();
+        let err = load(&bad).unwrap_err();
+        assert!(format!("{:#}", err).contains("staged tables were discarded and main is untouched"), "{:#}", err);
+        assert_eq!(state(), (4, 4, 0));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn post_sql_runs_after_the_load_and_before_the_dump() {
+        let dir = std::env::temp_dir().join(format!("post_sql_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (script, dump) = (dir.join("post.sql"), dir.join("dump.sql"));
+        fs::write(&script, "-- keep one row per user\nCREATE TABLE user_names AS SELECT name FROM users_Sheet1;\nDELETE FROM user_names WHERE name = 'Bob';\n").unwrap();
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/users.xlsx");
+        let post = format!("@{}", script.display());
+        run(Config::parse_from(["excel_loader_rs", "--quiet", "--path", path, "--db", ":memory:", "--post-sql", &post, "--sql-dump", dump.to_str().unwrap()])).unwrap();
+        let sql = fs::read_to_string(&dump).unwrap();
+        assert!(sql.contains(r#"CREATE TABLE "user_names" ("name" VARCHAR);"#), "{}", sql);
+        assert!(sql.contains("'Alice'") && sql.contains("'Charlie'"), "{}", sql);
+        assert_eq!(sql.matches("'Bob'").count(), 1, "{}", sql);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tight_ints_size_columns_from_the_sample_and_widen_or_stop_past_it() {
+        // Transaction_ID runs 0..9999 and Category_Code 1..99, so the first 100 rows fit TINYINT
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/data_10k.xlsx"));
+        let load = |flags: &[&str]| {
+            let args = Config::parse_from([&["excel_loader_rs", "--tight-ints", "--infer-rows", "100"], flags].concat());
+            load_fixture_with(&path, "data_10k", &args, InsertMode::Appender)
+        };
+        let (conn, loaded) = load(&[]).unwrap();
+        let types: Vec<&str> = loaded[0].columns.iter().map(|(_, dtype)| dtype.as_str()).collect();
+        assert_eq!(types, ["SMALLINT", "DOUBLE", "TINYINT", "DATE", "VARCHAR"]);
+        let last: i64 = conn.query_row("SELECT max(Transaction_ID) FROM data_10k_Sheet1", [], |row| row.get(0)).unwrap();
+        assert_eq!((last, loaded[0].rows), (9999, 10_000));
+        let err = load(&["--int-overflow", "error"]).err().unwrap();
+        assert!(format!("{:#}", err).contains("Value 128 in data_10k_Sheet1.Transaction_ID (data row 129) does not fit TINYINT"), "{:#}", err);
+    }
+
+    #[test]
+    fn mmap_loads_the_same_tables_as_the_buffered_reader() {
+        let dir = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        for fixture in ["data_10k.xlsx", "zip_codes.xlsx", "ledger.xlsb", "legacy.xls", "inventory.ods", "many_sheets.xlsx"] {
+            let load = |flags: &[&str]| -> Vec<(String, Vec<String>)> {
+                let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+                let (conn, loaded) = load_fixture_with(&dir.join(fixture), "t", &args, InsertMode::Appender).unwrap();
+                loaded.into_iter().map(|sheet| {
+                    let mut stmt = conn.prepare(&format!("SELECT t::VARCHAR FROM {} t ORDER BY 1", quote_identifier(&sheet.table))).unwrap();
+                    let rows = stmt.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+                    (sheet.table, rows)
+                }).collect()
+            };
+            let buffered = load(&[]);
+            assert!(!buffered.is_empty() && buffered.iter().all(|(_, rows)| !rows.is_empty()), "{}", fixture);
+            assert_eq!(load(&["--mmap"]), buffered, "{}", fixture);
+        }
+    }
+
+    #[test]
+    fn probe_reports_sheets_sizes_and_types_without_loading() {
+        let data = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let files = [data.join("gaps.xlsx"), data.join("unreadable/encrypted.xlsx")];
+        let report = |flags: &[&str]| probe_report(&files, &data, &Config::parse_from([&["excel_loader_rs"], flags].concat())).unwrap();
+        let probed = report(&["--tables-prefix", "raw"]);
+        assert_eq!(probed[0]["sheets"], serde_json::json!([{
+            "sheet": "Data",
+            "table": "raw_gaps_Data",
+            "rows": 7,
+            "columns": 2,
+            "schema": [{ "name": "region", "type": "VARCHAR" }, { "name": "sales", "type": "DOUBLE" }],
+        }]));
+        // A file that can't be opened is reported and the others are still probed
+        assert!(probed[1]["error"].as_str().unwrap().contains("password-protected"), "{}", probed[1]);
+
+        let explained = report(&["--explain-types", "--load-rows", "3"]);
+        assert_eq!(explained[0]["sheets"][0]["rows"], 3);
+        let reason = explained[0]["sheets"][0]["schema"][1]["reason"].as_str().unwrap();
+        assert!(reason.starts_with("DOUBLE: "), "{}", reason);
+    }
+
+    #[test]
+    fn on_truncate_loads_rounded_values_or_fails_the_sheet() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/orders.xlsx"));
+        // One decimal place can't hold the third order's 50.25
+        let schema = std::env::temp_dir().join(format!("on_truncate_{}.toml", std::process::id()));
+        fs::write(&schema, "orders_Sheet1.amount = \"DECIMAL(6,1)\"\n").unwrap();
+        let load = |policy: &str| {
+            let mut args = Config::parse_from(["excel_loader_rs", "--schema", schema.to_str().unwrap(), "--on-truncate", policy]);
+            args.read_option_files().unwrap();
+            load_fixture_with(&path, "orders", &args, InsertMode::Appender).map(|(conn, _)| {
+                conn.prepare("SELECT amount::VARCHAR FROM orders_Sheet1 ORDER BY id").unwrap()
+                    .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect::<Vec<String>>()
+            })
+        };
+        let rounded = ["250.5", "100.0", "50.3", "300.0"];
+        assert_eq!(load("warn").unwrap(), rounded);
+        assert_eq!(load("silent").unwrap(), rounded);
+        let err = load("error").unwrap_err();
+        fs::remove_file(&schema).unwrap();
+        assert!(format!("{:#}", err).contains("Value in orders_Sheet1.amount (data row 3) would lose precision: 50.25 would be rounded to 1 decimal place(s) in DECIMAL(6,1)"), "{:#}", err);
+    }
+
+    #[test]
+    fn single_table_skips_sheets_that_do_not_fit_unless_widened() {
+        let data = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let files = [data.join("bad_rows.xlsx"), data.join("refused_rows.xlsx"), data.join("users.xlsx")];
+        // Three sampled rows make bad_rows.xlsx's amounts VARCHAR ("oops, late") and refused_rows.xlsx's DOUBLE
+        let merged = |flags: &[&str]| -> (Vec<(String, i64)>, String) {
+            let args = Config::parse_from([&["excel_loader_rs", "--sorted", "--infer-rows", "3", "--on-bad-row", "null", "--single-table", "all"], flags].concat());
+            let conn = Connection::open_in_memory().unwrap();
+            load_excel_into_db(&files, &args, &conn).unwrap();
+            let sources = conn.prepare(r#"SELECT _source_file || ':' || _source_sheet, count(*) FROM "all" GROUP BY 1 ORDER BY 1"#).unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(Result::unwrap).collect();
+            let dtype = conn.query_row("SELECT data_type FROM information_schema.columns WHERE table_name = 'all' AND column_name = 'amount'", [], |row| row.get(0)).unwrap();
+            (sources, dtype)
+        };
+        // users.xlsx's columns differ, so it is left out either way
+        assert_eq!(merged(&[]), (vec![("bad_rows.xlsx:Data".to_string(), 4)], "VARCHAR".to_string()));
+        assert_eq!(merged(&["--single-table-widen"]), (vec![("bad_rows.xlsx:Data".to_string(), 4), ("refused_rows.xlsx:Data".to_string(), 5)], "VARCHAR".to_string()));
+    }
+
+    #[test]
+    fn control_files_check_row_counts_and_drop_the_tables_of_a_mismatch() {
+        let dir = std::env::temp_dir().join(format!("control_files_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("many_sheets.xlsx");
+        fs::copy(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/many_sheets.xlsx"), &path).unwrap();
+        let load = |control: &str| {
+            fs::write(dir.join("many_sheets.ctl"), control).unwrap();
+            let args = Config::parse_from(["excel_loader_rs", "--control-files"]);
+            let conn = Connection::open_in_memory().unwrap();
+            let db: Box<dyn Db> = Box::new(DuckBackend(conn.try_clone().unwrap(), InsertMode::Appender));
+            let progress = MultiProgress::with_draw_target(indicatif::ProgressDrawTarget::hidden());
+            let loaded = process_excel_file(&path, "many", &Arc::new(Mutex::new(db)), &progress, &args);
+            let tables: i64 = conn.query_row("SELECT count(*) FROM information_schema.tables", [], |row| row.get(0)).unwrap();
+            (loaded.map(|sheets| sheets.len()), tables)
+        };
+        // The six sheets hold 3 to 8 rows
+        let (loaded, tables) = load("# from the export job\n33\n\nRegion1=3\nregion6, 8\n");
+        assert_eq!((loaded.unwrap(), tables), (6, 6));
+        let (loaded, tables) = load("33\nRegion2=5\nSummary=1\n");
+        let err = format!("{:#}", loaded.unwrap_err());
+        assert!(err.contains("Control file check failed: "), "{}", err);
+        assert!(err.contains("Region2") && err.contains("Summary"), "{}", err);
+        assert_eq!(tables, 0);
+        let err = format!("{:#}", load("Region1=three\n").0.unwrap_err());
+        assert!(err.contains("many_sheets.ctl:1: expected a row count, got 'three'"), "{}", err);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn append_creates_missing_tables_then_grows_them_run_after_run() {
+        let dir = std::env::temp_dir().join(format!("append_runs_{}", std::process::id()));
+        let incoming = dir.join("incoming");
+        fs::create_dir_all(&incoming).unwrap();
+        let db = dir.join("daily.duckdb");
+        // The same export arrives each month, its Notes sheet gaining an author column in February
+        for month in ["monthly_jan.xlsx", "monthly_feb.xlsx"] {
+            fs::copy(format!("{}/../test_data/{}", env!("CARGO_MANIFEST_DIR"), month), incoming.join("sales.xlsx")).unwrap();
+            run(Config::parse_from(["excel_loader_rs", "--quiet", "--append", "--path", incoming.to_str().unwrap(), "--db", db.to_str().unwrap()])).unwrap();
+        }
+        let conn = Connection::open(&db).unwrap();
+        let counts: (i64, i64) = conn.query_row("SELECT (SELECT count(*) FROM sales_Sales), (SELECT count(*) FROM sales_Notes)", [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!(counts, (3, 2));
+        let authors: Vec<Option<String>> = conn.prepare("SELECT author FROM sales_Notes ORDER BY author NULLS FIRST").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(authors.len(), 2);
+        assert!(authors[0].is_none() && authors[1].is_some(), "{:?}", authors);
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_crlf_and_encoding_rewrite_exports_for_their_consumer() {
+        let data = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let out = std::env::temp_dir().join(format!("csv_encoding_{}.csv", std::process::id()));
+        let export = |conn: &Connection, table: &str, flags: &[&str]| {
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            run_query(conn, &format!("SELECT * FROM {} >> {}", table, out.display()), &args).map(|_| fs::read(&out).unwrap())
+        };
+        // The first note spans two lines inside its quotes
+        let quoted = load_fixture(&data.join("quoted_text.xlsx"), "quoted", &[]);
+        let plain = String::from_utf8(export(&quoted, "quoted_Data", &[]).unwrap()).unwrap();
+        assert!(!plain.contains('\r'));
+        let bytes = export(&quoted, "quoted_Data", &["--csv-crlf", "--csv-encoding", "utf16le"]).unwrap();
+        assert_eq!(bytes[..2], [0xFF, 0xFE]);
+        let units: Vec<u16> = bytes[2..].chunks(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        assert_eq!(String::from_utf16(&units).unwrap(), plain.replace('\n', "\r\n"));
+        let bom = export(&quoted, "quoted_Data", &["--csv-encoding", "utf8-bom"]).unwrap();
+        assert_eq!(bom, [&[0xEF, 0xBB, 0xBF][..], plain.as_bytes()].concat());
+
+        // Latin-1 holds é but not the euro sign of shares.xlsx's third price
+        let shares = load_fixture(&data.join("shares.xlsx"), "shares", &[]);
+        let latin1 = export(&shares, "(SELECT 'café' AS word)", &["--csv-encoding", "latin1"]).unwrap();
+        assert_eq!(latin1, b"word\ncaf\xe9\n");
+        let args = Config::parse_from(["excel_loader_rs", "--csv-encoding", "latin1"]);
+        fs::write(&out, "id,price\n3,-€5\n").unwrap();
+        let err = CsvOutput::from_args(&args).apply(&out).unwrap_err();
+        assert!(format!("{:#}", err).contains("Character '€' on line 2 of"), "{:#}", err);
+        // The export is refused rather than left behind as UTF-8
+        run_query(&shares, &format!("SELECT * FROM shares_Data >> {}", out.display()), &args).unwrap();
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn explain_types_gives_the_reason_for_each_column_type() {
+        let data = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let files = [data.join("bad_rows.xlsx"), data.join("users.xlsx")];
+        let reasons = |flags: &[&str]| -> Vec<Vec<String>> {
+            let args = Config::parse_from([&["excel_loader_rs", "--explain-types"], flags].concat());
+            probe_report(&files, &data, &args).unwrap().iter()
+                .map(|file| file["sheets"][0]["schema"].as_array().unwrap().iter().map(|column| column["reason"].as_str().unwrap().to_string()).collect())
+                .collect()
+        };
+        assert_eq!(reasons(&["--text-columns", "name"]), [
+            vec![
+                "DOUBLE: sampled 4 rows (4 whole numbers)".to_string(),
+                "VARCHAR: sampled 4 rows (2 whole numbers, 1 fractional value, 1 text value), conflict between DOUBLE and VARCHAR at sampled row 3".to_string(),
+            ],
+            vec![
+                "DOUBLE: sampled 3 rows (3 whole numbers)".to_string(),
+                "VARCHAR: named in --text-columns".to_string(),
+                "VARCHAR: sampled 3 rows (3 text values)".to_string(),
+            ],
+        ]);
+        assert_eq!(reasons(&["--infer-rows", "2"])[0][1], "DOUBLE: sampled 2 rows (1 whole number, 1 fractional value)");
+
+        // A load with the option types the table as explained
+        let args = Config::parse_from(["excel_loader_rs", "--explain-types", "--on-bad-row", "null", "--infer-rows", "2"]);
+        let (_, loaded) = load_fixture_with(&files[0], "bad_rows", &args, InsertMode::Appender).unwrap();
+        assert_eq!((loaded[0].columns[1].1.as_str(), loaded[0].rows), ("DOUBLE", 4));
+    }
+
+    #[test]
+    fn orientation_columns_loads_each_column_of_a_form_as_a_record() {
+        // Field names run down column A and each customer fills a column to their right
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/key_value.xlsx"));
+        let config = std::env::temp_dir().join(format!("key_value_{}.toml", std::process::id()));
+        fs::write(&config, "[[sheet]]\nfile = \"key_value.xlsx\"\ntypes = { seats = \"INTEGER\" }\n").unwrap();
+        let load = |flags: &[&str]| {
+            let mut args = Config::parse_from([&["excel_loader_rs", "--orientation", "columns"], flags].concat());
+            args.read_option_files().unwrap();
+            let (conn, loaded) = load_fixture_with(&path, "key_value", &args, InsertMode::Appender).unwrap();
+            let rows: Vec<String> = conn.prepare("SELECT t::VARCHAR FROM key_value_Form t ORDER BY customer").unwrap()
+                .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+            (loaded[0].columns.clone(), rows)
+        };
+        let (columns, rows) = load(&[]);
+        let names: Vec<(&str, &str)> = columns.iter().map(|(name, dtype)| (name.as_str(), dtype.as_str())).collect();
+        assert_eq!(names, [("customer", "VARCHAR"), ("region", "VARCHAR"), ("seats", "DOUBLE"), ("discount", "DOUBLE")]);
+        assert_eq!(rows, ["{'customer': Acme, 'region': EU, 'seats': 12.0, 'discount': 0.1}", "{'customer': Globex, 'region': US, 'seats': 40.0, 'discount': NULL}"]);
+        // [[sheet]] types name the fields of the transposed layout
+        let (columns, _) = load(&["--config", config.to_str().unwrap()]);
+        fs::remove_file(&config).unwrap();
+        assert_eq!(columns[2], ("seats".to_string(), "INTEGER".to_string()));
+    }
+
+    #[test]
+    fn keep_raw_stores_each_rows_cells_as_read_beside_the_typed_columns() {
+        let data = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+        let raw = |conn: &Connection, sql: &str| -> Vec<(Option<String>, String)> {
+            conn.prepare(sql).unwrap().query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap().map(Result::unwrap).collect()
+        };
+        // The value the DOUBLE column can't take is kept in _raw
+        let conn = load_fixture(&data.join("bad_rows.xlsx"), "bad_rows", &["--keep-raw", "--infer-rows", "2", "--on-bad-row", "null"]);
+        assert_eq!(raw(&conn, "SELECT amount::VARCHAR, _raw::VARCHAR FROM bad_rows_Data WHERE id = 3"), [(None, r#"[3.0,"oops, late"]"#.to_string())]);
+        let dtype: String = conn.query_row("SELECT typeof(_raw) FROM bad_rows_Data LIMIT 1", [], |row| row.get(0)).unwrap();
+        assert_eq!(dtype, "JSON");
+        // So is a null token, and dates stay the serials the sheet holds
+        let conn = load_fixture(&data.join("gaps.xlsx"), "gaps", &["--keep-raw", "--null-values", "North"]);
+        assert_eq!(raw(&conn, "SELECT region, _raw::VARCHAR FROM gaps_Data LIMIT 2"), [(None, r#"["North",10.0]"#.to_string()), (None, "[null,null]".to_string())]);
+        let conn = load_fixture(&data.join("complex_data.xlsx"), "complex_data", &["--keep-raw"]);
+        assert_eq!(raw(&conn, "SELECT Date_of_Joining::VARCHAR, _raw::VARCHAR FROM complex_data_Employee_Records LIMIT 1"),
+            [(Some("2020-01-15".to_string()), r#"[1001.0,"John Doe",43845.0,50000.5,true]"#.to_string())]);
+    }
+
+    #[test]
+    fn min_rows_skips_short_sheets_by_their_whole_length() {
+        // Region1 to Region6 hold 3 to 8 data rows
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/many_sheets.xlsx"));
+        let loaded = |flags: &[&str]| -> Vec<(String, usize)> {
+            let args = Config::parse_from([&["excel_loader_rs"], flags].concat());
+            let (conn, loaded) = load_fixture_with(&path, "many", &args, InsertMode::Appender).unwrap();
+            let tables: i64 = conn.query_row("SELECT count(*) FROM information_schema.tables", [], |row| row.get(0)).unwrap();
+            assert_eq!(tables as usize, loaded.len(), "{:?}", flags);
+            loaded.into_iter().map(|sheet| (sheet.sheet, sheet.rows)).collect()
+        };
+        let long = [("Region4".to_string(), 6), ("Region5".to_string(), 7), ("Region6".to_string(), 8)];
+        assert_eq!(loaded(&["--min-rows", "6"]), long);
+        // A --load-rows sample doesn't make a long sheet short
+        assert_eq!(loaded(&["--min-rows", "6", "--load-rows", "2"]), long.map(|(sheet, _)| (sheet, 2)));
+        assert_eq!(loaded(&["--min-rows", "3"]).len(), 6);
+        assert!(loaded(&["--min-rows", "9"]).is_empty());
+    }
+
+    #[test]
+    fn suffix_types_type_columns_by_the_end_of_their_header() {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data/complex_data.xlsx"));
+        let config = std::env::temp_dir().join(format!("suffix_types_{}.toml", std::process::id()));
+        fs::write(&config, "[[sheet]]\nfile = \"complex_data.xlsx\"\nsheet = \"Employee Records\"\ntypes = { Salary = \"DOUBLE\" }\n").unwrap();
+        let types = |flags: &[&str]| -> Vec<Vec<String>> {
+            let mut args = Config::parse_from([&["excel_loader_rs", "--suffix-types", "_ID=BIGINT, _amount=DECIMAL(18,2),salary=DECIMAL(10,2),_active=VARCHAR"], flags].concat());
+            args.read_option_files().unwrap();
+            let (_, loaded) = load_fixture_with(&path, "complex_data", &args, InsertMode::Appender).unwrap();
+            loaded.into_iter().map(|sheet| sheet.columns.into_iter().map(|(_, dtype)| dtype).collect()).collect()
+        };
+        assert_eq!(types(&[]), [
+            vec!["BIGINT", "VARCHAR", "DATE", "DECIMAL(10,2)", "VARCHAR"],
+            vec!["DOUBLE", "DECIMAL(18,2)", "VARCHAR"],
+        ]);
+        // A [[sheet]] type beats the suffix
+        assert_eq!(types(&["--config", config.to_str().unwrap()])[0][3], "DOUBLE");
+        fs::remove_file(&config).unwrap();
+        assert!(Config::try_parse_from(["excel_loader_rs", "--suffix-types", "_dt=DATETIME2"]).is_err());
+        assert!(Config::try_parse_from(["excel_loader_rs", "--suffix-types", "=BIGINT"]).is_err());
+    }
+
+    #[test]
+    fn load_log_records_each_run_and_how_each_file_fared() {
+        let dir = std::env::temp_dir().join(format!("load_log_{}", std::process::id()));
+        let files = dir.join("files");
+        fs::create_dir_all(&files).unwrap();
+        let data = concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data");
+        for file in ["users.xlsx", "orders.xlsx"] {
+            fs::copy(Path::new(data).join(file), files.join(file)).unwrap();
+        }
+        let db = dir.join("logged.duckdb");
+        let load = || run(Config::parse_from(["excel_loader_rs", "--quiet", "--path", files.to_str().unwrap(), "--db", db.to_str().unwrap()]));
+        load().unwrap();
+        fs::copy(Path::new(data).join("unreadable/encrypted.xlsx"), files.join("encrypted.xlsx")).unwrap();
+        let _ = load();
+
+        let conn = Connection::open(&db).unwrap();
+        let runs: Vec<(i64, i64, i64, i64, i64, bool)> = conn
+            .prepare("SELECT run_id, files, failed_files, tables, rows, finished_at >= started_at FROM _load_runs ORDER BY run_id").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))).unwrap()
+            .collect::<duckdb::Result<_>>().unwrap();
+        assert_eq!(runs, [(1, 2, 0, 2, 7, true), (2, 3, 1, 2, 7, true)]);
+
+        let files: Vec<(i64, String, i64, i64, Option<String>)> = conn
+            .prepare("SELECT run_id, file, tables, rows, error FROM _load_run_files ORDER BY run_id, file").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))).unwrap()
+            .collect::<duckdb::Result<_>>().unwrap();
+        let names: Vec<_> = files.iter().map(|(run, file, tables, rows, error)| (*run, Path::new(file).file_name().unwrap().to_string_lossy().into_owned(), *tables, *rows, error.is_some())).collect();
+        assert_eq!(names, [
+            (1, "orders.xlsx".to_string(), 1, 4, false),
+            (1, "users.xlsx".to_string(), 1, 3, false),
+            (2, "encrypted.xlsx".to_string(), 0, 0, true),
+            (2, "orders.xlsx".to_string(), 1, 4, false),
+            (2, "users.xlsx".to_string(), 1, 3, false),
+        ]);
+        drop(conn);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,33 @@
+use excel_loader_rs::calamine::{open_workbook_auto, Reader};
+use excel_loader_rs::duckdb::Connection;
+use excel_loader_rs::{infer_schema, load_excel_into_db, sanitize_identifier, Config};
+use std::path::PathBuf;
+
+#[test]
+fn loads_and_infers_without_a_command_line() {
+    let test_data = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../test_data"));
+    let mut config = Config::default();
+    config.path = test_data.display().to_string();
+    config.chunk_size = 2;
+
+    let conn = Connection::open_in_memory().unwrap();
+    let files = [test_data.join("legacy.xls"), test_data.join("unreadable/encrypted.xlsx")];
+    let stats = load_excel_into_db(&files, &config, &conn).unwrap();
+    assert_eq!((stats.files, stats.tables, stats.failures.len()), (2, 1, 1));
+    let rows: i64 = conn.query_row("SELECT count(*) FROM legacy_Legacy", [], |row| row.get(0)).unwrap();
+    assert_eq!(rows as usize, stats.rows);
+
+    let mut workbook = open_workbook_auto(test_data.join("legacy.xls")).unwrap();
+    let range = workbook.worksheet_range("Legacy").unwrap();
+    let schema = infer_schema(range, "legacy_Legacy", &config).unwrap();
+    let loaded: Vec<(String, String)> = conn
+        .prepare("SELECT column_name, data_type FROM information_schema.columns WHERE table_name = 'legacy_Legacy' ORDER BY ordinal_position")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(schema, loaded);
+
+    assert_eq!(sanitize_identifier("Q1 Revenue ($)"), "Q1_Revenue");
+}